@@ -0,0 +1,174 @@
+//! Audit trail of privileged actions.
+//!
+//! Every privileged action - rerunning a job, reading someone else's
+//! files, approving or rejecting a job, changing queue configs or quotas,
+//! withdrawing someone's research contribution, changing who belongs to an
+//! organization - appends an `AuditEvent` recording who did it, to what, with which
+//! parameters, and whether it succeeded. Attempts that were refused are
+//! recorded too.
+//!
+//! Events are stored in Firebase RTDB at `audit/{YYYY-MM-DD}/{event_id}`
+//! (UTC), and are only ever written once.
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use igait_lib::microservice::{FirebaseRtdb, now_ms};
+
+use super::{lib::AppError, webhooks::generate_id};
+
+/// A kind of privileged action.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AuditAction {
+    /// A job was rerun from a stage, deleting its later outputs
+    JobRerun,
+    /// A job's files were accessed by someone other than its owner
+    JobFilesAccessed,
+    /// A job awaiting approval was approved
+    JobApproved,
+    /// A job awaiting approval was rejected
+    JobRejected,
+    /// A stage's queue config was replaced
+    QueueConfigUpdated,
+    /// A stage's queue was paused
+    QueuePaused,
+    /// A stage's queue was resumed
+    QueueResumed,
+    /// A role's quota limits were replaced
+    QuotaRoleUpdated,
+    /// A user's quota override was replaced
+    QuotaOverrideUpdated,
+    /// A user's quota override was removed
+    QuotaOverrideRemoved,
+    /// A research contribution was withdrawn on the contributor's behalf
+    ContributionWithdrawn,
+    /// A de-identified research dataset export was started
+    DataExported,
+    /// An email template was overridden
+    EmailTemplateUpdated,
+    /// An email template override was removed
+    EmailTemplateReset,
+    /// An organization was created
+    OrgCreated,
+    /// A user was invited to an organization, or a member's role was changed
+    OrgMemberSet,
+    /// An invitation to an organization was accepted
+    OrgInvitationAccepted,
+    /// A member left or was removed from an organization, or an invitation was withdrawn
+    OrgMemberRemoved,
+}
+
+/// Whether a privileged action went through.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum AuditOutcome {
+    /// The action was carried out
+    Success,
+    /// The action was refused or failed part-way
+    Failure {
+        /// Why it didn't go through
+        error: String,
+    },
+}
+
+/// A record of a privileged action.
+///
+/// # Fields
+/// * `id` - The event's ID, which sorts by time
+/// * `actor` - The UID of the user who performed the action
+/// * `action` - What they did
+/// * `job_id` - The job they did it to, if any
+/// * `parameters` - The parameters of the request
+/// * `outcome` - Whether it went through
+/// * `timestamp` - When it happened (Unix timestamp ms)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuditEvent {
+    pub id: String,
+    pub actor: String,
+    pub action: AuditAction,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub job_id: Option<String>,
+    #[serde(default)]
+    pub parameters: Value,
+    pub outcome: AuditOutcome,
+    pub timestamp: u64,
+}
+
+/// Records the outcome of a privileged action.
+///
+/// The action has already happened (or been refused) by this point,
+/// so a failure to record it is logged rather than failing the request.
+///
+/// # Arguments
+/// * `actor` - The UID of the user who performed the action
+/// * `action` - What they did
+/// * `job_id` - The job they did it to, if any
+/// * `parameters` - The parameters of the request
+/// * `result` - The result the route is about to return
+pub async fn record<T>(
+    actor: &str,
+    action: AuditAction,
+    job_id: Option<&str>,
+    parameters: Value,
+    result: &Result<T, AppError>,
+) {
+    let outcome = match result {
+        Ok(_) => AuditOutcome::Success,
+        Err(err) => AuditOutcome::Failure {
+            error: match err.client_message() {
+                Some(message) => message.to_string(),
+                None => format!("{:#}", err.0),
+            },
+        },
+    };
+
+    let event = AuditEvent {
+        id: generate_id(),
+        actor: actor.to_string(),
+        action,
+        job_id: job_id.map(str::to_string),
+        parameters,
+        outcome,
+        timestamp: now_ms(),
+    };
+
+    let recorded = async {
+        FirebaseRtdb::from_env()
+            .context("Failed to initialise Firebase RTDB client")?
+            .set(&event_path(&event), &event)
+            .await
+    }.await;
+
+    if let Err(e) = recorded {
+        eprintln!("Failed to record audit event {:?}: {e:?}", event);
+    }
+}
+
+/// Reads every event recorded on a day (UTC), oldest first.
+pub async fn read_day(rtdb: &FirebaseRtdb, day: NaiveDate) -> Result<Vec<AuditEvent>> {
+    let events: Option<std::collections::HashMap<String, AuditEvent>> = rtdb
+        .get(&day_path(day))
+        .await
+        .context(format!("Failed to read the audit log for {}", day))?;
+
+    let mut events: Vec<AuditEvent> = events.unwrap_or_default().into_values().collect();
+    events.sort_by(|a, b| a.timestamp.cmp(&b.timestamp).then_with(|| a.id.cmp(&b.id)));
+    Ok(events)
+}
+
+/// Returns the Firebase RTDB path of a day's events.
+fn day_path(day: NaiveDate) -> String {
+    format!("audit/{}", day.format("%Y-%m-%d"))
+}
+
+/// Returns the Firebase RTDB path of an event.
+fn event_path(event: &AuditEvent) -> String {
+    let day = DateTime::<Utc>::from_timestamp_millis(event.timestamp as i64)
+        .unwrap_or_default()
+        .date_naive();
+
+    format!("{}/{}", day_path(day), event.id)
+}
@@ -0,0 +1,258 @@
+//! Batches of jobs submitted together for a research cohort.
+//!
+//! A batch is created from a manifest with one row per participant. Every
+//! row is validated before any job is created, and the outcome of each
+//! row - the job it became, or why it was rejected - is kept with the
+//! batch so it can be looked up later.
+//!
+//! Jobs in a batch don't get their own result emails from the finalize
+//! stage. Instead, the monitor started in `main` watches each open batch
+//! and sends one summary email once every job in it has finished.
+//!
+//! Batches are stored in Firebase RTDB at `batches/{batch_id}`, and each
+//! job created from one records the batch's ID in `Job::batch_id`. Each
+//! user's batches are indexed at `user_batches/{uid}`, and batches still
+//! waiting on jobs at `open_batches`.
+
+use std::{collections::{BTreeMap, HashMap}, sync::Arc, time::Duration};
+
+use anyhow::{Context, Result};
+use axum::http::StatusCode;
+use igait_lib::microservice::{FirebaseRtdb, Locale, QueueOps, now_ms};
+use serde::{Deserialize, Serialize};
+
+use super::{
+    email::send_batch_summary_email,
+    lib::{AppError, AppState, Job, JobStatus},
+};
+
+/// How often the monitor checks whether open batches have finished.
+const MONITOR_INTERVAL: Duration = Duration::from_secs(60);
+
+/// The outcome of one row of a batch manifest.
+///
+/// # Fields
+/// * `row` - The row's number in the manifest (1-indexed, excluding the header)
+/// * `participant_id` - The study's own label for the participant, if given
+/// * `job_id` - The job created for the row, if it was accepted
+/// * `errors` - Why the row was rejected, if it was
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BatchRow {
+    pub row: usize,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub participant_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub job_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub errors: Vec<String>,
+}
+
+impl BatchRow {
+    /// Whether a job was created for the row.
+    pub fn accepted(&self) -> bool {
+        self.job_id.is_some()
+    }
+}
+
+/// A batch of jobs submitted together.
+///
+/// # Fields
+/// * `id` - The batch's ID
+/// * `name` - The batch's display name (e.g. the study or cohort)
+/// * `user_id` - The UID of the user who submitted it
+/// * `email` - Where to send the summary once every job has finished
+/// * `locale` - The language of the summary email
+/// * `created_at` - When it was submitted (Unix timestamp ms)
+/// * `completed_at` - When every job had finished, if they have (Unix timestamp ms)
+/// * `rows` - The outcome of each manifest row, in manifest order
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Batch {
+    pub id: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    pub user_id: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub email: Option<String>,
+    #[serde(default)]
+    pub locale: Locale,
+    pub created_at: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub completed_at: Option<u64>,
+    #[serde(default)]
+    pub rows: Vec<BatchRow>,
+}
+
+impl Batch {
+    /// Returns the IDs of the jobs created for the batch, in manifest order.
+    pub fn job_ids(&self) -> impl Iterator<Item = &str> {
+        self.rows.iter().filter_map(|row| row.job_id.as_deref())
+    }
+}
+
+/// How far along a batch's jobs are.
+///
+/// # Fields
+/// * `counts` - How many of the batch's jobs have each status code
+///   (e.g. `Processing`)
+/// * `finished` - Whether every job is `Complete` or `Error`
+#[derive(Debug, Clone, Serialize)]
+pub struct BatchProgress {
+    pub counts: BTreeMap<&'static str, usize>,
+    pub finished: bool,
+}
+
+impl BatchProgress {
+    /// Tallies the statuses of a batch's jobs.
+    pub fn of(batch: &Batch, jobs: &HashMap<String, Job>) -> Self {
+        let mut counts = BTreeMap::new();
+        let mut finished = true;
+        for job_id in batch.job_ids() {
+            let Some(job) = jobs.get(job_id) else {
+                continue;
+            };
+
+            *counts.entry(job.status.code()).or_insert(0) += 1;
+            finished &= matches!(job.status, JobStatus::Complete { .. } | JobStatus::Error { .. });
+        }
+
+        Self { counts, finished }
+    }
+}
+
+/// Reads a batch.
+///
+/// # Fails
+/// * With a `404` if the batch doesn't exist
+pub async fn read_batch(rtdb: &FirebaseRtdb, batch_id: &str) -> Result<Batch, AppError> {
+    let batch: Option<Batch> = rtdb.get(&batch_path(batch_id))
+        .await
+        .context("Failed to read the batch")?;
+
+    batch.ok_or_else(|| AppError::client(
+        StatusCode::NOT_FOUND,
+        format!("No batch with ID '{}'.", batch_id),
+    ))
+}
+
+/// Reads the jobs created for a batch, keyed by job ID.
+///
+/// Every job in a batch belongs to the user who submitted it, so this
+/// reads their jobs once rather than one at a time.
+pub async fn read_jobs(app: &AppState, batch: &Batch) -> Result<HashMap<String, Job>> {
+    let jobs = app.db
+        .lock()
+        .await
+        .get_all_jobs(&batch.user_id)
+        .await
+        .context("Failed to read the batch's jobs")?;
+
+    Ok(batch
+        .job_ids()
+        .filter_map(|job_id| {
+            let (_, job_index) = QueueOps::parse_job_id(job_id).ok()?;
+            let job = jobs.get(job_index)?.clone();
+            Some((job_id.to_string(), job))
+        })
+        .collect())
+}
+
+/// Runs the batch monitor until the process exits.
+///
+/// Every `MONITOR_INTERVAL`, each open batch is checked, and those whose
+/// jobs have all finished are closed and their summary email sent.
+pub async fn run_monitor(app: Arc<AppState>) {
+    let rtdb = match FirebaseRtdb::from_env() {
+        Ok(rtdb) => rtdb,
+        Err(e) => {
+            eprintln!("Batch monitor disabled - failed to initialise Firebase RTDB client: {e:?}");
+            return;
+        }
+    };
+
+    println!("✅ Batch monitor started");
+    let mut ticker = tokio::time::interval(MONITOR_INTERVAL);
+    loop {
+        ticker.tick().await;
+
+        if let Err(e) = check_open_batches(&app, &rtdb).await {
+            eprintln!("Failed to check open batches: {e:?}");
+        }
+    }
+}
+
+/// Closes every open batch whose jobs have all finished.
+async fn check_open_batches(app: &Arc<AppState>, rtdb: &FirebaseRtdb) -> Result<()> {
+    let open: HashMap<String, bool> = rtdb
+        .get(open_batches_path())
+        .await
+        .context("Failed to read the open batches")?
+        .unwrap_or_default();
+
+    for batch_id in open.keys() {
+        if let Err(e) = check_batch(app, rtdb, batch_id).await {
+            eprintln!("Failed to check batch {batch_id}: {e:?}");
+        }
+    }
+
+    Ok(())
+}
+
+/// Closes a batch if its jobs have all finished, sending its summary email.
+async fn check_batch(app: &Arc<AppState>, rtdb: &FirebaseRtdb, batch_id: &str) -> Result<()> {
+    let Some(mut batch) = rtdb.get::<Batch>(&batch_path(batch_id))
+        .await
+        .context("Failed to read the batch")?
+    else {
+        // The batch is gone, so there's nothing left to wait for
+        return rtdb.delete(&open_batch_path(batch_id)).await;
+    };
+
+    let jobs = read_jobs(app, &batch).await?;
+    if !BatchProgress::of(&batch, &jobs).finished {
+        return Ok(());
+    }
+
+    // Close the batch before emailing, so a failure can't send the summary twice
+    batch.completed_at = Some(now_ms());
+    rtdb.set(&batch_path(batch_id), &batch)
+        .await
+        .context("Failed to close the batch")?;
+    rtdb.delete(&open_batch_path(batch_id))
+        .await
+        .context("Failed to remove the batch from the open batches")?;
+    println!("Batch {} finished", batch_id);
+
+    match &batch.email {
+        Some(email) => send_batch_summary_email(app.clone(), email, &batch, &jobs)
+            .await
+            .context("Failed to send the batch summary email")?,
+        None => println!("Batch {} has no email address, so no summary was sent", batch_id),
+    }
+
+    Ok(())
+}
+
+/// Returns the Firebase RTDB path of a batch.
+pub fn batch_path(batch_id: &str) -> String {
+    format!("batches/{}", batch_id)
+}
+
+/// Returns the Firebase RTDB path of a user's batch index.
+pub fn user_batches_path(uid: &str) -> String {
+    format!("user_batches/{}", uid)
+}
+
+/// Returns the Firebase RTDB path of a batch in its user's index.
+pub fn user_batch_path(uid: &str, batch_id: &str) -> String {
+    format!("{}/{}", user_batches_path(uid), batch_id)
+}
+
+/// Returns the Firebase RTDB path of the batches waiting on jobs.
+pub fn open_batches_path() -> &'static str {
+    "open_batches"
+}
+
+/// Returns the Firebase RTDB path of a batch's entry in the open batches.
+pub fn open_batch_path(batch_id: &str) -> String {
+    format!("{}/{}", open_batches_path(), batch_id)
+}
@@ -0,0 +1,133 @@
+//! Consent records for research contributions.
+//!
+//! Every contribution records who gave consent, to which version of the
+//! consent form, when, and where their videos were stored. A contributor
+//! can later withdraw, which deletes the videos but keeps the record -
+//! marked as withdrawn - as evidence of what was consented to and when.
+//!
+//! Records are stored in Firebase RTDB at `contributions/{uid}/{contribution_id}`.
+
+use std::collections::HashMap;
+
+use anyhow::{Context, Result};
+use axum::http::StatusCode;
+use serde::{Deserialize, Serialize};
+
+use igait_lib::microservice::FirebaseRtdb;
+
+use super::lib::AppError;
+
+/// The longest consent form version accepted.
+const MAX_CONSENT_VERSION_LENGTH: usize = 64;
+
+/// Whether a contribution can still be used for research.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ContributionStatus {
+    /// The videos are stored and may be used
+    Active,
+    /// The contributor withdrew, and the videos were deleted
+    Withdrawn,
+}
+
+/// A research contribution and the consent it was given under.
+///
+/// # Fields
+/// * `id` - The contribution's ID
+/// * `user_id` - The UID of the contributor
+/// * `name` - The contributor's name
+/// * `email` - The contributor's email
+/// * `consent_version` - The version of the consent form they agreed to
+/// * `consented_at` - When they gave consent (Unix timestamp ms)
+/// * `front_key` - The storage key of the front video
+/// * `side_key` - The storage key of the side video
+/// * `status` - Whether the contribution is active or withdrawn
+/// * `withdrawn_at` - When it was withdrawn, if it was (Unix timestamp ms)
+/// * `withdrawn_by` - The UID of the user who withdrew it, if it was
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Contribution {
+    pub id: String,
+    pub user_id: String,
+    pub name: String,
+    pub email: String,
+    pub consent_version: String,
+    pub consented_at: u64,
+    pub front_key: String,
+    pub side_key: String,
+    pub status: ContributionStatus,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub withdrawn_at: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub withdrawn_by: Option<String>,
+}
+
+/// Checks the version of the consent form a contributor agreed to.
+///
+/// When `CONSENT_FORM_VERSION` is set, contributors must have agreed to
+/// that version, so nobody contributes under a form that has since changed.
+///
+/// # Fails
+/// * With a `400` if the version is empty or too long
+/// * With a `409` if it isn't the current version
+pub fn validate_consent_version(version: &str) -> Result<(), AppError> {
+    if version.is_empty() || version.chars().count() > MAX_CONSENT_VERSION_LENGTH {
+        return Err(AppError::client(
+            StatusCode::BAD_REQUEST,
+            format!("'consent_version' must be between 1 and {} characters.", MAX_CONSENT_VERSION_LENGTH),
+        ));
+    }
+
+    if let Ok(current) = std::env::var("CONSENT_FORM_VERSION") {
+        if !current.is_empty() && current != version {
+            return Err(AppError::client(
+                StatusCode::CONFLICT,
+                format!(
+                    "The consent form has changed (version {} agreed to, {} is current). Please review it and agree again.",
+                    version, current
+                ),
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+/// Reads a contribution.
+///
+/// # Fails
+/// * With a `404` if the contribution doesn't exist
+pub async fn read_contribution(
+    rtdb: &FirebaseRtdb,
+    uid: &str,
+    contribution_id: &str,
+) -> Result<Contribution, AppError> {
+    let contribution: Option<Contribution> = rtdb.get(&contribution_path(uid, contribution_id))
+        .await
+        .context("Failed to read the contribution")?;
+
+    contribution.ok_or_else(|| AppError::client(
+        StatusCode::NOT_FOUND,
+        format!("No contribution with ID '{}'.", contribution_id),
+    ))
+}
+
+/// Reads every contribution a user has made, oldest first.
+pub async fn read_contributions(rtdb: &FirebaseRtdb, uid: &str) -> Result<Vec<Contribution>> {
+    let contributions: Option<HashMap<String, Contribution>> = rtdb.get(&contributions_path(uid))
+        .await
+        .context("Failed to read the user's contributions")?;
+
+    let mut contributions: Vec<Contribution> = contributions.unwrap_or_default().into_values().collect();
+    contributions.sort_by(|a, b| a.consented_at.cmp(&b.consented_at).then_with(|| a.id.cmp(&b.id)));
+    Ok(contributions)
+}
+
+/// Returns the Firebase RTDB path of a user's contributions.
+pub fn contributions_path(uid: &str) -> String {
+    format!("contributions/{}", uid)
+}
+
+/// Returns the Firebase RTDB path of a contribution.
+pub fn contribution_path(uid: &str, contribution_id: &str) -> String {
+    format!("{}/{}", contributions_path(uid), contribution_id)
+}
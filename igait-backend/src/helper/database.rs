@@ -1,9 +1,11 @@
 use crate::helper::lib::User;
 
+use std::collections::HashMap;
+
 use firebase_rs::*;
 use anyhow::{ Context, Result, anyhow };
 
-use super::lib::{Job, JobStatus};
+use super::lib::{Job, JobMedia, JobReview, JobStatus};
 
 /// A wrapper class on the Firebase database to make it easier to interact with.
 #[derive( Debug )]
@@ -246,6 +248,114 @@ impl Database {
         Ok(())
     }
 
+    /// Records an administrator's approval decision on a job.
+    /// 
+    /// # Arguments
+    /// * `uid` - The user ID that owns the job.
+    /// * `job_id` - The ID of the job that was reviewed.
+    /// * `review` - The administrator's decision.
+    /// 
+    /// # Fails
+    /// * If the user doesn't exist and can't be created
+    /// * If the job ID doesn't exist
+    /// 
+    /// # Returns
+    /// * A successful result if the review was recorded
+    /// 
+    /// # Notes
+    /// * This function mirrors the decision onto the job's `approved` flag.
+    pub async fn set_review (
+        &self,
+        uid:         &str,
+        job_id:      usize,
+        review:      JobReview
+    ) -> Result<()> {
+        println!("Recording review...");
+
+        // First double check that the user actually exists
+        self.ensure_user(uid).await.context("Failed to ensure user!")?;
+
+        // Get the user handle
+        let user_handle = self._state.at(uid);
+
+        // Get the jobs as a mutable vector
+        let mut jobs = self.get_jobs(uid).await
+            .context("Failed to get jobs!")?;
+
+        // Mirror the decision onto the job
+        let job = jobs.get_mut(job_id).ok_or(anyhow!("Job ID does not exist!"))?;
+        job.approved = review.approved;
+        job.review = Some(review);
+
+        // Get existing user to preserve administrator status
+        let existing_user = user_handle.get::<User>().await
+            .map_err(|e| anyhow!("{e:?}"))
+            .context("Failed to get existing user!")?;
+
+        // Update the user with the modified job array
+        user_handle.update(&User {
+                uid: String::from(uid),
+                jobs,
+                administrator: existing_user.administrator,
+            }).await
+            .map_err(|e| anyhow!("{e:?}"))
+            .context("Failed to update the user object in the database!")?;
+
+        println!("Recorded review successfully!");
+        Ok(())
+    }
+
+    /// Records the probed properties of a job's videos.
+    /// 
+    /// # Arguments
+    /// * `uid` - The user ID that owns the job.
+    /// * `job_id` - The ID of the job the videos belong to.
+    /// * `media` - The properties of the videos.
+    /// 
+    /// # Fails
+    /// * If the user doesn't exist and can't be created
+    /// * If the job ID doesn't exist
+    /// 
+    /// # Returns
+    /// * A successful result if the properties were recorded
+    pub async fn set_media (
+        &self,
+        uid:         &str,
+        job_id:      usize,
+        media:       JobMedia
+    ) -> Result<()> {
+        println!("Recording media properties...");
+
+        // First double check that the user actually exists
+        self.ensure_user(uid).await.context("Failed to ensure user!")?;
+
+        // Get the user handle
+        let user_handle = self._state.at(uid);
+
+        // Get the jobs as a mutable vector
+        let mut jobs = self.get_jobs(uid).await
+            .context("Failed to get jobs!")?;
+
+        jobs.get_mut(job_id).ok_or(anyhow!("Job ID does not exist!"))?.media = Some(media);
+
+        // Get existing user to preserve administrator status
+        let existing_user = user_handle.get::<User>().await
+            .map_err(|e| anyhow!("{e:?}"))
+            .context("Failed to get existing user!")?;
+
+        // Update the user with the modified job array
+        user_handle.update(&User {
+                uid: String::from(uid),
+                jobs,
+                administrator: existing_user.administrator,
+            }).await
+            .map_err(|e| anyhow!("{e:?}"))
+            .context("Failed to update the user object in the database!")?;
+
+        println!("Recorded media properties successfully!");
+        Ok(())
+    }
+
     /// Gets the status of a job.
     /// 
     /// # Arguments
@@ -345,4 +455,28 @@ impl Database {
         self.get_jobs(uid).await
             .context("Failed to get jobs!")
     }
+
+    /// Gets every user in the database, keyed by user ID.
+    /// 
+    /// # Fails
+    /// * If the users can't be read
+    /// 
+    /// # Returns
+    /// * Every user, including their jobs
+    /// 
+    /// # Notes
+    /// * This loads the entire `users` tree, so it should only be used
+    ///   for administrative views.
+    /// * This function returns an empty map if there are no users.
+    pub async fn get_all_users (
+        &self
+    ) -> Result<HashMap<String, User>> {
+        println!("Getting all users...");
+
+        match self._state.get::<HashMap<String, User>>().await {
+            Ok(users) => Ok(users),
+            Err(RequestError::NotFoundOrNullBody) => Ok(HashMap::new()),
+            Err(e) => Err(anyhow!("{e:?}")).context("Failed to get users!"),
+        }
+    }
 }
\ No newline at end of file
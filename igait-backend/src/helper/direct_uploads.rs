@@ -0,0 +1,164 @@
+//! Jobs waiting for their videos to be uploaded directly to AWS S3.
+//!
+//! A direct upload is recorded in Firebase RTDB at `pending_uploads/{job_id}`
+//! from when its job is created until it's confirmed. Uploads that are never
+//! confirmed would otherwise wait forever, holding one of the user's in-flight
+//! job slots, so the sweeper started in `main` expires them once their upload
+//! URLs have: the job is marked as failed, its quota slot is given back and
+//! the pending upload is removed.
+
+use std::{collections::HashMap, sync::Arc, time::Duration};
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+use igait_lib::microservice::{FirebaseRtdb, QueueOps, now_ms};
+
+use super::{
+    lib::{AppState, JobStatus},
+    quota::QuotaReservation,
+};
+
+/// How often the sweeper looks for expired uploads.
+const SWEEP_INTERVAL: Duration = Duration::from_secs(5 * 60);
+
+/// A video the backend is waiting for.
+///
+/// # Fields
+/// * `key` - Where the video is uploaded to
+/// * `size` - The declared size of the video in bytes
+/// * `content_type` - The content type the video must be uploaded with
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PendingFile {
+    pub key: String,
+    pub size: u64,
+    pub content_type: String,
+}
+
+/// A job whose videos haven't been confirmed yet.
+///
+/// # Fields
+/// * `job_id` - The job waiting for its videos
+/// * `user_id` - The job's owner
+/// * `front` - The front video
+/// * `side` - The side video
+/// * `expires_at` - When the upload URLs stop working (Unix timestamp ms)
+/// * `reservation` - The quota slot the job holds, if its owner is limited
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PendingUpload {
+    pub job_id: String,
+    pub user_id: String,
+    pub front: PendingFile,
+    pub side: PendingFile,
+    pub expires_at: u64,
+    #[serde(default)]
+    pub reservation: Option<QuotaReservation>,
+}
+
+/// Returns the RTDB path of every pending upload.
+fn pending_uploads_path() -> &'static str {
+    "pending_uploads"
+}
+
+/// Returns the RTDB path of a pending upload.
+pub fn pending_upload_path(job_id: &str) -> String {
+    format!("{}/{}", pending_uploads_path(), job_id)
+}
+
+/// Expires a pending upload whose upload URLs have expired: its job is
+/// marked as failed, its quota slot is given back, its videos (if any
+/// arrived) are deleted and the pending upload is removed.
+///
+/// The pending upload is removed first, and only if it hasn't changed,
+/// so an upload is expired once even if the sweeper and its owner's
+/// confirmation get to it at the same time.
+///
+/// # Returns
+/// * Whether this call expired the upload (`false` if it's gone, or
+///   hasn't expired)
+pub async fn expire_upload(app: &Arc<AppState>, rtdb: &FirebaseRtdb, job_id: &str) -> Result<bool> {
+    let path = pending_upload_path(job_id);
+    let (pending, etag) = rtdb.get_with_etag::<PendingUpload>(&path)
+        .await
+        .context("Failed to read the pending upload")?;
+
+    let Some(pending) = pending.filter(|pending| now_ms() > pending.expires_at) else {
+        return Ok(false);
+    };
+    if !rtdb.delete_if_match(&path, &etag).await.context("Failed to remove the pending upload")? {
+        return Ok(false);
+    }
+
+    // ── Fail the job ────────────────────────────────────────────────
+    let (uid, job_index) = QueueOps::parse_job_id(&pending.job_id)
+        .context("Invalid job ID")?;
+    {
+        let db = app.db.lock().await;
+        let locale = db.get_job(&uid, job_index)
+            .await
+            .map(|job| job.locale)
+            .unwrap_or_default();
+        let status = JobStatus::error(
+            "Upload failed: The videos were not uploaded before the upload URLs expired.".to_string(),
+            locale,
+        );
+        db.update_status(&uid, job_index, status)
+            .await
+            .context("Failed to update the status of the job")?;
+    }
+
+    // ── Clean up after it ───────────────────────────────────────────
+    if let Some(reservation) = pending.reservation {
+        reservation.release().await;
+    }
+    for file in [&pending.front, &pending.side] {
+        if let Err(e) = app.storage.delete(&file.key).await {
+            eprintln!("Failed to remove expired upload {}: {e:?}", file.key);
+        }
+    }
+
+    println!("Direct upload for job {} expired", pending.job_id);
+    Ok(true)
+}
+
+/// Runs the pending upload sweeper until the process exits.
+///
+/// Every `SWEEP_INTERVAL`, each pending upload whose URLs have expired
+/// is expired.
+pub async fn run_sweeper(app: Arc<AppState>) {
+    let rtdb = match FirebaseRtdb::from_env() {
+        Ok(rtdb) => rtdb,
+        Err(e) => {
+            eprintln!("Pending upload sweeper disabled - failed to initialise Firebase RTDB client: {e:?}");
+            return;
+        }
+    };
+
+    println!("✅ Pending upload sweeper started");
+    let mut ticker = tokio::time::interval(SWEEP_INTERVAL);
+    loop {
+        ticker.tick().await;
+
+        if let Err(e) = sweep_expired(&app, &rtdb).await {
+            eprintln!("Failed to sweep pending uploads: {e:?}");
+        }
+    }
+}
+
+/// Expires every pending upload whose URLs have expired.
+async fn sweep_expired(app: &Arc<AppState>, rtdb: &FirebaseRtdb) -> Result<()> {
+    let pending: HashMap<String, PendingUpload> = rtdb
+        .get(pending_uploads_path())
+        .await
+        .context("Failed to read the pending uploads")?
+        .unwrap_or_default();
+
+    let now = now_ms();
+    for job_id in pending.into_iter().filter(|(_, pending)| now > pending.expires_at).map(|(job_id, _)| job_id) {
+        if let Err(e) = expire_upload(app, rtdb, &job_id).await {
+            eprintln!("Failed to expire the pending upload for job {job_id}: {e:?}");
+        }
+    }
+
+    Ok(())
+}
@@ -3,21 +3,24 @@
 //! This module provides email sending capabilities using the shared
 //! email client from igait-lib, with some backend-specific wrappers.
 
-use std::time::SystemTime;
+use std::{collections::HashMap, time::SystemTime};
 
 use anyhow::Result;
 use chrono::{DateTime, Utc};
 
 use crate::{AppState, Arc};
-use igait_lib::microservice::EmailTemplates;
+use igait_lib::microservice::{Email, EmailTemplates, Locale};
 
-use super::lib::Job;
+use super::{
+    batches::Batch,
+    lib::{Job, JobStatus},
+};
 
-/// Sends an email using the app's AWS SES client.
+/// Renders an email and sends it using the app's email client.
 ///
 /// This is a low-level function - prefer using the higher-level wrappers below.
-pub async fn send_email(app: Arc<AppState>, to: &str, subject: &str, body: &str) -> Result<()> {
-    app.email_client.send(to, subject, body).await
+pub async fn send_email(app: Arc<AppState>, to: &str, message: &Email) -> Result<()> {
+    app.email_client.send(to, message).await
 }
 
 /// Sends a "submission received" welcome email to the user.
@@ -32,7 +35,8 @@ pub async fn send_welcome_email(
     let dt_now_utc: DateTime<Utc> = SystemTime::now().into();
     let dt_now_cst = dt_now_utc.with_timezone(&chrono_tz::US::Central);
 
-    let (subject, body) = EmailTemplates::submission_received(
+    let message = EmailTemplates::submission_received(
+        job.locale,
         &dt_now_cst.to_string(),
         job.age,
         &job.ethnicity.to_string(),
@@ -43,13 +47,65 @@ pub async fn send_welcome_email(
         &job_id.to_string(),
     );
 
-    send_email(app, &job.email, &subject, &body).await
+    send_email(app, &job.email, &message).await
 }
 
 /// Sends a contribution thank-you email.
 ///
 /// Called when a user contributes data to the research study.
-pub async fn send_contribution_email(app: Arc<AppState>, email: &str, name: &str) -> Result<()> {
-    let (subject, body) = EmailTemplates::contribution_received(name);
-    send_email(app, email, &subject, &body).await
-}
\ No newline at end of file
+pub async fn send_contribution_email(app: Arc<AppState>, email: &str, name: &str, locale: Locale) -> Result<()> {
+    let message = EmailTemplates::contribution_received(locale, name);
+    send_email(app, email, &message).await
+}
+
+/// Sends a "submission rejected" email to the user.
+///
+/// Called when an administrator rejects a job that was awaiting approval.
+pub async fn send_rejection_email(
+    app: Arc<AppState>,
+    job: &Job,
+    uid: &str,
+    job_id: usize,
+    note: Option<&str>,
+) -> Result<()> {
+    let dt_now_utc: DateTime<Utc> = SystemTime::now().into();
+    let dt_now_cst = dt_now_utc.with_timezone(&chrono_tz::US::Central);
+
+    let message = EmailTemplates::submission_rejected(
+        job.locale,
+        &dt_now_cst.to_string(),
+        note,
+        uid,
+        &job_id.to_string(),
+    );
+
+    send_email(app, &job.email, &message).await
+}
+/// Sends a summary email for a batch whose jobs have all finished.
+///
+/// Called by the batch monitor in place of an email for each job.
+pub async fn send_batch_summary_email(
+    app: Arc<AppState>,
+    email: &str,
+    batch: &Batch,
+    jobs: &HashMap<String, Job>,
+) -> Result<()> {
+    let submitted_utc = DateTime::<Utc>::from_timestamp_millis(batch.created_at as i64).unwrap_or_default();
+    let submitted_cst = submitted_utc.with_timezone(&chrono_tz::US::Central);
+
+    let completed = jobs.values().filter(|job| matches!(job.status, JobStatus::Complete { .. })).count();
+    let asd = jobs.values().filter(|job| matches!(job.status, JobStatus::Complete { asd: true, .. })).count();
+    let failed = jobs.values().filter(|job| matches!(job.status, JobStatus::Error { .. })).count();
+
+    let message = EmailTemplates::batch_completed(
+        batch.locale,
+        &submitted_cst.to_string(),
+        batch.name.as_deref(),
+        &batch.id,
+        completed,
+        asd,
+        failed,
+    );
+
+    send_email(app, email, &message).await
+}
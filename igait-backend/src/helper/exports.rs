@@ -0,0 +1,511 @@
+//! De-identified research dataset exports.
+//!
+//! An export collects the artifacts researchers work with - pose landmarks
+//! (stage 4), gait analysis (stage 5) and predictions (stage 6) - along
+//! with each job's demographics, for every job matching a filter.
+//!
+//! User IDs, job IDs and emails never appear in an export. Each is replaced
+//! by a pseudonymous ID: an HMAC of the real value keyed with
+//! `EXPORT_PSEUDONYM_SECRET`. The same user gets the same ID in every
+//! export, so datasets can be joined, but the IDs can't be reversed
+//! without the secret. Artifacts are rewritten rather than copied, since
+//! the stages name what they describe after the job (e.g. the subject of
+//! a gait analysis is `{job_id}_front`); artifacts that can't be parsed
+//! are left out.
+//!
+//! Exports run in the background. The archive, whose name and manifest
+//! carry `EXPORT_FORMAT_VERSION`, is built in a temporary file and
+//! uploaded in parts, so it's never held in memory whole. It and a data
+//! dictionary describing it are written to AWS S3 under
+//! `exports/{export_id}/`, and the export's progress is tracked in
+//! Firebase RTDB at `exports/{export_id}`. Exports still running when the
+//! backend stops are marked as failed when it starts again.
+
+use std::{
+    collections::{HashMap, HashSet},
+    fs::File,
+    io::Write,
+    path::PathBuf,
+    sync::Arc,
+    time::SystemTime,
+};
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+use sha2::Sha256;
+use zip::{CompressionMethod, ZipWriter, write::SimpleFileOptions};
+
+use igait_lib::microservice::{FirebaseRtdb, StorageKeyExt, StoragePaths, now_ms};
+
+use super::{
+    contributions::{Contribution, ContributionStatus},
+    lib::{AppState, Job, JobStatus},
+};
+
+/// The version of the archive layout. Bump it whenever the layout or the
+/// meaning of a column changes, and update `data_dictionary` to match.
+pub const EXPORT_FORMAT_VERSION: u32 = 1;
+
+/// The most jobs a single export can contain.
+pub const MAX_EXPORT_JOBS: usize = 2000;
+
+/// The artifacts collected for each job, as (stage, file name suffix).
+const ARTIFACTS: [(u8, &str); 3] = [
+    (4, "_landmarks.json"),
+    (5, "_gait_analysis.json"),
+    (6, "prediction.json"),
+];
+
+/// Which jobs to export. Jobs must match every filter given.
+///
+/// # Fields
+/// * `from` - Only jobs submitted at or after this time (Unix timestamp in seconds)
+/// * `to` - Only jobs submitted at or before this time (Unix timestamp in seconds)
+/// * `batch_id` - Only jobs in this batch
+/// * `consenting_only` - Only jobs of users with an active research consent record
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ExportFilter {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub from: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub to: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub batch_id: Option<String>,
+    pub consenting_only: bool,
+}
+
+impl ExportFilter {
+    /// Whether a job matches the filter.
+    ///
+    /// # Arguments
+    /// * `uid` - The user who submitted the job
+    /// * `job` - The job
+    /// * `consenting` - The users with an active consent record, if `consenting_only` is set
+    fn matches(&self, uid: &str, job: &Job, consenting: Option<&HashSet<String>>) -> bool {
+        let submitted = job.timestamp
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map(|d| d.as_secs() as i64)
+            .unwrap_or_default();
+
+        self.from.is_none_or(|from| submitted >= from)
+            && self.to.is_none_or(|to| submitted <= to)
+            && self.batch_id.as_ref().is_none_or(|batch_id| job.batch_id.as_ref() == Some(batch_id))
+            && consenting.is_none_or(|consenting| consenting.contains(uid))
+    }
+}
+
+/// The state of an export.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ExportStatus {
+    /// The artifacts are being collected
+    Running,
+    /// The archive and data dictionary are in AWS S3
+    Complete,
+    /// The export stopped part-way
+    Failed,
+}
+
+/// A research dataset export.
+///
+/// # Fields
+/// * `id` - The export's ID
+/// * `requested_by` - The UID of the administrator who requested it
+/// * `filter` - Which jobs it contains
+/// * `format_version` - The version of the archive layout
+/// * `status` - The state of the export
+/// * `job_count` - How many jobs it contains
+/// * `created_at` - When it was requested (Unix timestamp ms)
+/// * `finished_at` - When it completed or failed, if it has (Unix timestamp ms)
+/// * `archive_key` - The storage key of the archive, once complete
+/// * `dictionary_key` - The storage key of the data dictionary, once complete
+/// * `error` - Why it failed, if it did
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Export {
+    pub id: String,
+    pub requested_by: String,
+    #[serde(default)]
+    pub filter: ExportFilter,
+    pub format_version: u32,
+    pub status: ExportStatus,
+    pub job_count: usize,
+    pub created_at: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub finished_at: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub archive_key: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dictionary_key: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// A job selected for an export.
+pub struct ExportJob {
+    uid: String,
+    job_index: usize,
+    job: Job,
+}
+
+/// Returns the key pseudonymous IDs are derived with, if one is configured.
+pub fn pseudonym_secret() -> Option<String> {
+    std::env::var("EXPORT_PSEUDONYM_SECRET")
+        .ok()
+        .filter(|secret| !secret.is_empty())
+}
+
+/// Derives a stable pseudonymous ID for a value.
+///
+/// # Arguments
+/// * `secret` - The key from `EXPORT_PSEUDONYM_SECRET`
+/// * `kind` - What the value is (e.g. "participant"), so the same string
+///   gets a different ID as a user and as an email
+/// * `value` - The value to replace
+fn pseudonym(secret: &str, kind: &str, value: &str) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes())
+        .expect("HMAC accepts keys of any length");
+    mac.update(format!("{}:{}", kind, value).as_bytes());
+
+    let digest: String = mac
+        .finalize()
+        .into_bytes()
+        .iter()
+        .take(8)
+        .map(|byte| format!("{:02x}", byte))
+        .collect();
+    format!("{}_{}", kind, digest)
+}
+
+/// Replaces every identifier in a string with its pseudonymous ID.
+///
+/// # Arguments
+/// * `replacements` - (identifier, pseudonymous ID) pairs, applied in order
+fn scrub_str(value: &str, replacements: &[(&str, &str)]) -> String {
+    replacements
+        .iter()
+        .filter(|(identifier, _)| !identifier.is_empty())
+        .fold(value.to_string(), |value, (identifier, pseudonym)| value.replace(identifier, pseudonym))
+}
+
+/// Replaces every identifier in a JSON artifact, in both its strings and
+/// its object keys, with its pseudonymous ID.
+fn scrub(value: Value, replacements: &[(&str, &str)]) -> Value {
+    match value {
+        Value::String(value) => Value::String(scrub_str(&value, replacements)),
+        Value::Array(items) => Value::Array(items
+            .into_iter()
+            .map(|item| scrub(item, replacements))
+            .collect()),
+        Value::Object(fields) => Value::Object(fields
+            .into_iter()
+            .map(|(name, value)| (scrub_str(&name, replacements), scrub(value, replacements)))
+            .collect()),
+        other => other,
+    }
+}
+
+/// Selects the jobs matching a filter, oldest first.
+pub async fn select_jobs(app: &AppState, filter: &ExportFilter) -> Result<Vec<ExportJob>> {
+    let consenting = match filter.consenting_only {
+        true => Some(consenting_users().await?),
+        false => None,
+    };
+
+    let users = app.db
+        .lock()
+        .await
+        .get_all_users()
+        .await
+        .context("Failed to read users")?;
+
+    let mut jobs: Vec<ExportJob> = users
+        .into_iter()
+        .flat_map(|(uid, user)| {
+            user.jobs
+                .into_iter()
+                .enumerate()
+                .map(move |(job_index, job)| ExportJob { uid: uid.clone(), job_index, job })
+        })
+        .filter(|selected| filter.matches(&selected.uid, &selected.job, consenting.as_ref()))
+        .collect();
+    jobs.sort_by(|a, b| a.job.timestamp
+        .cmp(&b.job.timestamp)
+        .then_with(|| a.uid.cmp(&b.uid))
+        .then_with(|| a.job_index.cmp(&b.job_index)));
+
+    Ok(jobs)
+}
+
+/// Reads the users with at least one active research consent record.
+async fn consenting_users() -> Result<HashSet<String>> {
+    let rtdb = FirebaseRtdb::from_env()
+        .context("Failed to initialise Firebase RTDB client")?;
+    let contributions: Option<HashMap<String, HashMap<String, Contribution>>> = rtdb
+        .get("contributions")
+        .await
+        .context("Failed to read the consent records")?;
+
+    Ok(contributions
+        .unwrap_or_default()
+        .into_iter()
+        .filter(|(_, records)| records.values().any(|record| record.status == ContributionStatus::Active))
+        .map(|(uid, _)| uid)
+        .collect())
+}
+
+/// Builds an export and records the outcome.
+///
+/// Meant to be spawned once the export's record has been stored as `Running`.
+pub async fn run_export(app: Arc<AppState>, mut export: Export, jobs: Vec<ExportJob>, secret: String) {
+    let built = build_export(&app, &export, &jobs, &secret).await;
+    match std::fs::remove_file(archive_path(&export.id)) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+            eprintln!("Failed to remove the archive of export {}: {e:?}", export.id);
+        }
+        _ => {}
+    }
+
+    match built {
+        Ok((archive_key, dictionary_key)) => {
+            println!("Export {} finished with {} job(s)", export.id, jobs.len());
+            export.status = ExportStatus::Complete;
+            export.archive_key = Some(archive_key);
+            export.dictionary_key = Some(dictionary_key);
+        }
+        Err(e) => {
+            eprintln!("Export {} failed: {e:?}", export.id);
+            export.status = ExportStatus::Failed;
+            export.error = Some(format!("{e:#}"));
+        }
+    }
+    export.finished_at = Some(now_ms());
+
+    let recorded = async {
+        FirebaseRtdb::from_env()
+            .context("Failed to initialise Firebase RTDB client")?
+            .set(&export_path(&export.id), &export)
+            .await
+    }.await;
+    if let Err(e) = recorded {
+        eprintln!("Failed to record the outcome of export {}: {e:?}", export.id);
+    }
+}
+
+/// Marks every export left `Running` by a backend that stopped before it
+/// finished as failed, and removes its temporary archive.
+///
+/// Meant to be called once at startup, before any new export is started.
+pub async fn fail_interrupted_exports() -> Result<()> {
+    let rtdb = FirebaseRtdb::from_env()
+        .context("Failed to initialise Firebase RTDB client")?;
+    let exports: HashMap<String, Export> = rtdb
+        .get(exports_path())
+        .await
+        .context("Failed to read the exports")?
+        .unwrap_or_default();
+
+    for mut export in exports.into_values().filter(|export| export.status == ExportStatus::Running) {
+        eprintln!("Export {} was interrupted by a restart", export.id);
+        let _ = std::fs::remove_file(archive_path(&export.id));
+
+        export.status = ExportStatus::Failed;
+        export.error = Some("The backend restarted before the export finished. Please request it again.".to_string());
+        export.finished_at = Some(now_ms());
+        rtdb.set(&export_path(&export.id), &export)
+            .await
+            .context(format!("Failed to record the interruption of export {}", export.id))?;
+    }
+
+    Ok(())
+}
+
+/// Returns where an export's archive is built before it's uploaded.
+fn archive_path(export_id: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("igait-export-{}.zip", export_id))
+}
+
+/// Collects every job's artifacts into the archive and uploads it with
+/// the data dictionary.
+///
+/// # Returns
+/// * The storage keys of the archive and the data dictionary
+async fn build_export(
+    app: &AppState,
+    export: &Export,
+    jobs: &[ExportJob],
+    secret: &str,
+) -> Result<(String, String)> {
+    let options = SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+    let path = archive_path(&export.id);
+    let file = File::create(&path)
+        .context(format!("Failed to create the archive at {}", path.display()))?;
+    let mut archive = ZipWriter::new(file);
+    let mut table = csv::Writer::from_writer(Vec::new());
+    table.write_record(JOB_COLUMNS.iter().map(|(name, _)| *name))?;
+
+    for selected in jobs {
+        let job_id = format!("{}_{}", selected.uid, selected.job_index);
+        let job_pseudonym = pseudonym(secret, "job", &job_id);
+        let participant = pseudonym(secret, "participant", &selected.uid);
+        let email = selected.job.email.trim().to_lowercase();
+        let contact = pseudonym(secret, "contact", &email);
+
+        // The job ID starts with the user ID, so it's replaced first
+        let replacements = [
+            (job_id.as_str(), job_pseudonym.as_str()),
+            (selected.uid.as_str(), participant.as_str()),
+            (selected.job.email.trim(), contact.as_str()),
+            (email.as_str(), contact.as_str()),
+        ];
+
+        // ── Artifacts ───────────────────────────────────────────────
+        let mut found = [false; ARTIFACTS.len()];
+        for (artifact, (stage, suffix)) in ARTIFACTS.iter().enumerate() {
+            let keys = app.storage
+                .list_by_prefix(&StoragePaths::stage_dir(&job_id, *stage))
+                .await
+                .context(format!("Failed to list the stage {} outputs of {}", stage, job_id))?;
+
+            for key in keys.iter().filter(|key| key.filename().is_some_and(|name| name.ends_with(suffix))) {
+                let bytes = app.storage
+                    .download(key)
+                    .await
+                    .context(format!("Failed to download {}", key))?;
+
+                // Identifiers can appear anywhere in an artifact, so one that
+                // can't be parsed and rewritten is left out
+                let artifact_json = match serde_json::from_slice::<Value>(&bytes) {
+                    Ok(value) => serde_json::to_vec(&scrub(value, &replacements))?,
+                    Err(e) => {
+                        eprintln!("Leaving {} out of export {} - it isn't valid JSON: {e}", key, export.id);
+                        continue;
+                    }
+                };
+
+                let file_name = scrub_str(key.filename().unwrap_or_default(), &replacements);
+                archive.start_file(format!("jobs/{}/stage_{}/{}", job_pseudonym, stage, file_name), options)?;
+                archive.write_all(&artifact_json)?;
+                found[artifact] = true;
+            }
+        }
+
+        // ── Demographics and outcome ────────────────────────────────
+        let job = &selected.job;
+        let submitted_on = DateTime::<Utc>::from(job.timestamp).format("%Y-%m-%d").to_string();
+        let (prediction, asd) = match &job.status {
+            JobStatus::Complete { prediction, asd, .. } => (prediction.to_string(), asd.to_string()),
+            _ => (String::new(), String::new()),
+        };
+        table.write_record([
+            job_pseudonym.as_str(),
+            &participant,
+            &contact,
+            job.batch_id.as_deref().unwrap_or_default(),
+            &submitted_on,
+            &job.age.to_string(),
+            &job.sex.to_string(),
+            &job.ethnicity.to_string(),
+            &job.height,
+            &job.weight.to_string(),
+            job.status.code(),
+            &prediction,
+            &asd,
+            &found[0].to_string(),
+            &found[1].to_string(),
+            &found[2].to_string(),
+        ])?;
+    }
+
+    // ── Table, manifest and data dictionary ─────────────────────────
+    let dictionary = serde_json::to_vec_pretty(&data_dictionary())?;
+    let manifest = serde_json::to_vec_pretty(&json!({
+        "format_version": EXPORT_FORMAT_VERSION,
+        "export_id": export.id,
+        "created_at": export.created_at,
+        "filter": export.filter,
+        "job_count": jobs.len(),
+    }))?;
+
+    archive.start_file("jobs.csv", options)?;
+    archive.write_all(&table.into_inner().map_err(|e| e.into_error())?)?;
+    archive.start_file("manifest.json", options)?;
+    archive.write_all(&manifest)?;
+    archive.start_file("data_dictionary.json", options)?;
+    archive.write_all(&dictionary)?;
+    archive.finish()?.sync_all()?;
+
+    // ── Upload ──────────────────────────────────────────────────────
+    let archive_key = StoragePaths::export_archive(&export.id, EXPORT_FORMAT_VERSION);
+    let dictionary_key = StoragePaths::export_dictionary(&export.id);
+    app.storage
+        .upload_file(&archive_key, &path, Some("application/zip"))
+        .await
+        .context("Failed to upload the export archive")?;
+    app.storage
+        .upload(&dictionary_key, dictionary, Some("application/json"))
+        .await
+        .context("Failed to upload the data dictionary")?;
+
+    Ok((archive_key, dictionary_key))
+}
+
+/// The columns of `jobs.csv`, with their descriptions.
+const JOB_COLUMNS: [(&str, &str); 16] = [
+    ("job_id", "Pseudonymous ID of the job, matching its folder under jobs/"),
+    ("participant_id", "Pseudonymous ID of the account that submitted the job"),
+    ("contact_id", "Pseudonymous ID of the email results were sent to"),
+    ("batch_id", "The batch the job was submitted in, if any"),
+    ("submitted_on", "The date the job was submitted (UTC, YYYY-MM-DD)"),
+    ("age", "The patient's age in years"),
+    ("sex", "The patient's assigned sex (M, F or O)"),
+    ("ethnicity", "The patient's ethnicity"),
+    ("height", "The patient's height, as entered (e.g. 5'10\")"),
+    ("weight", "The patient's weight in pounds"),
+    ("status", "The job's status: Submitted, Processing, Complete or Error"),
+    ("prediction", "The averaged ensemble probability of ASD gait markers (0-1), for completed jobs"),
+    ("asd", "Whether the prediction met the ASD threshold, for completed jobs"),
+    ("has_landmarks", "Whether stage 4 landmarks are included"),
+    ("has_gait_analysis", "Whether stage 5 gait analysis is included"),
+    ("has_prediction", "Whether the stage 6 prediction is included"),
+];
+
+/// Describes the contents of an export archive.
+pub fn data_dictionary() -> Value {
+    let columns: serde_json::Map<String, Value> = JOB_COLUMNS
+        .iter()
+        .map(|(name, description)| (name.to_string(), Value::from(*description)))
+        .collect();
+
+    json!({
+        "format_version": EXPORT_FORMAT_VERSION,
+        "pseudonymisation": "User IDs, job IDs and emails are replaced by pseudonymous IDs \
+            (an HMAC-SHA256 of the real value with a secret key), in jobs.csv, file names \
+            and the contents of every artifact. IDs are stable across exports, so datasets \
+            can be joined, but can't be reversed without the key.",
+        "files": {
+            "manifest.json": "The export's ID, format version, filter and job count",
+            "data_dictionary.json": "This file",
+            "jobs.csv": {
+                "description": "One row per exported job, with its demographics and outcome",
+                "columns": columns,
+            },
+            "jobs/{job_id}/stage_4/*_landmarks.json": "Per-frame pose landmarks for each video",
+            "jobs/{job_id}/stage_5/*_gait_analysis.json": "Gait cycles and features detected in each video",
+            "jobs/{job_id}/stage_6/prediction.json": "The raw output of the prediction ensemble",
+        },
+    })
+}
+
+/// Returns the Firebase RTDB path of every export.
+pub fn exports_path() -> &'static str {
+    "exports"
+}
+
+/// Returns the Firebase RTDB path of an export.
+pub fn export_path(export_id: &str) -> String {
+    format!("{}/{}", exports_path(), export_id)
+}
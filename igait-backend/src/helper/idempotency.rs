@@ -0,0 +1,187 @@
+//! Idempotency keys for submission endpoints.
+//!
+//! A client that retries a submission (e.g. after a timeout) sends the same
+//! `Idempotency-Key` header with each attempt. The first attempt claims the
+//! key; once it has created its job, its response is remembered and returned
+//! to every retry within the window instead of creating another job - even
+//! if a later step of the attempt fails. If the first attempt fails before
+//! creating anything, the key is released so a retry can try again.
+//!
+//! Keys are stored in Firebase RTDB at `idempotency/{uid}/{key_hash}`, and
+//! claimed with a conditional write, so two concurrent attempts can't both
+//! claim the same key.
+
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use axum::http::{HeaderMap, StatusCode};
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+
+use igait_lib::microservice::{FirebaseRtdb, now_ms};
+
+use super::lib::AppError;
+
+/// The header clients send the key in.
+pub const IDEMPOTENCY_KEY_HEADER: &str = "Idempotency-Key";
+
+/// How long a completed submission is remembered.
+const IDEMPOTENCY_WINDOW: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// How long an attempt can hold a key before it's assumed to have died.
+const IN_PROGRESS_TIMEOUT: Duration = Duration::from_secs(30 * 60);
+
+/// The longest key accepted.
+const MAX_KEY_LENGTH: usize = 255;
+
+/// A claimed or completed idempotency key.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct IdempotencyRecord {
+    /// The endpoint the key was used on (e.g. "upload")
+    endpoint: String,
+    /// The response of the completed attempt, if it has completed
+    #[serde(default)]
+    response: Option<Value>,
+    /// When the key was claimed (Unix timestamp ms)
+    created_at: u64,
+    /// When the key is forgotten (Unix timestamp ms)
+    expires_at: u64,
+}
+
+/// What to do with a request that may be a retry.
+pub enum Idempotency<T> {
+    /// The request carried no key - process it as normal
+    None,
+    /// The key is new - process the request, then call `complete` or `release`
+    Claimed(IdempotencyKey),
+    /// The key has already been used - return this response instead
+    Replay(T),
+}
+
+/// A key claimed by the current attempt.
+pub struct IdempotencyKey {
+    path: String,
+    record: IdempotencyRecord,
+}
+
+impl IdempotencyKey {
+    /// Remembers the attempt's response for retries, once it has created
+    /// whatever it submits.
+    pub async fn complete<T: Serialize>(self, response: &T) -> Result<()> {
+        let record = IdempotencyRecord {
+            response: Some(serde_json::to_value(response)
+                .context("Failed to serialize the response")?),
+            expires_at: now_ms() + IDEMPOTENCY_WINDOW.as_millis() as u64,
+            ..self.record
+        };
+
+        FirebaseRtdb::from_env()
+            .context("Failed to initialise Firebase RTDB client")?
+            .set(&self.path, &record)
+            .await
+            .context("Failed to store the idempotency key")
+    }
+
+    /// Releases the key after an attempt that failed before creating
+    /// anything, so a retry can try again.
+    pub async fn release(self) {
+        let released = async {
+            FirebaseRtdb::from_env()
+                .context("Failed to initialise Firebase RTDB client")?
+                .delete(&self.path)
+                .await
+        }.await;
+
+        if let Err(e) = released {
+            eprintln!("Failed to release idempotency key {}: {e:?}", self.path);
+        }
+    }
+}
+
+/// Checks a request's idempotency key, claiming it if it's new.
+///
+/// # Arguments
+/// * `uid` - The user making the request
+/// * `endpoint` - The endpoint being called (e.g. "upload")
+/// * `headers` - The request headers
+///
+/// # Fails
+/// * With a `400` if the key is empty or too long
+/// * With a `409` if an attempt with the same key is still in progress
+/// * With a `422` if the key was already used on a different endpoint
+pub async fn begin<T: DeserializeOwned>(
+    uid: &str,
+    endpoint: &str,
+    headers: &HeaderMap,
+) -> Result<Idempotency<T>, AppError> {
+    let Some(key) = headers.get(IDEMPOTENCY_KEY_HEADER) else {
+        return Ok(Idempotency::None);
+    };
+    let key = key.to_str().unwrap_or_default().trim();
+    if key.is_empty() || key.len() > MAX_KEY_LENGTH {
+        return Err(AppError::client(
+            StatusCode::BAD_REQUEST,
+            format!("The {} header must be between 1 and {} characters.", IDEMPOTENCY_KEY_HEADER, MAX_KEY_LENGTH),
+        ));
+    }
+
+    let rtdb = FirebaseRtdb::from_env()
+        .context("Failed to initialise Firebase RTDB client")?;
+    let path = format!("idempotency/{}/{}", uid, hash_key(key));
+
+    let (existing, etag) = rtdb
+        .get_with_etag::<IdempotencyRecord>(&path)
+        .await
+        .context("Failed to read the idempotency key")?;
+
+    if let Some(existing) = existing.filter(|record| record.expires_at > now_ms()) {
+        if existing.endpoint != endpoint {
+            return Err(AppError::client(
+                StatusCode::UNPROCESSABLE_ENTITY,
+                format!("This {} was already used for a different request.", IDEMPOTENCY_KEY_HEADER),
+            ));
+        }
+
+        return match existing.response {
+            Some(response) => {
+                println!("Replaying {} response for idempotency key {}", endpoint, path);
+                let response = serde_json::from_value(response)
+                    .context("Failed to read the stored response")?;
+                Ok(Idempotency::Replay(response))
+            }
+            None => Err(AppError::client(
+                StatusCode::CONFLICT,
+                "A request with this Idempotency-Key is still being processed. Try again shortly.",
+            )),
+        };
+    }
+
+    let record = IdempotencyRecord {
+        endpoint: endpoint.to_string(),
+        response: None,
+        created_at: now_ms(),
+        expires_at: now_ms() + IN_PROGRESS_TIMEOUT.as_millis() as u64,
+    };
+    // Only claimed if nobody else has since it was read, so two concurrent
+    // retries can't both claim the key
+    let claimed = rtdb.set_if_match(&path, &record, &etag)
+        .await
+        .context("Failed to claim the idempotency key")?;
+    if !claimed {
+        return Err(AppError::client(
+            StatusCode::CONFLICT,
+            "A request with this Idempotency-Key is still being processed. Try again shortly.",
+        ));
+    }
+
+    Ok(Idempotency::Claimed(IdempotencyKey { path, record }))
+}
+
+/// Hashes a key into something safe to use in an RTDB path.
+fn hash_key(key: &str) -> String {
+    Sha256::digest(key.as_bytes())
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
@@ -0,0 +1,259 @@
+//! Authentication for the internal microservice API.
+//!
+//! Requests to `/api/internal` must be signed with the secret shared
+//! between the backend and the workers (`INTERNAL_API_SECRET`), using the
+//! same scheme as outgoing webhooks:
+//! * `X-iGait-Timestamp` - The Unix timestamp (seconds) the request was signed at
+//! * `X-iGait-Signature` - `sha256=` followed by the hex-encoded HMAC-SHA256
+//!   of `"{timestamp}.{body}"`, keyed with the shared secret
+//!
+//! Requests signed more than `MAX_CLOCK_SKEW` away from now are rejected,
+//! and the signatures of accepted requests are remembered until then, so a
+//! captured request can't be replayed. If the secret isn't set, every
+//! internal request is rejected.
+
+use std::{
+    collections::HashMap,
+    sync::{Mutex, OnceLock},
+    time::Duration,
+};
+
+use axum::{
+    body::{to_bytes, Body},
+    extract::Request,
+    http::StatusCode,
+    middleware::Next,
+    response::Response,
+};
+
+use igait_lib::microservice::now_ms;
+
+use super::{
+    lib::AppError,
+    webhooks::{SIGNATURE_HEADER, TIMESTAMP_HEADER, sign_payload},
+};
+
+/// The environment variable holding the shared secret.
+pub const INTERNAL_API_SECRET_VAR: &str = "INTERNAL_API_SECRET";
+
+/// How far a request's timestamp may be from the backend's clock.
+const MAX_CLOCK_SKEW: Duration = Duration::from_secs(5 * 60);
+
+/// The largest internal request body accepted (status logs can be long).
+pub const MAX_BODY_SIZE: usize = 16 * 1024 * 1024;
+
+/// Reads the shared secret, if it's set.
+pub fn internal_api_secret() -> Option<String> {
+    std::env::var(INTERNAL_API_SECRET_VAR)
+        .ok()
+        .filter(|secret| !secret.trim().is_empty())
+}
+
+/// Signatures accepted recently, so each signed request is only accepted once.
+static SEEN_SIGNATURES: OnceLock<Mutex<SeenSignatures>> = OnceLock::new();
+
+/// The signatures of requests accepted within the last `MAX_CLOCK_SKEW`.
+///
+/// A request is only accepted while its timestamp is fresh, so once that
+/// has passed, its signature can be forgotten.
+/// They're kept in memory by each backend process.
+#[derive(Debug, Default)]
+struct SeenSignatures {
+    /// When each signature can be forgotten (Unix timestamp seconds)
+    forget_at: HashMap<String, u64>,
+}
+
+impl SeenSignatures {
+    /// Records the signature of a request signed at `timestamp`.
+    ///
+    /// # Returns
+    /// * Whether it's new (`false` if the request is a replay)
+    fn record(&mut self, signature: &str, timestamp: u64, now: u64) -> bool {
+        self.forget_at.retain(|_, forget_at| *forget_at >= now);
+        if self.forget_at.contains_key(signature) {
+            return false;
+        }
+
+        self.forget_at.insert(signature.to_string(), timestamp + MAX_CLOCK_SKEW.as_secs());
+        true
+    }
+}
+
+/// Middleware that rejects internal requests without a valid signature.
+///
+/// Each signed request is only accepted once, since replaying an old
+/// status update would wind its job back. A retry must be signed again
+/// with a new timestamp.
+///
+/// # Fails
+/// * With a `503` if `INTERNAL_API_SECRET` isn't set
+/// * With a `401` if the timestamp or signature is missing, stale or wrong,
+///   or the request has already been accepted
+/// * With a `413` if the body is over `MAX_BODY_SIZE`
+pub async fn require_signature(request: Request, next: Next) -> Result<Response, AppError> {
+    let Some(secret) = internal_api_secret() else {
+        return Err(AppError::client(
+            StatusCode::SERVICE_UNAVAILABLE,
+            format!("The internal API is disabled until {} is set.", INTERNAL_API_SECRET_VAR),
+        ));
+    };
+
+    let (parts, body) = request.into_parts();
+    let header = |name: &str| parts.headers
+        .get(name)
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_string);
+    let timestamp = header(TIMESTAMP_HEADER);
+    let signature = header(SIGNATURE_HEADER);
+
+    let bytes = to_bytes(body, MAX_BODY_SIZE)
+        .await
+        .map_err(|_| AppError::client(StatusCode::PAYLOAD_TOO_LARGE, "Request body is too large."))?;
+
+    // ── 1. Check the timestamp and signature ────────────────────────
+    let now = now_ms() / 1000;
+    let timestamp = match verify(&secret, timestamp.as_deref(), signature.as_deref(), &bytes, now) {
+        Ok(timestamp) => timestamp,
+        Err(err) => {
+            eprintln!("Rejected internal request to {}: {}", parts.uri, err.client_message().unwrap_or_default());
+            return Err(err);
+        }
+    };
+
+    // ── 2. Check it isn't a replay ──────────────────────────────────
+    let signature = signature.unwrap_or_default();
+    let new = SEEN_SIGNATURES
+        .get_or_init(Default::default)
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+        .record(signature.trim(), timestamp, now);
+    if !new {
+        eprintln!("Rejected replayed internal request to {}", parts.uri);
+        return Err(unauthorized("This request has already been received."));
+    }
+
+    Ok(next.run(Request::from_parts(parts, Body::from(bytes))).await)
+}
+
+/// Checks a request's timestamp and signature.
+///
+/// # Arguments
+/// * `secret` - The shared secret
+/// * `timestamp` - The `X-iGait-Timestamp` header, if present
+/// * `signature` - The `X-iGait-Signature` header, if present
+/// * `body` - The request body
+/// * `now` - The current time (Unix timestamp seconds)
+///
+/// # Returns
+/// * The timestamp the request was signed at (Unix timestamp seconds)
+///
+/// # Fails
+/// * With a `401` if the timestamp or signature is missing, stale or wrong
+fn verify(
+    secret: &str,
+    timestamp: Option<&str>,
+    signature: Option<&str>,
+    body: &[u8],
+    now: u64,
+) -> Result<u64, AppError> {
+    // Check the timestamp is fresh
+    let timestamp: u64 = timestamp
+        .and_then(|timestamp| timestamp.trim().parse().ok())
+        .ok_or_else(|| unauthorized(format!("Missing or invalid {} header.", TIMESTAMP_HEADER)))?;
+    if now.abs_diff(timestamp) > MAX_CLOCK_SKEW.as_secs() {
+        return Err(unauthorized(format!("The {} is too far from the current time.", TIMESTAMP_HEADER)));
+    }
+
+    // Check the signature
+    let signature = signature
+        .ok_or_else(|| unauthorized(format!("Missing {} header.", SIGNATURE_HEADER)))?;
+    let body = std::str::from_utf8(body)
+        .map_err(|_| unauthorized("Invalid signature."))?;
+
+    let expected = sign_payload(secret, timestamp, body);
+    if !constant_time_eq(expected.as_bytes(), signature.trim().as_bytes()) {
+        return Err(unauthorized("Invalid signature."));
+    }
+
+    Ok(timestamp)
+}
+
+/// Creates a `401` for a request that couldn't be authenticated.
+fn unauthorized(message: impl Into<String>) -> AppError {
+    AppError::client(StatusCode::UNAUTHORIZED, message)
+}
+
+/// Compares two byte strings in time independent of where they differ.
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len()
+        && a.iter().zip(b).fold(0, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SECRET: &str = "internal-secret";
+    const NOW: u64 = 1_700_000_000;
+    const BODY: &str = r#"{"user_id":"user_1","job_index":0}"#;
+
+    #[test]
+    fn test_verify_valid_signature() {
+        let signature = sign_payload(SECRET, NOW, BODY);
+        let timestamp = NOW.to_string();
+
+        assert_eq!(verify(SECRET, Some(&timestamp), Some(&signature), BODY.as_bytes(), NOW).unwrap(), NOW);
+        // Clocks may disagree by up to `MAX_CLOCK_SKEW`
+        assert!(verify(SECRET, Some(&timestamp), Some(&signature), BODY.as_bytes(), NOW + 300).is_ok());
+    }
+
+    #[test]
+    fn test_verify_rejects_tampering() {
+        let signature = sign_payload(SECRET, NOW, BODY);
+        let timestamp = NOW.to_string();
+
+        let tampered = BODY.replace("job_index\":0", "job_index\":1");
+        assert!(verify(SECRET, Some(&timestamp), Some(&signature), tampered.as_bytes(), NOW).is_err());
+        assert!(verify("another-secret", Some(&timestamp), Some(&signature), BODY.as_bytes(), NOW).is_err());
+        // The timestamp is signed too, so it can't be refreshed
+        let refreshed = (NOW + 1).to_string();
+        assert!(verify(SECRET, Some(&refreshed), Some(&signature), BODY.as_bytes(), NOW).is_err());
+        assert!(verify(SECRET, Some(&timestamp), None, BODY.as_bytes(), NOW).is_err());
+    }
+
+    #[test]
+    fn test_verify_rejects_stale_timestamps() {
+        for signed_at in [NOW - 301, NOW + 301] {
+            let signature = sign_payload(SECRET, signed_at, BODY);
+            let timestamp = signed_at.to_string();
+
+            let err = verify(SECRET, Some(&timestamp), Some(&signature), BODY.as_bytes(), NOW).unwrap_err();
+            assert!(err.client_message().unwrap().contains("too far"));
+        }
+        assert!(verify(SECRET, None, Some("sha256=00"), BODY.as_bytes(), NOW).is_err());
+        assert!(verify(SECRET, Some("yesterday"), Some("sha256=00"), BODY.as_bytes(), NOW).is_err());
+    }
+
+    #[test]
+    fn test_constant_time_eq() {
+        assert!(constant_time_eq(b"sha256=abcd", b"sha256=abcd"));
+        assert!(!constant_time_eq(b"sha256=abcd", b"sha256=abce"));
+        // A prefix of the right signature isn't enough
+        assert!(!constant_time_eq(b"sha256=abcd", b"sha256=abc"));
+        assert!(!constant_time_eq(b"sha256=abc", b"sha256=abcd"));
+        assert!(!constant_time_eq(b"", b"sha256=abcd"));
+    }
+
+    #[test]
+    fn test_seen_signatures() {
+        let mut seen = SeenSignatures::default();
+
+        assert!(seen.record("sha256=aa", NOW, NOW));
+        assert!(!seen.record("sha256=aa", NOW, NOW + 10));
+        assert!(seen.record("sha256=bb", NOW, NOW + 10));
+
+        // Forgotten once the request would be rejected as stale anyway
+        assert!(!seen.record("sha256=aa", NOW, NOW + 300));
+        assert!(seen.record("sha256=aa", NOW + 301, NOW + 301));
+    }
+}
@@ -1,4 +1,4 @@
-use std::{sync::Arc, time::SystemTime};
+use std::{sync::Arc, time::{Duration, SystemTime}};
 
 use anyhow::{ Result, Context };
 use axum::{
@@ -10,7 +10,7 @@ use async_openai::{
 };
 use tokio::sync::Mutex;
 use firebase_auth::{FirebaseAuth, FirebaseUser};
-use igait_lib::microservice::{EmailClient, StorageClient};
+use igait_lib::microservice::{EmailClient, Locale, StorageClient, complete_message, processing_message};
 use ts_rs::TS;
 
 use super::database::Database;
@@ -67,6 +67,13 @@ pub struct User {
 /// * `email` - The email of the person who submitted the job
 /// * `requires_approval` - Whether the user requested manual approval for this job
 /// * `approved` - Whether this job has been approved for processing
+/// * `stage_logs` - Per-stage logs collected during processing
+/// * `stages` - Per-stage timing results recorded by the workers
+/// * `review` - The administrator's approval decision, if one was made
+/// * `media` - The properties of the uploaded videos
+/// * `batch_id` - The batch the job was submitted in, if any
+/// * `archive_key` - The storage key of the results archive, once finalized
+/// * `locale` - The language the submitter's status updates and emails are in
 #[derive( Serialize, Deserialize, Clone, Debug, TS )]
 #[ts(export)]
 pub struct Job {
@@ -90,6 +97,121 @@ pub struct Job {
     /// Keys are "stage_1" through "stage_7", values are the log text.
     #[serde(default)]
     pub stage_logs: std::collections::HashMap<String, String>,
+    /// Per-stage timing results recorded by the workers.
+    /// Keys are "stage_1" through "stage_7".
+    #[serde(default)]
+    pub stages: std::collections::HashMap<String, StageResult>,
+    /// The administrator's approval decision, if one was made
+    #[serde(default)]
+    pub review: Option<JobReview>,
+    /// The properties of the uploaded videos, recorded when they were probed
+    #[serde(default)]
+    pub media: Option<JobMedia>,
+    /// The batch the job was submitted in, if it was part of a cohort
+    #[serde(default)]
+    pub batch_id: Option<String>,
+    /// The storage key of the results archive, set when the job is finalized
+    #[serde(default)]
+    pub archive_key: Option<String>,
+    /// The language the submitter's status updates and emails are in
+    #[serde(default)]
+    #[ts(type = "\"en\" | \"es\"")]
+    pub locale: Locale,
+}
+
+/// An administrator's decision on a job that was awaiting approval.
+///
+/// # Fields
+/// * `approved` - Whether the job was approved (`false` means rejected)
+/// * `reviewer` - The UID of the administrator who made the decision
+/// * `note` - An optional note from the reviewer, shared with the submitter on rejection
+/// * `timestamp` - When the decision was made (Unix timestamp in seconds)
+#[derive( Serialize, Deserialize, Clone, Debug, TS )]
+#[ts(export)]
+pub struct JobReview {
+    pub approved: bool,
+    pub reviewer: String,
+    #[serde(default)]
+    pub note: Option<String>,
+    #[serde(with = "systemtime_as_secs")]
+    #[ts(type = "number")]
+    pub timestamp: SystemTime,
+}
+
+/// The timing result of a single pipeline stage for a job.
+///
+/// Mirrors `FirestoreStageResult` from `igait-lib`, which the workers write
+/// to `users/{uid}/jobs/{index}/stages/stage_{n}`.
+///
+/// # Fields
+/// * `status` - The status of the stage
+/// * `started_at` - When a worker started the stage (RFC 3339)
+/// * `completed_at` - When the stage finished (RFC 3339)
+/// * `duration_ms` - How long the stage took to process
+/// * `output_keys` - The outputs the stage produced
+/// * `error` - The error message, if the stage failed
+/// * `worker_id` - The ID of the worker that ran the stage
+#[derive( Serialize, Deserialize, Clone, Debug, TS )]
+#[ts(export)]
+pub struct StageResult {
+    pub status: StageStatus,
+    #[ts(type = "string | null")]
+    pub started_at: Option<chrono::DateTime<chrono::Utc>>,
+    #[ts(type = "string | null")]
+    pub completed_at: Option<chrono::DateTime<chrono::Utc>>,
+    #[ts(type = "number | null")]
+    pub duration_ms: Option<u64>,
+    pub output_keys: Option<Vec<String>>,
+    pub error: Option<String>,
+    #[serde(default)]
+    pub worker_id: Option<String>,
+}
+
+/// The properties of a submitted video, as reported by `ffprobe`.
+///
+/// # Fields
+/// * `container` - The container format (e.g. "mov,mp4,m4a,3gp,3g2,mj2")
+/// * `codec` - The codec of the video stream (e.g. "h264")
+/// * `duration_secs` - The length of the video in seconds
+/// * `width` - The width of the video in pixels
+/// * `height` - The height of the video in pixels
+/// * `frame_rate` - The average frame rate in frames per second
+/// * `size_bytes` - The size of the file in bytes
+#[derive( Serialize, Deserialize, Clone, Debug, TS )]
+#[ts(export)]
+pub struct VideoProbe {
+    pub container: String,
+    pub codec: String,
+    pub duration_secs: f64,
+    pub width: u32,
+    pub height: u32,
+    pub frame_rate: f64,
+    #[ts(type = "number")]
+    pub size_bytes: u64,
+}
+
+/// The probed properties of both of a job's videos.
+///
+/// # Fields
+/// * `front` - The front video
+/// * `side` - The side video
+#[derive( Serialize, Deserialize, Clone, Debug, TS )]
+#[ts(export)]
+pub struct JobMedia {
+    pub front: VideoProbe,
+    pub side: VideoProbe,
+}
+
+/// The status of a single pipeline stage for a job.
+#[derive( Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, TS )]
+#[ts(export)]
+#[serde(rename_all = "snake_case")]
+pub enum StageStatus {
+    Pending,
+    Processing,
+    Success,
+    Failed,
+    Skipped,
 }
 
 /// The total number of processing stages in the pipeline
@@ -104,7 +226,7 @@ pub const NUM_STAGES: u8 = 7;
 /// * `Processing` - Job is currently being processed by a stage
 /// * `Complete` - Job completed successfully with prediction results
 /// * `Error` - Job failed at some point in the pipeline
-#[derive(Debug, Serialize, Deserialize, Clone, TS)]
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, TS)]
 #[ts(export)]
 #[serde(tag = "code")]
 pub enum JobStatus {
@@ -136,55 +258,54 @@ pub enum JobStatus {
 
 impl JobStatus {
     /// Create a new Submitted status
-    pub fn submitted() -> Self {
+    pub fn submitted(locale: Locale) -> Self {
         Self::Submitted {
-            value: "Job submitted successfully".to_string(),
+            value: locale.message("status.submitted", &[]),
+        }
+    }
+
+    /// Create a new Submitted status for a job whose videos haven't
+    /// been uploaded yet
+    pub fn awaiting_upload(locale: Locale) -> Self {
+        Self::Submitted {
+            value: locale.message("status.awaiting_upload", &[]),
         }
     }
 
     /// Create a new Processing status for a given stage
-    pub fn processing(stage: u8) -> Self {
-        let stage_name = match stage {
-            1 => "Converting video format",
-            2 => "Checking video validity",
-            3 => "Reframing video",
-            4 => "Estimating pose landmarks",
-            5 => "Detecting gait cycles",
-            6 => "Running ML prediction",
-            7 => "Finalizing results",
-            _ => "Processing",
-        };
-        
+    pub fn processing(stage: u8, locale: Locale) -> Self {
         Self::Processing {
             stage,
             num_stages: NUM_STAGES,
-            value: format!("Stage {}/{}: {}...", stage, NUM_STAGES, stage_name),
+            value: processing_message(stage, locale),
         }
     }
 
     /// Create a new Complete status with prediction results
-    pub fn complete(prediction: f32, asd: bool) -> Self {
-        let value = if asd {
-            format!("Analysis complete - ASD indicators detected ({:.1}% confidence)", prediction * 100.0)
-        } else {
-            format!("Analysis complete - No ASD indicators ({:.1}% confidence)", (1.0 - prediction) * 100.0)
-        };
-        
+    pub fn complete(prediction: f32, asd: bool, locale: Locale) -> Self {
         Self::Complete {
             prediction,
             asd,
-            value,
+            value: complete_message(prediction, asd, locale),
         }
     }
 
     /// Create a new Error status with logs
-    pub fn error(logs: String) -> Self {
+    pub fn error(logs: String, locale: Locale) -> Self {
         Self::Error {
-            value: "Analysis failed - see logs for details".to_string(),
+            value: locale.message("status.error", &[]),
             logs,
         }
     }
 
+    /// Create a new Error status for a job an administrator rejected
+    pub fn rejected(note: Option<&str>, locale: Locale) -> Self {
+        Self::Error {
+            value: locale.message("status.rejected", &[]),
+            logs: note.unwrap_or("Rejected by reviewer").to_string(),
+        }
+    }
+
     /// Get human-readable description
     pub fn description(&self) -> &str {
         match self {
@@ -450,7 +571,8 @@ impl AppState {
     ///   - `GOOGLE_APPLICATION_CREDENTIALS` - Path to GCP service account JSON
     ///   - `FIREBASE_ACCESS_KEY` - Firebase RTDB access key
     ///   - `OPENAI_ASSISTANT_ID` - OpenAI assistant ID
-    ///   - AWS credentials for SES
+    ///   - `EMAIL_TRANSPORT` and the chosen transport's settings (AWS
+    ///     credentials for SES, the default) - see `igait_lib::microservice::EmailClient`
     pub async fn new() -> Result<Self> {
         let client = Client::new();
         let firebase_auth = FirebaseAuth::new("network-technology-project")
@@ -486,7 +608,8 @@ impl AppState {
         // Initialize email client
         let email_client = EmailClient::from_env()
             .await
-            .context("Failed to initialize email client")?;
+            .context("Failed to initialize email client")?
+            .with_template_overrides(storage.clone());
 
         Ok(Self {
             db: Mutex::new(Database::init().await.context("Failed to initialize database while setting up app state!")?),
@@ -497,6 +620,29 @@ impl AppState {
             firebase_auth
         })
     }
+
+    /// Ensures that a user has administrator privileges.
+    /// 
+    /// # Arguments
+    /// * `uid` - The user ID to check.
+    /// 
+    /// # Fails
+    /// * If the user can't be looked up
+    /// * If the user is not an administrator
+    pub async fn ensure_administrator(&self, uid: &str) -> Result<()> {
+        let user = self.db
+            .lock()
+            .await
+            .get_user(uid)
+            .await
+            .context("Failed to look up caller in the database")?;
+
+        if !user.administrator {
+            anyhow::bail!("Forbidden: this action requires administrator privileges.");
+        }
+
+        Ok(())
+    }
 }
 
 
@@ -508,12 +654,53 @@ impl AppState {
 /// # Notes
 /// * This error type is used to handle errors in the application.
 /// * The reason for its existence is to allow for a more detailed error message to be returned by `axum` routes.
+/// * Errors are returned as a 500, unless a `ClientError` is found in the chain.
 #[derive(Debug)]
 pub struct AppError(pub anyhow::Error);
+impl AppError {
+    /// Creates an error caused by the request, returned with the given status.
+    pub fn client(status: http::StatusCode, message: impl Into<String>) -> Self {
+        Self(anyhow::Error::new(ClientError {
+            status,
+            message: message.into(),
+            retry_after: None,
+        }))
+    }
+
+    /// Creates a `429` for a caller who has hit a limit, telling them
+    /// when to try again with a `Retry-After` header.
+    pub fn too_many_requests(message: impl Into<String>, retry_after: Duration) -> Self {
+        Self(anyhow::Error::new(ClientError {
+            status: http::StatusCode::TOO_MANY_REQUESTS,
+            message: message.into(),
+            retry_after: Some(retry_after),
+        }))
+    }
+
+    /// Returns the message meant for the caller, if the error was caused by the request.
+    pub fn client_message(&self) -> Option<&str> {
+        self.0
+            .chain()
+            .find_map(|e| e.downcast_ref::<ClientError>())
+            .map(|client_err| client_err.message.as_str())
+    }
+}
 impl IntoResponse for AppError {
     fn into_response(self) -> Response<Body> {
         let err = &self.0;
 
+        // Errors caused by the request are the caller's to fix
+        if let Some(client_err) = err.chain().find_map(|e| e.downcast_ref::<ClientError>()) {
+            eprintln!("Rejected request ({}): {err:#}", client_err.status);
+            let mut response = (client_err.status, client_err.message.clone()).into_response();
+            if let Some(retry_after) = client_err.retry_after {
+                // Round up, so a retry never comes back before the limit has reset
+                let secs = retry_after.as_secs() + u64::from(retry_after.subsec_nanos() > 0);
+                response.headers_mut().insert(http::header::RETRY_AFTER, http::HeaderValue::from(secs));
+            }
+            return response;
+        }
+
         eprintln!("Encountered an error: {err:#?}");
         for (ind, ctx) in err.chain().enumerate() {
             eprintln!("  [{ind}] {ctx:#?}");
@@ -538,3 +725,24 @@ where
     }
 }
 
+/// An error caused by the request rather than the server.
+///
+/// Wrapped in an `AppError` (see `AppError::client`), it's returned to
+/// the caller with its own status code and message.
+///
+/// # Fields
+/// * `status` - The 4xx status code to return
+/// * `message` - What was wrong with the request
+/// * `retry_after` - How long to wait before retrying, sent as `Retry-After`
+#[derive(Debug)]
+pub struct ClientError {
+    pub status: http::StatusCode,
+    pub message: String,
+    pub retry_after: Option<Duration>,
+}
+impl std::fmt::Display for ClientError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+impl std::error::Error for ClientError {}
@@ -0,0 +1,343 @@
+//! Upload-time media probing.
+//!
+//! Runs `ffprobe` over submitted videos so unreadable or unsuitable files
+//! are rejected when they're uploaded, rather than minutes later when
+//! Stage 1 fails to convert them.
+//!
+//! # Limits
+//! Each limit can be overridden with an environment variable:
+//! * `MEDIA_ALLOWED_CONTAINERS` - Comma-separated container formats (default: "mov,mp4,matroska,webm,avi")
+//! * `MEDIA_ALLOWED_CODECS` - Comma-separated video codecs (default: "h264,hevc,vp8,vp9,av1,mpeg4")
+//! * `MEDIA_MIN_DURATION_SECS` / `MEDIA_MAX_DURATION_SECS` - Duration bounds (default: 3 / 300)
+//! * `MEDIA_MIN_RESOLUTION` - Minimum length of the shorter side in pixels (default: 360)
+//! * `MEDIA_MAX_RESOLUTION` - Maximum length of the longer side in pixels (default: 4096)
+//! * `MEDIA_MIN_FRAME_RATE` / `MEDIA_MAX_FRAME_RATE` - Frame rate bounds (default: 15 / 240)
+
+use std::{str::FromStr, time::Duration};
+
+use anyhow::{Context, Result};
+use axum::http::StatusCode;
+use igait_lib::microservice::StorageClient;
+use serde::Deserialize;
+use tokio::process::Command;
+
+use super::lib::{AppError, VideoProbe};
+
+/// How long `ffprobe` has to read a video stored in AWS S3.
+const PROBE_URL_LIFETIME: Duration = Duration::from_secs(10 * 60);
+
+/// The limits a submitted video must fall within.
+#[derive(Debug, Clone)]
+pub struct MediaLimits {
+    pub allowed_containers: Vec<String>,
+    pub allowed_codecs: Vec<String>,
+    pub min_duration_secs: f64,
+    pub max_duration_secs: f64,
+    pub min_resolution: u32,
+    pub max_resolution: u32,
+    pub min_frame_rate: f64,
+    pub max_frame_rate: f64,
+}
+
+impl Default for MediaLimits {
+    fn default() -> Self {
+        Self {
+            allowed_containers: ["mov", "mp4", "matroska", "webm", "avi"]
+                .map(String::from)
+                .to_vec(),
+            allowed_codecs: ["h264", "hevc", "vp8", "vp9", "av1", "mpeg4"]
+                .map(String::from)
+                .to_vec(),
+            min_duration_secs: 3.0,
+            max_duration_secs: 300.0,
+            min_resolution: 360,
+            max_resolution: 4096,
+            min_frame_rate: 15.0,
+            max_frame_rate: 240.0,
+        }
+    }
+}
+
+impl MediaLimits {
+    /// Reads the limits from the environment, falling back to the defaults.
+    pub fn from_env() -> Self {
+        let defaults = Self::default();
+
+        Self {
+            allowed_containers: env_list("MEDIA_ALLOWED_CONTAINERS")
+                .unwrap_or(defaults.allowed_containers),
+            allowed_codecs: env_list("MEDIA_ALLOWED_CODECS")
+                .unwrap_or(defaults.allowed_codecs),
+            min_duration_secs: env_parse("MEDIA_MIN_DURATION_SECS")
+                .unwrap_or(defaults.min_duration_secs),
+            max_duration_secs: env_parse("MEDIA_MAX_DURATION_SECS")
+                .unwrap_or(defaults.max_duration_secs),
+            min_resolution: env_parse("MEDIA_MIN_RESOLUTION")
+                .unwrap_or(defaults.min_resolution),
+            max_resolution: env_parse("MEDIA_MAX_RESOLUTION")
+                .unwrap_or(defaults.max_resolution),
+            min_frame_rate: env_parse("MEDIA_MIN_FRAME_RATE")
+                .unwrap_or(defaults.min_frame_rate),
+            max_frame_rate: env_parse("MEDIA_MAX_FRAME_RATE")
+                .unwrap_or(defaults.max_frame_rate),
+        }
+    }
+
+    /// Checks a probed video against the limits.
+    ///
+    /// # Returns
+    /// * Every way in which the video is unsuitable
+    pub fn check(&self, probe: &VideoProbe) -> Vec<MediaIssue> {
+        let mut issues = Vec::new();
+
+        // ffprobe reports families of formats, e.g. "mov,mp4,m4a,3gp,3g2,mj2"
+        if !probe.container.split(',').any(|format| self.allowed_containers.iter().any(|allowed| allowed == format)) {
+            issues.push(MediaIssue::new("unsupported_container", format!(
+                "the container format '{}' is not supported (supported: {})",
+                probe.container,
+                self.allowed_containers.join(", ")
+            )));
+        }
+        if !self.allowed_codecs.contains(&probe.codec) {
+            issues.push(MediaIssue::new("unsupported_codec", format!(
+                "the video codec '{}' is not supported (supported: {})",
+                probe.codec,
+                self.allowed_codecs.join(", ")
+            )));
+        }
+        if probe.duration_secs < self.min_duration_secs {
+            issues.push(MediaIssue::new("too_short", format!(
+                "it is {:.1} seconds long, but must be at least {} seconds",
+                probe.duration_secs, self.min_duration_secs
+            )));
+        }
+        if probe.duration_secs > self.max_duration_secs {
+            issues.push(MediaIssue::new("too_long", format!(
+                "it is {:.1} seconds long, but must be at most {} seconds",
+                probe.duration_secs, self.max_duration_secs
+            )));
+        }
+        if probe.width.min(probe.height) < self.min_resolution {
+            issues.push(MediaIssue::new("resolution_too_low", format!(
+                "its resolution of {}x{} is too low (the shorter side must be at least {} pixels)",
+                probe.width, probe.height, self.min_resolution
+            )));
+        }
+        if probe.width.max(probe.height) > self.max_resolution {
+            issues.push(MediaIssue::new("resolution_too_high", format!(
+                "its resolution of {}x{} is too high (the longer side must be at most {} pixels)",
+                probe.width, probe.height, self.max_resolution
+            )));
+        }
+        if probe.frame_rate < self.min_frame_rate || probe.frame_rate > self.max_frame_rate {
+            issues.push(MediaIssue::new("frame_rate_out_of_range", format!(
+                "its frame rate of {:.2} fps is outside the supported range of {} to {} fps",
+                probe.frame_rate, self.min_frame_rate, self.max_frame_rate
+            )));
+        }
+
+        issues
+    }
+}
+
+/// A way in which a video falls outside the media limits.
+#[derive(Debug, Clone)]
+pub struct MediaIssue {
+    /// A stable identifier for the kind of issue (e.g. "too_short")
+    pub code: &'static str,
+    /// A description of the issue, e.g. "it is 1.2 seconds long, but must be at least 3 seconds"
+    pub message: String,
+}
+
+impl MediaIssue {
+    fn new(code: &'static str, message: String) -> Self {
+        Self { code, message }
+    }
+}
+
+/// Probes a video held in memory.
+///
+/// The bytes are written to a temporary file first, since MP4s often
+/// keep their index at the end of the file, which `ffprobe` can't seek
+/// to when reading from a pipe.
+///
+/// # Arguments
+/// * `bytes` - The contents of the video
+/// * `extension` - The video's file extension
+pub async fn probe_bytes(bytes: &[u8], extension: &str) -> Result<Option<VideoProbe>> {
+    let path = std::env::temp_dir().join(format!(
+        "igait-probe-{}.{}",
+        super::webhooks::generate_id(),
+        extension
+    ));
+    tokio::fs::write(&path, bytes)
+        .await
+        .context("Failed to write video to a temporary file")?;
+
+    let result = probe_input(path.to_string_lossy().as_ref(), bytes.len() as u64).await;
+
+    if let Err(e) = tokio::fs::remove_file(&path).await {
+        eprintln!("Failed to remove temporary file {}: {e:?}", path.display());
+    }
+
+    result
+}
+
+/// Probes a video already in AWS S3.
+///
+/// `ffprobe` reads the object through a short-lived presigned URL, so
+/// only the parts of the file it needs are downloaded.
+///
+/// # Arguments
+/// * `storage` - The storage client
+/// * `key` - The storage key of the video
+/// * `size_bytes` - The size of the video
+pub async fn probe_stored(storage: &StorageClient, key: &str, size_bytes: u64) -> Result<Option<VideoProbe>> {
+    let url = storage
+        .presign_download(key, PROBE_URL_LIFETIME)
+        .await
+        .context("Failed to presign the video for probing")?;
+
+    probe_input(&url, size_bytes).await
+}
+
+/// Checks the result of a probe against the configured limits.
+///
+/// # Arguments
+/// * `name` - How to refer to the video in the rejection (e.g. "front")
+/// * `probe` - The result of `probe_bytes` or `probe_stored`
+///
+/// # Fails
+/// * With a `422 Unprocessable Entity` if the video couldn't be read or is unsuitable
+pub fn validate(name: &str, probe: Option<VideoProbe>) -> Result<VideoProbe, AppError> {
+    let Some(probe) = probe else {
+        return Err(AppError::client(
+            StatusCode::UNPROCESSABLE_ENTITY,
+            format!("The {} video could not be read. It may be corrupt or not a video.", name),
+        ));
+    };
+
+    let issues = MediaLimits::from_env().check(&probe);
+    if !issues.is_empty() {
+        let reasons: Vec<&str> = issues.iter().map(|issue| issue.message.as_str()).collect();
+        return Err(AppError::client(
+            StatusCode::UNPROCESSABLE_ENTITY,
+            format!("The {} video was rejected: {}.", name, reasons.join("; ")),
+        ));
+    }
+
+    Ok(probe)
+}
+
+/// Returns the content type to store a video with, based on its extension.
+pub fn content_type(extension: &str) -> &'static str {
+    match extension.to_lowercase().as_str() {
+        "mov" | "qt" => "video/quicktime",
+        "webm" => "video/webm",
+        "mkv" => "video/x-matroska",
+        "avi" => "video/x-msvideo",
+        _ => "video/mp4",
+    }
+}
+
+/// The parts of `ffprobe -print_format json` output that are used.
+#[derive(Debug, Deserialize)]
+struct FfprobeOutput {
+    #[serde(default)]
+    streams: Vec<FfprobeStream>,
+    format: Option<FfprobeFormat>,
+}
+
+#[derive(Debug, Deserialize)]
+struct FfprobeStream {
+    codec_type: Option<String>,
+    codec_name: Option<String>,
+    width: Option<u32>,
+    height: Option<u32>,
+    avg_frame_rate: Option<String>,
+    r_frame_rate: Option<String>,
+    duration: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct FfprobeFormat {
+    format_name: Option<String>,
+    duration: Option<String>,
+}
+
+/// Runs `ffprobe` on a file path or URL.
+///
+/// # Returns
+/// * `None` if `ffprobe` couldn't make sense of the input as a video
+///
+/// # Fails
+/// * If `ffprobe` couldn't be run at all
+async fn probe_input(input: &str, size_bytes: u64) -> Result<Option<VideoProbe>> {
+    let output = Command::new("ffprobe")
+        .args(["-v", "error", "-print_format", "json", "-show_format", "-show_streams"])
+        .arg(input)
+        .output()
+        .await
+        .context("Failed to run ffprobe! Is ffmpeg installed?")?;
+
+    if !output.status.success() {
+        eprintln!("ffprobe rejected input: {}", String::from_utf8_lossy(&output.stderr).trim());
+        return Ok(None);
+    }
+
+    let parsed: FfprobeOutput = serde_json::from_slice(&output.stdout)
+        .context("Failed to parse ffprobe output")?;
+
+    Ok(summarise(parsed, size_bytes))
+}
+
+/// Picks the video stream out of `ffprobe`'s output.
+fn summarise(output: FfprobeOutput, size_bytes: u64) -> Option<VideoProbe> {
+    let stream = output.streams
+        .into_iter()
+        .find(|stream| stream.codec_type.as_deref() == Some("video"))?;
+    let format = output.format?;
+
+    // Prefer the container's duration, since not every format records
+    // one per stream
+    let duration_secs = format.duration.as_deref()
+        .or(stream.duration.as_deref())
+        .and_then(|duration| duration.parse::<f64>().ok())?;
+    let frame_rate = stream.avg_frame_rate.as_deref()
+        .and_then(parse_frame_rate)
+        .or_else(|| stream.r_frame_rate.as_deref().and_then(parse_frame_rate))?;
+
+    Some(VideoProbe {
+        container: format.format_name?,
+        codec: stream.codec_name?,
+        duration_secs,
+        width: stream.width?,
+        height: stream.height?,
+        frame_rate,
+        size_bytes,
+    })
+}
+
+/// Parses an `ffprobe` frame rate such as "30000/1001".
+fn parse_frame_rate(rate: &str) -> Option<f64> {
+    let (numerator, denominator) = rate.split_once('/')?;
+    let numerator: f64 = numerator.parse().ok()?;
+    let denominator: f64 = denominator.parse().ok()?;
+
+    (denominator > 0.0 && numerator > 0.0).then(|| numerator / denominator)
+}
+
+fn env_list(name: &str) -> Option<Vec<String>> {
+    let value = std::env::var(name).ok()?;
+    let list: Vec<String> = value
+        .split(',')
+        .map(|item| item.trim().to_lowercase())
+        .filter(|item| !item.is_empty())
+        .collect();
+
+    (!list.is_empty()).then_some(list)
+}
+
+fn env_parse<T: FromStr>(name: &str) -> Option<T> {
+    std::env::var(name).ok()?.trim().parse().ok()
+}
@@ -2,11 +2,47 @@
 //! 
 //! To learn more about the helper functions, check out the individual modules.
 
+/// Contains the audit trail of privileged actions.
+pub mod audit;
+
+/// Contains the batch model for cohort submissions and the batch monitor.
+pub mod batches;
+
+/// Contains the consent records for research contributions.
+pub mod contributions;
+
+/// Contains the pending direct uploads and the sweeper that expires them.
+pub mod direct_uploads;
+
+/// Contains the de-identified research dataset exports.
+pub mod exports;
+
 /// Contains the database helper functions.
 pub mod database;
 
 /// Contains the email helper functions.
 pub mod email;
 
+/// Contains the upload-time media probing helpers.
+pub mod media;
+
+/// Contains the idempotency key helpers for submission endpoints.
+pub mod idempotency;
+
+/// Contains the organization model and job access checks.
+pub mod organizations;
+
+/// Contains the per-user submission quota helpers.
+pub mod quota;
+
+/// Contains the request signing checks for the internal API.
+pub mod internal_auth;
+
 /// Contains the filesystem helper functions and custom types.
-pub mod lib;
\ No newline at end of file
+pub mod lib;
+
+/// Contains the resumable upload sessions' paths and the sweeper that expires them.
+pub mod upload_sessions;
+
+/// Contains the webhook registration and delivery helpers.
+pub mod webhooks;
\ No newline at end of file
@@ -0,0 +1,254 @@
+//! Organizations and shared access to jobs.
+//!
+//! An organization (e.g. a clinic) groups users together, each with a
+//! membership role. Members can see the jobs submitted by every other
+//! member; owners and clinicians can also manage them (e.g. rerun them).
+//!
+//! Since membership shares a user's clinical files, nobody can be added
+//! without agreeing: owners invite users, and an invitation only becomes
+//! a membership once the invitee accepts it. Until then it grants nothing.
+//!
+//! Organizations are stored in Firebase RTDB at `organizations/{org_id}`,
+//! with an index of each user's accepted memberships at
+//! `user_organizations/{uid}` so access checks don't need to scan every
+//! organization, and of their pending invitations at `user_invitations/{uid}`.
+
+use std::collections::HashMap;
+
+use anyhow::{Context, Result};
+use axum::http::StatusCode;
+use serde::{Deserialize, Serialize};
+
+use igait_lib::microservice::FirebaseRtdb;
+
+use super::lib::{AppError, AppState};
+
+/// A member's role in an organization.
+///
+/// # Variants
+/// * `Owner` - Manages the organization's members, and its jobs
+/// * `Clinician` - Sees and manages the organization's jobs
+/// * `Viewer` - Sees the organization's jobs
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OrgRole {
+    Owner,
+    Clinician,
+    Viewer,
+}
+
+impl OrgRole {
+    /// Whether members with this role can do something to a member's job.
+    pub fn allows(&self, access: JobAccess) -> bool {
+        match access {
+            JobAccess::View => true,
+            JobAccess::Manage => matches!(self, OrgRole::Owner | OrgRole::Clinician),
+        }
+    }
+}
+
+/// What a caller wants to do with a job.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JobAccess {
+    /// Read the job, its status and its files
+    View,
+    /// Act on the job, e.g. rerun it
+    Manage,
+}
+
+/// A user's membership of an organization.
+///
+/// # Fields
+/// * `role` - The member's role
+/// * `added_by` - The UID of the user who invited them
+/// * `added_at` - When they were invited (Unix timestamp ms)
+/// * `accepted_at` - When they accepted the invitation, if they have (Unix timestamp ms)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OrgMember {
+    pub role: OrgRole,
+    pub added_by: String,
+    pub added_at: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub accepted_at: Option<u64>,
+}
+
+/// An organization and its members.
+///
+/// # Fields
+/// * `id` - The organization's ID
+/// * `name` - The organization's display name
+/// * `created_by` - The UID of the user who created it
+/// * `created_at` - When it was created (Unix timestamp ms)
+/// * `members` - Every member and invitee, keyed by UID
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Organization {
+    pub id: String,
+    pub name: String,
+    pub created_by: String,
+    pub created_at: u64,
+    #[serde(default)]
+    pub members: HashMap<String, OrgMember>,
+}
+
+impl Organization {
+    /// Whether a user has accepted their membership. The creator's
+    /// membership never needed accepting.
+    pub fn is_active_member(&self, uid: &str) -> bool {
+        self.members
+            .get(uid)
+            .is_some_and(|member| member.accepted_at.is_some() || uid == self.created_by)
+    }
+
+    /// Returns a user's role in the organization, if they're a member
+    /// who has accepted.
+    pub fn role_of(&self, uid: &str) -> Option<OrgRole> {
+        match self.is_active_member(uid) {
+            true => self.members.get(uid).map(|member| member.role),
+            false => None,
+        }
+    }
+
+    /// Returns the UIDs of the members who have accepted.
+    pub fn active_members(&self) -> Vec<String> {
+        self.members
+            .keys()
+            .filter(|uid| self.is_active_member(uid))
+            .cloned()
+            .collect()
+    }
+
+    /// Counts the organization's owners who have accepted.
+    pub fn owner_count(&self) -> usize {
+        self.members
+            .keys()
+            .filter(|uid| self.role_of(uid) == Some(OrgRole::Owner))
+            .count()
+    }
+}
+
+/// Reads an organization.
+///
+/// # Fails
+/// * With a `404` if the organization doesn't exist
+pub async fn read_organization(rtdb: &FirebaseRtdb, org_id: &str) -> Result<Organization, AppError> {
+    let organization: Option<Organization> = rtdb.get(&organization_path(org_id))
+        .await
+        .context("Failed to read the organization")?;
+
+    organization.ok_or_else(|| AppError::client(
+        StatusCode::NOT_FOUND,
+        format!("No organization with ID '{}'.", org_id),
+    ))
+}
+
+/// Reads the organizations a user belongs to, and their role in each.
+///
+/// This is only an index - the organization itself is the authority on
+/// whether the membership has been accepted.
+pub async fn memberships(rtdb: &FirebaseRtdb, uid: &str) -> Result<HashMap<String, OrgRole>> {
+    let memberships: Option<HashMap<String, OrgRole>> = rtdb.get(&user_organizations_path(uid))
+        .await
+        .context("Failed to read the user's organizations")?;
+
+    Ok(memberships.unwrap_or_default())
+}
+
+/// Ensures a caller may view or manage a job.
+///
+/// The job's submitter can always view it. Administrators can do anything.
+/// Otherwise, the caller and the submitter must both be accepted members
+/// of an organization in which the caller's role allows the access.
+///
+/// # Arguments
+/// * `app` - The application state
+/// * `caller_uid` - The user making the request
+/// * `owner_uid` - The user who submitted the job
+/// * `access` - What the caller wants to do
+///
+/// # Fails
+/// * With a `403` if the caller isn't allowed
+/// * If the caller or their memberships can't be looked up
+pub async fn ensure_job_access(
+    app: &AppState,
+    caller_uid: &str,
+    owner_uid: &str,
+    access: JobAccess,
+) -> Result<(), AppError> {
+    if caller_uid == owner_uid && access == JobAccess::View {
+        return Ok(());
+    }
+
+    let caller = app.db
+        .lock()
+        .await
+        .get_user(caller_uid)
+        .await
+        .context("Failed to look up caller")?;
+    if caller.administrator {
+        return Ok(());
+    }
+
+    let rtdb = FirebaseRtdb::from_env()
+        .context("Failed to initialise Firebase RTDB client")?;
+    let caller_orgs = memberships(&rtdb, caller_uid).await?;
+    let owner_orgs = memberships(&rtdb, owner_uid).await?;
+
+    // The indexes narrow down the organizations to check, but only the
+    // organizations themselves say whether both memberships were accepted
+    let mut allowed = false;
+    for org_id in caller_orgs.keys().filter(|org_id| owner_orgs.contains_key(*org_id)) {
+        let organization: Option<Organization> = rtdb.get(&organization_path(org_id))
+            .await
+            .context("Failed to read the organization")?;
+
+        if let Some(organization) = organization {
+            if organization.role_of(owner_uid).is_some()
+                && organization.role_of(caller_uid).is_some_and(|role| role.allows(access))
+            {
+                allowed = true;
+                break;
+            }
+        }
+    }
+    if !allowed {
+        return Err(AppError::client(
+            StatusCode::FORBIDDEN,
+            match access {
+                JobAccess::View => "Forbidden: you do not have access to this job.",
+                JobAccess::Manage => "Forbidden: you may not manage this job.",
+            },
+        ));
+    }
+
+    Ok(())
+}
+
+/// Returns the Firebase RTDB path of an organization.
+pub fn organization_path(org_id: &str) -> String {
+    format!("organizations/{}", org_id)
+}
+
+/// Returns the Firebase RTDB path of an organization's member.
+pub fn member_path(org_id: &str, uid: &str) -> String {
+    format!("{}/members/{}", organization_path(org_id), uid)
+}
+
+/// Returns the Firebase RTDB path of a user's membership index.
+pub fn user_organizations_path(uid: &str) -> String {
+    format!("user_organizations/{}", uid)
+}
+
+/// Returns the Firebase RTDB path of a user's role in an organization.
+pub fn user_membership_path(uid: &str, org_id: &str) -> String {
+    format!("{}/{}", user_organizations_path(uid), org_id)
+}
+
+/// Returns the Firebase RTDB path of a user's pending invitations.
+pub fn user_invitations_path(uid: &str) -> String {
+    format!("user_invitations/{}", uid)
+}
+
+/// Returns the Firebase RTDB path of a user's invitation to an organization.
+pub fn user_invitation_path(uid: &str, org_id: &str) -> String {
+    format!("{}/{}", user_invitations_path(uid), org_id)
+}
@@ -0,0 +1,533 @@
+//! Per-user submission quotas.
+//!
+//! Every submission reserves a slot against two limits before any work is
+//! done: how many submissions the user has made today (UTC), and - for
+//! jobs - how many of their jobs are still in the pipeline. A user over
+//! either limit gets a `429` with a `Retry-After`. Prechecks, which jump
+//! ahead of real jobs in the Stage 2 queue, have a daily limit of their own.
+//!
+//! A batch reserves a slot for every job in it at once, so it's either
+//! accepted whole or rejected before any of its jobs are created.
+//!
+//! Limits are resolved per field, most specific first:
+//! 1. The user's override at `quotas/users/{uid}`
+//! 2. The limits of the user's role at `quotas/roles/{role}`
+//!    (users without a role in their override get the `default` role)
+//! 3. The `QUOTA_JOBS_PER_DAY`, `QUOTA_MAX_IN_FLIGHT` and `QUOTA_PRECHECKS_PER_DAY`
+//!    environment variables
+//!
+//! Administrators, and users whose override sets `exempt`, are never limited.
+//! Daily counts are stored at `quota_usage/{uid}/{jobs|contributions|prechecks}`, and
+//! are only ever changed with conditional writes, so concurrent submissions
+//! can't share a slot.
+//!
+//! A job's slot is reserved before its job is created - sometimes long
+//! before, for uploads that take a while - so until then the reservation
+//! holds its place in the in-flight count, as a hold stored with the daily
+//! count and changed by the same conditional writes. The hold is dropped
+//! once the job exists (see `QuotaReservation::settle`), and given up on
+//! after `HOLD_TIMEOUT` in case its submission never finishes.
+
+use std::{collections::HashMap, time::Duration};
+
+use anyhow::{Context, Result, bail};
+use serde::{Deserialize, Serialize};
+
+use igait_lib::microservice::{FirebaseRtdb, now_ms};
+
+use super::{
+    lib::{AppError, AppState, Job, JobStatus},
+    webhooks::generate_id,
+};
+
+/// The role of users who haven't been given one.
+pub const DEFAULT_ROLE: &str = "default";
+
+/// Daily submissions allowed when nothing else is configured.
+const DEFAULT_JOBS_PER_DAY: u32 = 10;
+
+/// In-flight jobs allowed when nothing else is configured.
+const DEFAULT_MAX_IN_FLIGHT: u32 = 3;
+
+/// Daily prechecks allowed when nothing else is configured.
+const DEFAULT_PRECHECKS_PER_DAY: u32 = 30;
+
+/// When to suggest retrying after hitting the in-flight limit.
+/// A job finishing is what frees a slot, so this is only a guess.
+const IN_FLIGHT_RETRY_AFTER: Duration = Duration::from_secs(10 * 60);
+
+/// The length of a quota day.
+const DAY_MS: u64 = 24 * 60 * 60 * 1000;
+
+/// How many times a daily count is re-read and written again when another
+/// submission changes it at the same time.
+const MAX_WRITE_ATTEMPTS: usize = 5;
+
+/// How long a reservation holds its place in the in-flight count before
+/// its job is created. Longer than a resumable upload session lasts.
+const HOLD_TIMEOUT: Duration = Duration::from_secs(25 * 60 * 60);
+
+/// Submission limits. Unset fields fall back to the next, less specific level.
+///
+/// # Fields
+/// * `jobs_per_day` - Submissions allowed per UTC day
+/// * `max_in_flight` - Jobs allowed in the pipeline at once
+/// * `prechecks_per_day` - Prechecks allowed per UTC day
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct QuotaLimits {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub jobs_per_day: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_in_flight: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub prechecks_per_day: Option<u32>,
+}
+
+impl QuotaLimits {
+    /// Reads the fallback limits from the environment.
+    ///
+    /// # Notes
+    /// * Optional environment variables:
+    ///   - `QUOTA_JOBS_PER_DAY` - Defaults to `10`
+    ///   - `QUOTA_MAX_IN_FLIGHT` - Defaults to `3`
+    ///   - `QUOTA_PRECHECKS_PER_DAY` - Defaults to `30`
+    pub fn from_env() -> Self {
+        let var = |name: &str, default: u32| {
+            std::env::var(name)
+                .ok()
+                .and_then(|value| value.trim().parse().ok())
+                .unwrap_or(default)
+        };
+
+        Self {
+            jobs_per_day: Some(var("QUOTA_JOBS_PER_DAY", DEFAULT_JOBS_PER_DAY)),
+            max_in_flight: Some(var("QUOTA_MAX_IN_FLIGHT", DEFAULT_MAX_IN_FLIGHT)),
+            prechecks_per_day: Some(var("QUOTA_PRECHECKS_PER_DAY", DEFAULT_PRECHECKS_PER_DAY)),
+        }
+    }
+
+    /// Fills in unset fields from a less specific level.
+    fn or(self, fallback: QuotaLimits) -> Self {
+        Self {
+            jobs_per_day: self.jobs_per_day.or(fallback.jobs_per_day),
+            max_in_flight: self.max_in_flight.or(fallback.max_in_flight),
+            prechecks_per_day: self.prechecks_per_day.or(fallback.prechecks_per_day),
+        }
+    }
+}
+
+/// An administrator's per-user quota settings.
+///
+/// # Fields
+/// * `role` - The role whose limits apply, instead of `default`
+/// * `exempt` - Whether the user is never limited
+/// * `limits` - Limits that take precedence over the role's
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct QuotaOverride {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub role: Option<String>,
+    #[serde(default)]
+    pub exempt: bool,
+    #[serde(flatten)]
+    pub limits: QuotaLimits,
+}
+
+/// What a submission counts against.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Submission {
+    /// A job for the pipeline - counts against both limits
+    Job,
+    /// A research contribution - only counts against the daily limit
+    Contribution,
+    /// A precheck of a clip - only counts against the daily precheck limit
+    Precheck,
+}
+
+impl Submission {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Submission::Job => "jobs",
+            Submission::Contribution => "contributions",
+            Submission::Precheck => "prechecks",
+        }
+    }
+
+    /// The daily limit the submission counts against.
+    fn daily_limit(&self, limits: &QuotaLimits) -> Option<u32> {
+        match self {
+            Submission::Job | Submission::Contribution => limits.jobs_per_day,
+            Submission::Precheck => limits.prechecks_per_day,
+        }
+    }
+
+    /// What the daily limit is a limit of, for messages.
+    fn noun(&self) -> &'static str {
+        match self {
+            Submission::Job | Submission::Contribution => "submissions",
+            Submission::Precheck => "video checks",
+        }
+    }
+}
+
+/// A user's submissions on a given day.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct DailyCount {
+    /// Days since the Unix epoch (UTC)
+    day: u64,
+    /// Submissions made that day
+    count: u32,
+    /// Reservations whose jobs haven't been created yet, and when each
+    /// is given up on (Unix timestamp ms). Carried over between days.
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    holds: HashMap<String, u64>,
+}
+
+impl DailyCount {
+    /// Brings a stored count up to date: the count starts again on a new
+    /// day, and holds that have timed out are dropped.
+    fn current(stored: Option<DailyCount>) -> Self {
+        let mut daily = stored.unwrap_or_default();
+        if daily.day != today() {
+            daily = DailyCount { day: today(), count: 0, holds: daily.holds };
+        }
+
+        let now = now_ms();
+        daily.holds.retain(|_, expires_at| *expires_at > now);
+        daily
+    }
+}
+
+/// A user's limits and how much of them they've used.
+///
+/// # Fields
+/// * `role` - The role whose limits apply
+/// * `exempt` - Whether the user is never limited
+/// * `limits` - The effective limits
+/// * `jobs_today` - Jobs submitted today (UTC)
+/// * `contributions_today` - Contributions made today (UTC)
+/// * `prechecks_today` - Prechecks run today (UTC)
+/// * `in_flight` - Jobs still in the pipeline, or still being uploaded
+/// * `resets_at` - When the daily counts reset (Unix timestamp ms)
+#[derive(Debug, Serialize)]
+pub struct QuotaUsage {
+    pub role: String,
+    pub exempt: bool,
+    pub limits: QuotaLimits,
+    pub jobs_today: u32,
+    pub contributions_today: u32,
+    pub prechecks_today: u32,
+    pub in_flight: u32,
+    pub resets_at: u64,
+}
+
+/// A slot reserved by the current submission.
+///
+/// Reservations can be stored with a submission that finishes later
+/// (e.g. a direct upload), to be released if it never does.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QuotaReservation {
+    path: String,
+    /// The day the slot was reserved on (days since the Unix epoch, UTC)
+    day: u64,
+    /// The reservation's hold on the in-flight count, until its job is created
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    hold: Option<String>,
+}
+
+impl QuotaReservation {
+    /// Drops the reservation's hold on the in-flight count, once its job
+    /// has been created and counts as in flight itself.
+    ///
+    /// The slot stays reserved, and can still be released.
+    pub async fn settle(&mut self) {
+        let Some(hold) = self.hold.take() else {
+            return;
+        };
+
+        let settled = update_count(&self.path, |daily| daily.holds.remove(&hold).is_some()).await;
+        if let Err(e) = settled {
+            eprintln!("Failed to settle quota slot {}: {e:?}", self.path);
+        }
+    }
+
+    /// Gives the slot back after a failed submission.
+    pub async fn release(self) {
+        let released = update_count(&self.path, |daily| {
+            let held = self.hold.as_ref().is_some_and(|hold| daily.holds.remove(hold).is_some());
+            let counted = daily.day == self.day && daily.count > 0;
+            if counted {
+                daily.count -= 1;
+            }
+            held || counted
+        }).await;
+
+        if let Err(e) = released {
+            eprintln!("Failed to release quota slot {}: {e:?}", self.path);
+        }
+    }
+}
+
+/// Changes a stored daily count with a conditional write, reading it again
+/// if another submission changes it at the same time.
+///
+/// `change` returns whether it changed the count; nothing is written if not.
+async fn update_count(path: &str, change: impl Fn(&mut DailyCount) -> bool) -> Result<()> {
+    let rtdb = FirebaseRtdb::from_env()
+        .context("Failed to initialise Firebase RTDB client")?;
+
+    for _ in 0..MAX_WRITE_ATTEMPTS {
+        let (daily, etag) = rtdb.get_with_etag::<DailyCount>(path).await?;
+        let Some(mut daily) = daily else {
+            return Ok(());
+        };
+
+        if !change(&mut daily) {
+            return Ok(());
+        }
+        if rtdb.set_if_match(path, &daily, &etag).await? {
+            return Ok(());
+        }
+    }
+
+    bail!("The count kept changing while it was being updated")
+}
+
+/// Reserves a slot for a submission, if the user has one left.
+///
+/// Call `settle` on the reservation once the submission's job has been
+/// created, or `release` if the submission fails before then.
+///
+/// # Arguments
+/// * `app` - The application state
+/// * `uid` - The user making the submission
+/// * `submission` - What's being submitted
+///
+/// # Fails
+/// * With a `429` if the user has hit their daily or in-flight limit
+/// * If the quota settings or counts can't be read or written
+///
+/// # Returns
+/// * `None` if the user isn't limited
+pub async fn reserve(
+    app: &AppState,
+    uid: &str,
+    submission: Submission,
+) -> Result<Option<QuotaReservation>, AppError> {
+    let reservations = reserve_many(app, uid, submission, 1).await?;
+    Ok(reservations.and_then(|mut reservations| reservations.pop()))
+}
+
+/// Reserves a slot for each of several submissions, if the user has
+/// enough left for all of them. No slots are reserved otherwise.
+///
+/// Call `settle` on each reservation once its submission's job has been
+/// created, or `release` if its submission fails before then.
+///
+/// # Arguments
+/// * `app` - The application state
+/// * `uid` - The user making the submissions
+/// * `submission` - What's being submitted
+/// * `count` - How many submissions are being made
+///
+/// # Fails
+/// * With a `429` if the submissions would take the user over their
+///   daily or in-flight limit
+/// * If the quota settings or counts can't be read or written
+///
+/// # Returns
+/// * One reservation per submission, or `None` if the user isn't limited
+pub async fn reserve_many(
+    app: &AppState,
+    uid: &str,
+    submission: Submission,
+    count: u32,
+) -> Result<Option<Vec<QuotaReservation>>, AppError> {
+    let rtdb = FirebaseRtdb::from_env()
+        .context("Failed to initialise Firebase RTDB client")?;
+
+    let user = app.db
+        .lock()
+        .await
+        .get_user(uid)
+        .await
+        .context("Failed to look up the user")?;
+    if user.administrator {
+        return Ok(None);
+    }
+
+    let (role, quota) = resolve(&rtdb, uid).await?;
+    if quota.exempt {
+        return Ok(None);
+    }
+    let limits = quota.limits;
+
+    let path = usage_path(uid, submission);
+    for _ in 0..MAX_WRITE_ATTEMPTS {
+        let (daily, etag) = rtdb.get_with_etag::<DailyCount>(&path)
+            .await
+            .context("Failed to read the user's quota usage")?;
+        let mut daily = DailyCount::current(daily);
+
+        // ── 1. In-flight jobs ───────────────────────────────────────
+        // The jobs are read after the count, so a job created since is
+        // either among them or has changed the count by settling its hold
+        if submission == Submission::Job {
+            let in_flight = count_in_flight(app, uid).await? + daily.holds.len() as u32;
+            if let Some(max) = limits.max_in_flight.filter(|max| in_flight.saturating_add(count) > *max) {
+                println!("User {} ({}) is at their in-flight limit of {}", uid, role, max);
+                let message = match count {
+                    1 => format!("You already have {} jobs being uploaded or processed. Please wait for one to finish before submitting another.", in_flight),
+                    _ => format!(
+                        "You can only have {} jobs being uploaded or processed at once, and already have {}, so these {} can't be submitted together.",
+                        max, in_flight, count
+                    ),
+                };
+                return Err(AppError::too_many_requests(message, IN_FLIGHT_RETRY_AFTER));
+            }
+        }
+
+        // ── 2. Daily submissions ────────────────────────────────────
+        if let Some(max) = submission.daily_limit(&limits).filter(|max| daily.count.saturating_add(count) > *max) {
+            println!("User {} ({}) is at their daily {} limit of {}", uid, role, submission.as_str(), max);
+            let message = match count {
+                1 => format!("You've reached your limit of {} {} per day. Please try again tomorrow.", max, submission.noun()),
+                _ => format!(
+                    "You can make {} more {} today (your limit is {} per day), so these {} can't be submitted together.",
+                    max.saturating_sub(daily.count), submission.noun(), max, count
+                ),
+            };
+            return Err(AppError::too_many_requests(message, Duration::from_millis(resets_at() - now_ms())));
+        }
+
+        // ── 3. Reserve the slots ────────────────────────────────────
+        // Only counted if no other submission has changed the count since
+        // it was read - otherwise, read it again
+        let reservations: Vec<QuotaReservation> = (0..count)
+            .map(|_| QuotaReservation {
+                path: path.clone(),
+                day: daily.day,
+                hold: (submission == Submission::Job).then(generate_id),
+            })
+            .collect();
+        let hold_expires_at = now_ms() + HOLD_TIMEOUT.as_millis() as u64;
+        for hold in reservations.iter().filter_map(|reservation| reservation.hold.clone()) {
+            daily.holds.insert(hold, hold_expires_at);
+        }
+        daily.count += count;
+
+        let reserved = rtdb.set_if_match(&path, &daily, &etag)
+            .await
+            .context("Failed to update the user's quota usage")?;
+        if reserved {
+            return Ok(Some(reservations));
+        }
+    }
+
+    Err(AppError(anyhow::anyhow!("The user's quota usage kept changing while a slot was being reserved")))
+}
+
+/// Reports a user's limits and how much of them they've used.
+///
+/// # Fails
+/// * If the user, quota settings or counts can't be read
+pub async fn usage(app: &AppState, uid: &str) -> Result<QuotaUsage> {
+    let rtdb = FirebaseRtdb::from_env()
+        .context("Failed to initialise Firebase RTDB client")?;
+
+    let user = app.db
+        .lock()
+        .await
+        .get_user(uid)
+        .await
+        .context("Failed to look up the user's jobs")?;
+    let (role, quota) = resolve(&rtdb, uid).await?;
+
+    let mut counts = [DailyCount::default(), DailyCount::default(), DailyCount::default()];
+    for (daily, submission) in counts.iter_mut().zip([Submission::Job, Submission::Contribution, Submission::Precheck]) {
+        let stored: Option<DailyCount> = rtdb.get(&usage_path(uid, submission))
+            .await
+            .context("Failed to read the user's quota usage")?;
+        *daily = DailyCount::current(stored);
+    }
+    let [jobs, contributions, prechecks] = counts;
+
+    Ok(QuotaUsage {
+        role,
+        exempt: user.administrator || quota.exempt,
+        limits: quota.limits,
+        jobs_today: jobs.count,
+        contributions_today: contributions.count,
+        prechecks_today: prechecks.count,
+        in_flight: in_flight_jobs(&user.jobs) + jobs.holds.len() as u32,
+        resets_at: resets_at(),
+    })
+}
+
+/// Resolves a user's role and effective quota settings.
+///
+/// # Returns
+/// * The user's role, and their override with every limit filled in
+async fn resolve(rtdb: &FirebaseRtdb, uid: &str) -> Result<(String, QuotaOverride)> {
+    let user_override: QuotaOverride = rtdb.get(&user_override_path(uid))
+        .await
+        .context("Failed to read the user's quota override")?
+        .unwrap_or_default();
+
+    let role = user_override.role
+        .clone()
+        .unwrap_or_else(|| DEFAULT_ROLE.to_string());
+    let role_limits: QuotaLimits = rtdb.get(&role_limits_path(&role))
+        .await
+        .context("Failed to read the role's quota limits")?
+        .unwrap_or_default();
+
+    let limits = user_override.limits
+        .or(role_limits)
+        .or(QuotaLimits::from_env());
+
+    Ok((role, QuotaOverride { limits, ..user_override }))
+}
+
+/// Counts a user's jobs still making their way through the pipeline.
+async fn count_in_flight(app: &AppState, uid: &str) -> Result<u32> {
+    let user = app.db
+        .lock()
+        .await
+        .get_user(uid)
+        .await
+        .context("Failed to look up the user's jobs")?;
+
+    Ok(in_flight_jobs(&user.jobs))
+}
+
+/// Counts the jobs still making their way through the pipeline.
+fn in_flight_jobs(jobs: &[Job]) -> u32 {
+    jobs.iter()
+        .filter(|job| matches!(job.status, JobStatus::Submitted { .. } | JobStatus::Processing { .. }))
+        .count() as u32
+}
+
+/// Returns the current UTC day, in days since the Unix epoch.
+fn today() -> u64 {
+    now_ms() / DAY_MS
+}
+
+/// Returns when the daily counts next reset (Unix timestamp ms).
+fn resets_at() -> u64 {
+    (today() + 1) * DAY_MS
+}
+
+/// Returns the Firebase RTDB path of a role's limits.
+pub fn role_limits_path(role: &str) -> String {
+    format!("quotas/roles/{}", role)
+}
+
+/// Returns the Firebase RTDB path of a user's override.
+pub fn user_override_path(uid: &str) -> String {
+    format!("quotas/users/{}", uid)
+}
+
+/// Returns the Firebase RTDB path of a user's daily count.
+fn usage_path(uid: &str, submission: Submission) -> String {
+    format!("quota_usage/{}/{}", uid, submission.as_str())
+}
@@ -0,0 +1,152 @@
+//! Expiry of resumable upload sessions.
+//!
+//! Resumable upload sessions (see `crate::routes::resumable_upload`) are
+//! stored in Firebase RTDB at `upload_sessions/{uid}/{session_id}`, each
+//! with an AWS S3 multipart upload per video. A session that's never
+//! completed or cancelled would otherwise keep both forever - and S3 keeps
+//! (and bills for) the parts of a multipart upload until it's aborted - so
+//! the sweeper started in `main` expires them once they can no longer be
+//! resumed: the multipart uploads are aborted, any assembled videos are
+//! deleted, the session's quota slot is given back and the session is
+//! removed.
+
+use std::{collections::HashMap, sync::Arc, time::Duration};
+
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use serde_json::Value;
+
+use igait_lib::microservice::{FirebaseRtdb, StoragePaths, now_ms};
+
+use super::{lib::AppState, quota::QuotaReservation};
+
+/// How often the sweeper looks for expired sessions.
+const SWEEP_INTERVAL: Duration = Duration::from_secs(15 * 60);
+
+/// The parts of an upload session the sweeper needs.
+#[derive(Debug, Deserialize)]
+struct ExpiringSession {
+    front: ExpiringFile,
+    side: ExpiringFile,
+    expires_at: u64,
+    #[serde(default)]
+    reservation: Option<QuotaReservation>,
+}
+
+/// The parts of a session's video the sweeper needs.
+#[derive(Debug, Deserialize)]
+struct ExpiringFile {
+    key: String,
+    upload_id: String,
+}
+
+/// Returns the RTDB path of every upload session.
+fn upload_sessions_path() -> &'static str {
+    "upload_sessions"
+}
+
+/// Returns the RTDB path of an upload session.
+pub fn upload_session_path(uid: &str, session_id: &str) -> String {
+    format!("{}/{}/{}", upload_sessions_path(), uid, session_id)
+}
+
+/// Expires an upload session that can no longer be resumed.
+///
+/// The session is removed first, and only if it hasn't changed, so it
+/// isn't expired while it's being completed or cancelled. Anything at the
+/// session's path that isn't a session (e.g. a chunk recorded after the
+/// session was completed) is removed too.
+///
+/// # Returns
+/// * Whether this call expired the session (`false` if it's gone, or
+///   hasn't expired)
+async fn expire_session(app: &Arc<AppState>, rtdb: &FirebaseRtdb, uid: &str, session_id: &str) -> Result<bool> {
+    let path = upload_session_path(uid, session_id);
+    let (session, etag) = rtdb.get_with_etag::<Value>(&path)
+        .await
+        .context("Failed to read the upload session")?;
+    let Some(session) = session else {
+        return Ok(false);
+    };
+
+    let session = serde_json::from_value::<ExpiringSession>(session).ok();
+    if session.as_ref().is_some_and(|session| now_ms() <= session.expires_at) {
+        return Ok(false);
+    }
+    if !rtdb.delete_if_match(&path, &etag).await.context("Failed to remove the upload session")? {
+        return Ok(false);
+    }
+    let Some(session) = session else {
+        println!("Removed unreadable upload session {} for user {}", session_id, uid);
+        return Ok(true);
+    };
+
+    // ── Clean up after it ───────────────────────────────────────────
+    for file in [&session.front, &session.side] {
+        // Fails harmlessly if the video was already assembled
+        if let Err(e) = app.storage.abort_multipart_upload(&file.key, &file.upload_id).await {
+            eprintln!("Failed to abort multipart upload {}: {e:?}", file.key);
+        }
+    }
+    if let Err(e) = app.storage
+        .delete_by_prefix(&StoragePaths::upload_session_dir(uid, session_id))
+        .await
+    {
+        eprintln!("Failed to clean up staged videos for upload session {}: {e:?}", session_id);
+    }
+    if let Some(reservation) = session.reservation {
+        reservation.release().await;
+    }
+
+    println!("Upload session {} for user {} expired", session_id, uid);
+    Ok(true)
+}
+
+/// Runs the upload session sweeper until the process exits.
+///
+/// Every `SWEEP_INTERVAL`, each session that has expired is expired.
+pub async fn run_sweeper(app: Arc<AppState>) {
+    let rtdb = match FirebaseRtdb::from_env() {
+        Ok(rtdb) => rtdb,
+        Err(e) => {
+            eprintln!("Upload session sweeper disabled - failed to initialise Firebase RTDB client: {e:?}");
+            return;
+        }
+    };
+
+    println!("✅ Upload session sweeper started");
+    let mut ticker = tokio::time::interval(SWEEP_INTERVAL);
+    loop {
+        ticker.tick().await;
+
+        if let Err(e) = sweep_expired(&app, &rtdb).await {
+            eprintln!("Failed to sweep upload sessions: {e:?}");
+        }
+    }
+}
+
+/// Expires every upload session that has expired.
+async fn sweep_expired(app: &Arc<AppState>, rtdb: &FirebaseRtdb) -> Result<()> {
+    let sessions: HashMap<String, HashMap<String, Value>> = rtdb
+        .get(upload_sessions_path())
+        .await
+        .context("Failed to read the upload sessions")?
+        .unwrap_or_default();
+
+    let now = now_ms();
+    for (uid, sessions) in sessions {
+        for (session_id, session) in sessions {
+            let expired = serde_json::from_value::<ExpiringSession>(session)
+                .map_or(true, |session| now > session.expires_at);
+            if !expired {
+                continue;
+            }
+
+            if let Err(e) = expire_session(app, rtdb, &uid, &session_id).await {
+                eprintln!("Failed to expire upload session {} for user {}: {e:?}", session_id, uid);
+            }
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,629 @@
+//! Outbound webhooks for job lifecycle events.
+//!
+//! Users register webhook URLs, which are stored in Firebase RTDB at
+//! `webhooks/registrations/{uid}/{webhook_id}`. Events are written to the
+//! outbox (`webhooks/outbox`) by the finalize worker and by the backend
+//! itself, and the dispatcher started in `main` turns each one into a
+//! delivery per matching registration.
+//!
+//! Every delivery is recorded at `webhooks/deliveries/{uid}/{delivery_id}`
+//! with one entry per attempt. Failed attempts are retried with exponential
+//! backoff, scheduled through `webhooks/pending/{delivery_id}`, so retries
+//! survive a backend restart.
+//!
+//! Webhooks may only point at public addresses, so they can't be used to
+//! reach the backend's own network (e.g. `localhost`, private ranges or the
+//! cloud metadata service). URLs are checked when they're registered, and
+//! again every time they're delivered to, since where a host resolves to
+//! can change after it was registered (DNS rebinding). Redirects aren't
+//! followed.
+
+use std::{
+    collections::HashMap,
+    net::{IpAddr, SocketAddr},
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use anyhow::{Context, Result, bail};
+use hmac::{Hmac, Mac};
+use rand::{distributions::Alphanumeric, Rng};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::Sha256;
+
+use igait_lib::microservice::{
+    FirebaseRtdb, WebhookEvent, WebhookEventKind,
+    now_ms, webhook_outbox_item_path, webhook_outbox_path,
+};
+
+/// How often the dispatcher checks the outbox and pending retries.
+const DISPATCH_INTERVAL: Duration = Duration::from_secs(5);
+
+/// How long a receiver has to respond before the attempt fails.
+const DELIVERY_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How many times a delivery is attempted before it's marked as failed.
+pub const MAX_DELIVERY_ATTEMPTS: usize = 8;
+
+/// The delay before the first retry. Each retry after that waits twice
+/// as long as the last (30s, 1m, 2m, ... up to about 32m).
+const RETRY_BASE_DELAY_MS: u64 = 30 * 1000;
+
+/// The header carrying the event name (e.g. "job.completed").
+pub const EVENT_HEADER: &str = "X-iGait-Event";
+
+/// The header carrying the delivery ID, which stays the same across retries.
+pub const DELIVERY_HEADER: &str = "X-iGait-Delivery";
+
+/// The header carrying the Unix timestamp (seconds) the payload was signed at.
+pub const TIMESTAMP_HEADER: &str = "X-iGait-Timestamp";
+
+/// The header carrying the payload's HMAC-SHA256 signature.
+pub const SIGNATURE_HEADER: &str = "X-iGait-Signature";
+
+/// A webhook a user has registered.
+///
+/// # Fields
+/// * `id` - The webhook's ID
+/// * `url` - Where payloads are POSTed
+/// * `secret` - The key payloads are signed with
+/// * `events` - The event kinds this webhook is subscribed to
+/// * `description` - An optional label chosen by the user
+/// * `created_at` - When the webhook was registered (Unix timestamp ms)
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct WebhookRegistration {
+    pub id: String,
+    pub url: String,
+    pub secret: String,
+    pub events: Vec<WebhookEventKind>,
+    #[serde(default)]
+    pub description: Option<String>,
+    pub created_at: u64,
+}
+
+/// The state of a webhook delivery.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum DeliveryStatus {
+    /// Waiting for its first attempt, or for a retry
+    Pending,
+    /// The receiver responded with a 2xx status
+    Delivered,
+    /// Every attempt failed, or the webhook was deleted
+    Failed,
+}
+
+/// A single attempt at delivering a webhook.
+///
+/// # Fields
+/// * `attempted_at` - When the attempt was made (Unix timestamp ms)
+/// * `status_code` - The receiver's HTTP status, if it responded
+/// * `error` - Why the attempt failed, if it did
+/// * `duration_ms` - How long the attempt took
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct DeliveryAttempt {
+    pub attempted_at: u64,
+    #[serde(default)]
+    pub status_code: Option<u16>,
+    #[serde(default)]
+    pub error: Option<String>,
+    pub duration_ms: u64,
+}
+
+/// The delivery of one event to one webhook, with its attempt history.
+///
+/// # Fields
+/// * `id` - The delivery's ID, sent in the `X-iGait-Delivery` header
+/// * `webhook_id` - The webhook being delivered to
+/// * `event` - The event being delivered
+/// * `status` - The state of the delivery
+/// * `attempts` - Every attempt made so far, oldest first
+/// * `next_attempt_at` - When the next attempt is due, if one is scheduled (Unix timestamp ms)
+/// * `created_at` - When the delivery was created (Unix timestamp ms)
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct WebhookDelivery {
+    pub id: String,
+    pub webhook_id: String,
+    pub event: WebhookEvent,
+    pub status: DeliveryStatus,
+    #[serde(default)]
+    pub attempts: Vec<DeliveryAttempt>,
+    #[serde(default)]
+    pub next_attempt_at: Option<u64>,
+    pub created_at: u64,
+}
+
+/// An entry in the retry schedule, pointing at a pending delivery.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+struct PendingDelivery {
+    user_id: String,
+    next_attempt_at: u64,
+}
+
+/// The JSON body POSTed to a webhook.
+#[derive(Serialize, Debug)]
+struct WebhookPayload<'a> {
+    id: &'a str,
+    event: WebhookEventKind,
+    occurred_at: u64,
+    job_id: &'a str,
+    data: &'a Value,
+}
+
+/// Returns the RTDB path of a user's webhook registrations.
+pub fn registrations_path(uid: &str) -> String {
+    format!("webhooks/registrations/{}", uid)
+}
+
+/// Returns the RTDB path of a single webhook registration.
+pub fn registration_path(uid: &str, webhook_id: &str) -> String {
+    format!("{}/{}", registrations_path(uid), webhook_id)
+}
+
+/// Returns the RTDB path of a user's delivery log.
+pub fn deliveries_path(uid: &str) -> String {
+    format!("webhooks/deliveries/{}", uid)
+}
+
+/// Returns the RTDB path of a single delivery.
+fn delivery_path(uid: &str, delivery_id: &str) -> String {
+    format!("{}/{}", deliveries_path(uid), delivery_id)
+}
+
+/// Returns the RTDB path of the retry schedule.
+fn pending_path() -> &'static str {
+    "webhooks/pending"
+}
+
+/// Returns the RTDB path of a delivery's entry in the retry schedule.
+fn pending_item_path(delivery_id: &str) -> String {
+    format!("{}/{}", pending_path(), delivery_id)
+}
+
+/// Generates a random ID that sorts by creation time.
+pub fn generate_id() -> String {
+    format!("{}_{}", now_ms(), random_string(8))
+}
+
+/// Generates a new signing secret for a webhook.
+pub fn generate_secret() -> String {
+    format!("whsec_{}", random_string(32))
+}
+
+fn random_string(len: usize) -> String {
+    rand::thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(len)
+        .map(char::from)
+        .collect()
+}
+
+/// Signs a payload for the `X-iGait-Signature` header.
+///
+/// The signature is the hex-encoded HMAC-SHA256 of `"{timestamp}.{body}"`
+/// keyed with the webhook's secret, prefixed with `sha256=`. Receivers
+/// should recompute it, and reject stale timestamps to prevent replays.
+pub fn sign_payload(secret: &str, timestamp: u64, body: &str) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes())
+        .expect("HMAC accepts keys of any length");
+    mac.update(format!("{}.{}", timestamp, body).as_bytes());
+
+    let signature: String = mac
+        .finalize()
+        .into_bytes()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect();
+    format!("sha256={}", signature)
+}
+
+/// How long to wait before retrying a delivery that has failed `attempts` times.
+fn retry_delay_ms(attempts: usize) -> u64 {
+    let doublings = attempts.saturating_sub(1).min(16) as u32;
+    RETRY_BASE_DELAY_MS.saturating_mul(2u64.pow(doublings))
+}
+
+/// Checks a webhook URL can be delivered to: it must use https, and its
+/// host must only resolve to public addresses.
+///
+/// # Returns
+/// * The parsed URL
+///
+/// # Fails
+/// * If the URL is invalid, doesn't use https, can't be resolved, or
+///   resolves to a non-public address
+pub async fn check_webhook_url(url: &str) -> Result<reqwest::Url> {
+    let url = reqwest::Url::parse(url).context("Invalid webhook URL")?;
+    if url.scheme() != "https" {
+        bail!("Webhook URLs must use https.");
+    }
+
+    // IPv6 hosts are bracketed in URLs, but not when resolved
+    let host = url.host_str().context("Webhook URLs must have a host.")?;
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+    let port = url.port_or_known_default().unwrap_or(443);
+
+    let addrs: Vec<SocketAddr> = tokio::net::lookup_host((host, port))
+        .await
+        .with_context(|| format!("Failed to resolve the webhook host {}", host))?
+        .collect();
+    ensure_public(&addrs)?;
+
+    Ok(url)
+}
+
+/// Fails unless there's at least one address, and every address is public.
+fn ensure_public(addrs: &[SocketAddr]) -> Result<()> {
+    if addrs.is_empty() {
+        bail!("The webhook host doesn't resolve to any address.");
+    }
+    if let Some(addr) = addrs.iter().find(|addr| !is_public_ip(addr.ip())) {
+        bail!("Webhooks can't be delivered to {}, which isn't a public address.", addr.ip());
+    }
+
+    Ok(())
+}
+
+/// Whether an address is reachable on the public internet, rather than
+/// being loopback, private, link-local (including the metadata service at
+/// 169.254.169.254) or otherwise reserved.
+fn is_public_ip(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            let [a, b, ..] = ip.octets();
+            !(ip.is_unspecified()
+                || ip.is_loopback()
+                || ip.is_private()
+                || ip.is_link_local()
+                || ip.is_broadcast()
+                || ip.is_documentation()
+                || ip.is_multicast()
+                || a == 0
+                // Shared address space (carrier-grade NAT)
+                || (a == 100 && (64..128).contains(&b))
+                // Benchmarking
+                || (a == 198 && (b == 18 || b == 19))
+                // Reserved
+                || a >= 240)
+        }
+        IpAddr::V6(ip) => {
+            if let Some(ip) = ip.to_ipv4_mapped() {
+                return is_public_ip(ip.into());
+            }
+
+            let [first, second, ..] = ip.segments();
+            !(ip.is_unspecified()
+                || ip.is_loopback()
+                || ip.is_multicast()
+                // Unique local, including the metadata service at fd00:ec2::254
+                || (first & 0xfe00) == 0xfc00
+                // Link-local
+                || (first & 0xffc0) == 0xfe80
+                // NAT64, which can reach any IPv4 address
+                || (first == 0x64 && second == 0xff9b))
+        }
+    }
+}
+
+/// Resolves hosts for the delivery client, refusing any that resolve to a
+/// non-public address. Doing this as the connection is made means a host
+/// can't be pointed somewhere else between being checked and being used.
+struct PublicResolver;
+
+impl reqwest::dns::Resolve for PublicResolver {
+    fn resolve(&self, name: reqwest::dns::Name) -> reqwest::dns::Resolving {
+        Box::pin(async move {
+            let addrs: Vec<SocketAddr> = tokio::net::lookup_host((name.as_str(), 0))
+                .await?
+                .collect();
+            ensure_public(&addrs)?;
+
+            let addrs: reqwest::dns::Addrs = Box::new(addrs.into_iter());
+            Ok(addrs)
+        })
+    }
+}
+
+/// Adds an event to the webhook outbox for the dispatcher to deliver.
+///
+/// # Arguments
+/// * `event` - The event to deliver to the user's webhooks.
+///
+/// # Fails
+/// * If the RTDB client can't be created or the write fails
+pub async fn enqueue_event(event: &WebhookEvent) -> Result<()> {
+    let rtdb = FirebaseRtdb::from_env()
+        .context("Failed to initialise Firebase RTDB client")?;
+
+    rtdb.set(&webhook_outbox_item_path(&event.id), event)
+        .await
+        .context("Failed to add the event to the webhook outbox")
+}
+
+/// Runs the webhook dispatcher until the process exits.
+///
+/// Every `DISPATCH_INTERVAL`, new events in the outbox are turned into
+/// deliveries, and every delivery that is due is attempted.
+pub async fn run_dispatcher() {
+    let rtdb = match FirebaseRtdb::from_env() {
+        Ok(rtdb) => rtdb,
+        Err(e) => {
+            eprintln!("Webhook dispatcher disabled - failed to initialise Firebase RTDB client: {e:?}");
+            return;
+        }
+    };
+    let client = reqwest::Client::builder()
+        .timeout(DELIVERY_TIMEOUT)
+        .redirect(reqwest::redirect::Policy::none())
+        .dns_resolver(Arc::new(PublicResolver))
+        .build();
+    let client = match client {
+        Ok(client) => client,
+        Err(e) => {
+            eprintln!("Webhook dispatcher disabled - failed to build HTTP client: {e:?}");
+            return;
+        }
+    };
+
+    println!("✅ Webhook dispatcher started");
+    let mut ticker = tokio::time::interval(DISPATCH_INTERVAL);
+    loop {
+        ticker.tick().await;
+
+        if let Err(e) = drain_outbox(&rtdb).await {
+            eprintln!("Failed to drain the webhook outbox: {e:?}");
+        }
+        if let Err(e) = deliver_due(&rtdb, &client).await {
+            eprintln!("Failed to deliver pending webhooks: {e:?}");
+        }
+    }
+}
+
+/// Creates a delivery for each webhook subscribed to each event in the
+/// outbox, then removes the events.
+async fn drain_outbox(rtdb: &FirebaseRtdb) -> Result<()> {
+    let events: HashMap<String, WebhookEvent> = rtdb
+        .get(webhook_outbox_path())
+        .await
+        .context("Failed to read the webhook outbox")?
+        .unwrap_or_default();
+
+    let mut events: Vec<(String, WebhookEvent)> = events.into_iter().collect();
+    events.sort_by_key(|(_, event)| event.occurred_at);
+
+    for (key, event) in events {
+        let registrations: HashMap<String, WebhookRegistration> = rtdb
+            .get(&registrations_path(&event.user_id))
+            .await
+            .context("Failed to read webhook registrations")?
+            .unwrap_or_default();
+
+        for registration in registrations.values().filter(|r| r.events.contains(&event.kind)) {
+            let delivery = WebhookDelivery {
+                id: generate_id(),
+                webhook_id: registration.id.clone(),
+                event: event.clone(),
+                status: DeliveryStatus::Pending,
+                attempts: Vec::new(),
+                next_attempt_at: Some(now_ms()),
+                created_at: now_ms(),
+            };
+
+            rtdb.set(&delivery_path(&event.user_id, &delivery.id), &delivery)
+                .await
+                .context("Failed to record the webhook delivery")?;
+            rtdb.set(&pending_item_path(&delivery.id), &PendingDelivery {
+                    user_id: event.user_id.clone(),
+                    next_attempt_at: now_ms(),
+                })
+                .await
+                .context("Failed to schedule the webhook delivery")?;
+        }
+
+        rtdb.delete(&webhook_outbox_item_path(&key))
+            .await
+            .context("Failed to remove the event from the webhook outbox")?;
+    }
+
+    Ok(())
+}
+
+/// Attempts every delivery whose next attempt is due.
+async fn deliver_due(rtdb: &FirebaseRtdb, client: &reqwest::Client) -> Result<()> {
+    let pending: HashMap<String, PendingDelivery> = rtdb
+        .get(pending_path())
+        .await
+        .context("Failed to read pending webhook deliveries")?
+        .unwrap_or_default();
+
+    let now = now_ms();
+    for (delivery_id, entry) in pending.into_iter().filter(|(_, entry)| entry.next_attempt_at <= now) {
+        if let Err(e) = attempt_delivery(rtdb, client, &delivery_id, &entry.user_id).await {
+            eprintln!("Failed to attempt webhook delivery {delivery_id}: {e:?}");
+        }
+    }
+
+    Ok(())
+}
+
+/// Makes one attempt at a delivery, then records the outcome and
+/// schedules a retry if needed.
+async fn attempt_delivery(
+    rtdb: &FirebaseRtdb,
+    client: &reqwest::Client,
+    delivery_id: &str,
+    user_id: &str,
+) -> Result<()> {
+    let Some(mut delivery) = rtdb
+        .get::<WebhookDelivery>(&delivery_path(user_id, delivery_id))
+        .await
+        .context("Failed to read the webhook delivery")?
+    else {
+        // The delivery log was cleared - nothing left to deliver
+        return rtdb.delete(&pending_item_path(delivery_id)).await;
+    };
+
+    let registration: Option<WebhookRegistration> = rtdb
+        .get(&registration_path(user_id, &delivery.webhook_id))
+        .await
+        .context("Failed to read the webhook registration")?;
+
+    // ── Send the payload ────────────────────────────────────────────
+    // A deleted webhook can never succeed, so it isn't retried
+    let started = Instant::now();
+    let retryable = registration.is_some();
+    let attempt = match registration {
+        None => DeliveryAttempt {
+            attempted_at: now_ms(),
+            status_code: None,
+            error: Some("The webhook was deleted".to_string()),
+            duration_ms: 0,
+        },
+        Some(registration) => {
+            let body = serde_json::to_string(&WebhookPayload {
+                id: &delivery.event.id,
+                event: delivery.event.kind,
+                occurred_at: delivery.event.occurred_at,
+                job_id: &delivery.event.job_id,
+                data: &delivery.event.data,
+            }).context("Failed to serialize the webhook payload")?;
+            let timestamp = now_ms() / 1000;
+
+            // Checked again here, as the host may resolve somewhere else now
+            let response = match check_webhook_url(&registration.url).await {
+                Ok(url) => client
+                    .post(url)
+                    .header(reqwest::header::CONTENT_TYPE, "application/json")
+                    .header(EVENT_HEADER, delivery.event.kind.as_str())
+                    .header(DELIVERY_HEADER, &delivery.id)
+                    .header(TIMESTAMP_HEADER, timestamp.to_string())
+                    .header(SIGNATURE_HEADER, sign_payload(&registration.secret, timestamp, &body))
+                    .body(body)
+                    .send()
+                    .await
+                    .map_err(anyhow::Error::new),
+                Err(e) => Err(e),
+            };
+
+            let (status_code, error) = match response {
+                Ok(response) if response.status().is_success() => (Some(response.status().as_u16()), None),
+                Ok(response) => (
+                    Some(response.status().as_u16()),
+                    Some(format!("Receiver responded with {}", response.status())),
+                ),
+                Err(e) => (None, Some(format!("{:#}", e))),
+            };
+
+            DeliveryAttempt {
+                attempted_at: now_ms(),
+                status_code,
+                error,
+                duration_ms: started.elapsed().as_millis() as u64,
+            }
+        }
+    };
+
+    // ── Record the outcome ──────────────────────────────────────────
+    let succeeded = attempt.error.is_none();
+    delivery.attempts.push(attempt);
+
+    if succeeded || !retryable || delivery.attempts.len() >= MAX_DELIVERY_ATTEMPTS {
+        delivery.status = if succeeded { DeliveryStatus::Delivered } else { DeliveryStatus::Failed };
+        delivery.next_attempt_at = None;
+
+        rtdb.delete(&pending_item_path(delivery_id))
+            .await
+            .context("Failed to unschedule the webhook delivery")?;
+    } else {
+        let next_attempt_at = now_ms() + retry_delay_ms(delivery.attempts.len());
+        delivery.next_attempt_at = Some(next_attempt_at);
+
+        rtdb.set(&pending_item_path(delivery_id), &PendingDelivery {
+                user_id: user_id.to_string(),
+                next_attempt_at,
+            })
+            .await
+            .context("Failed to reschedule the webhook delivery")?;
+    }
+
+    println!(
+        "Webhook delivery {} ({} for job {}): {:?} after {} attempt(s)",
+        delivery.id,
+        delivery.event.kind.as_str(),
+        delivery.event.job_id,
+        delivery.status,
+        delivery.attempts.len()
+    );
+    rtdb.set(&delivery_path(user_id, delivery_id), &delivery)
+        .await
+        .context("Failed to record the webhook delivery")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sign_payload() {
+        // Computed independently with Python's hmac module
+        assert_eq!(
+            sign_payload("whsec_test", 1_700_000_000, r#"{"event":"job.completed"}"#),
+            "sha256=51be9920773f454007b9aaf2ef84578604f287a1ad8b1cf6918458c66aac6bd8"
+        );
+        assert_ne!(
+            sign_payload("whsec_test", 1_700_000_001, r#"{"event":"job.completed"}"#),
+            sign_payload("whsec_test", 1_700_000_000, r#"{"event":"job.completed"}"#)
+        );
+    }
+
+    #[test]
+    fn test_retry_delay_ms() {
+        assert_eq!(retry_delay_ms(0), RETRY_BASE_DELAY_MS);
+        assert_eq!(retry_delay_ms(1), RETRY_BASE_DELAY_MS);
+        assert_eq!(retry_delay_ms(2), RETRY_BASE_DELAY_MS * 2);
+        assert_eq!(retry_delay_ms(4), RETRY_BASE_DELAY_MS * 8);
+        assert_eq!(retry_delay_ms(usize::MAX), RETRY_BASE_DELAY_MS << 16);
+    }
+
+    #[test]
+    fn test_is_public_ip() {
+        let public = ["8.8.8.8", "1.1.1.1", "100.63.255.255", "100.128.0.0", "2606:4700:4700::1111", "::ffff:8.8.8.8"];
+        for ip in public {
+            assert!(is_public_ip(ip.parse().unwrap()), "{} is public", ip);
+        }
+
+        let internal = [
+            // Unspecified and loopback
+            "0.0.0.0", "127.0.0.1", "::", "::1",
+            // RFC 1918
+            "10.0.0.1", "172.16.0.1", "172.31.255.255", "192.168.1.1",
+            // Carrier-grade NAT
+            "100.64.0.1", "100.127.255.255",
+            // Link-local, including the metadata service
+            "169.254.169.254", "fe80::1",
+            // Reserved, benchmarking, documentation, broadcast and multicast
+            "240.0.0.1", "198.18.0.1", "192.0.2.1", "255.255.255.255", "224.0.0.1", "ff02::1",
+            // IPv4-mapped IPv6
+            "::ffff:127.0.0.1", "::ffff:169.254.169.254", "::ffff:10.0.0.1",
+            // NAT64
+            "64:ff9b::a9fe:a9fe", "64:ff9b::808:808",
+            // Unique local (fc00::/7), including the metadata service
+            "fc00::1", "fd00:ec2::254",
+        ];
+        for ip in internal {
+            assert!(!is_public_ip(ip.parse().unwrap()), "{} isn't public", ip);
+        }
+    }
+
+    #[test]
+    fn test_ensure_public() {
+        let addr = |ip: &str| SocketAddr::new(ip.parse().unwrap(), 443);
+
+        assert!(ensure_public(&[addr("8.8.8.8"), addr("2606:4700:4700::1111")]).is_ok());
+        assert!(ensure_public(&[]).is_err());
+        // One internal address is enough to refuse the host
+        assert!(ensure_public(&[addr("8.8.8.8"), addr("169.254.169.254")]).is_err());
+        assert!(ensure_public(&[addr("::ffff:127.0.0.1")]).is_err());
+    }
+}
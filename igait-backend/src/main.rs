@@ -7,14 +7,13 @@ mod routes;
 
 use anyhow::{ Context, Result };
 use axum::{
-    extract::DefaultBodyLimit, routing::{any, get, post}, Router
+    extract::DefaultBodyLimit, middleware, routing::{any, delete, get, post, put}, Router
 };
 use helper::lib::{AppState, AppStatePtr};
 use std::sync::Arc;
 use dotenv::dotenv;
 
 pub const ASD_CLASSIFICATION_THRESHOLD: f32 = 0.5;
-pub const DISABLE_RESULT_EMAIL: bool = true;
 
 /// The main entrypoint for the iGait backend.
 /// 
@@ -31,6 +30,8 @@ pub const DISABLE_RESULT_EMAIL: bool = true;
 /// * The API is served at the root of the server
 /// * The API is served with a body limit of 500MB
 /// * The API is served with the V1 API nested under `/api/v1`
+/// * The internal API is served separately on `INTERNAL_PORT` (default 3001),
+///   under `/api/internal`, and requires signed requests (see `helper::internal_auth`)
 /// * Gracefully shuts down on SIGTERM or Ctrl+C
 #[tokio::main]
 async fn main() -> Result<()> {
@@ -47,64 +48,145 @@ async fn main() -> Result<()> {
     // Build the V1 API router
     let api_v1 = Router::new()
         .route("/upload", post(crate::routes::upload::upload_entrypoint) )
+        .route("/uploads", post(crate::routes::resumable_upload::create_upload_session_entrypoint))
+        .route("/uploads/direct", post(crate::routes::direct_upload::create_direct_upload_entrypoint))
+        .route("/uploads/direct/:job_id/confirm", post(crate::routes::direct_upload::confirm_direct_upload_entrypoint))
+        .route("/uploads/:session_id", get(crate::routes::resumable_upload::upload_session_status_entrypoint).delete(crate::routes::resumable_upload::cancel_upload_session_entrypoint))
+        .route("/uploads/:session_id/complete", post(crate::routes::resumable_upload::complete_upload_session_entrypoint))
+        .route("/uploads/:session_id/:file/parts/:part_number", put(crate::routes::resumable_upload::upload_part_entrypoint))
+        .route("/batches", get(crate::routes::batches::list_batches_entrypoint).post(crate::routes::batches::create_batch_entrypoint))
+        .route("/batches/:batch_id", get(crate::routes::batches::batch_status_entrypoint))
+        .route("/batches/:batch_id/results", get(crate::routes::batches::batch_results_entrypoint))
+        .route("/precheck", post(crate::routes::precheck::precheck_entrypoint))
         .route("/contribute", post(crate::routes::contribute::contribute_entrypoint))
+        .route("/contributions", get(crate::routes::contributions::list_contributions_entrypoint))
+        .route("/contributions/:contribution_id", delete(crate::routes::contributions::withdraw_contribution_entrypoint))
         .route("/rerun", post(crate::routes::rerun::rerun_entrypoint))
         .route("/assistant", any(crate::routes::assistant::assistant_entrypoint))
         .route("/assistant_proxied", any(crate::routes::assistant::assistant_proxied_entrypoint))
         .route("/files/:job_id", get(crate::routes::files::files_entrypoint))
+        .route("/jobs", get(crate::routes::jobs::list_jobs_entrypoint))
+        .route("/jobs/:job_id/timeline", get(crate::routes::timeline::timeline_entrypoint))
+        .route("/jobs/:job_id/events", get(crate::routes::events::events_entrypoint))
+        .route("/organizations", get(crate::routes::organizations::list_organizations_entrypoint).post(crate::routes::organizations::create_organization_entrypoint))
+        .route("/organizations/invitations", get(crate::routes::organizations::list_invitations_entrypoint))
+        .route("/organizations/:org_id", get(crate::routes::organizations::get_organization_entrypoint))
+        .route("/organizations/:org_id/accept", post(crate::routes::organizations::accept_invitation_entrypoint))
+        .route("/organizations/:org_id/members/:uid", put(crate::routes::organizations::set_member_entrypoint).delete(crate::routes::organizations::remove_member_entrypoint))
+        .route("/webhooks", get(crate::routes::webhooks::list_webhooks_entrypoint).post(crate::routes::webhooks::create_webhook_entrypoint))
+        .route("/webhooks/:webhook_id", delete(crate::routes::webhooks::delete_webhook_entrypoint))
+        .route("/webhooks/:webhook_id/deliveries", get(crate::routes::webhooks::list_deliveries_entrypoint))
+        .route("/quota", get(crate::routes::quotas::my_quota_entrypoint))
+        .route("/admin/jobs", get(crate::routes::jobs::admin_list_jobs_entrypoint))
+        .route("/admin/approvals", get(crate::routes::approval::list_approvals_entrypoint))
+        .route("/admin/approvals/:job_id/approve", post(crate::routes::approval::approve_entrypoint))
+        .route("/admin/approvals/:job_id/reject", post(crate::routes::approval::reject_entrypoint))
+        .route("/admin/pipeline", get(crate::routes::pipeline::pipeline_entrypoint))
+        .route("/admin/queue-config", get(crate::routes::queue_config::list_queue_configs_entrypoint))
+        .route("/admin/queue-config/:stage", put(crate::routes::queue_config::update_queue_config_entrypoint))
+        .route("/admin/queue-config/:stage/pause", post(crate::routes::queue_config::pause_queue_entrypoint))
+        .route("/admin/queue-config/:stage/resume", post(crate::routes::queue_config::resume_queue_entrypoint))
+        .route("/admin/contributions/:uid/:contribution_id", delete(crate::routes::contributions::admin_withdraw_contribution_entrypoint))
+        .route("/admin/exports", get(crate::routes::exports::list_exports_entrypoint).post(crate::routes::exports::create_export_entrypoint))
+        .route("/admin/exports/:export_id", get(crate::routes::exports::export_status_entrypoint))
+        .route("/admin/email-templates", get(crate::routes::email_templates::list_email_templates_entrypoint))
+        .route("/admin/email-templates/:locale/:template/:part", put(crate::routes::email_templates::update_email_template_entrypoint).delete(crate::routes::email_templates::reset_email_template_entrypoint))
+        .route("/admin/audit", get(crate::routes::audit::audit_log_entrypoint))
+        .route("/admin/quotas", get(crate::routes::quotas::list_quotas_entrypoint))
+        .route("/admin/quotas/roles/:role", put(crate::routes::quotas::update_role_quota_entrypoint))
+        .route("/admin/quotas/users/:uid", get(crate::routes::quotas::get_user_quota_entrypoint).put(crate::routes::quotas::update_user_quota_entrypoint).delete(crate::routes::quotas::delete_user_quota_entrypoint))
         .with_state(app_state_ptr.clone());
     
     // Build the internal API router (for microservice communication)
     let api_internal = Router::new()
         .route("/update-status", post(crate::routes::internal::update_status))
+        .layer(middleware::from_fn(helper::internal_auth::require_signature))
         .with_state(app_state_ptr);
 
     // Nest the API into the general app router
     let app = Router::new()
         .nest("/api/v1", api_v1)
-        .nest("/api/internal", api_internal)
         .layer(DefaultBodyLimit::max(500000000));
 
-    // Setup graceful shutdown signal handling
-    let shutdown_signal = async {
-        let ctrl_c = async {
-            tokio::signal::ctrl_c()
-                .await
-                .expect("Failed to install Ctrl+C handler");
-        };
-
-        #[cfg(unix)]
-        let terminate = async {
-            tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
-                .expect("Failed to install SIGTERM handler")
-                .recv()
-                .await;
-        };
-
-        #[cfg(not(unix))]
-        let terminate = std::future::pending::<()>();
-
-        tokio::select! {
-            _ = ctrl_c => {
-                println!("\nReceived Ctrl+C, shutting down gracefully...");
-            },
-            _ = terminate => {
-                println!("\nReceived SIGTERM, shutting down gracefully...");
-            },
-        }
-    };
+    // The internal API gets its own listener, so it can be kept off the public network
+    let internal_app = Router::new()
+        .nest("/api/internal", api_internal)
+        .layer(DefaultBodyLimit::max(helper::internal_auth::MAX_BODY_SIZE));
+    if helper::internal_auth::internal_api_secret().is_none() {
+        eprintln!("⚠️  INTERNAL_API_SECRET not set");
+        eprintln!("   The internal API will reject every request until it is");
+    }
+
+    // Exports don't survive a restart, so any left running have failed
+    if let Err(e) = helper::exports::fail_interrupted_exports().await {
+        eprintln!("Failed to mark interrupted exports as failed: {e:?}");
+    }
 
-    // Serve the API with graceful shutdown
+    // Deliver webhooks in the background
+    tokio::spawn(helper::webhooks::run_dispatcher());
+
+    // Send batch summaries as batches finish
+    tokio::spawn(helper::batches::run_monitor(state.clone()));
+
+    // Expire direct uploads that were never confirmed
+    tokio::spawn(helper::direct_uploads::run_sweeper(state.clone()));
+
+    // Expire resumable upload sessions that were never completed
+    tokio::spawn(helper::upload_sessions::run_sweeper(state.clone()));
+
+    // Serve both APIs with graceful shutdown
     let port = std::env::var("PORT").unwrap_or("3000".to_string());
-    println!("Starting iGait backend on port {port}...");
+    let internal_port = std::env::var("INTERNAL_PORT").unwrap_or("3001".to_string());
+    println!("Starting iGait backend on port {port} (internal API on port {internal_port})...");
     let listener = tokio::net::TcpListener::bind(&format!("0.0.0.0:{port}")).await
         .context("Couldn't start up listener!")?;
-    
-    axum::serve(listener, app)
-        .with_graceful_shutdown(shutdown_signal)
-        .await
-        .context("Could't serve the API!")?;
+    let internal_listener = tokio::net::TcpListener::bind(&format!("0.0.0.0:{internal_port}")).await
+        .context("Couldn't start up the internal listener!")?;
+
+    tokio::try_join!(
+        async {
+            axum::serve(listener, app)
+                .with_graceful_shutdown(shutdown_signal())
+                .await
+                .context("Could't serve the API!")
+        },
+        async {
+            axum::serve(internal_listener, internal_app)
+                .with_graceful_shutdown(shutdown_signal())
+                .await
+                .context("Couldn't serve the internal API!")
+        },
+    )?;
 
     println!("Server shut down gracefully");
     Ok(())
 }
+
+/// Resolves once the process is asked to shut down (SIGTERM or Ctrl+C).
+async fn shutdown_signal() {
+    let ctrl_c = async {
+        tokio::signal::ctrl_c()
+            .await
+            .expect("Failed to install Ctrl+C handler");
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
+            .expect("Failed to install SIGTERM handler")
+            .recv()
+            .await;
+    };
+
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {
+            println!("\nReceived Ctrl+C, shutting down gracefully...");
+        },
+        _ = terminate => {
+            println!("\nReceived SIGTERM, shutting down gracefully...");
+        },
+    }
+}
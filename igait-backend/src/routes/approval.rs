@@ -0,0 +1,355 @@
+//! Approval endpoints for jobs gated behind manual review.
+//!
+//! Jobs can require approval either because the submitter requested it
+//! (`QueueItem::requires_approval`) or because the whole stage is gated
+//! (`QueueConfig::requires_approval`). Workers skip these jobs until an
+//! administrator approves them here.
+//!
+//! Only users with `administrator: true` in the database are authorised.
+
+use std::{collections::HashMap, time::SystemTime};
+
+use axum::{extract::{Path, State}, Json};
+use anyhow::{Context, Result, anyhow};
+use firebase_auth::FirebaseUser;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use igait_lib::microservice::{
+    FirebaseRtdb, JobMetadata, QueueConfig, QueueItem, QueueOps, StageNumber,
+    WebhookEvent, WebhookEventKind, queue_config_path, queue_item_path, queue_path,
+};
+
+use crate::helper::{
+    audit::{self, AuditAction},
+    email::send_rejection_email,
+    lib::{AppError, AppStatePtr, JobReview, JobStatus},
+    webhooks::enqueue_event,
+};
+
+/// A job waiting in a stage queue for an administrator's decision.
+#[derive(Debug, Serialize)]
+pub struct PendingApproval {
+    /// The job ID (format: "{user_id}_{job_index}")
+    pub job_id: String,
+    /// The UID of the user who submitted the job
+    pub user_id: String,
+    /// The stage queue the job is waiting in (1-6)
+    pub stage: u8,
+    /// When the job entered the queue (Unix timestamp ms)
+    pub enqueued_at: u64,
+    /// Whether the submitter requested approval for this job
+    pub requires_approval: bool,
+    /// Whether the stage itself requires approval for every job
+    pub queue_requires_approval: bool,
+    /// The job's metadata (email, demographics)
+    pub metadata: JobMetadata,
+}
+
+/// Response body for the approvals listing.
+#[derive(Debug, Serialize)]
+pub struct PendingApprovalsResponse {
+    /// Every job awaiting approval, oldest first
+    pub jobs: Vec<PendingApproval>,
+}
+
+/// Request body for approving or rejecting a job.
+#[derive(Debug, Default, Deserialize)]
+pub struct ReviewRequest {
+    /// An optional note from the reviewer.
+    /// On rejection, this is included in the email to the submitter.
+    #[serde(default)]
+    pub note: Option<String>,
+}
+
+/// Response body for approving or rejecting a job.
+#[derive(Debug, Serialize)]
+pub struct ReviewResponse {
+    /// Whether the decision was successfully applied.
+    pub success: bool,
+    /// Human-readable message.
+    pub message: String,
+}
+
+/// `GET /api/v1/admin/approvals`
+///
+/// Lists every job across `queues/stage_1` through `queues/stage_6` that
+/// workers are currently skipping because it hasn't been approved.
+/// **Admin-only** — the caller must have `administrator: true`.
+pub async fn list_approvals_entrypoint(
+    current_user: FirebaseUser,
+    State(app): State<AppStatePtr>,
+) -> Result<Json<PendingApprovalsResponse>, AppError> {
+    let app = app.state;
+
+    // ── 0. Verify the caller is an administrator ────────────────────
+    app.ensure_administrator(&current_user.user_id).await?;
+
+    // ── 1. Scan each stage queue ────────────────────────────────────
+    let rtdb = FirebaseRtdb::from_env()
+        .context("Failed to initialise Firebase RTDB client")?;
+
+    let mut jobs = Vec::new();
+    for stage in approval_stages() {
+        let queue_config: QueueConfig = rtdb
+            .get(&queue_config_path(stage))
+            .await
+            .context(format!("Failed to read queue config for stage {}", stage.as_u8()))?
+            .unwrap_or_default();
+
+        let items: HashMap<String, QueueItem> = rtdb
+            .get(&queue_path(stage))
+            .await
+            .context(format!("Failed to read queue for stage {}", stage.as_u8()))?
+            .unwrap_or_default();
+
+        jobs.extend(items
+            .into_values()
+            .filter(|item| !item.is_approved_for_processing(queue_config.requires_approval))
+            .map(|item| PendingApproval {
+                job_id: item.job_id,
+                user_id: item.user_id,
+                stage: stage.as_u8(),
+                enqueued_at: item.enqueued_at,
+                requires_approval: item.requires_approval,
+                queue_requires_approval: queue_config.requires_approval,
+                metadata: item.metadata,
+            }));
+    }
+
+    jobs.sort_by_key(|job| job.enqueued_at);
+
+    Ok(Json(PendingApprovalsResponse { jobs }))
+}
+
+/// `POST /api/v1/admin/approvals/:job_id/approve`
+///
+/// Approves a job that is waiting in a stage queue, allowing workers to
+/// claim it. The decision is mirrored onto the user's `Job.approved`.
+/// **Admin-only** — the caller must have `administrator: true`.
+///
+/// # Workflow
+/// 1. Verify the caller is an administrator
+/// 2. Find the job in the stage queues
+/// 3. Set `approved = true` on the queue item
+/// 4. Record the review on the user's job
+/// 5. Notify the submitter's webhooks
+pub async fn approve_entrypoint(
+    current_user: FirebaseUser,
+    State(app): State<AppStatePtr>,
+    Path(job_id): Path<String>,
+    request: Option<Json<ReviewRequest>>,
+) -> Result<Json<ReviewResponse>, AppError> {
+    let caller_uid = current_user.user_id.clone();
+    let Json(request) = request.unwrap_or_default();
+    let parameters = json!({ "note": request.note });
+
+    let result = approve_job(current_user, app, &job_id, request).await;
+    audit::record(&caller_uid, AuditAction::JobApproved, Some(&job_id), parameters, &result).await;
+
+    result
+}
+
+/// Approves a job awaiting approval (see `approve_entrypoint`).
+async fn approve_job(
+    current_user: FirebaseUser,
+    app: AppStatePtr,
+    job_id: &str,
+    request: ReviewRequest,
+) -> Result<Json<ReviewResponse>, AppError> {
+    let app = app.state;
+    let caller_uid = &current_user.user_id;
+
+    // ── 0. Verify the caller is an administrator ────────────────────
+    app.ensure_administrator(caller_uid).await?;
+
+    let (user_id, job_index) = QueueOps::parse_job_id(job_id)?;
+    println!("Approval requested by admin {}: job={}", caller_uid, job_id);
+
+    // ── 1. Find the job and approve it in place ─────────────────────
+    let rtdb = FirebaseRtdb::from_env()
+        .context("Failed to initialise Firebase RTDB client")?;
+
+    let stage = find_queued_job(&rtdb, job_id)
+        .await?
+        .ok_or_else(|| anyhow!("Job {} is not waiting in any stage queue.", job_id))?;
+
+    rtdb.update(&queue_item_path(stage, job_id), &json!({ "approved": true }))
+        .await
+        .context("Failed to approve the queue item")?;
+
+    // ── 2. Mirror the decision onto the user's job ──────────────────
+    app.db
+        .lock()
+        .await
+        .set_review(&user_id, job_index, JobReview {
+            approved: true,
+            reviewer: caller_uid.to_string(),
+            note: request.note.clone(),
+            timestamp: SystemTime::now(),
+        })
+        .await
+        .context("Failed to record the review on the job")?;
+
+    // ── 3. Notify the submitter's webhooks ──────────────────────────
+    notify_webhooks(WebhookEventKind::JobApproved, job_id, &user_id, json!({
+        "stage": stage.as_u8(),
+        "note": request.note,
+    })).await;
+
+    Ok(Json(ReviewResponse {
+        success: true,
+        message: format!(
+            "Job {} was approved and can now be processed by stage {} ({}).",
+            job_id,
+            stage.as_u8(),
+            stage.name()
+        ),
+    }))
+}
+
+/// `POST /api/v1/admin/approvals/:job_id/reject`
+///
+/// Rejects a job that is waiting in a stage queue. The job is removed from
+/// the pipeline, marked as an error, and the submitter is emailed.
+/// **Admin-only** — the caller must have `administrator: true`.
+///
+/// # Workflow
+/// 1. Verify the caller is an administrator
+/// 2. Find the job in the stage queues and remove it
+/// 3. Record the review on the user's job and update its status
+/// 4. Email the submitter with the reviewer's note, and notify their webhooks
+pub async fn reject_entrypoint(
+    current_user: FirebaseUser,
+    State(app): State<AppStatePtr>,
+    Path(job_id): Path<String>,
+    request: Option<Json<ReviewRequest>>,
+) -> Result<Json<ReviewResponse>, AppError> {
+    let caller_uid = current_user.user_id.clone();
+    let Json(request) = request.unwrap_or_default();
+    let parameters = json!({ "note": request.note });
+
+    let result = reject_job(current_user, app, &job_id, request).await;
+    audit::record(&caller_uid, AuditAction::JobRejected, Some(&job_id), parameters, &result).await;
+
+    result
+}
+
+/// Rejects a job awaiting approval (see `reject_entrypoint`).
+async fn reject_job(
+    current_user: FirebaseUser,
+    app: AppStatePtr,
+    job_id: &str,
+    request: ReviewRequest,
+) -> Result<Json<ReviewResponse>, AppError> {
+    let app = app.state;
+    let caller_uid = &current_user.user_id;
+
+    // ── 0. Verify the caller is an administrator ────────────────────
+    app.ensure_administrator(caller_uid).await?;
+
+    let (user_id, job_index) = QueueOps::parse_job_id(job_id)?;
+    println!("Rejection requested by admin {}: job={}", caller_uid, job_id);
+
+    // ── 1. Find the job and remove it from the pipeline ─────────────
+    let rtdb = FirebaseRtdb::from_env()
+        .context("Failed to initialise Firebase RTDB client")?;
+
+    let stage = find_queued_job(&rtdb, job_id)
+        .await?
+        .ok_or_else(|| anyhow!("Job {} is not waiting in any stage queue.", job_id))?;
+
+    rtdb.delete(&queue_item_path(stage, job_id))
+        .await
+        .context("Failed to remove the job from its stage queue")?;
+
+    // ── 2. Mirror the decision onto the user's job ──────────────────
+    let note = request.note;
+    {
+        let db = app.db.lock().await;
+
+        db.set_review(&user_id, job_index, JobReview {
+                approved: false,
+                reviewer: caller_uid.to_string(),
+                note: note.clone(),
+                timestamp: SystemTime::now(),
+            })
+            .await
+            .context("Failed to record the review on the job")?;
+
+        let locale = db.get_job(&user_id, job_index)
+            .await
+            .context("Failed to fetch the job — does it exist?")?
+            .locale;
+        db.update_status(&user_id, job_index, JobStatus::rejected(note.as_deref(), locale))
+            .await
+            .context("Failed to update job status")?;
+    }
+
+    // ── 3. Let the submitter know ───────────────────────────────────
+    let job = app
+        .db
+        .lock()
+        .await
+        .get_job(&user_id, job_index)
+        .await
+        .context("Failed to fetch the job — does it exist?")?;
+
+    notify_webhooks(WebhookEventKind::JobRejected, job_id, &user_id, json!({
+        "stage": stage.as_u8(),
+        "note": note,
+    })).await;
+
+    send_rejection_email(app.clone(), &job, &user_id, job_index, note.as_deref())
+        .await
+        .context("Failed to send rejection email!")?;
+
+    Ok(Json(ReviewResponse {
+        success: true,
+        message: format!(
+            "Job {} was rejected and removed from stage {} ({}).",
+            job_id,
+            stage.as_u8(),
+            stage.name()
+        ),
+    }))
+}
+
+/// Queues a webhook event for a review decision.
+///
+/// The decision has already been applied by this point, so a failure
+/// here is logged rather than failing the request.
+async fn notify_webhooks(kind: WebhookEventKind, job_id: &str, user_id: &str, data: serde_json::Value) {
+    let event = WebhookEvent::new(kind, job_id.to_string(), user_id.to_string(), data);
+    if let Err(e) = enqueue_event(&event).await {
+        eprintln!("Failed to queue {} webhook event for {}: {e:?}", kind.as_str(), job_id);
+    }
+}
+
+/// The stages whose queues can hold jobs awaiting approval.
+///
+/// Stage 7 (finalize) never requires approval.
+fn approval_stages() -> impl Iterator<Item = StageNumber> {
+    (1..=6).filter_map(StageNumber::from_u8)
+}
+
+/// Finds which stage queue a job is currently waiting in.
+///
+/// Returns `None` if the job isn't waiting in any of them.
+async fn find_queued_job(
+    rtdb: &FirebaseRtdb,
+    job_id: &str,
+) -> Result<Option<StageNumber>> {
+    for stage in approval_stages() {
+        let item: Option<QueueItem> = rtdb
+            .get(&queue_item_path(stage, job_id))
+            .await
+            .context(format!("Failed to read queue for stage {}", stage.as_u8()))?;
+
+        if item.is_some() {
+            return Ok(Some(stage));
+        }
+    }
+
+    Ok(None)
+}
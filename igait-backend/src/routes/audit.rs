@@ -0,0 +1,140 @@
+//! Audit log query endpoint.
+//!
+//! Lets administrators look back through the audit trail of privileged
+//! actions (see `crate::helper::audit`), filtered by who performed them,
+//! which job they touched, what they did and when.
+//!
+//! Only users with `administrator: true` in the database are authorised.
+
+use axum::{extract::{Query, State}, http::StatusCode, Json};
+use anyhow::Context;
+use chrono::{DateTime, Duration, Utc};
+use firebase_auth::FirebaseUser;
+use serde::{Deserialize, Serialize};
+
+use igait_lib::microservice::FirebaseRtdb;
+
+use crate::helper::{
+    audit::{self, AuditAction, AuditEvent},
+    lib::{AppError, AppStatePtr},
+};
+
+/// How far back the log is searched when `from` isn't given.
+const DEFAULT_RANGE_DAYS: i64 = 7;
+
+/// The longest date range that can be searched at once.
+const MAX_RANGE_DAYS: i64 = 92;
+
+/// The number of events returned when `limit` isn't given.
+const DEFAULT_LIMIT: usize = 100;
+
+/// The most events that can be returned at once.
+const MAX_LIMIT: usize = 1000;
+
+/// Query parameters accepted by the audit log endpoint.
+///
+/// Every filter is optional; events must match all of the filters given.
+#[derive(Debug, Deserialize)]
+pub struct AuditQuery {
+    /// Only include actions performed by this user
+    pub actor: Option<String>,
+    /// Only include actions on this job
+    pub job_id: Option<String>,
+    /// Only include this kind of action (e.g. `job_rerun`)
+    pub action: Option<AuditAction>,
+    /// Only include actions at or after this time (Unix timestamp in seconds).
+    /// Defaults to 7 days before `to`.
+    pub from: Option<i64>,
+    /// Only include actions at or before this time (Unix timestamp in seconds).
+    /// Defaults to now.
+    pub to: Option<i64>,
+    /// The most events to return, newest first (1-1000, default 100)
+    pub limit: Option<usize>,
+}
+
+/// Response body for the audit log.
+#[derive(Debug, Serialize)]
+pub struct AuditLogResponse {
+    /// The matching events, newest first
+    pub events: Vec<AuditEvent>,
+    /// Whether more events matched than were returned
+    pub truncated: bool,
+}
+
+/// `GET /api/v1/admin/audit`
+///
+/// Searches the audit log. The date range may span at most 92 days.
+/// **Admin-only** — the caller must have `administrator: true`.
+///
+/// # Query Parameters
+/// See `AuditQuery`.
+pub async fn audit_log_entrypoint(
+    current_user: FirebaseUser,
+    State(app): State<AppStatePtr>,
+    Query(query): Query<AuditQuery>,
+) -> Result<Json<AuditLogResponse>, AppError> {
+    let app = app.state;
+
+    // ── 0. Verify the caller is an administrator ────────────────────
+    app.ensure_administrator(&current_user.user_id).await?;
+
+    // ── 1. Validate the request ─────────────────────────────────────
+    let to = match query.to {
+        Some(secs) => timestamp(secs, "to")?,
+        None => Utc::now(),
+    };
+    let from = match query.from {
+        Some(secs) => timestamp(secs, "from")?,
+        None => to - Duration::days(DEFAULT_RANGE_DAYS),
+    };
+    if from > to {
+        return Err(AppError::client(StatusCode::BAD_REQUEST, "`from` must not be after `to`."));
+    }
+    if to.date_naive() - from.date_naive() > Duration::days(MAX_RANGE_DAYS) {
+        return Err(AppError::client(
+            StatusCode::BAD_REQUEST,
+            format!("The date range may span at most {} days.", MAX_RANGE_DAYS),
+        ));
+    }
+
+    let limit = query.limit.unwrap_or(DEFAULT_LIMIT);
+    if !(1..=MAX_LIMIT).contains(&limit) {
+        return Err(AppError::client(
+            StatusCode::BAD_REQUEST,
+            format!("Invalid limit {}. Must be between 1 and {}.", limit, MAX_LIMIT),
+        ));
+    }
+
+    // ── 2. Read each day in the range ───────────────────────────────
+    let rtdb = FirebaseRtdb::from_env()
+        .context("Failed to initialise Firebase RTDB client")?;
+
+    let (from_ms, to_ms) = (from.timestamp_millis() as u64, to.timestamp_millis() as u64);
+    let mut events = Vec::new();
+    for day in from.date_naive().iter_days().take_while(|day| *day <= to.date_naive()) {
+        events.extend(audit::read_day(&rtdb, day)
+            .await?
+            .into_iter()
+            .filter(|event| {
+                (from_ms..=to_ms).contains(&event.timestamp)
+                    && query.actor.as_ref().is_none_or(|actor| &event.actor == actor)
+                    && query.job_id.as_ref().is_none_or(|job_id| event.job_id.as_ref() == Some(job_id))
+                    && query.action.is_none_or(|action| event.action == action)
+            }));
+    }
+
+    // ── 3. Newest first, up to the limit ────────────────────────────
+    events.reverse();
+    let truncated = events.len() > limit;
+    events.truncate(limit);
+
+    Ok(Json(AuditLogResponse { events, truncated }))
+}
+
+/// Converts a query parameter into a time.
+fn timestamp(secs: i64, name: &str) -> Result<DateTime<Utc>, AppError> {
+    DateTime::from_timestamp(secs, 0).ok_or_else(|| AppError::client(
+        StatusCode::BAD_REQUEST,
+        format!("Invalid `{}` timestamp {}.", name, secs),
+    ))
+}
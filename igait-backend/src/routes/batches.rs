@@ -0,0 +1,720 @@
+//! Batch submission endpoint for research cohorts.
+//!
+//! A study submits a manifest of participants - each with their details
+//! and the file names of their front and side videos - along with the
+//! videos themselves, all in one multipart request. Every row is checked
+//! before any job is created; each valid row then becomes its own job
+//! under a shared batch ID, and the response reports what happened to
+//! every row.
+//!
+//! The submitter, and anyone who can see their jobs, can then follow the
+//! batch's progress and download its results as a CSV. See
+//! `crate::helper::batches` for how batches are stored and summarised.
+
+use std::{collections::HashMap, fmt::Display, str::FromStr, sync::Arc};
+
+use axum::{
+    body::Bytes,
+    extract::{Multipart, Path, State},
+    http::{header, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use anyhow::Context;
+use firebase_auth::FirebaseUser;
+use serde::Serialize;
+use serde_json::Value;
+
+use igait_lib::microservice::{FirebaseRtdb, Locale, now_ms};
+
+use crate::{
+    helper::{
+        batches::{
+            Batch, BatchProgress, BatchRow, batch_path, open_batch_path, read_batch, read_jobs,
+            user_batch_path, user_batches_path,
+        },
+        lib::{AppError, AppState, AppStatePtr, Ethnicity, Job, JobMedia, JobStatus, Sex},
+        organizations::{JobAccess, ensure_job_access},
+        quota::{self, QuotaReservation, Submission},
+        webhooks::generate_id,
+    },
+    routes::upload::{
+        JobDetails, UploadRequestFile, create_job, fail_submission, probe_files,
+        submit_to_pipeline, upload_files,
+    },
+};
+
+/// The most participants a single batch can contain.
+const MAX_ROWS: usize = 100;
+
+/// The longest batch name accepted.
+const MAX_NAME_LENGTH: usize = 100;
+
+/// Response body for a submitted batch.
+#[derive(Debug, Serialize)]
+pub struct BatchResponse {
+    /// The ID shared by every job in the batch
+    pub batch_id: String,
+    /// How many rows became jobs
+    pub accepted: usize,
+    /// How many rows were rejected
+    pub rejected: usize,
+    /// The outcome of each manifest row, in manifest order
+    pub rows: Vec<BatchRow>,
+}
+
+/// A batch the caller submitted.
+#[derive(Debug, Serialize)]
+pub struct BatchSummary {
+    /// The batch's ID
+    pub id: String,
+    /// The batch's display name, if it has one
+    pub name: Option<String>,
+    /// When it was submitted (Unix timestamp ms)
+    pub created_at: u64,
+    /// When every job had finished, if they have (Unix timestamp ms)
+    pub completed_at: Option<u64>,
+    /// How many rows became jobs
+    pub accepted: usize,
+    /// How many rows were rejected
+    pub rejected: usize,
+}
+
+/// Response body for the caller's batches.
+#[derive(Debug, Serialize)]
+pub struct BatchesResponse {
+    /// Every batch the caller submitted, newest first
+    pub batches: Vec<BatchSummary>,
+}
+
+/// Response body for a batch's progress.
+#[derive(Debug, Serialize)]
+pub struct BatchStatusResponse {
+    /// The batch and the outcome of each manifest row
+    pub batch: Batch,
+    /// How far along the batch's jobs are
+    pub progress: BatchProgress,
+}
+
+/// The parts of a batch request.
+struct BatchRequestArguments {
+    name:     Option<String>,
+    email:    Option<String>,
+    locale:   Option<Locale>,
+    manifest: UploadRequestFile,
+    videos:   HashMap<String, Bytes>,
+}
+
+/// A manifest row's columns, keyed by lowercase column name,
+/// or why the row couldn't be read.
+type ManifestRow = Result<HashMap<String, String>, String>;
+
+/// A manifest row that passed validation.
+struct Participant {
+    details:    JobDetails,
+    front_file: UploadRequestFile,
+    side_file:  UploadRequestFile,
+}
+
+/// `POST /api/v1/batches`
+///
+/// Submits a cohort of participants, creating one job per valid row.
+///
+/// # Multipart Fields
+/// * `manifest` - A CSV (with a header row) or JSON array of participants, with
+///   the columns `age`, `ethnicity`, `sex`, `height`, `weight`, `email`,
+///   `front_file` and `side_file`, and optionally `participant_id`,
+///   `requires_approval` and `locale`
+/// * `name` - An optional display name for the batch (e.g. the study)
+/// * `email` - Where to send the summary once every job has finished
+///   (defaults to the caller's account email)
+/// * `locale` - The language of the summary email, and of the jobs whose
+///   row doesn't give one (e.g. "es", defaults to English)
+/// * Every other field carrying a file is a video, matched to the manifest
+///   by its file name
+///
+/// # Workflow
+/// 1. Unpack the manifest and videos
+/// 2. Validate every row - its details, its videos' presence and format,
+///    and that no participant or video appears twice - before creating anything
+/// 3. Reserve a slot in the user's submission quota for every valid row
+///    at once, then create, upload and dispatch a job for each
+/// 4. Store the batch with the outcome of every row, and open it for the
+///    batch monitor if any jobs were created
+///
+/// # Fails
+/// * With a `400` if the manifest is missing, unreadable, empty or too long
+/// * With a `429` if the valid rows would take the user over their daily or
+///   in-flight job limit, in which case no jobs are created
+/// * If the batch fails to save to the database
+///
+/// Batches count against the same quota as single submissions, so the
+/// limits of a user submitting cohorts should be raised accordingly (e.g.
+/// with a role at `quotas/roles/{role}`). Rows that are invalid, or that
+/// fail to be submitted, are reported in the response rather than failing
+/// the request.
+pub async fn create_batch_entrypoint(
+    current_user: FirebaseUser,
+    State(app): State<AppStatePtr>,
+    mut multipart: Multipart,
+) -> Result<Json<BatchResponse>, AppError> {
+    let app = app.state;
+    let uid = &current_user.user_id;
+
+    // ── 1. Unpack the request ───────────────────────────────────────
+    let arguments = unpack_batch_arguments(&mut multipart).await?;
+    let manifest = parse_manifest(&arguments.manifest)?;
+    if manifest.is_empty() || manifest.len() > MAX_ROWS {
+        return Err(AppError::client(
+            StatusCode::BAD_REQUEST,
+            format!("Manifests must list between 1 and {} participants.", MAX_ROWS),
+        ));
+    }
+
+    // ── 2. Validate every row ───────────────────────────────────────
+    let mut rows = Vec::with_capacity(manifest.len());
+    let mut participants = Vec::new();
+    let mut participant_rows: HashMap<String, usize> = HashMap::new();
+    let mut video_rows: HashMap<String, usize> = HashMap::new();
+    for (index, fields) in manifest.into_iter().enumerate() {
+        let mut row = BatchRow {
+            row: index + 1,
+            participant_id: None,
+            job_id: None,
+            errors: Vec::new(),
+        };
+
+        let participant = match fields {
+            Ok(fields) => {
+                row.participant_id = fields.get("participant_id").cloned();
+                parse_participant(&fields, &arguments.videos, arguments.locale.unwrap_or_default())
+            }
+            Err(error) => Err(vec![error]),
+        };
+        let participant = match participant {
+            Ok(participant) => participant,
+            Err(errors) => {
+                row.errors = errors;
+                rows.push(row);
+                continue;
+            }
+        };
+
+        // A participant or video appearing twice is a mistake in the manifest
+        if let Some(participant_id) = &row.participant_id {
+            if let Some(other) = participant_rows.insert(participant_id.clone(), row.row) {
+                row.errors.push(format!("Participant '{}' is also listed in row {}.", participant_id, other));
+            }
+        }
+        let mut names = vec![&participant.front_file.name];
+        if participant.side_file.name != participant.front_file.name {
+            names.push(&participant.side_file.name);
+        }
+        for name in names {
+            if let Some(other) = video_rows.insert(name.clone(), row.row) {
+                row.errors.push(format!("'{}' is also used by row {}.", name, other));
+            }
+        }
+
+        if row.errors.is_empty() {
+            match probe_files(&participant.front_file, &participant.side_file).await {
+                Ok(media) => participants.push((rows.len(), participant, media)),
+                Err(err) => row.errors.push(describe_error(&err)),
+            }
+        }
+        rows.push(row);
+    }
+
+    // ── 3. Submit a job for each valid row ──────────────────────────
+    // Reserved together, so a batch is never left half-submitted by the quota
+    let mut reservations = match participants.len() {
+        0 => None,
+        count => quota::reserve_many(&app, uid, Submission::Job, count as u32).await?,
+    };
+
+    let batch_id = generate_id();
+    for (index, participant, media) in participants {
+        let reservation = reservations.as_mut().and_then(Vec::pop);
+        match submit_participant(&app, uid, &batch_id, participant, media, reservation).await {
+            Ok(job_id) => rows[index].job_id = Some(job_id),
+            Err(err) => rows[index].errors.push(describe_error(&err)),
+        }
+    }
+
+    // ── 4. Store the batch ──────────────────────────────────────────
+    let accepted = rows.iter().filter(|row| row.accepted()).count();
+    let created_at = now_ms();
+    let batch = Batch {
+        id: batch_id,
+        name: arguments.name,
+        user_id: uid.clone(),
+        email: arguments.email.or(current_user.email),
+        locale: arguments.locale.unwrap_or_default(),
+        created_at,
+        // With no jobs to wait for, the batch is already finished
+        completed_at: (accepted == 0).then_some(created_at),
+        rows,
+    };
+
+    let rtdb = FirebaseRtdb::from_env()
+        .context("Failed to initialise Firebase RTDB client")?;
+    rtdb.set(&batch_path(&batch.id), &batch)
+        .await
+        .context("Failed to store the batch")?;
+    rtdb.set(&user_batch_path(uid, &batch.id), &true)
+        .await
+        .context("Failed to index the batch")?;
+    if accepted > 0 {
+        rtdb.set(&open_batch_path(&batch.id), &true)
+            .await
+            .context("Failed to open the batch")?;
+    }
+
+    println!(
+        "User {} submitted batch {} ({} accepted, {} rejected)",
+        uid, batch.id, accepted, batch.rows.len() - accepted
+    );
+    Ok(Json(BatchResponse {
+        batch_id: batch.id,
+        accepted,
+        rejected: batch.rows.len() - accepted,
+        rows: batch.rows,
+    }))
+}
+
+/// `GET /api/v1/batches`
+///
+/// Lists the batches the caller submitted, newest first.
+pub async fn list_batches_entrypoint(
+    current_user: FirebaseUser,
+) -> Result<Json<BatchesResponse>, AppError> {
+    let rtdb = FirebaseRtdb::from_env()
+        .context("Failed to initialise Firebase RTDB client")?;
+
+    let batch_ids: Option<HashMap<String, bool>> = rtdb.get(&user_batches_path(&current_user.user_id))
+        .await
+        .context("Failed to read the user's batches")?;
+
+    let mut batches = Vec::new();
+    for batch_id in batch_ids.unwrap_or_default().keys() {
+        let batch: Option<Batch> = rtdb.get(&batch_path(batch_id))
+            .await
+            .context("Failed to read the batch")?;
+
+        if let Some(batch) = batch {
+            let accepted = batch.rows.iter().filter(|row| row.accepted()).count();
+            batches.push(BatchSummary {
+                rejected: batch.rows.len() - accepted,
+                accepted,
+                id: batch.id,
+                name: batch.name,
+                created_at: batch.created_at,
+                completed_at: batch.completed_at,
+            });
+        }
+    }
+    batches.sort_by(|a, b| b.created_at.cmp(&a.created_at).then_with(|| b.id.cmp(&a.id)));
+
+    Ok(Json(BatchesResponse { batches }))
+}
+
+/// `GET /api/v1/batches/:batch_id`
+///
+/// Returns a batch, the outcome of each row, and how many of its jobs
+/// have each status. Anyone who can see the submitter's jobs can see it.
+pub async fn batch_status_entrypoint(
+    current_user: FirebaseUser,
+    State(app): State<AppStatePtr>,
+    Path(batch_id): Path<String>,
+) -> Result<Json<BatchStatusResponse>, AppError> {
+    let app = app.state;
+
+    let rtdb = FirebaseRtdb::from_env()
+        .context("Failed to initialise Firebase RTDB client")?;
+    let batch = read_batch(&rtdb, &batch_id).await?;
+    ensure_job_access(&app, &current_user.user_id, &batch.user_id, JobAccess::View).await?;
+
+    let jobs = read_jobs(&app, &batch).await?;
+    let progress = BatchProgress::of(&batch, &jobs);
+
+    Ok(Json(BatchStatusResponse { batch, progress }))
+}
+
+/// `GET /api/v1/batches/:batch_id/results`
+///
+/// Downloads a batch's results as a CSV, with one line per manifest row:
+/// its job and status, the prediction for completed jobs, and the error
+/// for failed jobs and rejected rows. Anyone who can see the submitter's
+/// jobs can download it.
+pub async fn batch_results_entrypoint(
+    current_user: FirebaseUser,
+    State(app): State<AppStatePtr>,
+    Path(batch_id): Path<String>,
+) -> Result<Response, AppError> {
+    let app = app.state;
+
+    let rtdb = FirebaseRtdb::from_env()
+        .context("Failed to initialise Firebase RTDB client")?;
+    let batch = read_batch(&rtdb, &batch_id).await?;
+    ensure_job_access(&app, &current_user.user_id, &batch.user_id, JobAccess::View).await?;
+
+    let jobs = read_jobs(&app, &batch).await?;
+    let csv = results_csv(&batch, &jobs).context("Failed to write the batch results")?;
+
+    Ok((
+        [
+            (header::CONTENT_TYPE, "text/csv; charset=utf-8".to_string()),
+            (header::CONTENT_DISPOSITION, format!("attachment; filename=\"batch-{}-results.csv\"", batch.id)),
+        ],
+        csv,
+    ).into_response())
+}
+
+/// Writes a batch's results as a CSV, one line per manifest row.
+fn results_csv(batch: &Batch, jobs: &HashMap<String, Job>) -> anyhow::Result<Vec<u8>> {
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    writer.write_record(["row", "participant_id", "job_id", "status", "prediction", "asd", "error"])?;
+
+    for row in &batch.rows {
+        let job = row.job_id.as_ref().and_then(|job_id| jobs.get(job_id));
+        let (status, prediction, asd, error) = match job.map(|job| &job.status) {
+            Some(JobStatus::Complete { prediction, asd, .. }) => {
+                ("Complete", prediction.to_string(), asd.to_string(), String::new())
+            }
+            Some(JobStatus::Error { value, .. }) => ("Error", String::new(), String::new(), value.clone()),
+            Some(status) => (status.code(), String::new(), String::new(), String::new()),
+            None if row.accepted() => ("Missing", String::new(), String::new(), String::new()),
+            None => ("Rejected", String::new(), String::new(), row.errors.join(" ")),
+        };
+
+        writer.write_record([
+            row.row.to_string().as_str(),
+            row.participant_id.as_deref().unwrap_or_default(),
+            row.job_id.as_deref().unwrap_or_default(),
+            status,
+            &prediction,
+            &asd,
+            &error,
+        ])?;
+    }
+
+    writer.into_inner().map_err(|e| e.into_error().into())
+}
+
+/// Takes in the `Multipart` request and unpacks the manifest, batch details and videos.
+///
+/// # Fails
+/// * With a `400` if the manifest is missing, the name is too long, the
+///   email is invalid, the locale is unsupported, or two videos share a name
+/// * If a field couldn't be read
+async fn unpack_batch_arguments(multipart: &mut Multipart) -> Result<BatchRequestArguments, AppError> {
+    let mut name: Option<String> = None;
+    let mut email: Option<String> = None;
+    let mut locale: Option<Locale> = None;
+    let mut manifest: Option<UploadRequestFile> = None;
+    let mut videos: HashMap<String, Bytes> = HashMap::new();
+
+    while let Some(field) = multipart
+        .next_field()
+        .await
+        .context("Bad batch request! Is it possible you submitted a file over the size limit?")?
+    {
+        match field.name() {
+            Some("manifest") => {
+                let file_name = field.file_name().unwrap_or_default().to_string();
+                let bytes = field
+                    .bytes()
+                    .await
+                    .context("Could not unpack bytes from field 'manifest'!")?;
+                manifest = Some(UploadRequestFile { name: file_name, bytes });
+            }
+            Some("name") => {
+                let text = field
+                    .text()
+                    .await
+                    .context("Field 'name' wasn't readable as text!")?;
+                name = Some(text.trim().to_string()).filter(|text| !text.is_empty());
+            }
+            Some("email") => {
+                let text = field
+                    .text()
+                    .await
+                    .context("Field 'email' wasn't readable as text!")?;
+                email = Some(text.trim().to_string()).filter(|text| !text.is_empty());
+            }
+            Some("locale") => {
+                let text = field
+                    .text()
+                    .await
+                    .context("Field 'locale' wasn't readable as text!")?;
+                locale = Some(text.parse().map_err(|_| AppError::client(
+                    StatusCode::BAD_REQUEST,
+                    format!("Invalid 'locale': '{}' isn't a supported locale.", text.trim()),
+                ))?);
+            }
+            _ => match field.file_name().map(String::from) {
+                Some(file_name) => {
+                    let bytes = field
+                        .bytes()
+                        .await
+                        .context(format!("Could not unpack bytes from '{}'!", file_name))?;
+                    if videos.insert(file_name.clone(), bytes).is_some() {
+                        return Err(AppError::client(
+                            StatusCode::BAD_REQUEST,
+                            format!("More than one video named '{}' was uploaded.", file_name),
+                        ));
+                    }
+                }
+                None => println!("Skipping unknown field: {:?}", field.name()),
+            },
+        }
+    }
+
+    if name.as_ref().is_some_and(|name| name.chars().count() > MAX_NAME_LENGTH) {
+        return Err(AppError::client(
+            StatusCode::BAD_REQUEST,
+            format!("Batch names must be at most {} characters.", MAX_NAME_LENGTH),
+        ));
+    }
+    if email.as_ref().is_some_and(|email| !email.contains('@')) {
+        return Err(AppError::client(StatusCode::BAD_REQUEST, "Invalid 'email': it must be an email address."));
+    }
+    let manifest = manifest.ok_or_else(|| AppError::client(
+        StatusCode::BAD_REQUEST,
+        "Missing 'manifest' in request!",
+    ))?;
+
+    Ok(BatchRequestArguments { name, email, locale, manifest, videos })
+}
+
+/// Parses a manifest into its rows.
+///
+/// The format is taken from the file's extension (`.csv` or `.json`),
+/// falling back to JSON if the contents start with `[`, and CSV otherwise.
+///
+/// # Fails
+/// * With a `400` if the manifest as a whole couldn't be read
+fn parse_manifest(manifest: &UploadRequestFile) -> Result<Vec<ManifestRow>, AppError> {
+    let is_json = match manifest.extension().as_deref() {
+        Ok("json") => true,
+        Ok("csv") => false,
+        _ => manifest.bytes.trim_ascii_start().starts_with(b"["),
+    };
+
+    if is_json {
+        parse_json_manifest(&manifest.bytes)
+    } else {
+        parse_csv_manifest(&manifest.bytes)
+    }
+}
+
+/// Parses a CSV manifest, whose first row names the columns.
+fn parse_csv_manifest(bytes: &[u8]) -> Result<Vec<ManifestRow>, AppError> {
+    let mut reader = csv::ReaderBuilder::new()
+        .trim(csv::Trim::All)
+        .from_reader(bytes);
+
+    let headers: Vec<String> = reader
+        .headers()
+        .map_err(|e| AppError::client(
+            StatusCode::BAD_REQUEST,
+            format!("The manifest's header row couldn't be read: {}", e),
+        ))?
+        .iter()
+        .map(str::to_lowercase)
+        .collect();
+
+    Ok(reader
+        .records()
+        .map(|record| {
+            let record = record.map_err(|e| format!("The row couldn't be read: {}", e))?;
+            Ok(headers
+                .iter()
+                .cloned()
+                .zip(record.iter().map(str::to_string))
+                .filter(|(_, value)| !value.is_empty())
+                .collect())
+        })
+        .collect())
+}
+
+/// Parses a JSON manifest, an array with an object per participant.
+fn parse_json_manifest(bytes: &[u8]) -> Result<Vec<ManifestRow>, AppError> {
+    let rows: Vec<Value> = serde_json::from_slice(bytes).map_err(|e| AppError::client(
+        StatusCode::BAD_REQUEST,
+        format!("The manifest must be a JSON array of participants: {}", e),
+    ))?;
+
+    Ok(rows
+        .into_iter()
+        .map(|row| {
+            let Value::Object(fields) = row else {
+                return Err("The row must be a JSON object.".to_string());
+            };
+
+            Ok(fields
+                .into_iter()
+                .filter_map(|(key, value)| {
+                    let value = match value {
+                        Value::Null => return None,
+                        Value::String(text) => text.trim().to_string(),
+                        other => other.to_string(),
+                    };
+                    Some((key.to_lowercase(), value))
+                })
+                .filter(|(_, value)| !value.is_empty())
+                .collect())
+        })
+        .collect())
+}
+
+/// Validates a manifest row's details and finds its videos.
+///
+/// # Returns
+/// * Every problem with the row, if there were any
+fn parse_participant(
+    fields: &HashMap<String, String>,
+    videos: &HashMap<String, Bytes>,
+    default_locale: Locale,
+) -> Result<Participant, Vec<String>> {
+    let mut errors = Vec::new();
+
+    let age:       Option<i16>       = column(fields, "age", &mut errors);
+    let ethnicity: Option<Ethnicity> = column(fields, "ethnicity", &mut errors);
+    let sex:       Option<Sex>       = column(fields, "sex", &mut errors);
+    let height:    Option<String>    = column(fields, "height", &mut errors);
+    let weight:    Option<i16>       = column(fields, "weight", &mut errors);
+    let email:     Option<String>    = column(fields, "email", &mut errors);
+    if email.as_ref().is_some_and(|email| !email.contains('@')) {
+        errors.push("Invalid 'email': it must be an email address.".to_string());
+    }
+
+    let requires_approval = match fields.get("requires_approval").map(|value| value.to_lowercase()).as_deref() {
+        None | Some("false") | Some("0") => false,
+        Some("true") | Some("1") => true,
+        Some(_) => {
+            errors.push("Invalid 'requires_approval': it must be 'true' or 'false'.".to_string());
+            false
+        }
+    };
+    let locale = match fields.get("locale") {
+        Some(_) => column(fields, "locale", &mut errors),
+        None => Some(default_locale),
+    };
+
+    let front_file = video(fields, "front_file", videos, &mut errors);
+    let side_file = video(fields, "side_file", videos, &mut errors);
+
+    match (age, ethnicity, sex, height, weight, email, front_file, side_file) {
+        (Some(age), Some(ethnicity), Some(sex), Some(height), Some(weight), Some(email), Some(front_file), Some(side_file))
+            if errors.is_empty() =>
+        {
+            Ok(Participant {
+                details: JobDetails { age, ethnicity, sex, height, weight, email, requires_approval, locale },
+                front_file,
+                side_file,
+            })
+        }
+        _ => Err(errors),
+    }
+}
+
+/// Reads and parses a required column, noting any problem in `errors`.
+fn column<T>(fields: &HashMap<String, String>, name: &str, errors: &mut Vec<String>) -> Option<T>
+where
+    T: FromStr,
+    T::Err: Display,
+{
+    let Some(value) = fields.get(name) else {
+        errors.push(format!("Missing '{}'.", name));
+        return None;
+    };
+
+    value
+        .parse()
+        .map_err(|e| errors.push(format!("Invalid '{}': {}.", name, e)))
+        .ok()
+}
+
+/// Finds the uploaded video a column names, noting any problem in `errors`.
+fn video(
+    fields: &HashMap<String, String>,
+    name: &str,
+    videos: &HashMap<String, Bytes>,
+    errors: &mut Vec<String>,
+) -> Option<UploadRequestFile> {
+    let file_name: String = column(fields, name, errors)?;
+    let Some(bytes) = videos.get(&file_name) else {
+        errors.push(format!("No video named '{}' was uploaded.", file_name));
+        return None;
+    };
+
+    let file = UploadRequestFile { name: file_name, bytes: bytes.clone() };
+    if let Err(e) = file.extension() {
+        errors.push(describe_error(&e));
+        return None;
+    }
+
+    Some(file)
+}
+
+/// Creates a job for a participant, uploads their videos and dispatches it.
+///
+/// The participant's quota slot, if the user is limited, is given back if
+/// the submission fails.
+///
+/// # Returns
+/// * The ID of the job that was created
+///
+/// # Fails
+/// * If the job couldn't be created, uploaded or dispatched
+async fn submit_participant(
+    app: &Arc<AppState>,
+    uid: &str,
+    batch_id: &str,
+    participant: Participant,
+    media: JobMedia,
+    mut reservation: Option<QuotaReservation>,
+) -> Result<String, AppError> {
+    let result = async {
+        let mut job = participant.details.into_job();
+        job.media = Some(media);
+        job.batch_id = Some(batch_id.to_string());
+        let job_index = create_job(app, uid, &job).await?;
+        let job_id = format!("{}_{}", uid, job_index);
+        if let Some(reservation) = reservation.as_mut() {
+            reservation.settle().await;
+        }
+
+        let (front_key, side_key) = match upload_files(
+            app.clone(),
+            &job_id,
+            participant.front_file,
+            participant.side_file,
+        )
+        .await
+        {
+            Ok(keys) => keys,
+            Err(err) => return Err(fail_submission(app, uid, job_index, err).await),
+        };
+
+        submit_to_pipeline(app.clone(), uid, job_index, &job, front_key, side_key).await?;
+        Ok(job_id)
+    }.await;
+
+    if let (Err(_), Some(reservation)) = (&result, reservation) {
+        reservation.release().await;
+    }
+    result
+}
+
+/// Describes why a row couldn't be submitted, for the response.
+fn describe_error(err: &AppError) -> String {
+    match err.client_message() {
+        Some(message) => message.to_string(),
+        None => {
+            eprintln!("Failed to submit a batch row: {:?}", err.0);
+            format!("Something went wrong: {}", err.0)
+        }
+    }
+}
@@ -1,15 +1,27 @@
 use std::{sync::Arc, time::SystemTime};
 
-use axum::{body::Bytes, extract::{Multipart, State}};
+use axum::{body::Bytes, extract::{Multipart, State}, http::HeaderMap, Json};
 use anyhow::{ Result, Context, anyhow };
 use firebase_auth::FirebaseUser;
+use serde::{Deserialize, Serialize};
 
-use crate::helper::{email::send_contribution_email, lib::{AppError, AppState, AppStatePtr}};
+use igait_lib::microservice::{FirebaseRtdb, Locale, now_ms};
+
+use crate::helper::{
+    contributions::{Contribution, ContributionStatus, contribution_path, validate_consent_version},
+    email::send_contribution_email,
+    idempotency::{self, Idempotency},
+    quota::{self, Submission},
+    lib::{AppError, AppState, AppStatePtr},
+    webhooks::generate_id,
+};
 
 /// A request to upload a video for the contribute endpoint.
 pub struct ContributeRequestArguments {
     name: String,
     email: String,
+    consent_version: String,
+    locale: Locale,
     front_file: ContributeRequestFile,
     side_file:  ContributeRequestFile,
 }
@@ -35,6 +47,8 @@ async fn unpack_contribute_arguments(
     // Initialize all of the fields as options
     let mut name_option:      Option<String> = None;
     let mut email_option:     Option<String> = None;
+    let mut consent_version_option: Option<String> = None;
+    let mut locale_option:    Option<Locale> = None;
 
     // Initialize the file fields as options
     let mut front_file_name_option:  Option<String> = None;
@@ -80,6 +94,22 @@ async fn unpack_contribute_arguments(
                         .context("Field 'name' wasn't readable as text!")?
                         .to_string());
             }
+            Some("consent_version") => {
+                consent_version_option = Some(
+                    field
+                        .text().await
+                        .context("Field 'consent_version' wasn't readable as text!")?
+                        .trim()
+                        .to_string());
+            }
+            Some("locale") => {
+                locale_option = Some(
+                    field
+                        .text().await
+                        .context("Field 'locale' wasn't readable as text!")?
+                        .parse()
+                        .context("Field 'locale' wasn't a supported locale!")?);
+            }
             Some("uid") => {
                 // uid is now derived from the authenticated FirebaseUser token;
                 // ignore any user-supplied value.
@@ -94,6 +124,7 @@ async fn unpack_contribute_arguments(
     // Make sure all of the fields are present
     let name:  String = name_option.ok_or( anyhow!( "Missing 'name' in request" ))?;
     let email: String = email_option.ok_or( anyhow!( "Missing 'email' in request" ))?;
+    let consent_version: String = consent_version_option.ok_or( anyhow!( "Missing 'consent_version' in request" ))?;
 
     // Make sure all of the file fields are present
     let front_file_name:  String = front_file_name_option.ok_or(  anyhow!( "Missing 'fileuploadfront' in request!" ))?;
@@ -104,6 +135,8 @@ async fn unpack_contribute_arguments(
     Ok(ContributeRequestArguments {
         name,
         email, 
+        consent_version,
+        locale: locale_option.unwrap_or_default(),
         front_file: ContributeRequestFile {
             name: front_file_name, 
             bytes: front_file_bytes
@@ -115,60 +148,171 @@ async fn unpack_contribute_arguments(
     })
 }
 
+/// Response body for a contribution.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ContributeResponse {
+    /// Whether the contribution was saved
+    pub success: bool,
+    /// The ID of the contribution's consent record, used to withdraw it
+    #[serde(default)]
+    pub contribution_id: Option<String>,
+}
+
 /// The entrypoint for the contribute request.
 /// 
+/// Requests carrying an `Idempotency-Key` that was already used return
+/// the original result without saving the videos or emailing again.
+/// 
+/// Each contribution is recorded along with the version of the consent
+/// form the contributor agreed to (`consent_version`), so it can later
+/// be withdrawn (see `crate::routes::contributions`).
+/// 
 /// # Fails
 /// * If the arguments are missing.
+/// * If the consent form version isn't the current one.
 /// * If the files are too large.
 /// * If the files fail to save to S3.
-/// * If the job fails to save to the database.
+/// * If the consent record fails to save to the database.
 /// * If the welcome email fails to send.
+/// * If a request with the same `Idempotency-Key` is still in progress.
+/// * If the user has hit their daily submission limit.
 /// 
 /// # Arguments
 /// * `app` - The application state.
+/// * `headers` - The request headers, for the `Idempotency-Key`.
 /// * `multipart` - The `Multipart` object to unpack.
-
 pub async fn contribute_entrypoint(
     current_user: FirebaseUser,
     State(app): State<AppStatePtr>,
+    headers: HeaderMap,
     mut multipart: Multipart
-) -> Result<(), AppError> {
+) -> Result<Json<ContributeResponse>, AppError> {
     let app = app.state;
     let uid = current_user.user_id;
 
+    let key = match idempotency::begin(&uid, "contribute", &headers).await? {
+        Idempotency::Replay(response) => return Ok(Json(response)),
+        Idempotency::Claimed(key) => Some(key),
+        Idempotency::None => None,
+    };
+
+    let reservation = match quota::reserve(&app, &uid, Submission::Contribution).await {
+        Ok(reservation) => reservation,
+        Err(err) => {
+            if let Some(key) = key {
+                key.release().await;
+            }
+            return Err(err);
+        }
+    };
+
+    let (response, thanks) = match save_contribution(app.clone(), &uid, &mut multipart).await {
+        Ok(saved) => saved,
+        Err(err) => {
+            // Nothing was saved, so the attempt can be retried from scratch
+            if let Some(reservation) = reservation {
+                reservation.release().await;
+            }
+            if let Some(key) = key {
+                key.release().await;
+            }
+            return Err(err);
+        }
+    };
+
+    // The contribution is saved from here on, even if the email fails,
+    // so it keeps its quota slot and retries are given it instead of a new one
+    if let Some(key) = key {
+        if let Err(e) = key.complete(&response).await {
+            eprintln!("Failed to remember the contribution for its idempotency key: {e:?}");
+        }
+    }
+
+    // Thank the user for their contribution
+    send_contribution_email(app, &thanks.email, &thanks.name, thanks.locale)
+        .await
+        .context("Failed to send contribution email!")?;
+    println!("Successfully sent contribution email!");
+
+    Ok(Json(response))
+}
+
+/// Who to thank for a saved contribution.
+struct Contributor {
+    name: String,
+    email: String,
+    locale: Locale,
+}
+
+/// Saves a contribution's videos and consent record.
+async fn save_contribution(
+    app: Arc<AppState>,
+    uid: &str,
+    multipart: &mut Multipart
+) -> Result<(ContributeResponse, Contributor), AppError> {
     println!("Unpacking arguments...");
+
     // Unpack the arguments
     let arguments: ContributeRequestArguments = unpack_contribute_arguments(
-            &mut multipart
+            multipart
         ).await
         .context("Failed to unpack arguments!")?;
 
+    // Only accept consent to the current form
+    validate_consent_version(&arguments.consent_version)?;
+
     // Try to save the files to S3
-    if let Err(err) = 
-        save_upload_files( 
+    let (front_key, side_key) = match save_upload_files(
             app.clone(),
             arguments.front_file,
             arguments.side_file,
-            &uid,
+            uid,
             &arguments.email,
-            &arguments.name
-        ).await 
+        ).await
     {
-        return Err(AppError(err
-            .context("Failed to save locally or upload files to S3!")));
+        Ok(keys) => keys,
+        Err(err) => return Err(AppError(err
+            .context("Failed to save locally or upload files to S3!"))),
+    };
+
+    // Record the consent the videos were contributed under
+    let contribution = Contribution {
+        id: generate_id(),
+        user_id: uid.to_string(),
+        name: arguments.name.clone(),
+        email: arguments.email.clone(),
+        consent_version: arguments.consent_version,
+        consented_at: now_ms(),
+        front_key,
+        side_key,
+        status: ContributionStatus::Active,
+        withdrawn_at: None,
+        withdrawn_by: None,
+    };
+    if let Err(err) = record_contribution(&contribution).await {
+        // Videos without a consent record can't be used, so don't keep them
+        for key in [&contribution.front_key, &contribution.side_key] {
+            if let Err(e) = app.storage.delete(key).await {
+                eprintln!("Failed to remove {} after its consent record failed to save: {e:?}", key);
+            }
+        }
+        return Err(AppError(err));
     }
+    println!("Recorded contribution {} for user {}", contribution.id, uid);
 
-    // Thank the user for their contribution
-    send_contribution_email(
-        app.clone(),
-        &arguments.email,
-        &arguments.name
-    )
-        .await
-        .context("Failed to send contribution email!")?;
-    println!("Successfully sent contribution email!");
+    Ok((
+        ContributeResponse { success: true, contribution_id: Some(contribution.id) },
+        Contributor { name: arguments.name, email: arguments.email, locale: arguments.locale },
+    ))
+}
 
-    Ok(())
+/// Saves a contribution's consent record.
+async fn record_contribution(contribution: &Contribution) -> Result<()> {
+    FirebaseRtdb::from_env()
+        .context("Failed to initialise Firebase RTDB client")?
+        .set(&contribution_path(&contribution.user_id, &contribution.id), contribution)
+        .await
+        .context("Failed to save the consent record!")
 }
 
 /// Saves the contributed video files to AWS S3 for research purposes.
@@ -182,15 +326,16 @@ pub async fn contribute_entrypoint(
 /// * `side_file` - The side video file to save
 /// * `user_id` - The user ID to save the files under
 /// * `email` - The email to save the files under
-/// * `_name` - The name of the contributor (unused but kept for API compatibility)
+/// 
+/// # Returns
+/// * The storage keys of the front and side videos
 async fn save_upload_files<'a> (
     app:              Arc<AppState>,
     front_file:       ContributeRequestFile,
     side_file:        ContributeRequestFile,
     user_id:          &str,
     email:            &str,
-    _name:            &str,
-) -> Result<()> {
+) -> Result<(String, String)> {
     // Unpack the extensions
     let front_extension = front_file.name.split('.')
         .last()
@@ -223,6 +368,6 @@ async fn save_upload_files<'a> (
         .context("Failed to upload side file to AWS S3!")?;
     println!("Successfully uploaded side file to AWS S3!");
     
-    // Return as successful
-    Ok(())
+    // Return the keys for the consent record
+    Ok((front_key, side_key))
 }
@@ -0,0 +1,134 @@
+//! Research contribution endpoints.
+//!
+//! Contributors can list the contributions they've made and the consent
+//! each was given under, and withdraw any of them. Withdrawing deletes
+//! the contributed videos and marks the consent record as withdrawn.
+//! Administrators can withdraw a contribution on a contributor's behalf
+//! (e.g. when asked to by email), which is recorded in the audit log.
+//!
+//! See `crate::helper::contributions` for how contributions are stored.
+
+use axum::{extract::{Path, State}, http::StatusCode, Json};
+use anyhow::Context;
+use firebase_auth::FirebaseUser;
+use serde::Serialize;
+use serde_json::json;
+
+use igait_lib::microservice::{FirebaseRtdb, now_ms};
+
+use crate::helper::{
+    audit::{self, AuditAction},
+    contributions::{
+        Contribution, ContributionStatus, contribution_path, read_contribution, read_contributions,
+    },
+    lib::{AppError, AppState, AppStatePtr},
+};
+
+/// Response body for the caller's contributions.
+#[derive(Debug, Serialize)]
+pub struct ContributionsResponse {
+    /// Every contribution the caller made, oldest first
+    pub contributions: Vec<Contribution>,
+}
+
+/// `GET /api/v1/contributions`
+///
+/// Lists the caller's contributions, including withdrawn ones.
+pub async fn list_contributions_entrypoint(
+    current_user: FirebaseUser,
+) -> Result<Json<ContributionsResponse>, AppError> {
+    let rtdb = FirebaseRtdb::from_env()
+        .context("Failed to initialise Firebase RTDB client")?;
+    let contributions = read_contributions(&rtdb, &current_user.user_id).await?;
+
+    Ok(Json(ContributionsResponse { contributions }))
+}
+
+/// `DELETE /api/v1/contributions/:contribution_id`
+///
+/// Withdraws one of the caller's contributions, deleting its videos.
+///
+/// # Fails
+/// * With a `404` if the caller has no such contribution
+/// * With a `409` if it was already withdrawn
+/// * If the videos couldn't be deleted (the record is left active, so it can be retried)
+pub async fn withdraw_contribution_entrypoint(
+    current_user: FirebaseUser,
+    State(app): State<AppStatePtr>,
+    Path(contribution_id): Path<String>,
+) -> Result<Json<Contribution>, AppError> {
+    let caller_uid = &current_user.user_id;
+
+    withdraw_contribution(&app.state, caller_uid, caller_uid, &contribution_id)
+        .await
+        .map(Json)
+}
+
+/// `DELETE /api/v1/admin/contributions/:uid/:contribution_id`
+///
+/// Withdraws a contribution on the contributor's behalf, deleting its videos.
+/// **Admin-only** — the caller must have `administrator: true`.
+///
+/// # Fails
+/// See `withdraw_contribution_entrypoint`.
+pub async fn admin_withdraw_contribution_entrypoint(
+    current_user: FirebaseUser,
+    State(app): State<AppStatePtr>,
+    Path((uid, contribution_id)): Path<(String, String)>,
+) -> Result<Json<Contribution>, AppError> {
+    let caller_uid = current_user.user_id.clone();
+    let parameters = json!({ "user_id": uid, "contribution_id": contribution_id });
+
+    let result = async {
+        app.state.ensure_administrator(&caller_uid).await?;
+        withdraw_contribution(&app.state, &caller_uid, &uid, &contribution_id).await
+    }.await;
+    audit::record(&caller_uid, AuditAction::ContributionWithdrawn, None, parameters, &result).await;
+
+    result.map(Json)
+}
+
+/// Deletes a contribution's videos and marks its record as withdrawn.
+///
+/// # Arguments
+/// * `app` - The application state
+/// * `caller_uid` - The user withdrawing the contribution
+/// * `uid` - The contributor
+/// * `contribution_id` - The contribution to withdraw
+async fn withdraw_contribution(
+    app: &AppState,
+    caller_uid: &str,
+    uid: &str,
+    contribution_id: &str,
+) -> Result<Contribution, AppError> {
+    let rtdb = FirebaseRtdb::from_env()
+        .context("Failed to initialise Firebase RTDB client")?;
+
+    // ── 1. Find the contribution ────────────────────────────────────
+    let mut contribution = read_contribution(&rtdb, uid, contribution_id).await?;
+    if contribution.status == ContributionStatus::Withdrawn {
+        return Err(AppError::client(
+            StatusCode::CONFLICT,
+            format!("Contribution {} was already withdrawn.", contribution_id),
+        ));
+    }
+
+    // ── 2. Delete the videos ────────────────────────────────────────
+    for key in [&contribution.front_key, &contribution.side_key] {
+        app.storage
+            .delete(key)
+            .await
+            .context(format!("Failed to delete the contributed video {}", key))?;
+    }
+
+    // ── 3. Mark the record as withdrawn ─────────────────────────────
+    contribution.status = ContributionStatus::Withdrawn;
+    contribution.withdrawn_at = Some(now_ms());
+    contribution.withdrawn_by = Some(caller_uid.to_string());
+    rtdb.set(&contribution_path(uid, contribution_id), &contribution)
+        .await
+        .context("Failed to mark the contribution as withdrawn")?;
+
+    println!("Contribution {} of user {} withdrawn by {}", contribution_id, uid, caller_uid);
+    Ok(contribution)
+}
@@ -0,0 +1,294 @@
+//! Direct-to-S3 video uploads.
+//!
+//! An alternative to `upload_entrypoint` that keeps the video bytes off
+//! the backend. The client creates the job with the patient details and
+//! the size of each video, and gets back a presigned PUT URL for each.
+//! Once both videos have been uploaded straight to AWS S3, the client
+//! confirms the upload; the backend checks both objects exist with the
+//! declared sizes before pushing the job to the Stage 1 queue.
+//!
+//! Pending uploads are stored in Firebase RTDB at `pending_uploads/{job_id}`
+//! (see `helper::direct_uploads`), and are expired by the sweeper if they
+//! aren't confirmed before their upload URLs expire.
+
+use std::{collections::HashMap, sync::Arc, time::Duration};
+
+use axum::{extract::{Path, State}, http::StatusCode, Json};
+use anyhow::{Context, anyhow};
+use firebase_auth::FirebaseUser;
+use serde::{Deserialize, Serialize};
+
+use igait_lib::microservice::{FirebaseRtdb, QueueOps, StorageClient, StoragePaths, now_ms};
+
+use crate::{
+    helper::{
+        direct_uploads::{PendingFile, PendingUpload, expire_upload, pending_upload_path},
+        lib::{AppError, AppState, AppStatePtr, JobMedia, JobStatus},
+        media,
+        quota::{self, QuotaReservation, Submission},
+    },
+    routes::upload::{FileDeclaration, JobDetails, create_job, fail_submission, submit_to_pipeline},
+};
+
+/// How long the presigned URLs, and so the upload window, last.
+const UPLOAD_WINDOW: Duration = Duration::from_secs(60 * 60);
+
+/// Request body for creating a job to upload directly to.
+#[derive(Debug, Deserialize)]
+pub struct CreateDirectUploadRequest {
+    /// The patient details for the job
+    #[serde(flatten)]
+    pub details: JobDetails,
+    /// The front video
+    pub front: FileDeclaration,
+    /// The side video
+    pub side: FileDeclaration,
+}
+
+/// Where and how to upload one of the videos.
+#[derive(Debug, Serialize)]
+pub struct UploadTarget {
+    /// The presigned URL to upload to
+    pub url: String,
+    /// The HTTP method to upload with
+    pub method: &'static str,
+    /// The headers that must be sent with the upload, exactly as given
+    pub headers: HashMap<&'static str, String>,
+}
+
+/// Response body for creating a direct upload.
+#[derive(Debug, Serialize)]
+pub struct CreateDirectUploadResponse {
+    /// The ID of the job that was created (format: "{user_id}_{job_index}")
+    pub job_id: String,
+    /// Where to upload the front video
+    pub front: UploadTarget,
+    /// Where to upload the side video
+    pub side: UploadTarget,
+    /// When the upload URLs stop working (Unix timestamp ms)
+    pub expires_at: u64,
+}
+
+/// Response body for confirming a direct upload.
+#[derive(Debug, Serialize)]
+pub struct ConfirmDirectUploadResponse {
+    /// Whether the job was submitted to the pipeline
+    pub success: bool,
+}
+
+/// `POST /api/v1/uploads/direct`
+///
+/// Creates a job and returns a presigned URL to upload each video to.
+/// The job isn't processed until `POST /api/v1/uploads/direct/:job_id/confirm`
+/// is called.
+///
+/// # Workflow
+/// 1. Validate the declared files
+/// 2. Reserve a slot in the user's submission quota
+/// 3. Create the job in the database, waiting for its videos
+/// 4. Presign an upload for each video
+/// 5. Record the pending upload
+///
+/// # Fails
+/// * With a `400` if a declared file is invalid
+/// * With a `429` if the user has hit their daily or in-flight job limit
+pub async fn create_direct_upload_entrypoint(
+    current_user: FirebaseUser,
+    State(app): State<AppStatePtr>,
+    Json(request): Json<CreateDirectUploadRequest>,
+) -> Result<Json<CreateDirectUploadResponse>, AppError> {
+    let app = app.state;
+    let uid = &current_user.user_id;
+
+    // ── 1. Validate the declared files ──────────────────────────────
+    let front_extension = request.front.extension()?;
+    let side_extension = request.side.extension()?;
+
+    // ── 2. Reserve a quota slot ─────────────────────────────────────
+    let reservation = quota::reserve(&app, uid, Submission::Job).await?;
+
+    let result = start_direct_upload(&app, uid, request, front_extension, side_extension, reservation.clone()).await;
+    if let (Err(_), Some(reservation)) = (&result, reservation) {
+        reservation.release().await;
+    }
+
+    result.map(Json)
+}
+
+/// Creates the job for a direct upload and presigns its uploads.
+async fn start_direct_upload(
+    app: &Arc<AppState>,
+    uid: &str,
+    request: CreateDirectUploadRequest,
+    front_extension: String,
+    side_extension: String,
+    mut reservation: Option<QuotaReservation>,
+) -> Result<CreateDirectUploadResponse, AppError> {
+    // ── 3. Create the job ───────────────────────────────────────────
+    let mut job = request.details.into_job();
+    job.status = JobStatus::awaiting_upload(job.locale);
+    let job_index = create_job(app, uid, &job).await?;
+    let job_id = format!("{}_{}", uid, job_index);
+    if let Some(reservation) = reservation.as_mut() {
+        reservation.settle().await;
+    }
+
+    // ── 4. Presign the uploads ──────────────────────────────────────
+    let pending = PendingUpload {
+        job_id: job_id.clone(),
+        user_id: uid.to_string(),
+        front: PendingFile {
+            key: StoragePaths::upload_front_video(&job_id, &front_extension),
+            size: request.front.size,
+            content_type: media::content_type(&front_extension).to_string(),
+        },
+        side: PendingFile {
+            key: StoragePaths::upload_side_video(&job_id, &side_extension),
+            size: request.side.size,
+            content_type: media::content_type(&side_extension).to_string(),
+        },
+        expires_at: now_ms() + UPLOAD_WINDOW.as_millis() as u64,
+        reservation,
+    };
+
+    let targets = async {
+        let front = presign(&app.storage, &pending.front).await?;
+        let side = presign(&app.storage, &pending.side).await?;
+        Ok((front, side))
+    }.await;
+    let (front, side) = match targets {
+        Ok(targets) => targets,
+        Err(err) => return Err(fail_submission(app, uid, job_index, err).await),
+    };
+
+    // ── 5. Record the pending upload ────────────────────────────────
+    let stored = async {
+        let rtdb = FirebaseRtdb::from_env()
+            .context("Failed to initialise Firebase RTDB client")?;
+        rtdb.set(&pending_upload_path(&job_id), &pending)
+            .await
+            .context("Failed to record the pending upload")
+    }.await;
+    if let Err(err) = stored {
+        return Err(fail_submission(app, uid, job_index, err).await);
+    }
+
+    println!("Created job {} for direct upload", job_id);
+    Ok(CreateDirectUploadResponse {
+        job_id,
+        front,
+        side,
+        expires_at: pending.expires_at,
+    })
+}
+
+/// `POST /api/v1/uploads/direct/:job_id/confirm`
+///
+/// Checks both videos have been uploaded with their declared sizes and
+/// pass the media probe, then pushes the job to the Stage 1 queue. If a
+/// video is missing, the wrong size or rejected by the probe, nothing
+/// changes and the upload can be retried until the URLs expire; after
+/// that, the job is marked as failed and its quota slot given back.
+pub async fn confirm_direct_upload_entrypoint(
+    current_user: FirebaseUser,
+    State(app): State<AppStatePtr>,
+    Path(job_id): Path<String>,
+) -> Result<Json<ConfirmDirectUploadResponse>, AppError> {
+    let app = app.state;
+    let uid = &current_user.user_id;
+
+    let (owner_uid, job_index) = QueueOps::parse_job_id(&job_id)
+        .context("Invalid job ID")?;
+    if &owner_uid != uid {
+        return Err(AppError(anyhow!("No pending upload for job {}.", job_id)));
+    }
+
+    // ── 1. Find the pending upload ──────────────────────────────────
+    let rtdb = FirebaseRtdb::from_env()
+        .context("Failed to initialise Firebase RTDB client")?;
+    let (pending, etag) = rtdb
+        .get_with_etag::<PendingUpload>(&pending_upload_path(&job_id))
+        .await
+        .context("Failed to read the pending upload")?;
+    let pending = pending.ok_or_else(|| anyhow!("No pending upload for job {}.", job_id))?;
+
+    if now_ms() > pending.expires_at {
+        expire_upload(&app, &rtdb, &job_id).await?;
+        return Err(AppError(anyhow!("The videos were not uploaded before the upload URLs expired.")));
+    }
+
+    // ── 2. Check both videos arrived intact ─────────────────────────
+    let mut probes = Vec::with_capacity(2);
+    for (name, file) in [("front", &pending.front), ("side", &pending.side)] {
+        let size = app.storage
+            .object_size(&file.key)
+            .await
+            .context(format!("Failed to check the {} video", name))?
+            .ok_or_else(|| AppError::client(
+                StatusCode::CONFLICT,
+                format!("The {} video has not been uploaded yet.", name),
+            ))?;
+
+        if size != file.size {
+            return Err(AppError::client(StatusCode::UNPROCESSABLE_ENTITY, format!(
+                "The {} video is {} bytes, but {} were declared. Please upload it again.",
+                name,
+                size,
+                file.size
+            )));
+        }
+
+        // ── 3. Probe it ─────────────────────────────────────────────
+        let probe = media::probe_stored(&app.storage, &file.key, size)
+            .await
+            .context(format!("Failed to probe the {} video", name))?;
+        probes.push(media::validate(name, probe)?);
+    }
+    let side = probes.pop().context("Missing side video probe")?;
+    let front = probes.pop().context("Missing front video probe")?;
+
+    // The upload is used up - remove it before dispatching, so a
+    // retried request can't submit the job twice. If it changed while the
+    // videos were checked, it was expired or confirmed in the meantime.
+    if !rtdb.delete_if_match(&pending_upload_path(&job_id), &etag)
+        .await
+        .context("Failed to remove the pending upload")?
+    {
+        return Err(AppError(anyhow!("No pending upload for job {}.", job_id)));
+    }
+
+    // ── 4. Dispatch to Stage 1 ──────────────────────────────────────
+    let job = {
+        let db = app.db.lock().await;
+        db.set_media(uid, job_index, JobMedia { front, side })
+            .await
+            .context("Failed to record the video properties")?;
+        db.get_job(uid, job_index)
+            .await
+            .context("Failed to read the job")?
+    };
+
+    println!("Direct upload for job {} confirmed", job_id);
+    submit_to_pipeline(app, uid, job_index, &job, pending.front.key, pending.side.key).await?;
+
+    Ok(Json(ConfirmDirectUploadResponse { success: true }))
+}
+
+/// Presigns the upload of a pending video.
+async fn presign(
+    storage: &StorageClient,
+    file: &PendingFile,
+) -> anyhow::Result<UploadTarget> {
+    let url = storage
+        .presign_upload(&file.key, &file.content_type, file.size, UPLOAD_WINDOW)
+        .await?;
+
+    Ok(UploadTarget {
+        url,
+        method: "PUT",
+        headers: HashMap::from([
+            ("Content-Type", file.content_type.clone()),
+            ("Content-Length", file.size.to_string()),
+        ]),
+    })
+}
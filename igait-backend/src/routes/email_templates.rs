@@ -0,0 +1,223 @@
+//! Email template override endpoints.
+//!
+//! Every email is rendered from a subject, an HTML body and a plain-text
+//! body template in each locale. Administrators can replace any of them by
+//! uploading an override to storage at `email_templates/{locale}/{template}.{ext}`,
+//! which the backend and the finalize stage pick up the next time they send
+//! that email - no rebuild or restart needed. Removing an override goes back
+//! to the built-in template.
+//!
+//! Overrides are checked before they're saved: they must compile and only
+//! use the variables their template is given.
+//!
+//! Only users with `administrator: true` in the database are authorised.
+
+use axum::{extract::{Path, State}, http::StatusCode, Json};
+use anyhow::Context;
+use firebase_auth::FirebaseUser;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use igait_lib::microservice::{EmailTemplate, EmailTemplates, Locale, StoragePaths, TemplatePart};
+
+use crate::helper::{
+    audit::{self, AuditAction},
+    lib::{AppError, AppState, AppStatePtr},
+};
+
+/// One part of an email template.
+#[derive(Debug, Serialize)]
+pub struct TemplatePartEntry {
+    pub part: TemplatePart,
+    /// Whether an override replaces the built-in template
+    pub overridden: bool,
+    /// The template in use - the override if there is one
+    pub source: String,
+}
+
+/// An email template in a locale, and its parts.
+#[derive(Debug, Serialize)]
+pub struct TemplateEntry {
+    pub template: EmailTemplate,
+    pub locale: Locale,
+    pub parts: Vec<TemplatePartEntry>,
+}
+
+/// Response body for the list of email templates.
+#[derive(Debug, Serialize)]
+pub struct EmailTemplatesResponse {
+    pub templates: Vec<TemplateEntry>,
+}
+
+/// Request body for overriding a template part.
+#[derive(Debug, Deserialize)]
+pub struct UpdateTemplateRequest {
+    /// The new template, in minijinja syntax
+    pub source: String,
+}
+
+/// `GET /api/v1/admin/email-templates`
+///
+/// Lists every email template's parts in each locale, and the source in use.
+/// **Admin-only** — the caller must have `administrator: true`.
+pub async fn list_email_templates_entrypoint(
+    current_user: FirebaseUser,
+    State(app): State<AppStatePtr>,
+) -> Result<Json<EmailTemplatesResponse>, AppError> {
+    let app = app.state;
+    app.ensure_administrator(&current_user.user_id).await?;
+
+    let mut templates = Vec::new();
+    for locale in Locale::ALL {
+        let overridden = app.storage
+            .list_by_prefix(&StoragePaths::email_template(locale.code(), ""))
+            .await
+            .context(format!("Failed to list the {} email template overrides", locale))?;
+
+        for template in EmailTemplate::ALL {
+            let mut parts = Vec::new();
+            for part in TemplatePart::ALL {
+                let key = StoragePaths::email_template(locale.code(), &template.file_name(part));
+                let source = match overridden.contains(&key) {
+                    true => Some(read_override(&app, &key).await?),
+                    false => None,
+                };
+
+                parts.push(TemplatePartEntry {
+                    part,
+                    overridden: source.is_some(),
+                    source: source.unwrap_or_else(|| template.builtin(locale, part).to_string()),
+                });
+            }
+            templates.push(TemplateEntry { template, locale, parts });
+        }
+    }
+
+    Ok(Json(EmailTemplatesResponse { templates }))
+}
+
+/// `PUT /api/v1/admin/email-templates/:locale/:template/:part`
+///
+/// Overrides one part (`subject`, `html` or `text`) of an email template
+/// in a locale.
+/// **Admin-only** — the caller must have `administrator: true`.
+///
+/// # Fails
+/// * With a `404` if there's no such locale, template or part
+/// * With a `422` if the template doesn't compile or uses unknown variables
+pub async fn update_email_template_entrypoint(
+    current_user: FirebaseUser,
+    State(app): State<AppStatePtr>,
+    Path((locale, template, part)): Path<(String, String, String)>,
+    Json(request): Json<UpdateTemplateRequest>,
+) -> Result<Json<TemplatePartEntry>, AppError> {
+    let caller_uid = current_user.user_id.clone();
+    let parameters = json!({ "locale": locale, "template": template, "part": part, "source": request.source });
+
+    let result = async {
+        app.state.ensure_administrator(&caller_uid).await?;
+        let (locale, template, part) = parse_template(&locale, &template, &part)?;
+
+        EmailTemplates::validate(template, locale, part, &request.source).map_err(|e| AppError::client(
+            StatusCode::UNPROCESSABLE_ENTITY,
+            format!("The template is invalid: {:#}", e),
+        ))?;
+
+        let content_type = match part {
+            TemplatePart::Html => "text/html; charset=utf-8",
+            TemplatePart::Subject | TemplatePart::Text => "text/plain; charset=utf-8",
+        };
+        app.state.storage
+            .upload(
+                &StoragePaths::email_template(locale.code(), &template.file_name(part)),
+                request.source.clone().into_bytes(),
+                Some(content_type),
+            )
+            .await
+            .context("Failed to save the template override")?;
+
+        println!("Email template {}/{} overridden by {}", locale, template.file_name(part), caller_uid);
+        Ok(TemplatePartEntry { part, overridden: true, source: request.source })
+    }.await;
+    audit::record(&caller_uid, AuditAction::EmailTemplateUpdated, None, parameters, &result).await;
+
+    result.map(Json)
+}
+
+/// `DELETE /api/v1/admin/email-templates/:locale/:template/:part`
+///
+/// Removes a template override, going back to the built-in template.
+/// **Admin-only** — the caller must have `administrator: true`.
+///
+/// # Fails
+/// * With a `404` if there's no such locale, template or part, or it isn't overridden
+pub async fn reset_email_template_entrypoint(
+    current_user: FirebaseUser,
+    State(app): State<AppStatePtr>,
+    Path((locale, template, part)): Path<(String, String, String)>,
+) -> Result<Json<TemplatePartEntry>, AppError> {
+    let caller_uid = current_user.user_id.clone();
+    let parameters = json!({ "locale": locale, "template": template, "part": part });
+
+    let result = async {
+        app.state.ensure_administrator(&caller_uid).await?;
+        let (locale, template, part) = parse_template(&locale, &template, &part)?;
+
+        let key = StoragePaths::email_template(locale.code(), &template.file_name(part));
+        let overridden = app.state.storage
+            .list_by_prefix(&key)
+            .await
+            .context("Failed to look up the template override")?;
+        if !overridden.contains(&key) {
+            return Err(AppError::client(
+                StatusCode::NOT_FOUND,
+                format!("{}/{} isn't overridden.", locale, template.file_name(part)),
+            ));
+        }
+
+        app.state.storage
+            .delete(&key)
+            .await
+            .context("Failed to delete the template override")?;
+
+        println!("Email template {}/{} reset by {}", locale, template.file_name(part), caller_uid);
+        Ok(TemplatePartEntry {
+            part,
+            overridden: false,
+            source: template.builtin(locale, part).to_string(),
+        })
+    }.await;
+    audit::record(&caller_uid, AuditAction::EmailTemplateReset, None, parameters, &result).await;
+
+    result.map(Json)
+}
+
+/// Parses a locale, template and part from their names in the path.
+///
+/// # Fails
+/// * With a `404` if any of them is unknown
+fn parse_template(locale: &str, template: &str, part: &str) -> Result<(Locale, EmailTemplate, TemplatePart), AppError> {
+    let not_found = |what: &str, name: &str| AppError::client(
+        StatusCode::NOT_FOUND,
+        format!("No email {} named '{}'.", what, name),
+    );
+
+    // Only exact codes, so overrides are always stored under the same path
+    let locale = Locale::ALL
+        .into_iter()
+        .find(|candidate| candidate.code() == locale)
+        .ok_or_else(|| not_found("locale", locale))?;
+    let template = EmailTemplate::from_name(template).ok_or_else(|| not_found("template", template))?;
+    let part = TemplatePart::from_name(part).ok_or_else(|| not_found("template part", part))?;
+    Ok((locale, template, part))
+}
+
+/// Reads a template override from storage.
+async fn read_override(app: &AppState, key: &str) -> Result<String, AppError> {
+    let data = app.storage
+        .download(key)
+        .await
+        .context(format!("Failed to read the template override {}", key))?;
+
+    Ok(String::from_utf8_lossy(&data).into_owned())
+}
@@ -0,0 +1,363 @@
+//! Live event stream for following a job through the pipeline.
+//!
+//! Workers write a job's status, per-stage results and logs straight to
+//! Firebase RTDB, so this endpoint watches the job and pushes whatever
+//! changed as Server-Sent Events. The caller must own the job **or** be
+//! an admin.
+//!
+//! The job is read straight from Firebase RTDB rather than through the
+//! shared database handle, so open streams never hold up other requests.
+//! Each open stream polls the job on its own, so a caller may only have
+//! `MAX_STREAMS_PER_CALLER` streams open at once.
+
+use std::{
+    collections::{HashMap, VecDeque},
+    convert::Infallible,
+    sync::{Mutex, MutexGuard, OnceLock},
+    time::Duration,
+};
+
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::sse::{Event, KeepAlive, Sse},
+};
+use anyhow::{Context, anyhow};
+use chrono::{DateTime, Utc};
+use firebase_auth::FirebaseUser;
+use futures_util::{stream, Stream};
+use serde::Serialize;
+use tokio::time::{interval, Interval, MissedTickBehavior};
+
+use igait_lib::microservice::{FirebaseRtdb, StageNumber};
+
+use crate::helper::{
+    lib::{AppError, AppStatePtr, Job, JobStatus, StageStatus, NUM_STAGES},
+    organizations::{JobAccess, ensure_job_access},
+};
+
+/// How often the job is re-read to look for changes.
+const POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+/// How many reads in a row may fail before the stream is ended.
+const MAX_POLL_FAILURES: u32 = 5;
+
+/// How many event streams one caller may have open at once.
+const MAX_STREAMS_PER_CALLER: usize = 5;
+
+/// How long a caller at the limit is asked to wait before opening another.
+const STREAM_LIMIT_RETRY_AFTER: Duration = Duration::from_secs(30);
+
+/// How many event streams each caller has open, in this backend process.
+static OPEN_STREAMS: OnceLock<Mutex<HashMap<String, usize>>> = OnceLock::new();
+
+/// A change to a job, sent to the client as an SSE event.
+///
+/// The SSE event name is the `type` field (e.g. `event: stage_started`),
+/// and the data is this enum as JSON.
+#[derive(Debug, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum JobEvent {
+    /// The job's overall status changed
+    Status {
+        status: JobStatus,
+    },
+    /// A worker started processing a stage
+    StageStarted {
+        stage: u8,
+        name: &'static str,
+        worker_id: Option<String>,
+        started_at: Option<DateTime<Utc>>,
+    },
+    /// A stage finished, successfully or not
+    StageFinished {
+        stage: u8,
+        name: &'static str,
+        status: StageStatus,
+        duration_ms: Option<u64>,
+        error: Option<String>,
+    },
+    /// New log lines were uploaded for a stage
+    Log {
+        stage: u8,
+        lines: Vec<String>,
+    },
+}
+
+impl JobEvent {
+    /// Converts the event into an SSE event named after its type.
+    fn to_sse(&self) -> Event {
+        let name = match self {
+            Self::Status { .. } => "status",
+            Self::StageStarted { .. } => "stage_started",
+            Self::StageFinished { .. } => "stage_finished",
+            Self::Log { .. } => "log",
+        };
+
+        Event::default()
+            .event(name)
+            .json_data(self)
+            .unwrap_or_else(|e| Event::default()
+                .event("error")
+                .data(format!("Failed to serialize event: {e}")))
+    }
+}
+
+/// `GET /api/v1/jobs/:job_id/events`
+///
+/// Streams a job's status transitions, stage starts and finishes, and new
+/// log lines as Server-Sent Events.
+///
+/// The first events describe the job as it currently is, so clients don't
+/// need to fetch it separately. The stream ends once the job reaches
+/// `Complete` or `Error`, or if it's deleted or can't be read.
+///
+/// # Authorization
+/// - The authenticated user must **own** the job (their UID is the prefix
+///   of `job_id`), share an organization with its owner, **or** be an
+///   administrator.
+///
+/// # Fails
+/// * With `429 Too Many Requests` if the caller already has
+///   `MAX_STREAMS_PER_CALLER` streams open.
+pub async fn events_entrypoint(
+    current_user: FirebaseUser,
+    State(app): State<AppStatePtr>,
+    Path(job_id): Path<String>,
+) -> Result<Sse<impl Stream<Item = Result<Event, Infallible>>>, AppError> {
+    let app = app.state;
+    let caller_uid = &current_user.user_id;
+
+    // ── 1. Authorization ────────────────────────────────────────────
+    // job_id format: "{user_id}_{job_index}"
+    let (owner_uid, job_index) = job_id
+        .rsplit_once('_')
+        .ok_or_else(|| anyhow!("Invalid job ID format: {}", job_id))?;
+    let job_index: usize = job_index
+        .parse()
+        .context("Invalid job index in job ID")?;
+
+    ensure_job_access(&app, caller_uid, owner_uid, JobAccess::View).await?;
+
+    // ── 2. Limit how many streams the caller has open ───────────────
+    let slot = StreamSlot::take(caller_uid).ok_or_else(|| AppError::too_many_requests(
+        format!("You can have at most {} event streams open at once.", MAX_STREAMS_PER_CALLER),
+        STREAM_LIMIT_RETRY_AFTER,
+    ))?;
+
+    // ── 3. Make sure the job exists before opening the stream ───────
+    let rtdb = FirebaseRtdb::from_env()
+        .context("Failed to initialise Firebase RTDB client")?;
+    let path = job_path(owner_uid, job_index);
+    rtdb.get::<Job>(&path)
+        .await
+        .context("Failed to fetch the job")?
+        .ok_or_else(|| AppError::client(StatusCode::NOT_FOUND, format!("No job {}.", job_id)))?;
+
+    // ── 4. Stream changes until the job finishes ────────────────────
+    println!("Opening event stream for job {} (requested by {})", job_id, caller_uid);
+    let watcher = JobWatcher::new(rtdb, path, slot);
+    let events = stream::unfold(watcher, |mut watcher| async move {
+        let event = watcher.next_event().await?;
+        Some((Ok(event.to_sse()), watcher))
+    });
+
+    Ok(Sse::new(events).keep_alive(KeepAlive::default()))
+}
+
+/// Returns the Firebase RTDB path of a job.
+fn job_path(user_id: &str, job_index: usize) -> String {
+    format!("users/{}/jobs/{}", user_id, job_index)
+}
+
+/// Locks the count of each caller's open event streams.
+fn open_streams() -> MutexGuard<'static, HashMap<String, usize>> {
+    OPEN_STREAMS
+        .get_or_init(Default::default)
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+/// One of a caller's open event streams, given back when it's dropped
+/// (i.e. when the stream ends or the client disconnects).
+struct StreamSlot {
+    caller_uid: String,
+}
+
+impl StreamSlot {
+    /// Takes one of the caller's event stream slots.
+    ///
+    /// # Returns
+    /// * The slot, or `None` if the caller already has
+    ///   `MAX_STREAMS_PER_CALLER` streams open
+    fn take(caller_uid: &str) -> Option<Self> {
+        let mut open = open_streams();
+        let count = open.entry(caller_uid.to_string()).or_default();
+        if *count >= MAX_STREAMS_PER_CALLER {
+            return None;
+        }
+        *count += 1;
+
+        Some(Self {
+            caller_uid: caller_uid.to_string(),
+        })
+    }
+}
+
+impl Drop for StreamSlot {
+    fn drop(&mut self) {
+        let mut open = open_streams();
+        if let Some(count) = open.get_mut(&self.caller_uid) {
+            *count = count.saturating_sub(1);
+            if *count == 0 {
+                open.remove(&self.caller_uid);
+            }
+        }
+    }
+}
+
+/// Polls a job and turns the differences between reads into events.
+struct JobWatcher {
+    rtdb: FirebaseRtdb,
+    /// The Firebase RTDB path of the job
+    path: String,
+    /// The job as of the last read, or `None` before the first read
+    last: Option<Job>,
+    /// Events found on the last read that haven't been sent yet
+    pending: VecDeque<JobEvent>,
+    /// Whether the job has reached a final status, or can't be followed
+    finished: bool,
+    /// How many reads in a row have failed
+    failures: u32,
+    ticker: Interval,
+    /// The caller's stream slot, held until the stream is dropped
+    _slot: StreamSlot,
+}
+
+impl JobWatcher {
+    fn new(rtdb: FirebaseRtdb, path: String, slot: StreamSlot) -> Self {
+        let mut ticker = interval(POLL_INTERVAL);
+        ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+        Self {
+            rtdb,
+            path,
+            last: None,
+            pending: VecDeque::new(),
+            finished: false,
+            failures: 0,
+            ticker,
+            _slot: slot,
+        }
+    }
+
+    /// Waits for the next event, or returns `None` once the job has
+    /// finished and every event has been sent.
+    async fn next_event(&mut self) -> Option<JobEvent> {
+        loop {
+            if let Some(event) = self.pending.pop_front() {
+                return Some(event);
+            }
+            if self.finished {
+                return None;
+            }
+
+            self.ticker.tick().await;
+
+            let job = match self.rtdb.get::<Job>(&self.path).await {
+                Ok(Some(job)) => job,
+                Ok(None) => {
+                    println!("Job at {} was deleted, ending its event stream", self.path);
+                    self.finished = true;
+                    continue;
+                }
+                Err(e) => {
+                    // Most likely transient - try again on the next tick,
+                    // unless it keeps happening
+                    eprintln!("Failed to poll job at {} for events: {e:?}", self.path);
+                    self.failures += 1;
+                    self.finished = self.failures >= MAX_POLL_FAILURES;
+                    continue;
+                }
+            };
+            self.failures = 0;
+
+            self.pending.extend(diff_jobs(self.last.as_ref(), &job));
+            self.finished = job.status.is_complete() || job.status.is_error();
+            self.last = Some(job);
+        }
+    }
+}
+
+/// Lists the events that turn `previous` into `current`.
+///
+/// If there is no previous read, every stage result and log line
+/// recorded so far is reported, followed by the current status.
+fn diff_jobs(previous: Option<&Job>, current: &Job) -> Vec<JobEvent> {
+    let mut events = Vec::new();
+
+    for n in 1..=NUM_STAGES {
+        let Some(stage) = StageNumber::from_u8(n) else {
+            continue;
+        };
+        let key = format!("stage_{}", n);
+
+        // Stage starts and finishes
+        if let Some(result) = current.stages.get(&key) {
+            let before = previous.and_then(|job| job.stages.get(&key));
+            let changed = before.is_none_or(|before| {
+                before.status != result.status
+                    || before.started_at != result.started_at
+                    || before.completed_at != result.completed_at
+            });
+
+            match result.status {
+                _ if !changed => {}
+                StageStatus::Processing => events.push(JobEvent::StageStarted {
+                    stage: n,
+                    name: stage.name(),
+                    worker_id: result.worker_id.clone(),
+                    started_at: result.started_at,
+                }),
+                StageStatus::Success | StageStatus::Failed | StageStatus::Skipped => {
+                    events.push(JobEvent::StageFinished {
+                        stage: n,
+                        name: stage.name(),
+                        status: result.status,
+                        duration_ms: result.duration_ms,
+                        error: result.error.clone(),
+                    })
+                }
+                StageStatus::Pending => {}
+            }
+        }
+
+        // New log lines - workers re-upload the whole log, so only send
+        // what was appended since the last read
+        if let Some(logs) = current.stage_logs.get(&key) {
+            let before = previous
+                .and_then(|job| job.stage_logs.get(&key))
+                .map(String::as_str)
+                .unwrap_or_default();
+            let appended = logs.strip_prefix(before).unwrap_or(logs);
+
+            let lines: Vec<String> = appended
+                .lines()
+                .filter(|line| !line.trim().is_empty())
+                .map(str::to_string)
+                .collect();
+            if !lines.is_empty() {
+                events.push(JobEvent::Log { stage: n, lines });
+            }
+        }
+    }
+
+    // Status transitions
+    if previous.is_none_or(|job| job.status != current.status) {
+        events.push(JobEvent::Status {
+            status: current.status.clone(),
+        });
+    }
+
+    events
+}
@@ -0,0 +1,197 @@
+//! Research dataset export endpoints.
+//!
+//! Administrators start an export with a filter, which selects the
+//! matching jobs up front and then collects their artifacts in the
+//! background. Its progress can be followed, and once complete, the
+//! archive and data dictionary are handed out as presigned S3 URLs.
+//!
+//! See `crate::helper::exports` for what an export contains and how IDs
+//! are pseudonymised.
+
+use std::{collections::HashMap, time::Duration};
+
+use axum::{extract::{Path, State}, http::StatusCode, Json};
+use anyhow::Context;
+use firebase_auth::FirebaseUser;
+use serde::Serialize;
+use serde_json::json;
+
+use igait_lib::microservice::{FirebaseRtdb, now_ms};
+
+use crate::helper::{
+    audit::{self, AuditAction},
+    exports::{
+        EXPORT_FORMAT_VERSION, Export, ExportFilter, ExportStatus, MAX_EXPORT_JOBS,
+        export_path, exports_path, pseudonym_secret, run_export, select_jobs,
+    },
+    lib::{AppError, AppStatePtr},
+    webhooks::generate_id,
+};
+
+/// How long presigned export URLs stay valid.
+const PRESIGN_EXPIRY: Duration = Duration::from_secs(60 * 60); // 1 hour
+
+/// Response body for the list of exports.
+#[derive(Debug, Serialize)]
+pub struct ExportsResponse {
+    /// Every export, newest first
+    pub exports: Vec<Export>,
+}
+
+/// Response body for a single export.
+#[derive(Debug, Serialize)]
+pub struct ExportStatusResponse {
+    pub export: Export,
+    /// A presigned URL for the archive, once the export is complete
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub archive_url: Option<String>,
+    /// A presigned URL for the data dictionary, once the export is complete
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dictionary_url: Option<String>,
+}
+
+/// `POST /api/v1/admin/exports`
+///
+/// Starts an export of the jobs matching the filter in the body.
+/// **Admin-only** — the caller must have `administrator: true`.
+///
+/// # Fails
+/// * With a `400` if the date range is reversed
+/// * With a `422` if no jobs match, or more than `MAX_EXPORT_JOBS` do
+/// * With a `503` if `EXPORT_PSEUDONYM_SECRET` isn't configured
+pub async fn create_export_entrypoint(
+    current_user: FirebaseUser,
+    State(app): State<AppStatePtr>,
+    Json(filter): Json<ExportFilter>,
+) -> Result<Json<Export>, AppError> {
+    let caller_uid = current_user.user_id.clone();
+    let parameters = json!({ "filter": filter });
+
+    let result = async {
+        app.state.ensure_administrator(&caller_uid).await?;
+
+        // ── 1. Validate the request ─────────────────────────────────
+        let Some(secret) = pseudonym_secret() else {
+            return Err(AppError::client(
+                StatusCode::SERVICE_UNAVAILABLE,
+                "Exports are disabled because no pseudonymisation key is configured.",
+            ));
+        };
+        if let (Some(from), Some(to)) = (filter.from, filter.to) {
+            if from > to {
+                return Err(AppError::client(
+                    StatusCode::BAD_REQUEST,
+                    "'from' must not be after 'to'.",
+                ));
+            }
+        }
+
+        // ── 2. Select the jobs ──────────────────────────────────────
+        let jobs = select_jobs(&app.state, &filter).await?;
+        if jobs.is_empty() {
+            return Err(AppError::client(
+                StatusCode::UNPROCESSABLE_ENTITY,
+                "No jobs match the filter.",
+            ));
+        }
+        if jobs.len() > MAX_EXPORT_JOBS {
+            return Err(AppError::client(
+                StatusCode::UNPROCESSABLE_ENTITY,
+                format!(
+                    "{} jobs match the filter, but an export can contain at most {}. Please narrow it.",
+                    jobs.len(), MAX_EXPORT_JOBS
+                ),
+            ));
+        }
+
+        // ── 3. Record the export and start it ───────────────────────
+        let export = Export {
+            id: generate_id(),
+            requested_by: caller_uid.clone(),
+            filter: filter.clone(),
+            format_version: EXPORT_FORMAT_VERSION,
+            status: ExportStatus::Running,
+            job_count: jobs.len(),
+            created_at: now_ms(),
+            finished_at: None,
+            archive_key: None,
+            dictionary_key: None,
+            error: None,
+        };
+        FirebaseRtdb::from_env()
+            .context("Failed to initialise Firebase RTDB client")?
+            .set(&export_path(&export.id), &export)
+            .await
+            .context("Failed to record the export")?;
+
+        println!("Export {} of {} job(s) started by {}", export.id, export.job_count, caller_uid);
+        tokio::spawn(run_export(app.state.clone(), export.clone(), jobs, secret));
+
+        Ok(export)
+    }.await;
+    audit::record(&caller_uid, AuditAction::DataExported, None, parameters, &result).await;
+
+    result.map(Json)
+}
+
+/// `GET /api/v1/admin/exports`
+///
+/// Lists every export.
+/// **Admin-only** — the caller must have `administrator: true`.
+pub async fn list_exports_entrypoint(
+    current_user: FirebaseUser,
+    State(app): State<AppStatePtr>,
+) -> Result<Json<ExportsResponse>, AppError> {
+    app.state.ensure_administrator(&current_user.user_id).await?;
+
+    let exports: Option<HashMap<String, Export>> = FirebaseRtdb::from_env()
+        .context("Failed to initialise Firebase RTDB client")?
+        .get(exports_path())
+        .await
+        .context("Failed to read the exports")?;
+
+    let mut exports: Vec<Export> = exports.unwrap_or_default().into_values().collect();
+    exports.sort_by(|a, b| b.created_at.cmp(&a.created_at).then_with(|| b.id.cmp(&a.id)));
+
+    Ok(Json(ExportsResponse { exports }))
+}
+
+/// `GET /api/v1/admin/exports/:export_id`
+///
+/// Returns an export, with download links once it's complete.
+/// **Admin-only** — the caller must have `administrator: true`.
+///
+/// # Fails
+/// * With a `404` if there's no such export
+pub async fn export_status_entrypoint(
+    current_user: FirebaseUser,
+    State(app): State<AppStatePtr>,
+    Path(export_id): Path<String>,
+) -> Result<Json<ExportStatusResponse>, AppError> {
+    app.state.ensure_administrator(&current_user.user_id).await?;
+
+    let export: Option<Export> = FirebaseRtdb::from_env()
+        .context("Failed to initialise Firebase RTDB client")?
+        .get(&export_path(&export_id))
+        .await
+        .context("Failed to read the export")?;
+    let Some(export) = export else {
+        return Err(AppError::client(
+            StatusCode::NOT_FOUND,
+            format!("No export with ID '{}'.", export_id),
+        ));
+    };
+
+    let mut urls = [None, None];
+    for (url, key) in urls.iter_mut().zip([&export.archive_key, &export.dictionary_key]) {
+        if let Some(key) = key {
+            *url = Some(app.state.storage
+                .presign_download(key, PRESIGN_EXPIRY)
+                .await
+                .context(format!("Failed to presign {}", key))?);
+        }
+    }
+    let [archive_url, dictionary_url] = urls;
+
+    Ok(Json(ExportStatusResponse { export, archive_url, dictionary_url }))
+}
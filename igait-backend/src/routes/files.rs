@@ -1,7 +1,8 @@
 //! Files endpoint for generating presigned S3 URLs.
 //!
 //! Returns presigned download URLs for all files belonging to a job,
-//! grouped by stage. The caller must own the job **or** be an admin.
+//! grouped by stage. The caller must own the job, share an organization
+//! with its owner, **or** be an admin.
 
 use std::collections::HashMap;
 use std::time::Duration;
@@ -10,10 +11,15 @@ use axum::{extract::{Path, State}, Json};
 use anyhow::{Context, anyhow};
 use firebase_auth::FirebaseUser;
 use serde::Serialize;
+use serde_json::json;
 
 use igait_lib::microservice::StoragePaths;
 
-use crate::helper::lib::{AppError, AppStatePtr};
+use crate::helper::{
+    audit::{self, AuditAction},
+    lib::{AppError, AppStatePtr},
+    organizations::{JobAccess, ensure_job_access},
+};
 
 /// How long presigned URLs stay valid.
 const PRESIGN_EXPIRY: Duration = Duration::from_secs(15 * 60); // 15 minutes
@@ -21,7 +27,7 @@ const PRESIGN_EXPIRY: Duration = Duration::from_secs(15 * 60); // 15 minutes
 /// A single file entry returned to the frontend.
 #[derive(Debug, Serialize)]
 pub struct FileEntry {
-    /// The filename (e.g. "front.mp4", "results.zip")
+    /// The filename (e.g. "front.mp4", "report.pdf", "results.zip")
     pub name: String,
     /// The presigned download URL
     pub url: String,
@@ -40,11 +46,33 @@ pub struct JobFilesResponse {
 ///
 /// # Authorization
 /// - The authenticated user must **own** the job (their UID is the prefix
-///   of `job_id`) **or** be an administrator.
+///   of `job_id`), share an organization with its owner, **or** be an
+///   administrator.
 pub async fn files_entrypoint(
     current_user: FirebaseUser,
     State(app): State<AppStatePtr>,
     Path(job_id): Path<String>,
+) -> Result<Json<JobFilesResponse>, AppError> {
+    let caller_uid = current_user.user_id.clone();
+
+    let result = list_job_files(current_user, app, &job_id).await;
+
+    // Only access to someone else's job is privileged
+    let is_owner = job_id
+        .rsplit_once('_')
+        .is_some_and(|(owner_uid, _)| owner_uid == caller_uid);
+    if !is_owner {
+        audit::record(&caller_uid, AuditAction::JobFilesAccessed, Some(&job_id), json!({}), &result).await;
+    }
+
+    result
+}
+
+/// Presigns every file belonging to a job (see `files_entrypoint`).
+async fn list_job_files(
+    current_user: FirebaseUser,
+    app: AppStatePtr,
+    job_id: &str,
 ) -> Result<Json<JobFilesResponse>, AppError> {
     let app = &app.state;
     let caller_uid = &current_user.user_id;
@@ -56,25 +84,10 @@ pub async fn files_entrypoint(
         .map(|(uid, _)| uid)
         .ok_or_else(|| anyhow!("Invalid job ID format: {}", job_id))?;
 
-    if caller_uid != owner_uid {
-        // Check if caller is admin
-        let caller = app
-            .db
-            .lock()
-            .await
-            .get_user(caller_uid)
-            .await
-            .context("Failed to look up caller")?;
-
-        if !caller.administrator {
-            return Err(AppError(anyhow!(
-                "Forbidden: you do not own this job."
-            )));
-        }
-    }
+    ensure_job_access(app, caller_uid, owner_uid, JobAccess::View).await?;
 
     // ── 2. List all objects for this job ─────────────────────────────
-    let prefix = StoragePaths::job_base(&job_id);
+    let prefix = StoragePaths::job_base(job_id);
     let files = app
         .storage
         .list_and_presign(&prefix, PRESIGN_EXPIRY)
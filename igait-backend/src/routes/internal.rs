@@ -1,7 +1,9 @@
 //! Internal endpoint for microservice status updates
 //!
 //! This endpoint is NOT exposed publicly and should only be called by
-//! the stage microservices to update job status.
+//! the stage microservices to update job status. It's served on its own
+//! listener (`INTERNAL_PORT`), and every request must be signed with the
+//! shared `INTERNAL_API_SECRET` (see `crate::helper::internal_auth`).
 
 use axum::{
     extract::State,
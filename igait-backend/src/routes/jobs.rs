@@ -0,0 +1,317 @@
+//! Job listing endpoints with pagination, filtering and sorting.
+//!
+//! `GET /api/v1/jobs` lists the caller's own jobs, or the jobs of every
+//! member of one of their organizations, and
+//! `GET /api/v1/admin/jobs` lists jobs across every user.
+//! Both accept the same query parameters (see `JobListQuery`).
+
+use std::time::{Duration, UNIX_EPOCH};
+
+use axum::{extract::{Query, State}, http::StatusCode, Json};
+use anyhow::{Result, anyhow, Context};
+use firebase_auth::FirebaseUser;
+use serde::{Deserialize, Serialize};
+
+use igait_lib::microservice::FirebaseRtdb;
+
+use crate::helper::{
+    lib::{AppError, AppStatePtr, Job, JobStatus},
+    organizations::read_organization,
+};
+
+/// The page size used when `per_page` isn't given.
+const DEFAULT_PER_PAGE: usize = 20;
+
+/// The largest page size a caller may request.
+const MAX_PER_PAGE: usize = 100;
+
+/// The classification of a completed job.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Classification {
+    /// ASD indicators were detected
+    Asd,
+    /// No ASD indicators were detected
+    NoAsd,
+}
+
+/// The field to sort jobs by.
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum JobSortField {
+    /// When the job was submitted
+    #[default]
+    Timestamp,
+    /// The job's status code
+    Status,
+    /// The patient's age
+    Age,
+}
+
+/// The direction to sort jobs in.
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SortOrder {
+    Asc,
+    #[default]
+    Desc,
+}
+
+/// Query parameters accepted by the job listing endpoints.
+///
+/// Every filter is optional; jobs must match all of the filters given.
+#[derive(Debug, Deserialize)]
+pub struct JobListQuery {
+    /// The page to return, starting at 1
+    pub page: Option<usize>,
+    /// How many jobs to return per page (1-100, default 20)
+    pub per_page: Option<usize>,
+    /// Only include jobs with this status code
+    /// (`Submitted`, `Processing`, `Complete` or `Error`, case-insensitive)
+    pub status: Option<String>,
+    /// Only include jobs submitted at or after this time (Unix timestamp in seconds)
+    pub from: Option<u64>,
+    /// Only include jobs submitted at or before this time (Unix timestamp in seconds)
+    pub to: Option<u64>,
+    /// Only include completed jobs with this classification
+    pub classification: Option<Classification>,
+    /// Only include jobs whose `requires_approval` flag matches
+    pub requires_approval: Option<bool>,
+    /// The field to sort by (default `timestamp`)
+    #[serde(default)]
+    pub sort: JobSortField,
+    /// The sort direction (default `desc`)
+    #[serde(default)]
+    pub order: SortOrder,
+    /// Only include jobs owned by this user. **Admin listing only.**
+    pub user_id: Option<String>,
+    /// Only include jobs owned by members of this organization.
+    /// On `/api/v1/jobs`, the caller must be a member.
+    pub organization_id: Option<String>,
+}
+
+/// A job in a listing, along with where it lives.
+#[derive(Debug, Serialize)]
+pub struct JobListEntry {
+    /// The job ID (format: "{user_id}_{job_index}")
+    pub job_id: String,
+    /// The UID of the user who owns the job
+    pub user_id: String,
+    /// The index of the job in the user's job list
+    pub job_index: usize,
+    /// The job itself
+    pub job: Job,
+}
+
+impl JobListEntry {
+    fn new(user_id: &str, job_index: usize, job: Job) -> Self {
+        Self {
+            job_id: format!("{}_{}", user_id, job_index),
+            user_id: user_id.to_string(),
+            job_index,
+            job,
+        }
+    }
+}
+
+/// Response body: a single page of jobs.
+#[derive(Debug, Serialize)]
+pub struct JobListResponse {
+    /// The jobs on this page
+    pub jobs: Vec<JobListEntry>,
+    /// The page number, starting at 1
+    pub page: usize,
+    /// The page size
+    pub per_page: usize,
+    /// How many jobs matched the filters, across every page
+    pub total: usize,
+    /// How many pages of results there are
+    pub total_pages: usize,
+}
+
+/// `GET /api/v1/jobs`
+///
+/// Lists the caller's own jobs, one page at a time. With `organization_id`,
+/// lists the jobs of every member of that organization instead.
+///
+/// # Query Parameters
+/// See `JobListQuery`. `user_id` is not accepted here.
+pub async fn list_jobs_entrypoint(
+    current_user: FirebaseUser,
+    State(app): State<AppStatePtr>,
+    Query(query): Query<JobListQuery>,
+) -> Result<Json<JobListResponse>, AppError> {
+    let app = app.state;
+    let caller_uid = &current_user.user_id;
+
+    // ── 1. Validate the request ─────────────────────────────────────
+    if query.user_id.is_some() {
+        return Err(AppError(anyhow!(
+            "The `user_id` filter is only available on /api/v1/admin/jobs."
+        )));
+    }
+
+    // ── 2. Work out whose jobs to list ──────────────────────────────
+    let owners = match &query.organization_id {
+        Some(org_id) => {
+            let rtdb = FirebaseRtdb::from_env()
+                .context("Failed to initialise Firebase RTDB client")?;
+            let organization = read_organization(&rtdb, org_id).await?;
+
+            if organization.role_of(caller_uid).is_none() {
+                return Err(AppError::client(
+                    StatusCode::FORBIDDEN,
+                    "Forbidden: you are not a member of this organization.",
+                ));
+            }
+            organization.active_members()
+        }
+        None => vec![caller_uid.clone()],
+    };
+
+    // ── 3. Fetch their jobs ─────────────────────────────────────────
+    let mut entries = Vec::new();
+    {
+        let db = app.db.lock().await;
+        for owner_uid in owners {
+            let jobs = db
+                .get_all_jobs(&owner_uid)
+                .await
+                .context("Failed to fetch the jobs!")?;
+
+            entries.extend(jobs
+                .into_iter()
+                .enumerate()
+                .map(|(job_index, job)| JobListEntry::new(&owner_uid, job_index, job)));
+        }
+    }
+
+    // ── 4. Filter, sort and paginate ────────────────────────────────
+    Ok(Json(paginate(entries, &query)?))
+}
+
+/// `GET /api/v1/admin/jobs`
+///
+/// Lists jobs across every user, one page at a time.
+/// **Admin-only** — the caller must have `administrator: true`.
+///
+/// # Query Parameters
+/// See `JobListQuery`. Pass `user_id` to only list one user's jobs, or
+/// `organization_id` to only list the jobs of an organization's members.
+pub async fn admin_list_jobs_entrypoint(
+    current_user: FirebaseUser,
+    State(app): State<AppStatePtr>,
+    Query(query): Query<JobListQuery>,
+) -> Result<Json<JobListResponse>, AppError> {
+    let app = app.state;
+
+    // ── 0. Verify the caller is an administrator ────────────────────
+    app.ensure_administrator(&current_user.user_id).await?;
+
+    // ── 1. Fetch the jobs ───────────────────────────────────────────
+    let mut entries: Vec<JobListEntry> = {
+        let db = app.db.lock().await;
+
+        match &query.user_id {
+            Some(user_id) => db
+                .get_all_jobs(user_id)
+                .await
+                .context("Failed to fetch the user's jobs!")?
+                .into_iter()
+                .enumerate()
+                .map(|(job_index, job)| JobListEntry::new(user_id, job_index, job))
+                .collect(),
+            None => db
+                .get_all_users()
+                .await
+                .context("Failed to fetch users!")?
+                .into_iter()
+                .flat_map(|(user_id, user)| {
+                    user.jobs
+                        .into_iter()
+                        .enumerate()
+                        .map(move |(job_index, job)| JobListEntry::new(&user_id, job_index, job))
+                })
+                .collect(),
+        }
+    };
+
+    // ── 2. Narrow down to an organization's members ─────────────────
+    if let Some(org_id) = &query.organization_id {
+        let rtdb = FirebaseRtdb::from_env()
+            .context("Failed to initialise Firebase RTDB client")?;
+        let organization = read_organization(&rtdb, org_id).await?;
+
+        let members = organization.active_members();
+        entries.retain(|entry| members.contains(&entry.user_id));
+    }
+
+    // ── 3. Filter, sort and paginate ────────────────────────────────
+    Ok(Json(paginate(entries, &query)?))
+}
+
+/// Applies the query's filters and sort order, then cuts out the requested page.
+fn paginate(mut entries: Vec<JobListEntry>, query: &JobListQuery) -> Result<JobListResponse> {
+    let page = query.page.unwrap_or(1);
+    let per_page = query.per_page.unwrap_or(DEFAULT_PER_PAGE);
+    if page == 0 {
+        return Err(anyhow!("Invalid page 0. Pages start at 1."));
+    }
+    if !(1..=MAX_PER_PAGE).contains(&per_page) {
+        return Err(anyhow!("Invalid per_page {}. Must be between 1 and {}.", per_page, MAX_PER_PAGE));
+    }
+
+    // Filter
+    let from = query.from.map(|secs| UNIX_EPOCH + Duration::from_secs(secs));
+    let to = query.to.map(|secs| UNIX_EPOCH + Duration::from_secs(secs));
+    entries.retain(|entry| {
+        let job = &entry.job;
+
+        query.status.as_ref().is_none_or(|code| job.status.code().eq_ignore_ascii_case(code))
+            && from.is_none_or(|from| job.timestamp >= from)
+            && to.is_none_or(|to| job.timestamp <= to)
+            && query.requires_approval.is_none_or(|flag| job.requires_approval == flag)
+            && query.classification.is_none_or(|classification| {
+                classify(&job.status) == Some(classification)
+            })
+    });
+
+    // Sort, breaking ties by job ID so pages are stable
+    entries.sort_by(|a, b| {
+        let ordering = match query.sort {
+            JobSortField::Timestamp => a.job.timestamp.cmp(&b.job.timestamp),
+            JobSortField::Status => a.job.status.code().cmp(b.job.status.code()),
+            JobSortField::Age => a.job.age.cmp(&b.job.age),
+        }.then_with(|| a.job_id.cmp(&b.job_id));
+
+        match query.order {
+            SortOrder::Asc => ordering,
+            SortOrder::Desc => ordering.reverse(),
+        }
+    });
+
+    // Paginate
+    let total = entries.len();
+    let jobs = entries
+        .into_iter()
+        .skip((page - 1).saturating_mul(per_page))
+        .take(per_page)
+        .collect();
+
+    Ok(JobListResponse {
+        jobs,
+        page,
+        per_page,
+        total,
+        total_pages: total.div_ceil(per_page),
+    })
+}
+
+/// Gets the classification of a job, if it has completed.
+fn classify(status: &JobStatus) -> Option<Classification> {
+    match status {
+        JobStatus::Complete { asd: true, .. } => Some(Classification::Asd),
+        JobStatus::Complete { asd: false, .. } => Some(Classification::NoAsd),
+        _ => None,
+    }
+}
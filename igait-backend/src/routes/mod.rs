@@ -54,11 +54,13 @@ pub mod assistant;
 /// * `fileuploadfront`: The front video file. Needs to be a file compatible with OpenPose.
 /// * `fileuploadside`: The side video file. Needs to be a file compatible with OpenPose.
 /// * `name`: The name of the user submitting the video.
+/// * `consent_version`: The version of the research consent form the user agreed to.
 /// 
 /// # Example cURL Request
 /// ```sh
 /// curl -v -F fileuploadfront=@test.mp4 -F fileuploadside=test.mp4 /
 ///     -F uid=curlplaceholder -F email=me@hiibolt.com -F name="John Doe" /
+///     -F consent_version=1.0 /
 ///     http://api.igaitapp.com/api/v1/contribute
 /// ```
 /// 
@@ -74,14 +76,134 @@ pub mod contribute;
 /// Cleans up S3 outputs from the target stage onward, then re-queues the job.
 pub mod rerun;
 
+/// Approval endpoints for jobs gated behind manual review.
+///
+/// Lets administrators list the jobs workers are skipping because they
+/// haven't been approved, and approve or reject them with a reviewer note.
+/// Rejected jobs are removed from the pipeline and the submitter is emailed.
+pub mod approval;
+
+/// Queue configuration endpoints for operating the pipeline.
+///
+/// Lets administrators read and replace each stage's queue config, and
+/// pause or resume a stage so it drains before maintenance. Each stage
+/// reports how many jobs are still in flight.
+pub mod queue_config;
+
+/// Pipeline overview endpoint for administrators.
+///
+/// Summarises every stage queue: depth, oldest job, live claims and the
+/// workers holding them, stale claims past the claim timeout, and jobs
+/// waiting on approval.
+pub mod pipeline;
+
 /// Files endpoint for generating presigned S3 download URLs.
 ///
 /// Returns all files for a job grouped by stage, with time-limited
 /// presigned URLs so the frontend can display/download them securely.
 pub mod files;
 
+/// Job listing endpoints with pagination, filtering and sorting.
+///
+/// Lists the caller's own jobs, or every user's jobs for administrators,
+/// filtered by status, submission date, classification and approval.
+pub mod jobs;
+
+/// Timeline endpoint for per-stage processing history.
+///
+/// Returns the timing result each worker recorded for a job (status,
+/// timestamps, duration, worker ID and outputs), ordered by stage.
+pub mod timeline;
+
+/// Live event stream for following a job through the pipeline.
+///
+/// Pushes status transitions, stage starts and finishes, and new log
+/// lines for a job as Server-Sent Events until the job finishes.
+pub mod events;
+
+/// Webhook registration endpoints.
+///
+/// Lets users register HTTPS URLs to receive signed notifications when
+/// their jobs complete, fail, or are approved or rejected, and inspect
+/// each webhook's delivery log.
+pub mod webhooks;
+
+/// Resumable, chunked video uploads.
+///
+/// Lets clients upload videos in fixed-size chunks backed by S3
+/// multipart uploads, resume after a dropped connection, and submit
+/// the job once both videos are complete.
+pub mod resumable_upload;
+
+/// Direct-to-S3 video uploads.
+///
+/// Creates a job and hands back presigned upload URLs, then submits the
+/// job once the client confirms both videos are in AWS S3 with their
+/// declared sizes.
+pub mod direct_upload;
+
+/// Pre-submission check of a single short clip.
+///
+/// Probes the clip and runs a shortened validity check on a Stage 2
+/// worker while the user waits, returning actionable feedback before
+/// a real job is submitted.
+pub mod precheck;
+
+/// Submission quota endpoints.
+///
+/// Lets users check their daily and in-flight job usage, and lets
+/// administrators set per-role limits and per-user overrides or exemptions.
+pub mod quotas;
+
+/// Organization management endpoints.
+///
+/// Lets users create organizations, owners invite members and manage
+/// their roles (owner, clinician or viewer), and invitees accept. Roles
+/// decide who can see and manage the jobs submitted by other members.
+pub mod organizations;
+
+/// Audit log query endpoint for administrators.
+///
+/// Searches the audit trail of privileged actions (reruns, approvals,
+/// access to other users' files, queue config and quota changes) by
+/// actor, job, action and date range.
+pub mod audit;
+
+/// Batch submission and tracking endpoints for research cohorts.
+///
+/// Takes a CSV or JSON manifest of participants and their videos in one
+/// request, validates every row up front, and creates one job per valid
+/// participant under a shared batch ID, reporting the outcome of each row.
+/// Batches can then be followed by status counts and their results
+/// downloaded as a CSV.
+pub mod batches;
+
+/// Research contribution endpoints.
+///
+/// Lets contributors list their contributions and the consent form
+/// version each was given under, and withdraw them, which deletes the
+/// videos and marks the consent record withdrawn.
+pub mod contributions;
+
+/// Research dataset export endpoints for administrators.
+///
+/// Starts background exports of the landmarks, gait analysis, predictions
+/// and demographics of the jobs matching a filter (date range, batch or
+/// consenting users only), with user IDs and emails replaced by stable
+/// pseudonymous IDs, and hands out download links once they're written.
+pub mod exports;
+
+/// Email template override endpoints for administrators.
+///
+/// Lists the subject, HTML and plain-text templates of every email in each
+/// locale, and lets their overrides in storage be replaced or removed. Overrides are
+/// checked against the template's variables before they're saved.
+pub mod email_templates;
+
 /// Internal endpoints for microservice communication
 /// 
 /// These endpoints are NOT exposed publicly and should only be called
-/// by internal microservices.
+/// by internal microservices. They're served on a separate port from the
+/// public API, and requests must carry an HMAC signature made with the
+/// shared `INTERNAL_API_SECRET`.
 pub mod internal;
\ No newline at end of file
@@ -0,0 +1,448 @@
+//! Organization management endpoints.
+//!
+//! Any user can create an organization, becoming its first owner. Owners
+//! invite users by UID with a membership role, change roles and remove
+//! members; invitees become members by accepting, and any member (or
+//! invitee) can leave. An organization always keeps at least one owner.
+//! Administrators can manage every organization.
+//!
+//! See `crate::helper::organizations` for what each role can do with the
+//! organization's jobs.
+
+use std::collections::HashMap;
+
+use axum::{extract::{Path, State}, http::StatusCode, Json};
+use anyhow::Context;
+use firebase_auth::FirebaseUser;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use igait_lib::microservice::{FirebaseRtdb, now_ms};
+
+use crate::helper::{
+    audit::{self, AuditAction},
+    lib::{AppError, AppState, AppStatePtr},
+    organizations::{
+        OrgMember, OrgRole, Organization, member_path, memberships, organization_path,
+        read_organization, user_invitation_path, user_invitations_path, user_membership_path,
+    },
+    webhooks::generate_id,
+};
+
+/// The longest organization name accepted.
+const MAX_NAME_LENGTH: usize = 100;
+
+/// Request body for creating an organization.
+#[derive(Debug, Deserialize)]
+pub struct CreateOrganizationRequest {
+    /// The organization's display name
+    pub name: String,
+}
+
+/// Request body for inviting a member or changing their role.
+#[derive(Debug, Deserialize)]
+pub struct SetMemberRequest {
+    /// The member's new role
+    pub role: OrgRole,
+}
+
+/// An invitation to join an organization.
+#[derive(Debug, Serialize)]
+pub struct InvitationSummary {
+    /// The organization's ID
+    pub id: String,
+    /// The organization's display name
+    pub name: String,
+    /// The role the caller would have
+    pub role: OrgRole,
+    /// The UID of the user who invited the caller
+    pub invited_by: String,
+    /// When the caller was invited (Unix timestamp ms)
+    pub invited_at: u64,
+}
+
+/// Response body for the caller's pending invitations.
+#[derive(Debug, Serialize)]
+pub struct InvitationsResponse {
+    /// Every organization the caller has been invited to
+    pub invitations: Vec<InvitationSummary>,
+}
+
+/// An organization the caller belongs to.
+#[derive(Debug, Serialize)]
+pub struct OrganizationSummary {
+    /// The organization's ID
+    pub id: String,
+    /// The organization's display name
+    pub name: String,
+    /// The caller's role in the organization
+    pub role: OrgRole,
+    /// How many members have accepted
+    pub member_count: usize,
+}
+
+/// Response body for the caller's organizations.
+#[derive(Debug, Serialize)]
+pub struct OrganizationsResponse {
+    /// Every organization the caller belongs to
+    pub organizations: Vec<OrganizationSummary>,
+}
+
+/// `GET /api/v1/organizations`
+///
+/// Lists the organizations the caller belongs to.
+pub async fn list_organizations_entrypoint(
+    current_user: FirebaseUser,
+) -> Result<Json<OrganizationsResponse>, AppError> {
+    let caller_uid = &current_user.user_id;
+
+    let rtdb = FirebaseRtdb::from_env()
+        .context("Failed to initialise Firebase RTDB client")?;
+
+    let mut organizations = Vec::new();
+    for org_id in memberships(&rtdb, caller_uid).await?.into_keys() {
+        let organization: Option<Organization> = rtdb.get(&organization_path(&org_id))
+            .await
+            .context("Failed to read the organization")?;
+
+        // Skip organizations that have since been removed, or that the
+        // caller was never accepted into
+        if let Some(organization) = organization {
+            if let Some(role) = organization.role_of(caller_uid) {
+                organizations.push(OrganizationSummary {
+                    member_count: organization.active_members().len(),
+                    id: organization.id,
+                    name: organization.name,
+                    role,
+                });
+            }
+        }
+    }
+    organizations.sort_by(|a, b| a.name.cmp(&b.name).then_with(|| a.id.cmp(&b.id)));
+
+    Ok(Json(OrganizationsResponse { organizations }))
+}
+
+/// `POST /api/v1/organizations`
+///
+/// Creates an organization with the caller as its owner.
+pub async fn create_organization_entrypoint(
+    current_user: FirebaseUser,
+    Json(request): Json<CreateOrganizationRequest>,
+) -> Result<Json<Organization>, AppError> {
+    let caller_uid = current_user.user_id.clone();
+    let parameters = json!({ "name": request.name });
+
+    let result = async {
+        // ── 1. Validate the request ─────────────────────────────────
+        let name = request.name.trim();
+        if name.is_empty() || name.chars().count() > MAX_NAME_LENGTH {
+            return Err(AppError::client(
+                StatusCode::BAD_REQUEST,
+                format!("Organization names must be between 1 and {} characters.", MAX_NAME_LENGTH),
+            ));
+        }
+
+        // ── 2. Create the organization ──────────────────────────────
+        let rtdb = FirebaseRtdb::from_env()
+            .context("Failed to initialise Firebase RTDB client")?;
+
+        let now = now_ms();
+        let organization = Organization {
+            id: generate_id(),
+            name: name.to_string(),
+            created_by: caller_uid.clone(),
+            created_at: now,
+            members: [(caller_uid.clone(), OrgMember {
+                role: OrgRole::Owner,
+                added_by: caller_uid.clone(),
+                added_at: now,
+                accepted_at: Some(now),
+            })].into(),
+        };
+
+        rtdb.set(&organization_path(&organization.id), &organization)
+            .await
+            .context("Failed to store the organization")?;
+        rtdb.set(&user_membership_path(&caller_uid, &organization.id), &OrgRole::Owner)
+            .await
+            .context("Failed to record the membership")?;
+
+        println!("User {} created organization {} ({})", caller_uid, organization.id, organization.name);
+        Ok(organization)
+    }.await;
+    audit::record(&caller_uid, AuditAction::OrgCreated, None, parameters, &result).await;
+
+    result.map(Json)
+}
+
+/// `GET /api/v1/organizations/:org_id`
+///
+/// Returns an organization and its members.
+/// Only its members, and administrators, can see it.
+pub async fn get_organization_entrypoint(
+    current_user: FirebaseUser,
+    State(app): State<AppStatePtr>,
+    Path(org_id): Path<String>,
+) -> Result<Json<Organization>, AppError> {
+    let app = app.state;
+    let caller_uid = &current_user.user_id;
+
+    let rtdb = FirebaseRtdb::from_env()
+        .context("Failed to initialise Firebase RTDB client")?;
+    let organization = read_organization(&rtdb, &org_id).await?;
+
+    if organization.role_of(caller_uid).is_none() && !is_administrator(&app, caller_uid).await? {
+        return Err(AppError::client(
+            StatusCode::FORBIDDEN,
+            "Forbidden: you are not a member of this organization.",
+        ));
+    }
+
+    Ok(Json(organization))
+}
+
+/// `PUT /api/v1/organizations/:org_id/members/:uid`
+///
+/// Invites a user to an organization, or changes a member's role. An
+/// invitee only becomes a member, and sees or shares any jobs, once they
+/// accept the invitation.
+/// Only owners, and administrators, can manage members.
+///
+/// # Fails
+/// * With a `409` if it would leave the organization without an owner
+pub async fn set_member_entrypoint(
+    current_user: FirebaseUser,
+    State(app): State<AppStatePtr>,
+    Path((org_id, member_uid)): Path<(String, String)>,
+    Json(request): Json<SetMemberRequest>,
+) -> Result<Json<Organization>, AppError> {
+    let app = app.state;
+    let caller_uid = &current_user.user_id;
+    let parameters = json!({ "org_id": org_id, "member_uid": member_uid, "role": request.role });
+
+    let result = async {
+        let rtdb = FirebaseRtdb::from_env()
+            .context("Failed to initialise Firebase RTDB client")?;
+
+        // ── 0. Verify the caller is an owner ────────────────────────
+        let mut organization = read_organization(&rtdb, &org_id).await?;
+        ensure_owner(&app, &organization, caller_uid).await?;
+
+        // ── 1. Keep at least one owner ──────────────────────────────
+        if organization.role_of(&member_uid) == Some(OrgRole::Owner)
+            && request.role != OrgRole::Owner
+            && organization.owner_count() == 1
+        {
+            return Err(AppError::client(
+                StatusCode::CONFLICT,
+                "An organization must keep at least one owner. Make someone else an owner first.",
+            ));
+        }
+
+        // ── 2. Write the membership, or the invitation ──────────────
+        let member = match organization.members.get(&member_uid) {
+            Some(existing) => OrgMember { role: request.role, ..existing.clone() },
+            None => OrgMember {
+                role: request.role,
+                added_by: caller_uid.clone(),
+                added_at: now_ms(),
+                accepted_at: None,
+            },
+        };
+
+        rtdb.set(&member_path(&org_id, &member_uid), &member)
+            .await
+            .context("Failed to store the member")?;
+        let index_path = match organization.is_active_member(&member_uid) {
+            true => user_membership_path(&member_uid, &org_id),
+            false => user_invitation_path(&member_uid, &org_id),
+        };
+        rtdb.set(&index_path, &request.role)
+            .await
+            .context("Failed to record the membership")?;
+
+        println!("User {} set {} as {:?} of organization {}", caller_uid, member_uid, request.role, org_id);
+        organization.members.insert(member_uid, member);
+        Ok(organization)
+    }.await;
+    audit::record(caller_uid, AuditAction::OrgMemberSet, None, parameters, &result).await;
+
+    result.map(Json)
+}
+
+/// `GET /api/v1/organizations/invitations`
+///
+/// Lists the organizations the caller has been invited to, but hasn't
+/// joined yet.
+pub async fn list_invitations_entrypoint(
+    current_user: FirebaseUser,
+) -> Result<Json<InvitationsResponse>, AppError> {
+    let caller_uid = &current_user.user_id;
+
+    let rtdb = FirebaseRtdb::from_env()
+        .context("Failed to initialise Firebase RTDB client")?;
+    let invited: Option<HashMap<String, OrgRole>> = rtdb.get(&user_invitations_path(caller_uid))
+        .await
+        .context("Failed to read the user's invitations")?;
+
+    let mut invitations = Vec::new();
+    for org_id in invited.unwrap_or_default().into_keys() {
+        let organization: Option<Organization> = rtdb.get(&organization_path(&org_id))
+            .await
+            .context("Failed to read the organization")?;
+
+        // Skip invitations that were withdrawn, or already accepted
+        let Some(organization) = organization else { continue };
+        let Some(member) = organization.members.get(caller_uid) else { continue };
+        if organization.is_active_member(caller_uid) {
+            continue;
+        }
+
+        invitations.push(InvitationSummary {
+            role: member.role,
+            invited_by: member.added_by.clone(),
+            invited_at: member.added_at,
+            id: organization.id,
+            name: organization.name,
+        });
+    }
+    invitations.sort_by(|a, b| a.name.cmp(&b.name).then_with(|| a.id.cmp(&b.id)));
+
+    Ok(Json(InvitationsResponse { invitations }))
+}
+
+/// `POST /api/v1/organizations/:org_id/accept`
+///
+/// Accepts the caller's invitation to an organization, making them a
+/// member with the role they were invited with.
+///
+/// # Fails
+/// * With a `404` if the caller hasn't been invited
+pub async fn accept_invitation_entrypoint(
+    current_user: FirebaseUser,
+    Path(org_id): Path<String>,
+) -> Result<Json<Organization>, AppError> {
+    let caller_uid = &current_user.user_id;
+    let parameters = json!({ "org_id": org_id });
+
+    let result = async {
+        let rtdb = FirebaseRtdb::from_env()
+            .context("Failed to initialise Firebase RTDB client")?;
+        let mut organization = read_organization(&rtdb, &org_id).await?;
+
+        let member = match organization.members.get(caller_uid) {
+            Some(member) if !organization.is_active_member(caller_uid) => OrgMember {
+                accepted_at: Some(now_ms()),
+                ..member.clone()
+            },
+            _ => return Err(AppError::client(
+                StatusCode::NOT_FOUND,
+                "You have no pending invitation to this organization.",
+            )),
+        };
+
+        rtdb.set(&member_path(&org_id, caller_uid), &member)
+            .await
+            .context("Failed to store the member")?;
+        rtdb.set(&user_membership_path(caller_uid, &org_id), &member.role)
+            .await
+            .context("Failed to record the membership")?;
+        rtdb.delete(&user_invitation_path(caller_uid, &org_id))
+            .await
+            .context("Failed to remove the invitation")?;
+
+        println!("User {} joined organization {} as {:?}", caller_uid, org_id, member.role);
+        organization.members.insert(caller_uid.clone(), member);
+        Ok(organization)
+    }.await;
+    audit::record(caller_uid, AuditAction::OrgInvitationAccepted, None, parameters, &result).await;
+
+    result.map(Json)
+}
+
+/// `DELETE /api/v1/organizations/:org_id/members/:uid`
+///
+/// Removes a member from an organization, or withdraws an invitation.
+/// Owners, and administrators, can remove anyone; any member can leave,
+/// and any invitee can decline.
+///
+/// # Fails
+/// * With a `409` if it would leave the organization without an owner
+pub async fn remove_member_entrypoint(
+    current_user: FirebaseUser,
+    State(app): State<AppStatePtr>,
+    Path((org_id, member_uid)): Path<(String, String)>,
+) -> Result<Json<Organization>, AppError> {
+    let app = app.state;
+    let caller_uid = &current_user.user_id;
+    let parameters = json!({ "org_id": org_id, "member_uid": member_uid });
+
+    let result = async {
+        let rtdb = FirebaseRtdb::from_env()
+            .context("Failed to initialise Firebase RTDB client")?;
+
+        // ── 0. Verify the caller is an owner, or leaving ────────────
+        let mut organization = read_organization(&rtdb, &org_id).await?;
+        if caller_uid != &member_uid {
+            ensure_owner(&app, &organization, caller_uid).await?;
+        }
+
+        if !organization.members.contains_key(&member_uid) {
+            return Err(AppError::client(
+                StatusCode::NOT_FOUND,
+                format!("User {} is not a member of this organization.", member_uid),
+            ));
+        }
+
+        // ── 1. Keep at least one owner ──────────────────────────────
+        if organization.role_of(&member_uid) == Some(OrgRole::Owner) && organization.owner_count() == 1 {
+            return Err(AppError::client(
+                StatusCode::CONFLICT,
+                "An organization must keep at least one owner. Make someone else an owner first.",
+            ));
+        }
+
+        // ── 2. Remove the membership ────────────────────────────────
+        rtdb.delete(&member_path(&org_id, &member_uid))
+            .await
+            .context("Failed to remove the member")?;
+        rtdb.delete(&user_membership_path(&member_uid, &org_id))
+            .await
+            .context("Failed to remove the membership")?;
+        rtdb.delete(&user_invitation_path(&member_uid, &org_id))
+            .await
+            .context("Failed to remove the invitation")?;
+
+        println!("User {} removed {} from organization {}", caller_uid, member_uid, org_id);
+        organization.members.remove(&member_uid);
+        Ok(organization)
+    }.await;
+    audit::record(caller_uid, AuditAction::OrgMemberRemoved, None, parameters, &result).await;
+
+    result.map(Json)
+}
+
+/// Ensures the caller is an owner of the organization, or an administrator.
+async fn ensure_owner(app: &AppState, organization: &Organization, caller_uid: &str) -> Result<(), AppError> {
+    if organization.role_of(caller_uid) == Some(OrgRole::Owner) || is_administrator(app, caller_uid).await? {
+        return Ok(());
+    }
+
+    Err(AppError::client(
+        StatusCode::FORBIDDEN,
+        "Forbidden: only owners may manage this organization's members.",
+    ))
+}
+
+/// Checks whether a user is an administrator.
+async fn is_administrator(app: &AppState, uid: &str) -> Result<bool, AppError> {
+    let user = app.db
+        .lock()
+        .await
+        .get_user(uid)
+        .await
+        .context("Failed to look up caller")?;
+
+    Ok(user.administrator)
+}
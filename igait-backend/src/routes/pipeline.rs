@@ -0,0 +1,197 @@
+//! Pipeline overview endpoint for administrators.
+//!
+//! Summarises every stage queue in Firebase RTDB - how deep it is, how
+//! long its oldest job has been waiting, which workers hold claims, and
+//! which jobs are stuck on stale claims or waiting for approval.
+//!
+//! Only users with `administrator: true` in the database are authorised.
+
+use std::collections::{BTreeSet, HashMap};
+
+use axum::{extract::State, Json};
+use anyhow::{Context, Result};
+use firebase_auth::FirebaseUser;
+use serde::Serialize;
+
+use igait_lib::microservice::{
+    FinalizeQueueItem, FirebaseRtdb, QueueConfig, QueueItem, StageNumber,
+    now_ms, queue_config_path, queue_path, CLAIM_TIMEOUT_MS,
+};
+
+use crate::helper::lib::{AppError, AppStatePtr, NUM_STAGES};
+
+/// A job whose worker stopped sending heartbeats.
+#[derive(Debug, Serialize)]
+pub struct StaleClaim {
+    /// The job ID (format: "{user_id}_{job_index}")
+    pub job_id: String,
+    /// The worker that last claimed the job
+    pub claimed_by: Option<String>,
+    /// When the claim was last refreshed (Unix timestamp ms)
+    pub claimed_at: u64,
+}
+
+/// A summary of a single stage queue.
+#[derive(Debug, Serialize)]
+pub struct QueueOverview {
+    /// The stage number (1-7)
+    pub stage: u8,
+    /// The human-readable stage name (e.g. "Pose Estimation")
+    pub name: &'static str,
+    /// Whether the stage is paused
+    pub paused: bool,
+    /// Whether the stage requires approval for every job
+    pub requires_approval: bool,
+    /// The total number of jobs in the queue
+    pub depth: usize,
+    /// When the longest-waiting job entered the queue (Unix timestamp ms)
+    pub oldest_enqueued_at: Option<u64>,
+    /// The number of jobs no worker has claimed
+    pub unclaimed: usize,
+    /// The number of jobs held by a worker that is still heartbeating
+    pub claimed: usize,
+    /// The IDs of the workers holding live claims
+    pub claimed_by: Vec<String>,
+    /// Jobs claimed by a worker that stopped heartbeating for longer
+    /// than `CLAIM_TIMEOUT_MS`. These will be re-claimed by another worker.
+    pub stale_claims: Vec<StaleClaim>,
+    /// The IDs of jobs workers are skipping until they're approved
+    pub awaiting_approval: Vec<String>,
+}
+
+/// Response body for the pipeline overview.
+#[derive(Debug, Serialize)]
+pub struct PipelineOverviewResponse {
+    /// When the overview was generated (Unix timestamp ms)
+    pub generated_at: u64,
+    /// The claim timeout used to detect stale claims
+    pub claim_timeout_ms: u64,
+    /// Every stage queue, in pipeline order
+    pub stages: Vec<QueueOverview>,
+}
+
+/// The fields of a queue item the overview is computed from.
+///
+/// Both `QueueItem` and `FinalizeQueueItem` are reduced to this, since
+/// they share their claim fields but not a type.
+struct QueueEntry {
+    job_id: String,
+    enqueued_at: u64,
+    claimed_by: Option<String>,
+    claimed_at: Option<u64>,
+    stale: bool,
+    awaiting_approval: bool,
+}
+
+/// `GET /api/v1/admin/pipeline`
+///
+/// Returns a summary of every stage queue, from `queues/stage_1` through
+/// `queues/finalize`.
+/// **Admin-only** — the caller must have `administrator: true`.
+pub async fn pipeline_entrypoint(
+    current_user: FirebaseUser,
+    State(app): State<AppStatePtr>,
+) -> Result<Json<PipelineOverviewResponse>, AppError> {
+    let app = app.state;
+
+    // ── 0. Verify the caller is an administrator ────────────────────
+    app.ensure_administrator(&current_user.user_id).await?;
+
+    // ── 1. Summarise each stage queue ───────────────────────────────
+    let rtdb = FirebaseRtdb::from_env()
+        .context("Failed to initialise Firebase RTDB client")?;
+
+    let mut stages = Vec::new();
+    for stage in (1..=NUM_STAGES).filter_map(StageNumber::from_u8) {
+        stages.push(summarise_queue(&rtdb, stage).await?);
+    }
+
+    Ok(Json(PipelineOverviewResponse {
+        generated_at: now_ms(),
+        claim_timeout_ms: CLAIM_TIMEOUT_MS,
+        stages,
+    }))
+}
+
+/// Reads a stage's queue and config, and summarises them.
+async fn summarise_queue(rtdb: &FirebaseRtdb, stage: StageNumber) -> Result<QueueOverview> {
+    let config: QueueConfig = rtdb
+        .get(&queue_config_path(stage))
+        .await
+        .context(format!("Failed to read queue config for stage {}", stage.as_u8()))?
+        .unwrap_or_default();
+
+    // The finalize queue holds a different item type, and never requires approval
+    let path = queue_path(stage);
+    let entries: Vec<QueueEntry> = match stage {
+        StageNumber::Stage7Finalize => rtdb
+            .get::<HashMap<String, FinalizeQueueItem>>(&path)
+            .await
+            .context("Failed to read the finalize queue")?
+            .unwrap_or_default()
+            .into_values()
+            .map(|item| QueueEntry {
+                stale: item.is_claim_stale(),
+                awaiting_approval: false,
+                job_id: item.job_id,
+                enqueued_at: item.enqueued_at,
+                claimed_by: item.claimed_by,
+                claimed_at: item.claimed_at,
+            })
+            .collect(),
+        _ => rtdb
+            .get::<HashMap<String, QueueItem>>(&path)
+            .await
+            .context(format!("Failed to read queue for stage {}", stage.as_u8()))?
+            .unwrap_or_default()
+            .into_values()
+            .map(|item| QueueEntry {
+                stale: item.is_claim_stale(),
+                awaiting_approval: !item.is_approved_for_processing(config.requires_approval),
+                job_id: item.job_id,
+                enqueued_at: item.enqueued_at,
+                claimed_by: item.claimed_by,
+                claimed_at: item.claimed_at,
+            })
+            .collect(),
+    };
+
+    let mut overview = QueueOverview {
+        stage: stage.as_u8(),
+        name: stage.name(),
+        paused: config.paused,
+        requires_approval: config.requires_approval,
+        depth: entries.len(),
+        oldest_enqueued_at: entries.iter().map(|entry| entry.enqueued_at).min(),
+        unclaimed: 0,
+        claimed: 0,
+        claimed_by: Vec::new(),
+        stale_claims: Vec::new(),
+        awaiting_approval: Vec::new(),
+    };
+
+    let mut workers = BTreeSet::new();
+    for entry in entries {
+        if entry.awaiting_approval {
+            overview.awaiting_approval.push(entry.job_id.clone());
+        }
+
+        match entry.claimed_at {
+            None => overview.unclaimed += 1,
+            Some(claimed_at) if entry.stale => overview.stale_claims.push(StaleClaim {
+                job_id: entry.job_id,
+                claimed_by: entry.claimed_by,
+                claimed_at,
+            }),
+            Some(_) => {
+                overview.claimed += 1;
+                workers.extend(entry.claimed_by);
+            }
+        }
+    }
+    overview.claimed_by = workers.into_iter().collect();
+    overview.awaiting_approval.sort();
+    overview.stale_claims.sort_by_key(|claim| claim.claimed_at);
+
+    Ok(overview)
+}
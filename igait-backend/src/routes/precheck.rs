@@ -0,0 +1,292 @@
+//! Pre-submission video check.
+//!
+//! Users otherwise only find out their video has no one in it, or shows
+//! someone standing still, once Stage 2 gets to their job - which can be
+//! hours later when the queues are deep. This endpoint checks a single
+//! short clip while the user waits, so they can re-record before submitting.
+//!
+//! The clip is probed with `ffprobe` against the same media limits as a
+//! real upload. If that passes, it's handed to the Stage 2 workers through
+//! the precheck queue (see `igait_lib::microservice::PrecheckRequest`) for
+//! a shortened run of the validity check.
+
+use std::time::Duration;
+
+use axum::{body::Bytes, extract::{Multipart, State}, http::StatusCode, Json};
+use anyhow::Context;
+use firebase_auth::FirebaseUser;
+use serde::Serialize;
+
+use igait_lib::microservice::{
+    FirebaseRtdb, PrecheckDetection, PrecheckRequest, PRECHECK_MAX_SECONDS,
+    precheck_item_path,
+};
+
+use crate::helper::{
+    lib::{AppError, AppStatePtr, VideoProbe},
+    media::{self, MediaLimits},
+    quota::{self, Submission},
+    webhooks::generate_id,
+};
+
+/// The largest clip that can be checked.
+const MAX_CLIP_SIZE: usize = 100_000_000;
+
+/// How long to wait for a Stage 2 worker to check the clip.
+const DETECTION_TIMEOUT: Duration = Duration::from_secs(90);
+
+/// How often to look for the Stage 2 worker's result.
+const DETECTION_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// The smallest share of the examined clip the subject must be visible in.
+const MIN_PERSON_COVERAGE: f64 = 0.8;
+
+/// Something the user should fix before submitting.
+#[derive(Debug, Serialize)]
+pub struct PrecheckFeedback {
+    /// A stable identifier for the kind of problem (e.g. "too_short")
+    pub code: &'static str,
+    /// What's wrong, and what to do about it
+    pub message: String,
+}
+
+impl PrecheckFeedback {
+    fn new(code: &'static str, message: impl Into<String>) -> Self {
+        Self { code, message: message.into() }
+    }
+}
+
+/// Response body for a precheck.
+#[derive(Debug, Serialize)]
+pub struct PrecheckResponse {
+    /// Whether the clip passed every check that could be run
+    pub passed: bool,
+    /// Whether the person and walking detection could be run
+    pub detection_ran: bool,
+    /// What to fix, if anything
+    pub feedback: Vec<PrecheckFeedback>,
+    /// The clip's properties, if it could be read
+    pub media: Option<VideoProbe>,
+    /// What the shortened validity check found, if it ran
+    pub detection: Option<PrecheckDetection>,
+}
+
+/// `POST /api/v1/precheck`
+///
+/// Checks a single short clip (the multipart field `video`) and returns
+/// actionable feedback, without creating a job.
+///
+/// # Workflow
+/// 1. Probe the clip and check it against the media limits
+/// 2. Reserve a slot in the user's daily precheck quota
+/// 3. Store it in AWS S3 and queue it for a Stage 2 worker
+/// 4. Wait for the shortened validity check to finish
+/// 5. Turn the results into feedback
+///
+/// If no Stage 2 worker picks the clip up in time, the media feedback is
+/// still returned, with `detection_ran` set to `false`.
+///
+/// Prechecks are taken ahead of real jobs by the Stage 2 workers, so
+/// every clip queued for one counts against the daily precheck limit.
+///
+/// # Fails
+/// * With a `400` if the clip is missing or has no file extension
+/// * With a `413` if the clip is too large
+/// * With a `429` if the user has hit their daily precheck limit
+pub async fn precheck_entrypoint(
+    current_user: FirebaseUser,
+    State(app): State<AppStatePtr>,
+    mut multipart: Multipart,
+) -> Result<Json<PrecheckResponse>, AppError> {
+    let app = app.state;
+    let uid = &current_user.user_id;
+
+    let (file_name, bytes) = unpack_clip(&mut multipart).await?;
+    let extension = file_name
+        .rsplit_once('.')
+        .map(|(_, extension)| extension.to_lowercase())
+        .filter(|extension| !extension.is_empty() && extension.chars().all(|c| c.is_ascii_alphanumeric()))
+        .ok_or_else(|| AppError::client(
+            StatusCode::BAD_REQUEST,
+            format!("'{}' must have a file extension!", file_name),
+        ))?;
+
+    // ── 1. Probe the clip ───────────────────────────────────────────
+    let probe = media::probe_bytes(&bytes, &extension)
+        .await
+        .context("Failed to probe the clip")?;
+    let Some(probe) = probe else {
+        return Ok(Json(PrecheckResponse {
+            passed: false,
+            detection_ran: false,
+            feedback: vec![PrecheckFeedback::new(
+                "unreadable",
+                "The video could not be read. It may be corrupt or not a video - try recording it again.",
+            )],
+            media: None,
+            detection: None,
+        }));
+    };
+
+    let mut feedback: Vec<PrecheckFeedback> = MediaLimits::from_env()
+        .check(&probe)
+        .into_iter()
+        .map(|issue| PrecheckFeedback::new(issue.code, format!("The video was rejected: {}.", issue.message)))
+        .collect();
+
+    // A clip the pipeline can't convert isn't worth running detection on
+    if feedback.iter().any(|issue| matches!(issue.code, "unsupported_container" | "unsupported_codec")) {
+        return Ok(Json(PrecheckResponse {
+            passed: false,
+            detection_ran: false,
+            feedback,
+            media: Some(probe),
+            detection: None,
+        }));
+    }
+
+    // ── 2. Reserve a quota slot ─────────────────────────────────────
+    quota::reserve(&app, uid, Submission::Precheck).await?;
+
+    // ── 3. Queue the clip for a Stage 2 worker ──────────────────────
+    let rtdb = FirebaseRtdb::from_env()
+        .context("Failed to initialise Firebase RTDB client")?;
+
+    let id = generate_id();
+    let video_key = format!("prechecks/{}/{}.{}", uid, id, extension);
+    app.storage
+        .upload(&video_key, bytes.to_vec(), Some(media::content_type(&extension)))
+        .await
+        .context("Failed to upload the clip to AWS S3!")?;
+
+    let request = PrecheckRequest::new(id.clone(), uid.clone(), video_key.clone());
+    rtdb.set(&precheck_item_path(&id), &request)
+        .await
+        .context("Failed to queue the precheck")?;
+
+    // ── 4. Wait for the result ──────────────────────────────────────
+    let detection = wait_for_detection(&rtdb, &id).await;
+
+    if let Err(e) = rtdb.delete(&precheck_item_path(&id)).await {
+        eprintln!("Failed to remove precheck {}: {e:?}", id);
+    }
+    if let Err(e) = app.storage.delete(&video_key).await {
+        eprintln!("Failed to remove precheck clip {}: {e:?}", video_key);
+    }
+
+    // ── 5. Turn the results into feedback ───────────────────────────
+    let detection = match detection? {
+        Some(PrecheckDetection { error: Some(error), .. }) => {
+            eprintln!("Precheck {} detection failed: {}", id, error);
+            None
+        }
+        other => other,
+    };
+    if let Some(detection) = &detection {
+        feedback.extend(detection_feedback(detection));
+    }
+
+    println!(
+        "Precheck {} for user {}: {} issue(s), detection {}",
+        id,
+        uid,
+        feedback.len(),
+        if detection.is_some() { "ran" } else { "unavailable" }
+    );
+    Ok(Json(PrecheckResponse {
+        passed: feedback.is_empty() && detection.is_some(),
+        detection_ran: detection.is_some(),
+        feedback,
+        media: Some(probe),
+        detection,
+    }))
+}
+
+/// Reads the clip out of the `Multipart` request.
+///
+/// # Returns
+/// * The clip's file name and contents
+async fn unpack_clip(multipart: &mut Multipart) -> Result<(String, Bytes), AppError> {
+    while let Some(field) = multipart
+        .next_field()
+        .await
+        .context("Bad precheck request! Is it possible you submitted a file over the size limit?")?
+    {
+        if field.name() != Some("video") {
+            continue;
+        }
+
+        let file_name = field.file_name().unwrap_or("video.mp4").to_string();
+        let bytes = field
+            .bytes()
+            .await
+            .context("Could not unpack bytes from field 'video'!")?;
+
+        if bytes.len() > MAX_CLIP_SIZE {
+            return Err(AppError::client(
+                StatusCode::PAYLOAD_TOO_LARGE,
+                format!("Clips must be at most {} bytes. Trim it to a few seconds of walking.", MAX_CLIP_SIZE),
+            ));
+        }
+
+        return Ok((file_name, bytes));
+    }
+
+    Err(AppError::client(StatusCode::BAD_REQUEST, "Missing 'video' in request!"))
+}
+
+/// Waits for a Stage 2 worker to write the result onto a precheck request.
+///
+/// # Returns
+/// * `None` if no result arrived in time
+async fn wait_for_detection(rtdb: &FirebaseRtdb, id: &str) -> anyhow::Result<Option<PrecheckDetection>> {
+    let path = format!("{}/result", precheck_item_path(id));
+    let deadline = tokio::time::Instant::now() + DETECTION_TIMEOUT;
+
+    while tokio::time::Instant::now() < deadline {
+        tokio::time::sleep(DETECTION_POLL_INTERVAL).await;
+
+        let result: Option<PrecheckDetection> = rtdb
+            .get(&path)
+            .await
+            .context("Failed to read the precheck result")?;
+        if result.is_some() {
+            return Ok(result);
+        }
+    }
+
+    Ok(None)
+}
+
+/// Explains what the shortened validity check found.
+fn detection_feedback(detection: &PrecheckDetection) -> Vec<PrecheckFeedback> {
+    if !detection.human_detected {
+        return vec![PrecheckFeedback::new(
+            "no_person",
+            "No one could be seen in the video. Make sure the subject is well lit and fully in frame.",
+        )];
+    }
+
+    let mut feedback = Vec::new();
+    if detection.person_coverage() < MIN_PERSON_COVERAGE {
+        feedback.push(PrecheckFeedback::new(
+            "subject_not_fully_in_frame",
+            format!(
+                "The subject was only visible in {} of the {} segments checked. Keep them fully in frame for the whole video.",
+                detection.clips_with_person,
+                detection.clips_processed
+            ),
+        ));
+    }
+    if !detection.walking_detected {
+        feedback.push(PrecheckFeedback::new(
+            "not_walking",
+            format!(
+                "The subject didn't appear to be walking in the first {} seconds. Start recording once they're walking.",
+                PRECHECK_MAX_SECONDS
+            ),
+        ));
+    }
+
+    feedback
+}
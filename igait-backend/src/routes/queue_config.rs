@@ -0,0 +1,287 @@
+//! Queue configuration endpoints for operating the pipeline.
+//!
+//! Each stage's queue has a `QueueConfig` at `queue_config/stage_{n}` in
+//! Firebase RTDB, which workers read every time they try to claim a job.
+//! These endpoints let administrators read and update it - for example,
+//! pausing a stage so it drains before deploying a new model.
+//!
+//! Only users with `administrator: true` in the database are authorised.
+
+use std::collections::HashMap;
+
+use axum::{extract::{Path, State}, http::StatusCode, Json};
+use anyhow::{Context, Result, anyhow};
+use firebase_auth::FirebaseUser;
+use serde::Serialize;
+use serde_json::{Value, json};
+
+use igait_lib::microservice::{
+    FinalizeQueueItem, FirebaseRtdb, QueueConfig, QueueItem, StageNumber,
+    queue_config_path, queue_path,
+};
+
+use crate::helper::{
+    audit::{self, AuditAction},
+    lib::{AppError, AppStatePtr, NUM_STAGES},
+};
+
+/// The video bitrates (in kbps) stage 1 may be configured to encode at.
+const VIDEO_BITRATE_KBPS: std::ops::RangeInclusive<u64> = 100..=100_000;
+
+/// The x264 presets stage 1 may be configured to encode with.
+const X264_PRESETS: [&str; 9] = [
+    "ultrafast", "superfast", "veryfast", "faster", "fast", "medium", "slow", "slower", "veryslow",
+];
+
+/// A stage's queue configuration, along with how busy the queue is.
+#[derive(Debug, Serialize)]
+pub struct StageQueueConfig {
+    /// The stage number (1-7)
+    pub stage: u8,
+    /// The human-readable stage name (e.g. "Pose Estimation")
+    pub name: &'static str,
+    /// The stage's queue configuration
+    pub config: QueueConfig,
+    /// The number of jobs currently claimed by live workers.
+    /// Once a paused stage reaches zero, it has fully drained.
+    pub in_flight: usize,
+}
+
+/// Response body for the queue configuration listing.
+#[derive(Debug, Serialize)]
+pub struct QueueConfigsResponse {
+    /// Every stage's queue configuration, in pipeline order
+    pub stages: Vec<StageQueueConfig>,
+}
+
+/// `GET /api/v1/admin/queue-config`
+///
+/// Returns the queue configuration of every stage.
+/// **Admin-only** — the caller must have `administrator: true`.
+pub async fn list_queue_configs_entrypoint(
+    current_user: FirebaseUser,
+    State(app): State<AppStatePtr>,
+) -> Result<Json<QueueConfigsResponse>, AppError> {
+    let app = app.state;
+
+    // ── 0. Verify the caller is an administrator ────────────────────
+    app.ensure_administrator(&current_user.user_id).await?;
+
+    // ── 1. Read every stage's config ────────────────────────────────
+    let rtdb = FirebaseRtdb::from_env()
+        .context("Failed to initialise Firebase RTDB client")?;
+
+    let mut stages = Vec::new();
+    for stage in (1..=NUM_STAGES).filter_map(StageNumber::from_u8) {
+        stages.push(read_stage_config(&rtdb, stage).await?);
+    }
+
+    Ok(Json(QueueConfigsResponse { stages }))
+}
+
+/// `PUT /api/v1/admin/queue-config/:stage`
+///
+/// Replaces a stage's queue configuration.
+/// **Admin-only** — the caller must have `administrator: true`.
+///
+/// # Arguments
+/// * `stage` - The stage number (1-7).
+/// * `config` - The new configuration. Omitted fields take their defaults.
+///
+/// # Fails
+/// * With a `400` if `max_concurrency` is 0, or stage 1's `preset` or
+///   `video_bitrate_kbps` isn't one its encoder accepts
+pub async fn update_queue_config_entrypoint(
+    current_user: FirebaseUser,
+    State(app): State<AppStatePtr>,
+    Path(stage): Path<u8>,
+    Json(config): Json<QueueConfig>,
+) -> Result<Json<StageQueueConfig>, AppError> {
+    let caller_uid = current_user.user_id.clone();
+    let parameters = json!({ "stage": stage, "config": config });
+
+    let result = replace_queue_config(current_user, app, stage, config).await;
+    audit::record(&caller_uid, AuditAction::QueueConfigUpdated, None, parameters, &result).await;
+
+    result
+}
+
+/// Replaces a stage's queue configuration (see `update_queue_config_entrypoint`).
+async fn replace_queue_config(
+    current_user: FirebaseUser,
+    app: AppStatePtr,
+    stage: u8,
+    config: QueueConfig,
+) -> Result<Json<StageQueueConfig>, AppError> {
+    let app = app.state;
+    let caller_uid = &current_user.user_id;
+
+    // ── 0. Verify the caller is an administrator ────────────────────
+    app.ensure_administrator(caller_uid).await?;
+
+    // ── 1. Validate the request ─────────────────────────────────────
+    let stage = parse_stage(stage)?;
+    if config.max_concurrency == Some(0) {
+        return Err(AppError::client(
+            StatusCode::BAD_REQUEST,
+            "Invalid max_concurrency of 0. To stop workers from claiming jobs, set `paused` instead.",
+        ));
+    }
+    if stage == StageNumber::Stage1MediaConversion {
+        validate_stage_1_parameters(&config.parameters)?;
+    }
+
+    // ── 2. Write the new config ─────────────────────────────────────
+    let rtdb = FirebaseRtdb::from_env()
+        .context("Failed to initialise Firebase RTDB client")?;
+
+    println!("Queue config for stage {} updated by admin {}: {:?}", stage.as_u8(), caller_uid, config);
+    rtdb.set(&queue_config_path(stage), &config)
+        .await
+        .context("Failed to write the queue config")?;
+
+    Ok(Json(read_stage_config(&rtdb, stage).await?))
+}
+
+/// `POST /api/v1/admin/queue-config/:stage/pause`
+///
+/// Pauses a stage: workers finish the jobs they've already claimed,
+/// but claim no new ones. Poll `in_flight` to know when it has drained.
+/// **Admin-only** — the caller must have `administrator: true`.
+pub async fn pause_queue_entrypoint(
+    current_user: FirebaseUser,
+    State(app): State<AppStatePtr>,
+    Path(stage): Path<u8>,
+) -> Result<Json<StageQueueConfig>, AppError> {
+    let caller_uid = current_user.user_id.clone();
+
+    let result = set_paused(current_user, app, stage, true).await;
+    audit::record(&caller_uid, AuditAction::QueuePaused, None, json!({ "stage": stage }), &result).await;
+
+    result
+}
+
+/// `POST /api/v1/admin/queue-config/:stage/resume`
+///
+/// Resumes a paused stage so workers claim jobs from it again.
+/// **Admin-only** — the caller must have `administrator: true`.
+pub async fn resume_queue_entrypoint(
+    current_user: FirebaseUser,
+    State(app): State<AppStatePtr>,
+    Path(stage): Path<u8>,
+) -> Result<Json<StageQueueConfig>, AppError> {
+    let caller_uid = current_user.user_id.clone();
+
+    let result = set_paused(current_user, app, stage, false).await;
+    audit::record(&caller_uid, AuditAction::QueueResumed, None, json!({ "stage": stage }), &result).await;
+
+    result
+}
+
+/// Sets the `paused` flag of a stage's queue config, leaving the rest untouched.
+async fn set_paused(
+    current_user: FirebaseUser,
+    app: AppStatePtr,
+    stage: u8,
+    paused: bool,
+) -> Result<Json<StageQueueConfig>, AppError> {
+    let app = app.state;
+    let caller_uid = &current_user.user_id;
+
+    // ── 0. Verify the caller is an administrator ────────────────────
+    app.ensure_administrator(caller_uid).await?;
+
+    let stage = parse_stage(stage)?;
+
+    // ── 1. Flip the flag ────────────────────────────────────────────
+    let rtdb = FirebaseRtdb::from_env()
+        .context("Failed to initialise Firebase RTDB client")?;
+
+    let mut config: QueueConfig = rtdb
+        .get(&queue_config_path(stage))
+        .await
+        .context("Failed to read the queue config")?
+        .unwrap_or_default();
+    config.paused = paused;
+
+    println!(
+        "Stage {} {} by admin {}",
+        stage.as_u8(),
+        if paused { "paused" } else { "resumed" },
+        caller_uid
+    );
+    rtdb.set(&queue_config_path(stage), &config)
+        .await
+        .context("Failed to write the queue config")?;
+
+    Ok(Json(read_stage_config(&rtdb, stage).await?))
+}
+
+/// Checks the encoder overrides in stage 1's parameters, so a typo is
+/// rejected here rather than failing every job the stage claims.
+fn validate_stage_1_parameters(parameters: &HashMap<String, Value>) -> Result<(), AppError> {
+    if let Some(bitrate) = parameters.get("video_bitrate_kbps") {
+        if !bitrate.as_u64().is_some_and(|kbps| VIDEO_BITRATE_KBPS.contains(&kbps)) {
+            return Err(AppError::client(
+                StatusCode::BAD_REQUEST,
+                format!(
+                    "Invalid video_bitrate_kbps of {}. It must be a whole number of kbps from {} to {}.",
+                    bitrate, VIDEO_BITRATE_KBPS.start(), VIDEO_BITRATE_KBPS.end()
+                ),
+            ));
+        }
+    }
+    if let Some(preset) = parameters.get("preset") {
+        if !preset.as_str().is_some_and(|preset| X264_PRESETS.contains(&preset)) {
+            return Err(AppError::client(
+                StatusCode::BAD_REQUEST,
+                format!("Invalid preset of {}. It must be one of: {}.", preset, X264_PRESETS.join(", ")),
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+/// Converts a stage number from a request path into a `StageNumber`.
+fn parse_stage(stage: u8) -> Result<StageNumber> {
+    StageNumber::from_u8(stage)
+        .ok_or_else(|| anyhow!("Invalid stage number {}. Must be between 1 and {}.", stage, NUM_STAGES))
+}
+
+/// Reads a stage's queue config and counts the jobs currently in flight.
+async fn read_stage_config(rtdb: &FirebaseRtdb, stage: StageNumber) -> Result<StageQueueConfig> {
+    let config: QueueConfig = rtdb
+        .get(&queue_config_path(stage))
+        .await
+        .context(format!("Failed to read queue config for stage {}", stage.as_u8()))?
+        .unwrap_or_default();
+
+    // The finalize queue holds a different item type
+    let path = queue_path(stage);
+    let in_flight = match stage {
+        StageNumber::Stage7Finalize => rtdb
+            .get::<HashMap<String, FinalizeQueueItem>>(&path)
+            .await
+            .context("Failed to read the finalize queue")?
+            .unwrap_or_default()
+            .values()
+            .filter(|item| !item.is_available())
+            .count(),
+        _ => rtdb
+            .get::<HashMap<String, QueueItem>>(&path)
+            .await
+            .context(format!("Failed to read queue for stage {}", stage.as_u8()))?
+            .unwrap_or_default()
+            .values()
+            .filter(|item| !item.is_available())
+            .count(),
+    };
+
+    Ok(StageQueueConfig {
+        stage: stage.as_u8(),
+        name: stage.name(),
+        config,
+        in_flight,
+    })
+}
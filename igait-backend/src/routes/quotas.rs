@@ -0,0 +1,268 @@
+//! Submission quota endpoints.
+//!
+//! Users can check how much of their quota they've used. Administrators
+//! can set the limits of each role, and override them per user - giving
+//! a user a different role, their own limits, or exempting them outright.
+//! See `crate::helper::quota` for how limits are resolved and enforced.
+
+use std::collections::HashMap;
+
+use axum::{extract::{Path, State}, http::StatusCode, Json};
+use anyhow::Context;
+use firebase_auth::FirebaseUser;
+use serde::Serialize;
+use serde_json::json;
+
+use igait_lib::microservice::FirebaseRtdb;
+
+use crate::helper::{
+    audit::{self, AuditAction},
+    lib::{AppError, AppStatePtr},
+    quota::{self, QuotaLimits, QuotaOverride, QuotaUsage, role_limits_path, user_override_path},
+};
+
+/// Response body for the quota settings listing.
+#[derive(Debug, Serialize)]
+pub struct QuotaSettingsResponse {
+    /// The limits used when neither a role nor a user sets one
+    pub defaults: QuotaLimits,
+    /// The limits of each configured role
+    pub roles: HashMap<String, QuotaLimits>,
+    /// Every user's override
+    pub users: HashMap<String, QuotaOverride>,
+}
+
+/// Response body for a user's quota.
+#[derive(Debug, Serialize)]
+pub struct UserQuotaResponse {
+    /// The administrator's override for the user, if there is one
+    #[serde(rename = "override")]
+    pub user_override: Option<QuotaOverride>,
+    /// The user's effective limits and usage
+    pub usage: QuotaUsage,
+}
+
+/// `GET /api/v1/quota`
+///
+/// Returns the caller's limits and how much of them they've used.
+pub async fn my_quota_entrypoint(
+    current_user: FirebaseUser,
+    State(app): State<AppStatePtr>,
+) -> Result<Json<QuotaUsage>, AppError> {
+    let usage = quota::usage(&app.state, &current_user.user_id).await?;
+
+    Ok(Json(usage))
+}
+
+/// `GET /api/v1/admin/quotas`
+///
+/// Returns the default limits, every role's limits and every user override.
+/// **Admin-only** — the caller must have `administrator: true`.
+pub async fn list_quotas_entrypoint(
+    current_user: FirebaseUser,
+    State(app): State<AppStatePtr>,
+) -> Result<Json<QuotaSettingsResponse>, AppError> {
+    let app = app.state;
+
+    // ── 0. Verify the caller is an administrator ────────────────────
+    app.ensure_administrator(&current_user.user_id).await?;
+
+    // ── 1. Read the settings ────────────────────────────────────────
+    let rtdb = FirebaseRtdb::from_env()
+        .context("Failed to initialise Firebase RTDB client")?;
+
+    let roles: Option<HashMap<String, QuotaLimits>> = rtdb.get("quotas/roles")
+        .await
+        .context("Failed to read the role quota limits")?;
+    let users: Option<HashMap<String, QuotaOverride>> = rtdb.get("quotas/users")
+        .await
+        .context("Failed to read the user quota overrides")?;
+
+    Ok(Json(QuotaSettingsResponse {
+        defaults: QuotaLimits::from_env(),
+        roles: roles.unwrap_or_default(),
+        users: users.unwrap_or_default(),
+    }))
+}
+
+/// `PUT /api/v1/admin/quotas/roles/:role`
+///
+/// Replaces a role's limits. Omitted limits fall back to the defaults.
+/// **Admin-only** — the caller must have `administrator: true`.
+pub async fn update_role_quota_entrypoint(
+    current_user: FirebaseUser,
+    State(app): State<AppStatePtr>,
+    Path(role): Path<String>,
+    Json(limits): Json<QuotaLimits>,
+) -> Result<Json<QuotaLimits>, AppError> {
+    let caller_uid = current_user.user_id.clone();
+    let parameters = json!({ "role": role, "limits": limits });
+
+    let result = replace_role_limits(current_user, app, role, limits).await;
+    audit::record(&caller_uid, AuditAction::QuotaRoleUpdated, None, parameters, &result).await;
+
+    result
+}
+
+/// Replaces a role's limits (see `update_role_quota_entrypoint`).
+async fn replace_role_limits(
+    current_user: FirebaseUser,
+    app: AppStatePtr,
+    role: String,
+    limits: QuotaLimits,
+) -> Result<Json<QuotaLimits>, AppError> {
+    let app = app.state;
+    let caller_uid = &current_user.user_id;
+
+    // ── 0. Verify the caller is an administrator ────────────────────
+    app.ensure_administrator(caller_uid).await?;
+
+    // ── 1. Validate the request ─────────────────────────────────────
+    validate_role(&role)?;
+
+    // ── 2. Write the new limits ─────────────────────────────────────
+    let rtdb = FirebaseRtdb::from_env()
+        .context("Failed to initialise Firebase RTDB client")?;
+
+    println!("Quota limits for role '{}' updated by admin {}: {:?}", role, caller_uid, limits);
+    rtdb.set(&role_limits_path(&role), &limits)
+        .await
+        .context("Failed to write the role quota limits")?;
+
+    Ok(Json(limits))
+}
+
+/// `GET /api/v1/admin/quotas/users/:uid`
+///
+/// Returns a user's override, effective limits and usage.
+/// **Admin-only** — the caller must have `administrator: true`.
+pub async fn get_user_quota_entrypoint(
+    current_user: FirebaseUser,
+    State(app): State<AppStatePtr>,
+    Path(uid): Path<String>,
+) -> Result<Json<UserQuotaResponse>, AppError> {
+    let app = app.state;
+
+    // ── 0. Verify the caller is an administrator ────────────────────
+    app.ensure_administrator(&current_user.user_id).await?;
+
+    // ── 1. Read the override and usage ──────────────────────────────
+    let rtdb = FirebaseRtdb::from_env()
+        .context("Failed to initialise Firebase RTDB client")?;
+
+    let user_override: Option<QuotaOverride> = rtdb.get(&user_override_path(&uid))
+        .await
+        .context("Failed to read the user quota override")?;
+    let usage = quota::usage(&app, &uid).await?;
+
+    Ok(Json(UserQuotaResponse { user_override, usage }))
+}
+
+/// `PUT /api/v1/admin/quotas/users/:uid`
+///
+/// Replaces a user's override. Omitted limits fall back to the user's role.
+/// **Admin-only** — the caller must have `administrator: true`.
+pub async fn update_user_quota_entrypoint(
+    current_user: FirebaseUser,
+    State(app): State<AppStatePtr>,
+    Path(uid): Path<String>,
+    Json(user_override): Json<QuotaOverride>,
+) -> Result<Json<UserQuotaResponse>, AppError> {
+    let caller_uid = current_user.user_id.clone();
+    let parameters = json!({ "user_id": uid, "override": user_override });
+
+    let result = replace_user_override(current_user, app, uid, user_override).await;
+    audit::record(&caller_uid, AuditAction::QuotaOverrideUpdated, None, parameters, &result).await;
+
+    result
+}
+
+/// Replaces a user's override (see `update_user_quota_entrypoint`).
+async fn replace_user_override(
+    current_user: FirebaseUser,
+    app: AppStatePtr,
+    uid: String,
+    user_override: QuotaOverride,
+) -> Result<Json<UserQuotaResponse>, AppError> {
+    let app = app.state;
+    let caller_uid = &current_user.user_id;
+
+    // ── 0. Verify the caller is an administrator ────────────────────
+    app.ensure_administrator(caller_uid).await?;
+
+    // ── 1. Validate the request ─────────────────────────────────────
+    if let Some(role) = &user_override.role {
+        validate_role(role)?;
+    }
+
+    // ── 2. Write the new override ───────────────────────────────────
+    let rtdb = FirebaseRtdb::from_env()
+        .context("Failed to initialise Firebase RTDB client")?;
+
+    println!("Quota override for user {} updated by admin {}: {:?}", uid, caller_uid, user_override);
+    rtdb.set(&user_override_path(&uid), &user_override)
+        .await
+        .context("Failed to write the user quota override")?;
+
+    let usage = quota::usage(&app, &uid).await?;
+    Ok(Json(UserQuotaResponse { user_override: Some(user_override), usage }))
+}
+
+/// `DELETE /api/v1/admin/quotas/users/:uid`
+///
+/// Removes a user's override, so their role's limits apply again.
+/// **Admin-only** — the caller must have `administrator: true`.
+pub async fn delete_user_quota_entrypoint(
+    current_user: FirebaseUser,
+    State(app): State<AppStatePtr>,
+    Path(uid): Path<String>,
+) -> Result<Json<UserQuotaResponse>, AppError> {
+    let caller_uid = current_user.user_id.clone();
+    let parameters = json!({ "user_id": uid });
+
+    let result = remove_user_override(current_user, app, uid).await;
+    audit::record(&caller_uid, AuditAction::QuotaOverrideRemoved, None, parameters, &result).await;
+
+    result
+}
+
+/// Removes a user's override (see `delete_user_quota_entrypoint`).
+async fn remove_user_override(
+    current_user: FirebaseUser,
+    app: AppStatePtr,
+    uid: String,
+) -> Result<Json<UserQuotaResponse>, AppError> {
+    let app = app.state;
+    let caller_uid = &current_user.user_id;
+
+    // ── 0. Verify the caller is an administrator ────────────────────
+    app.ensure_administrator(caller_uid).await?;
+
+    // ── 1. Remove the override ──────────────────────────────────────
+    let rtdb = FirebaseRtdb::from_env()
+        .context("Failed to initialise Firebase RTDB client")?;
+
+    println!("Quota override for user {} removed by admin {}", uid, caller_uid);
+    rtdb.delete(&user_override_path(&uid))
+        .await
+        .context("Failed to remove the user quota override")?;
+
+    let usage = quota::usage(&app, &uid).await?;
+    Ok(Json(UserQuotaResponse { user_override: None, usage }))
+}
+
+/// Checks a role name is safe to use as an RTDB key.
+fn validate_role(role: &str) -> Result<(), AppError> {
+    let valid = !role.is_empty()
+        && role.len() <= 64
+        && role.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-');
+
+    if !valid {
+        return Err(AppError::client(
+            StatusCode::BAD_REQUEST,
+            format!("Invalid role '{}'. Roles may only contain letters, numbers, '_' and '-'.", role),
+        ));
+    }
+
+    Ok(())
+}
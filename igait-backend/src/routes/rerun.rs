@@ -4,7 +4,9 @@
 //! a given stage. It cleans up S3 outputs from the target stage onward
 //! and re-inserts the job into the target stage's queue.
 //!
-//! Only users with `administrator: true` in the database are authorised.
+//! Users with `administrator: true` in the database are authorised for
+//! every job. Owners and clinicians of an organization are authorised for
+//! the jobs of its members.
 
 use std::collections::HashMap;
 
@@ -12,19 +14,23 @@ use axum::{extract::State, Json};
 use anyhow::{Context, anyhow};
 use firebase_auth::FirebaseUser;
 use serde::{Deserialize, Serialize};
+use serde_json::json;
 
 use igait_lib::microservice::{
     JobMetadata, QueueItem, StageNumber, StoragePaths,
     FirebaseRtdb, queue_item_path,
 };
 
-use crate::helper::lib::{AppError, AppStatePtr, JobStatus, NUM_STAGES};
+use crate::helper::{
+    audit::{self, AuditAction},
+    lib::{AppError, AppStatePtr, JobStatus, NUM_STAGES},
+    organizations::{JobAccess, ensure_job_access},
+};
 
 /// Request body for the rerun endpoint.
 #[derive(Debug, Deserialize)]
 pub struct RerunRequest {
     /// The UID of the user who owns the job.
-    /// Admins must specify this to indicate whose job to rerun.
     pub user_id: String,
     /// The index of the job in the user's job list (0-indexed).
     pub job_index: usize,
@@ -44,15 +50,17 @@ pub struct RerunResponse {
 }
 
 /// Authenticated endpoint to rerun a job from a specific stage.
-/// **Admin-only** — the caller must have `administrator: true`.
+/// The caller must have `administrator: true`, or be an owner or clinician
+/// of an organization the job's owner belongs to.
 ///
 /// # Workflow
-/// 1. Verify the caller is an administrator
+/// 1. Verify the caller may manage the job
 /// 2. Validate the stage number
 /// 3. Fetch the target user's job
-/// 4. Delete S3 outputs for stages `stage..=7`
+/// 4. Delete S3 outputs for stages `stage..=7` (including the results archive)
 /// 5. Reconstruct a `QueueItem` with the correct input keys
-/// 6. Push the item into the target stage's queue in Firebase RTDB
+/// 6. Clear the job's results archive and the results of stages `stage..=7`,
+///    and push the item into the target stage's queue in Firebase RTDB
 /// 7. Update the job status to "Processing" for the target stage
 ///
 /// # Arguments
@@ -63,6 +71,22 @@ pub async fn rerun_entrypoint(
     current_user: FirebaseUser,
     State(app): State<AppStatePtr>,
     Json(request): Json<RerunRequest>,
+) -> Result<Json<RerunResponse>, AppError> {
+    let caller_uid = current_user.user_id.clone();
+    let job_id = format!("{}_{}", request.user_id, request.job_index);
+    let parameters = json!({ "stage": request.stage });
+
+    let result = rerun_job(current_user, app, request).await;
+    audit::record(&caller_uid, AuditAction::JobRerun, Some(&job_id), parameters, &result).await;
+
+    result
+}
+
+/// Reruns a job from a stage (see `rerun_entrypoint`).
+async fn rerun_job(
+    current_user: FirebaseUser,
+    app: AppStatePtr,
+    request: RerunRequest,
 ) -> Result<Json<RerunResponse>, AppError> {
     let app = app.state;
     let caller_uid = &current_user.user_id;
@@ -70,20 +94,8 @@ pub async fn rerun_entrypoint(
     let stage = request.stage;
     let job_index = request.job_index;
 
-    // ── 0. Verify the caller is an administrator ────────────────────
-    let caller = app
-        .db
-        .lock()
-        .await
-        .get_user(caller_uid)
-        .await
-        .context("Failed to look up caller in the database")?;
-
-    if !caller.administrator {
-        return Err(AppError(anyhow!(
-            "Forbidden: only administrators may rerun jobs."
-        )));
-    }
+    // ── 0. Verify the caller may manage the job ─────────────────────
+    ensure_job_access(&app, caller_uid, target_uid, JobAccess::Manage).await?;
 
     // ── 1. Validate stage number ────────────────────────────────────
     // Stage 7 is the finalize stage and uses FinalizeQueueItem, not QueueItem.
@@ -108,7 +120,7 @@ pub async fn rerun_entrypoint(
         .context("Failed to fetch the job — does it exist?")?;
 
     let job_id = format!("{}_{}", target_uid, job_index);
-    println!("Rerun requested by admin {}: job={}, stage={}", caller_uid, job_id, stage);
+    println!("Rerun requested by {}: job={}, stage={}", caller_uid, job_id, stage);
 
     // ── 3. Delete S3 outputs for stages `stage..=7` ─────────────────
     let mut total_deleted: usize = 0;
@@ -136,6 +148,10 @@ pub async fn rerun_entrypoint(
         ethnicity: Some(job.ethnicity.to_string()),
         height: Some(job.height.clone()),
         weight: Some(job.weight),
+        // Reruns are reported on their own, since the batch's
+        // summary may already have been sent
+        batch_id: None,
+        locale: Some(job.locale),
         extra: HashMap::new(),
     };
 
@@ -147,13 +163,34 @@ pub async fn rerun_entrypoint(
         job.requires_approval,
     );
 
-    // Admin-initiated rerun: mark as approved immediately (admin check performed above at line 74-86)
-    queue_item.approved = true;
+    // Admin-initiated rerun: mark as approved immediately. Reruns by
+    // organization members can't get around a pending approval.
+    let caller_is_admin = app
+        .db
+        .lock()
+        .await
+        .get_user(caller_uid)
+        .await
+        .context("Failed to look up caller in the database")?
+        .administrator;
+    queue_item.approved = caller_is_admin || job.approved;
 
     // ── 5. Push into the target stage's queue ───────────────────────
     let rtdb = FirebaseRtdb::from_env()
         .context("Failed to initialise Firebase RTDB client")?;
 
+    // The results archive was deleted with the stage 7 outputs
+    rtdb.delete(&format!("users/{}/jobs/{}/archive_key", target_uid, job_index))
+        .await
+        .context("Failed to clear the job's results archive")?;
+
+    // So are the timings of the stages being rerun, until each runs again
+    for s in stage..=NUM_STAGES {
+        rtdb.delete(&format!("users/{}/jobs/{}/stages/stage_{}", target_uid, job_index, s))
+            .await
+            .context(format!("Failed to clear the job's stage {} result", s))?;
+    }
+
     let path = queue_item_path(target_stage, &job_id);
     rtdb.set(&path, &queue_item)
         .await
@@ -162,7 +199,7 @@ pub async fn rerun_entrypoint(
     println!("Job {} pushed to stage {} queue", job_id, stage);
 
     // ── 6. Update job status ────────────────────────────────────────
-    let status = JobStatus::processing(stage);
+    let status = JobStatus::processing(stage, job.locale);
     app.db
         .lock()
         .await
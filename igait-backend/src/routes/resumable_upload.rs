@@ -0,0 +1,676 @@
+//! Resumable, chunked video uploads.
+//!
+//! An alternative to `upload_entrypoint` for unreliable connections. The
+//! client opens an upload session with the patient details and the size of
+//! each video, then sends the videos in fixed-size chunks. Each chunk is
+//! stored as a part of an AWS S3 multipart upload, so a dropped connection
+//! only loses the chunk in flight - the client asks which parts have been
+//! received and carries on from there.
+//!
+//! The job is only created, and pushed to the Stage 1 queue, once the
+//! session is completed with both videos fully uploaded.
+//!
+//! Sessions are stored in Firebase RTDB at `upload_sessions/{uid}/{session_id}`.
+//! Sessions that are abandoned are expired by the sweeper in
+//! `crate::helper::upload_sessions` once they can no longer be resumed.
+
+use std::{collections::HashMap, sync::Arc, time::Duration};
+
+use axum::{body::Bytes, extract::{Path, State}, http::StatusCode, Json};
+use anyhow::{Context, Result};
+use firebase_auth::FirebaseUser;
+use serde::{Deserialize, Serialize};
+
+use igait_lib::microservice::{FirebaseRtdb, StorageClient, StoragePaths, now_ms};
+
+use crate::{
+    helper::{
+        lib::{AppError, AppState, AppStatePtr, JobMedia},
+        media,
+        quota::{self, QuotaReservation, Submission},
+        upload_sessions::upload_session_path,
+        webhooks::generate_id,
+    },
+    routes::upload::{FileDeclaration, JobDetails, create_job, fail_submission, submit_to_pipeline},
+};
+
+/// The size of every chunk except the last (8 MiB).
+///
+/// AWS S3 requires every part of a multipart upload except the last to
+/// be at least 5 MiB.
+pub const CHUNK_SIZE: u64 = 8 * 1024 * 1024;
+
+/// How long a session can be resumed for after it's opened.
+const SESSION_LIFETIME: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// One of the two videos in a submission.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum UploadFile {
+    Front,
+    Side,
+}
+
+impl UploadFile {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Self::Front => "front",
+            Self::Side => "side",
+        }
+    }
+}
+
+/// Request body for opening an upload session.
+#[derive(Debug, Deserialize)]
+pub struct CreateUploadSessionRequest {
+    /// The patient details for the job
+    #[serde(flatten)]
+    pub details: JobDetails,
+    /// The front video
+    pub front: FileDeclaration,
+    /// The side video
+    pub side: FileDeclaration,
+}
+
+/// A chunk that has been received and stored.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UploadedPart {
+    pub part_number: i32,
+    pub etag: String,
+    pub size: u64,
+}
+
+/// The upload state of one of a session's videos.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UploadSessionFile {
+    /// The original file name
+    pub file_name: String,
+    /// The file's extension, taken from its name
+    pub extension: String,
+    /// The declared size of the file in bytes
+    pub size: u64,
+    /// Where the video is staged until the job is created
+    pub key: String,
+    /// The AWS S3 multipart upload ID
+    pub upload_id: String,
+    /// The chunks received so far, keyed by "part_{n}"
+    #[serde(default)]
+    pub parts: HashMap<String, UploadedPart>,
+    /// Whether the multipart upload has been assembled
+    #[serde(default)]
+    pub assembled: bool,
+}
+
+impl UploadSessionFile {
+    /// How many chunks the file is split into.
+    fn total_parts(&self) -> i32 {
+        self.size.div_ceil(CHUNK_SIZE) as i32
+    }
+
+    /// How big a given chunk must be.
+    fn expected_part_size(&self, part_number: i32) -> u64 {
+        if part_number == self.total_parts() {
+            self.size - CHUNK_SIZE * (part_number as u64 - 1)
+        } else {
+            CHUNK_SIZE
+        }
+    }
+
+    /// The chunks that haven't been received yet.
+    fn missing_parts(&self) -> Vec<i32> {
+        (1..=self.total_parts())
+            .filter(|n| !self.parts.contains_key(&part_key(*n)))
+            .collect()
+    }
+}
+
+/// An open upload session.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UploadSession {
+    pub id: String,
+    pub details: JobDetails,
+    pub front: UploadSessionFile,
+    pub side: UploadSessionFile,
+    /// When the session was opened (Unix timestamp ms)
+    pub created_at: u64,
+    /// When the session can no longer be resumed (Unix timestamp ms)
+    pub expires_at: u64,
+    /// The quota slot the session holds, if its owner is limited
+    #[serde(default)]
+    pub reservation: Option<QuotaReservation>,
+}
+
+impl UploadSession {
+    fn file(&self, file: UploadFile) -> &UploadSessionFile {
+        match file {
+            UploadFile::Front => &self.front,
+            UploadFile::Side => &self.side,
+        }
+    }
+
+    fn file_mut(&mut self, file: UploadFile) -> &mut UploadSessionFile {
+        match file {
+            UploadFile::Front => &mut self.front,
+            UploadFile::Side => &mut self.side,
+        }
+    }
+}
+
+/// The progress of one of a session's videos.
+#[derive(Debug, Serialize)]
+pub struct FileProgress {
+    /// The original file name
+    pub file_name: String,
+    /// The size of the file in bytes
+    pub size: u64,
+    /// How many chunks the file is split into
+    pub total_parts: i32,
+    /// The chunks that still need to be sent
+    pub missing_parts: Vec<i32>,
+    /// How many bytes have been received
+    pub received_bytes: u64,
+}
+
+impl From<&UploadSessionFile> for FileProgress {
+    fn from(file: &UploadSessionFile) -> Self {
+        Self {
+            file_name: file.file_name.clone(),
+            size: file.size,
+            total_parts: file.total_parts(),
+            missing_parts: file.missing_parts(),
+            received_bytes: file.parts.values().map(|part| part.size).sum(),
+        }
+    }
+}
+
+/// Response body describing a session's progress.
+#[derive(Debug, Serialize)]
+pub struct UploadSessionResponse {
+    /// The session ID
+    pub session_id: String,
+    /// The size of every chunk except the last
+    pub chunk_size: u64,
+    /// When the session can no longer be resumed (Unix timestamp ms)
+    pub expires_at: u64,
+    /// The front video's progress
+    pub front: FileProgress,
+    /// The side video's progress
+    pub side: FileProgress,
+}
+
+impl From<&UploadSession> for UploadSessionResponse {
+    fn from(session: &UploadSession) -> Self {
+        Self {
+            session_id: session.id.clone(),
+            chunk_size: CHUNK_SIZE,
+            expires_at: session.expires_at,
+            front: (&session.front).into(),
+            side: (&session.side).into(),
+        }
+    }
+}
+
+/// Response body for completing a session.
+#[derive(Debug, Serialize)]
+pub struct CompleteUploadResponse {
+    /// The ID of the job that was created (format: "{user_id}_{job_index}")
+    pub job_id: String,
+}
+
+/// Response body for cancelling a session.
+#[derive(Debug, Serialize)]
+pub struct CancelUploadResponse {
+    /// Whether the session was cancelled
+    pub success: bool,
+}
+
+/// `POST /api/v1/uploads`
+///
+/// Opens an upload session. Both videos are then sent in `chunk_size`
+/// chunks to `PUT /api/v1/uploads/:session_id/:file/parts/:part_number`.
+///
+/// Opening a session reserves a slot in the user's submission quota. The
+/// slot is given back if the session is cancelled or expires, or if its
+/// videos are rejected when it's completed.
+///
+/// # Fails
+/// * With a `400` if a declared file is invalid
+/// * With a `429` if the user has hit their daily or in-flight job limit
+pub async fn create_upload_session_entrypoint(
+    current_user: FirebaseUser,
+    State(app): State<AppStatePtr>,
+    Json(request): Json<CreateUploadSessionRequest>,
+) -> Result<Json<UploadSessionResponse>, AppError> {
+    let app = app.state;
+    let uid = &current_user.user_id;
+
+    // ── 1. Validate the declared files ──────────────────────────────
+    let front_extension = request.front.extension()?;
+    let side_extension = request.side.extension()?;
+
+    // ── 2. Reserve a quota slot ─────────────────────────────────────
+    let reservation = quota::reserve(&app, uid, Submission::Job).await?;
+
+    let result = open_upload_session(&app, uid, request, front_extension, side_extension, reservation.clone()).await;
+    if let (Err(_), Some(reservation)) = (&result, reservation) {
+        reservation.release().await;
+    }
+
+    result.map(Json)
+}
+
+/// Starts the multipart uploads for a new session and stores it.
+async fn open_upload_session(
+    app: &Arc<AppState>,
+    uid: &str,
+    request: CreateUploadSessionRequest,
+    front_extension: String,
+    side_extension: String,
+    reservation: Option<QuotaReservation>,
+) -> Result<UploadSessionResponse, AppError> {
+    // ── 3. Start a multipart upload for each video ──────────────────
+    let session_id = generate_id();
+    let mut files = Vec::new();
+    for (file, declaration, extension) in [
+        (UploadFile::Front, request.front, front_extension),
+        (UploadFile::Side, request.side, side_extension),
+    ] {
+        let key = StoragePaths::upload_session_file(uid, &session_id, file.as_str(), &extension);
+        let upload_id = app.storage
+            .create_multipart_upload(&key, Some(media::content_type(&extension)))
+            .await
+            .context("Failed to start the upload in AWS S3!")?;
+
+        files.push(UploadSessionFile {
+            file_name: declaration.file_name,
+            extension,
+            size: declaration.size,
+            key,
+            upload_id,
+            parts: HashMap::new(),
+            assembled: false,
+        });
+    }
+    let side = files.pop().context("Missing side video")?;
+    let front = files.pop().context("Missing front video")?;
+
+    // ── 4. Store the session ────────────────────────────────────────
+    let session = UploadSession {
+        id: session_id,
+        details: request.details,
+        front,
+        side,
+        created_at: now_ms(),
+        expires_at: now_ms() + SESSION_LIFETIME.as_millis() as u64,
+        reservation,
+    };
+
+    let rtdb = FirebaseRtdb::from_env()
+        .context("Failed to initialise Firebase RTDB client")?;
+    rtdb.set(&upload_session_path(uid, &session.id), &session)
+        .await
+        .context("Failed to store the upload session")?;
+
+    println!("Opened upload session {} for user {}", session.id, uid);
+    Ok((&session).into())
+}
+
+/// `GET /api/v1/uploads/:session_id`
+///
+/// Returns which chunks of each video have been received, so an
+/// interrupted upload can be resumed.
+///
+/// # Fails
+/// * With a `404` if the session doesn't exist
+/// * With a `410` if the session has expired
+pub async fn upload_session_status_entrypoint(
+    current_user: FirebaseUser,
+    State(_app): State<AppStatePtr>,
+    Path(session_id): Path<String>,
+) -> Result<Json<UploadSessionResponse>, AppError> {
+    let rtdb = FirebaseRtdb::from_env()
+        .context("Failed to initialise Firebase RTDB client")?;
+
+    let (session, _) = read_session(&rtdb, &current_user.user_id, &session_id).await?;
+
+    Ok(Json((&session).into()))
+}
+
+/// `PUT /api/v1/uploads/:session_id/:file/parts/:part_number`
+///
+/// Stores one chunk of a video. `file` is `front` or `side`, and part
+/// numbers start at 1. Every chunk must be exactly `chunk_size` bytes,
+/// except the last, which holds the remainder. Sending a chunk again
+/// replaces it.
+///
+/// # Fails
+/// * With a `400` if the part number or chunk size is wrong
+/// * With a `404` if the session doesn't exist
+/// * With a `409` if the video has already been completed
+/// * With a `410` if the session has expired
+pub async fn upload_part_entrypoint(
+    current_user: FirebaseUser,
+    State(app): State<AppStatePtr>,
+    Path((session_id, file, part_number)): Path<(String, UploadFile, i32)>,
+    body: Bytes,
+) -> Result<Json<FileProgress>, AppError> {
+    let app = app.state;
+    let uid = &current_user.user_id;
+
+    let rtdb = FirebaseRtdb::from_env()
+        .context("Failed to initialise Firebase RTDB client")?;
+    let (session, _) = read_session(&rtdb, uid, &session_id).await?;
+    let session_file = session.file(file);
+
+    // ── 1. Validate the chunk ───────────────────────────────────────
+    if session_file.assembled {
+        return Err(AppError::client(
+            StatusCode::CONFLICT,
+            format!("The {} video has already been completed.", file.as_str()),
+        ));
+    }
+    if !(1..=session_file.total_parts()).contains(&part_number) {
+        return Err(AppError::client(
+            StatusCode::BAD_REQUEST,
+            format!(
+                "Invalid part number {}. The {} video has parts 1 to {}.",
+                part_number,
+                file.as_str(),
+                session_file.total_parts()
+            ),
+        ));
+    }
+    let expected_size = session_file.expected_part_size(part_number);
+    if body.len() as u64 != expected_size {
+        return Err(AppError::client(
+            StatusCode::BAD_REQUEST,
+            format!(
+                "Part {} of the {} video must be {} bytes, but {} were sent.",
+                part_number,
+                file.as_str(),
+                expected_size,
+                body.len()
+            ),
+        ));
+    }
+
+    // ── 2. Store it in AWS S3 ───────────────────────────────────────
+    let etag = app.storage
+        .upload_part(&session_file.key, &session_file.upload_id, part_number, body.to_vec())
+        .await
+        .context("Failed to store the chunk in AWS S3!")?;
+
+    // ── 3. Record it on the session ─────────────────────────────────
+    let part = UploadedPart {
+        part_number,
+        etag,
+        size: expected_size,
+    };
+    let part_path = format!(
+        "{}/{}/parts/{}",
+        upload_session_path(uid, &session_id),
+        file.as_str(),
+        part_key(part_number)
+    );
+    rtdb.set(&part_path, &part)
+        .await
+        .context("Failed to record the chunk on the upload session")?;
+
+    let mut session_file = session_file.clone();
+    session_file.parts.insert(part_key(part_number), part);
+
+    Ok(Json((&session_file).into()))
+}
+
+/// `POST /api/v1/uploads/:session_id/complete`
+///
+/// Assembles both videos, creates the job, and pushes it to the Stage 1
+/// queue. Fails without creating a job if any chunk is missing.
+///
+/// # Workflow
+/// 1. Check every chunk of both videos has been received
+/// 2. Claim the session, so it's only ever completed once
+/// 3. Assemble each video's multipart upload
+/// 4. Probe both videos and check them against the media limits
+/// 5. Create the job in the database
+/// 6. Move the videos to the job's storage path
+/// 7. Dispatch to Stage 1 and send the welcome email
+///
+/// If assembling or probing the videos fails, the session is put back so
+/// the request can be retried. A video that fails the probe can't be fixed
+/// by uploading more chunks, though, so the session is discarded and a
+/// `422` is returned.
+///
+/// # Fails
+/// * With a `404` if the session doesn't exist
+/// * With a `409` if a chunk is missing, or the session is already being
+///   completed or cancelled
+/// * With a `410` if the session has expired
+/// * With a `422` if either video is unreadable or outside the media limits
+pub async fn complete_upload_session_entrypoint(
+    current_user: FirebaseUser,
+    State(app): State<AppStatePtr>,
+    Path(session_id): Path<String>,
+) -> Result<Json<CompleteUploadResponse>, AppError> {
+    let app = app.state;
+    let uid = &current_user.user_id;
+
+    let rtdb = FirebaseRtdb::from_env()
+        .context("Failed to initialise Firebase RTDB client")?;
+    let (mut session, etag) = read_session(&rtdb, uid, &session_id).await?;
+
+    // ── 1. Check both videos are fully uploaded ─────────────────────
+    for file in [UploadFile::Front, UploadFile::Side] {
+        let missing = session.file(file).missing_parts();
+        if !missing.is_empty() {
+            return Err(AppError::client(
+                StatusCode::CONFLICT,
+                format!(
+                    "The {} video is missing parts {:?}. Upload them before completing.",
+                    file.as_str(),
+                    missing
+                ),
+            ));
+        }
+    }
+
+    // ── 2. Claim the session ────────────────────────────────────────
+    // Removing the session claims it, and only succeeds if nobody else has
+    // changed it since it was read, so concurrent requests can't both turn
+    // the same videos into a job
+    claim_session(&rtdb, uid, &session_id, &etag).await?;
+
+    // ── 3. Assemble each video ──────────────────────────────────────
+    for file in [UploadFile::Front, UploadFile::Side] {
+        let session_file = session.file_mut(file);
+        if session_file.assembled {
+            continue;
+        }
+
+        let mut parts: Vec<(i32, String)> = session_file.parts
+            .values()
+            .map(|part| (part.part_number, part.etag.clone()))
+            .collect();
+        parts.sort_by_key(|(part_number, _)| *part_number);
+
+        if let Err(err) = app.storage
+            .complete_multipart_upload(&session_file.key, &session_file.upload_id, &parts)
+            .await
+        {
+            restore_session(&rtdb, uid, &session).await;
+            return Err(AppError(err.context(format!("Failed to assemble the {} video!", file.as_str()))));
+        }
+        session_file.assembled = true;
+    }
+
+    // ── 4. Probe both videos ────────────────────────────────────────
+    let probed: Result<_> = async {
+        let front = media::probe_stored(&app.storage, &session.front.key, session.front.size)
+            .await
+            .context("Failed to probe the front video")?;
+        let side = media::probe_stored(&app.storage, &session.side.key, session.side.size)
+            .await
+            .context("Failed to probe the side video")?;
+        Ok((front, side))
+    }.await;
+    let (front, side) = match probed {
+        Ok(probed) => probed,
+        Err(err) => {
+            restore_session(&rtdb, uid, &session).await;
+            return Err(AppError(err));
+        }
+    };
+    let job_media = match (media::validate("front", front), media::validate("side", side)) {
+        (Ok(front), Ok(side)) => JobMedia { front, side },
+        (Err(err), _) | (_, Err(err)) => {
+            // The videos can't be fixed by resuming, so start over
+            discard_staged_videos(&app.storage, uid, &session_id).await;
+            if let Some(reservation) = session.reservation {
+                reservation.release().await;
+            }
+            return Err(err);
+        }
+    };
+
+    // ── 5. Create the job ───────────────────────────────────────────
+    let mut job = session.details.clone().into_job();
+    job.media = Some(job_media);
+    let job_index = create_job(&app, uid, &job).await?;
+    let job_id = format!("{}_{}", uid, job_index);
+    if let Some(reservation) = session.reservation.as_mut() {
+        reservation.settle().await;
+    }
+
+    // ── 6. Move the videos to the job's storage path ────────────────
+    let front_key = StoragePaths::upload_front_video(&job_id, &session.front.extension);
+    let side_key = StoragePaths::upload_side_video(&job_id, &session.side.extension);
+    let moved: Result<()> = async {
+        app.storage.copy(&session.front.key, &front_key).await?;
+        app.storage.copy(&session.side.key, &side_key).await?;
+        Ok(())
+    }.await;
+    if let Err(err) = moved {
+        return Err(fail_submission(&app, uid, job_index, err).await);
+    }
+
+    discard_staged_videos(&app.storage, uid, &session_id).await;
+
+    // ── 7. Dispatch to Stage 1 ──────────────────────────────────────
+    println!("Upload session {} completed as job {}", session_id, job_id);
+    submit_to_pipeline(app, uid, job_index, &job, front_key, side_key).await?;
+
+    Ok(Json(CompleteUploadResponse { job_id }))
+}
+
+/// `DELETE /api/v1/uploads/:session_id`
+///
+/// Cancels an upload session, discarding any chunks received.
+///
+/// # Fails
+/// * With a `404` if the session doesn't exist
+/// * With a `409` if the session is already being completed or cancelled
+pub async fn cancel_upload_session_entrypoint(
+    current_user: FirebaseUser,
+    State(app): State<AppStatePtr>,
+    Path(session_id): Path<String>,
+) -> Result<Json<CancelUploadResponse>, AppError> {
+    let app = app.state;
+    let uid = &current_user.user_id;
+
+    let rtdb = FirebaseRtdb::from_env()
+        .context("Failed to initialise Firebase RTDB client")?;
+    let (session, etag) = rtdb
+        .get_with_etag::<UploadSession>(&upload_session_path(uid, &session_id))
+        .await
+        .context("Failed to read the upload session")?;
+    let session = session.ok_or_else(|| session_not_found(&session_id))?;
+
+    claim_session(&rtdb, uid, &session_id, &etag).await?;
+
+    for file in [&session.front, &session.side] {
+        if let Err(e) = app.storage.abort_multipart_upload(&file.key, &file.upload_id).await {
+            eprintln!("Failed to abort multipart upload {}: {e:?}", file.key);
+        }
+    }
+    if let Some(reservation) = session.reservation {
+        reservation.release().await;
+    }
+
+    println!("Cancelled upload session {} for user {}", session_id, uid);
+    Ok(Json(CancelUploadResponse { success: true }))
+}
+
+/// Claims a session by removing it, but only if it hasn't changed since
+/// it was read, so it's completed or cancelled at most once.
+///
+/// # Fails
+/// * With a `409` if the session has changed or was already removed
+async fn claim_session(rtdb: &FirebaseRtdb, uid: &str, session_id: &str, etag: &str) -> Result<(), AppError> {
+    let claimed = rtdb.delete_if_match(&upload_session_path(uid, session_id), etag)
+        .await
+        .context("Failed to claim the upload session")?;
+    if !claimed {
+        return Err(AppError::client(
+            StatusCode::CONFLICT,
+            format!("Upload session {} is already being completed or cancelled.", session_id),
+        ));
+    }
+
+    Ok(())
+}
+
+/// Puts back a claimed session after a failure that retrying can fix,
+/// logging rather than failing.
+async fn restore_session(rtdb: &FirebaseRtdb, uid: &str, session: &UploadSession) {
+    if let Err(e) = rtdb.set(&upload_session_path(uid, &session.id), session).await {
+        eprintln!("Failed to restore upload session {}: {e:?}", session.id);
+    }
+}
+
+/// Returns the key a chunk is stored under on its session.
+///
+/// Numeric keys would make RTDB return the parts as an array.
+fn part_key(part_number: i32) -> String {
+    format!("part_{}", part_number)
+}
+
+/// Deletes a session's assembled videos, logging rather than failing.
+async fn discard_staged_videos(storage: &StorageClient, uid: &str, session_id: &str) {
+    if let Err(e) = storage
+        .delete_by_prefix(&StoragePaths::upload_session_dir(uid, session_id))
+        .await
+    {
+        eprintln!("Failed to clean up staged videos for upload session {}: {e:?}", session_id);
+    }
+}
+
+/// Reads one of the caller's upload sessions along with its ETag,
+/// failing if it has expired.
+///
+/// # Fails
+/// * With a `404` if the session doesn't exist
+/// * With a `410` if the session has expired
+async fn read_session(rtdb: &FirebaseRtdb, uid: &str, session_id: &str) -> Result<(UploadSession, String), AppError> {
+    let (session, etag) = rtdb
+        .get_with_etag::<UploadSession>(&upload_session_path(uid, session_id))
+        .await
+        .context("Failed to read the upload session")?;
+    let session = session.ok_or_else(|| session_not_found(session_id))?;
+
+    if now_ms() > session.expires_at {
+        return Err(AppError::client(
+            StatusCode::GONE,
+            format!("Upload session {} has expired. Please start the upload again.", session_id),
+        ));
+    }
+
+    Ok((session, etag))
+}
+
+/// The error for a session that doesn't exist.
+fn session_not_found(session_id: &str) -> AppError {
+    AppError::client(
+        StatusCode::NOT_FOUND,
+        format!("Upload session {} does not exist.", session_id),
+    )
+}
@@ -0,0 +1,124 @@
+//! Timeline endpoint for per-stage processing history.
+//!
+//! Returns the timing result each worker recorded for a job, ordered by
+//! stage, so the frontend can show where processing time went.
+//! The caller must own the job **or** be an admin.
+
+use axum::{extract::{Path, State}, Json};
+use anyhow::{Context, anyhow};
+use chrono::{DateTime, Utc};
+use firebase_auth::FirebaseUser;
+use serde::Serialize;
+
+use igait_lib::microservice::StageNumber;
+
+use crate::helper::{
+    lib::{AppError, AppStatePtr, StageStatus, NUM_STAGES},
+    organizations::{JobAccess, ensure_job_access},
+};
+
+/// A single stage in a job's timeline.
+#[derive(Debug, Serialize)]
+pub struct TimelineEntry {
+    /// The stage number (1-7)
+    pub stage: u8,
+    /// The human-readable stage name (e.g. "Pose Estimation")
+    pub name: &'static str,
+    /// The status of the stage
+    pub status: StageStatus,
+    /// When a worker started the stage
+    pub started_at: Option<DateTime<Utc>>,
+    /// When the stage finished
+    pub completed_at: Option<DateTime<Utc>>,
+    /// How long the stage took to process
+    pub duration_ms: Option<u64>,
+    /// The ID of the worker that ran the stage
+    pub worker_id: Option<String>,
+    /// The outputs the stage produced
+    pub output_keys: Vec<String>,
+    /// The error message, if the stage failed
+    pub error: Option<String>,
+}
+
+/// Response body: the job's stages in pipeline order.
+#[derive(Debug, Serialize)]
+pub struct JobTimelineResponse {
+    /// The job ID (format: "{user_id}_{job_index}")
+    pub job_id: String,
+    /// Every stage that has recorded a result, in pipeline order
+    pub stages: Vec<TimelineEntry>,
+    /// The sum of all recorded stage durations
+    pub total_duration_ms: u64,
+}
+
+/// `GET /api/v1/jobs/:job_id/timeline`
+///
+/// Returns the per-stage timing history recorded for the given job.
+/// Stages that haven't been reached yet are omitted.
+///
+/// # Authorization
+/// - The authenticated user must **own** the job (their UID is the prefix
+///   of `job_id`), share an organization with its owner, **or** be an
+///   administrator.
+pub async fn timeline_entrypoint(
+    current_user: FirebaseUser,
+    State(app): State<AppStatePtr>,
+    Path(job_id): Path<String>,
+) -> Result<Json<JobTimelineResponse>, AppError> {
+    let app = &app.state;
+    let caller_uid = &current_user.user_id;
+
+    // ── 1. Authorization ────────────────────────────────────────────
+    // job_id format: "{user_id}_{job_index}"
+    let (owner_uid, job_index) = job_id
+        .rsplit_once('_')
+        .ok_or_else(|| anyhow!("Invalid job ID format: {}", job_id))?;
+    let job_index: usize = job_index
+        .parse()
+        .context("Invalid job index in job ID")?;
+
+    ensure_job_access(app, caller_uid, owner_uid, JobAccess::View).await?;
+
+    // ── 2. Fetch the job ────────────────────────────────────────────
+    let job = app
+        .db
+        .lock()
+        .await
+        .get_job(owner_uid, job_index)
+        .await
+        .context("Failed to fetch the job — does it exist?")?;
+
+    // ── 3. Order the recorded stages ────────────────────────────────
+    let mut stages = Vec::new();
+    for n in 1..=NUM_STAGES {
+        let Some(result) = job.stages.get(&format!("stage_{}", n)) else {
+            continue;
+        };
+        let Some(stage) = StageNumber::from_u8(n) else {
+            continue;
+        };
+
+        stages.push(TimelineEntry {
+            stage: n,
+            name: stage.name(),
+            status: result.status,
+            started_at: result.started_at,
+            completed_at: result.completed_at,
+            duration_ms: result.duration_ms,
+            worker_id: result.worker_id.clone(),
+            output_keys: result.output_keys.clone().unwrap_or_default(),
+            error: result.error.clone(),
+        });
+    }
+
+    let total_duration_ms = stages
+        .iter()
+        .filter_map(|entry| entry.duration_ms)
+        .sum();
+
+    Ok(Json(JobTimelineResponse {
+        job_id,
+        stages,
+        total_duration_ms,
+    }))
+}
@@ -5,35 +5,142 @@
 
 use std::{collections::HashMap, sync::Arc, time::SystemTime};
 
-use axum::{body::Bytes, extract::{Multipart, State}};
+use axum::{body::Bytes, extract::{Multipart, State}, http::{HeaderMap, StatusCode}, Json};
 use anyhow::{Result, Context, anyhow};
 use firebase_auth::FirebaseUser;
+use serde::{Deserialize, Serialize};
 
-use igait_lib::microservice::{StoragePaths, JobMetadata, QueueItem, StageNumber, FirebaseRtdb, queue_item_path};
+use igait_lib::microservice::{StoragePaths, JobMetadata, Locale, QueueItem, StageNumber, FirebaseRtdb, queue_item_path};
 
 use crate::helper::{
     email::send_welcome_email,
-    lib::{AppError, AppState, AppStatePtr, Job, JobStatus, Sex, Ethnicity},
+    idempotency::{self, Idempotency},
+    quota::{self, Submission},
+    lib::{AppError, AppState, AppStatePtr, Job, JobMedia, JobStatus, Sex, Ethnicity},
+    media,
 };
 
 /// The required arguments for the upload request.
 struct UploadRequestArguments {
-    age:        i16,
-    ethnicity:  Ethnicity,
-    sex:        Sex,
-    height:     String,
-    weight:     i16,
-    email:      String,
+    details:    JobDetails,
     front_file: UploadRequestFile,
     side_file:  UploadRequestFile,
-    requires_approval: bool,
+}
+
+/// The patient details submitted alongside the videos.
+///
+/// # Fields
+/// * `age` - The age of the patient
+/// * `ethnicity` - The ethnicity of the patient
+/// * `sex` - The sex of the patient
+/// * `height` - The height of the patient
+/// * `weight` - The weight of the patient
+/// * `email` - The email to send results to
+/// * `requires_approval` - Whether the job must be approved before processing
+/// * `locale` - The language for status updates and emails (e.g. "es"), English if missing
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JobDetails {
+    pub age:       i16,
+    pub ethnicity: Ethnicity,
+    pub sex:       Sex,
+    pub height:    String,
+    pub weight:    i16,
+    pub email:     String,
+    #[serde(default)]
+    pub requires_approval: bool,
+    #[serde(default)]
+    pub locale: Option<Locale>,
+}
+
+impl JobDetails {
+    /// Builds a freshly submitted job from the details.
+    pub fn into_job(self) -> Job {
+        Job {
+            age:       self.age,
+            ethnicity: self.ethnicity,
+            sex:       self.sex,
+            height:    self.height,
+            weight:    self.weight,
+            status:    JobStatus::submitted(self.locale.unwrap_or_default()),
+            email:     self.email,
+            timestamp: SystemTime::now(),
+            requires_approval: self.requires_approval,
+            // Start unapproved — the worker logic will allow pick-up
+            // if neither the job nor the queue requires approval.
+            approved: false,
+            stage_logs: HashMap::new(),
+            stages: HashMap::new(),
+            review: None,
+            media: None,
+            batch_id: None,
+            archive_key: None,
+            locale: self.locale.unwrap_or_default(),
+        }
+    }
+}
+
+/// The largest video that can be uploaded.
+pub const MAX_FILE_SIZE: u64 = 500_000_000;
+
+/// A video the client intends to upload, for flows where the bytes
+/// are sent separately from the job details.
+#[derive(Debug, Deserialize)]
+pub struct FileDeclaration {
+    /// The original file name, used for its extension (e.g. "front.mp4")
+    pub file_name: String,
+    /// The size of the file in bytes
+    pub size: u64,
+}
+
+impl FileDeclaration {
+    /// Validates the declared size and returns the file's extension.
+    ///
+    /// # Fails
+    /// * With a `400` if the size is out of range or the name has no extension
+    pub fn extension(&self) -> Result<String, AppError> {
+        if self.size == 0 || self.size > MAX_FILE_SIZE {
+            return Err(AppError::client(StatusCode::BAD_REQUEST, format!(
+                "Invalid size for '{}'. Videos must be between 1 byte and {} bytes.",
+                self.file_name,
+                MAX_FILE_SIZE
+            )));
+        }
+
+        file_extension(&self.file_name)
+    }
 }
 
 /// A representation of a file in a `Multipart` request.
 #[derive(Debug)]
-struct UploadRequestFile {
-    name:  String,
-    bytes: Bytes,
+pub struct UploadRequestFile {
+    pub name:  String,
+    pub bytes: Bytes,
+}
+
+impl UploadRequestFile {
+    /// Returns the file's extension.
+    ///
+    /// # Fails
+    /// * With a `400` if the name has no extension
+    pub fn extension(&self) -> Result<String, AppError> {
+        file_extension(&self.name)
+    }
+}
+
+/// Returns a file name's extension, lowercased, since it ends up in
+/// storage keys.
+///
+/// # Fails
+/// * With a `400` if the name has no extension, or it isn't ASCII alphanumeric
+fn file_extension(file_name: &str) -> Result<String, AppError> {
+    file_name
+        .rsplit_once('.')
+        .map(|(_, extension)| extension.to_lowercase())
+        .filter(|extension| !extension.is_empty() && extension.chars().all(|c| c.is_ascii_alphanumeric()))
+        .ok_or_else(|| AppError::client(
+            StatusCode::BAD_REQUEST,
+            format!("'{}' must have a file extension!", file_name),
+        ))
 }
 
 /// Takes in the `Multipart` request and unpacks the arguments into an `UploadRequestArguments` object.
@@ -52,6 +159,7 @@ async fn unpack_upload_arguments(multipart: &mut Multipart) -> Result<UploadRequ
     let mut weight_option:    Option<i16>    = None;
     let mut email_option:     Option<String> = None;
     let mut requires_approval: bool = false;
+    let mut locale_option:    Option<Locale> = None;
 
     // Initialize the file fields as options
     let mut front_file_name_option:  Option<String> = None;
@@ -156,6 +264,16 @@ async fn unpack_upload_arguments(multipart: &mut Multipart) -> Result<UploadRequ
                     .context("Field 'requires_approval' wasn't readable as text!")?;
                 requires_approval = text == "true" || text == "1";
             }
+            Some("locale") => {
+                locale_option = Some(
+                    field
+                        .text()
+                        .await
+                        .context("Field 'locale' wasn't readable as text!")?
+                        .parse()
+                        .context("Field 'locale' wasn't a supported locale!")?,
+                );
+            }
             _ => {
                 println!("Skipping unknown field: {name:?}");
             }
@@ -177,13 +295,16 @@ async fn unpack_upload_arguments(multipart: &mut Multipart) -> Result<UploadRequ
     let side_file_bytes  = side_file_bytes_option.ok_or(anyhow!("Missing 'fileuploadside' bytes!"))?;
 
     Ok(UploadRequestArguments {
-        age,
-        ethnicity,
-        sex,
-        height,
-        weight,
-        email,
-        requires_approval,
+        details: JobDetails {
+            age,
+            ethnicity,
+            sex,
+            height,
+            weight,
+            email,
+            requires_approval,
+            locale: locale_option,
+        },
         front_file: UploadRequestFile {
             name: front_file_name,
             bytes: front_file_bytes,
@@ -195,112 +316,252 @@ async fn unpack_upload_arguments(multipart: &mut Multipart) -> Result<UploadRequ
     })
 }
 
+/// Response body for a submitted job.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct UploadResponse {
+    /// The ID of the job that was created (format: "{user_id}_{job_index}")
+    pub job_id: String,
+}
+
 /// The entrypoint for the upload request.
 ///
 /// # Workflow
-/// 1. Parse and validate the multipart form data
-/// 2. Create a new job in the database
-/// 3. Upload videos to AWS S3
-/// 4. Dispatch to Stage 1 microservice
-/// 5. Send welcome email
+/// 1. Check the `Idempotency-Key`, replaying the original result for retries
+/// 2. Reserve a slot in the user's submission quota
+/// 3. Parse and validate the multipart form data
+/// 4. Probe both videos and check them against the media limits
+/// 5. Create a new job in the database
+/// 6. Upload videos to AWS S3
+/// 7. Dispatch to Stage 1 microservice
+/// 8. Send welcome email
 ///
 /// # Fails
 /// * If the arguments are missing or invalid
+/// * With a `409` if a request with the same `Idempotency-Key` is still in progress
+/// * With a `429` if the user has hit their daily or in-flight job limit
+/// * With a `422` if either video is unreadable or outside the media limits
 /// * If the files fail to upload to AWS S3
 /// * If the job fails to save to the database
 /// * If the welcome email fails to send
 pub async fn upload_entrypoint(
     current_user: FirebaseUser,
     State(app): State<AppStatePtr>,
+    headers: HeaderMap,
     mut multipart: Multipart,
-) -> Result<(), AppError> {
+) -> Result<Json<UploadResponse>, AppError> {
     let app = app.state;
     let uid = current_user.user_id;
 
+    let key = match idempotency::begin(&uid, "upload", &headers).await? {
+        Idempotency::Replay(response) => return Ok(Json(response)),
+        Idempotency::Claimed(key) => Some(key),
+        Idempotency::None => None,
+    };
+
+    let reservation = match quota::reserve(&app, &uid, Submission::Job).await {
+        Ok(reservation) => reservation,
+        Err(err) => {
+            if let Some(key) = key {
+                key.release().await;
+            }
+            return Err(err);
+        }
+    };
+
+    let created = match create_upload(app.clone(), &uid, &mut multipart).await {
+        Ok(created) => created,
+        Err(err) => {
+            // Nothing was created, so the attempt can be retried from scratch
+            if let Some(reservation) = reservation {
+                reservation.release().await;
+            }
+            if let Some(key) = key {
+                key.release().await;
+            }
+            return Err(err);
+        }
+    };
+
+    // The job exists from here on, even if storing or dispatching it fails,
+    // so it keeps its quota slot and retries are given it instead of a new one
+    if let Some(mut reservation) = reservation {
+        reservation.settle().await;
+    }
+    let response = UploadResponse { job_id: format!("{}_{}", uid, created.job_index) };
+    if let Some(key) = key {
+        if let Err(e) = key.complete(&response).await {
+            eprintln!("Failed to remember the upload for its idempotency key: {e:?}");
+        }
+    }
+
+    finish_upload(app, &uid, created).await?;
+
+    Ok(Json(response))
+}
+
+/// A job created from a multipart upload, whose videos are still in memory.
+struct CreatedUpload {
+    job_index: usize,
+    job: Job,
+    front_file: UploadRequestFile,
+    side_file: UploadRequestFile,
+}
+
+/// Unpacks and probes a multipart upload, then creates its job.
+async fn create_upload(
+    app: Arc<AppState>,
+    uid: &str,
+    multipart: &mut Multipart,
+) -> Result<CreatedUpload, AppError> {
     println!("Unpacking upload arguments...");
-    let arguments = unpack_upload_arguments(&mut multipart)
+    let arguments = unpack_upload_arguments(multipart)
         .await
         .context("Failed to unpack arguments!")?;
 
-    // Build a new status object
-    let mut status = JobStatus::submitted();
+    // Reject unreadable or unsuitable videos before creating the job
+    println!("Probing videos...");
+    let media = probe_files(&arguments.front_file, &arguments.side_file).await?;
 
-    // Generate the new job ID (0-indexed)
-    let job_index = app
-        .db
-        .lock()
-        .await
-        .count_jobs(&uid)
-        .await
-        .context("Failed to count the number of jobs!")?;
+    // Add the job to the database
+    let mut job = arguments.details.into_job();
+    job.media = Some(media);
+    let job_index = create_job(&app, uid, &job).await?;
+
+    Ok(CreatedUpload {
+        job_index,
+        job,
+        front_file: arguments.front_file,
+        side_file: arguments.side_file,
+    })
+}
 
-    // Build job ID string (format: "{user_id}_{job_index}")
+/// Stores a created job's videos and submits it to the pipeline.
+async fn finish_upload(
+    app: Arc<AppState>,
+    uid: &str,
+    created: CreatedUpload,
+) -> Result<(), AppError> {
+    let job_index = created.job_index;
     let job_id = format!("{}_{}", uid, job_index);
-    println!("Created job ID: {}", job_id);
-
-    // Build the new job object
-    let job = Job {
-        age:       arguments.age,
-        ethnicity: arguments.ethnicity.clone(),
-        sex:       arguments.sex,
-        height:    arguments.height.clone(),
-        weight:    arguments.weight,
-        status:    status.clone(),
-        email:     arguments.email.clone(),
-        timestamp: SystemTime::now(),
-        requires_approval: arguments.requires_approval,
-        // Start unapproved — the worker logic will allow pick-up
-        // if neither the job nor the queue requires approval.
-        approved: false,
-        stage_logs: HashMap::new(),
-    };
 
-    // Add the job to the database
-    app.db
-        .lock()
-        .await
-        .new_job(&uid, job.clone())
-        .await
-        .context("Failed to add the new job to the database!")?;
-
-    // Upload files to AWS S3 and dispatch to Stage 1
-    if let Err(err) = upload_and_dispatch(
+    // Upload files to AWS S3
+    let (front_key, side_key) = match upload_files(
         app.clone(),
         &job_id,
-        &uid,
-        arguments.front_file,
-        arguments.side_file,
-        &job,
+        created.front_file,
+        created.side_file,
     )
     .await
     {
-        // Populate the status object with error
-        status = JobStatus::error(format!("Upload failed: {}", err));
+        Ok(keys) => keys,
+        Err(err) => return Err(fail_submission(&app, uid, job_index, err).await),
+    };
 
-        // Update the status of the job
-        app.db
-            .lock()
-            .await
-            .update_status(&uid, job_index, status)
-            .await
-            .context("Failed to update the status of the job!")?;
+    // Dispatch to Stage 1 and let the user know
+    submit_to_pipeline(app, uid, job_index, &created.job, front_key, side_key)
+        .await
+}
+
+/// Adds a new job to the user's job list.
+///
+/// # Arguments
+/// * `app` - The application state
+/// * `uid` - The user ID to add the job to
+/// * `job` - The job to add
+///
+/// # Returns
+/// * The index of the new job (0-indexed)
+pub async fn create_job(
+    app: &Arc<AppState>,
+    uid: &str,
+    job: &Job,
+) -> Result<usize, AppError> {
+    let db = app.db.lock().await;
 
-        return Err(AppError(err.context("Failed to upload files or dispatch job!")));
+    // Generate the new job ID (0-indexed)
+    let job_index = db
+        .count_jobs(uid)
+        .await
+        .context("Failed to count the number of jobs!")?;
+
+    // Job IDs are formatted as "{user_id}_{job_index}"
+    println!("Created job ID: {}_{}", uid, job_index);
+
+    db.new_job(uid, job.clone())
+        .await
+        .context("Failed to add the new job to the database!")?;
+
+    Ok(job_index)
+}
+
+/// Marks a job as failed after its videos couldn't be stored or dispatched.
+///
+/// # Returns
+/// * The error to return from the route
+pub async fn fail_submission(
+    app: &Arc<AppState>,
+    uid: &str,
+    job_index: usize,
+    err: anyhow::Error,
+) -> AppError {
+    let db = app.db.lock().await;
+
+    // Populate the status object with error, in the job's locale
+    let locale = db.get_job(uid, job_index)
+        .await
+        .map(|job| job.locale)
+        .unwrap_or_default();
+    let status = JobStatus::error(format!("Upload failed: {}", err), locale);
+
+    // Update the status of the job
+    if let Err(status_err) = db
+        .update_status(uid, job_index, status)
+        .await
+    {
+        return AppError(status_err.context("Failed to update the status of the job!"));
     }
 
-    // Update status - job has been submitted and is ready for Stage 1
-    status = JobStatus::submitted();
+    AppError(err.context("Failed to upload files or dispatch job!"))
+}
+
+/// Pushes a job whose videos are in AWS S3 to the Stage 1 queue, then
+/// sends the welcome email.
+///
+/// # Arguments
+/// * `app` - The application state
+/// * `uid` - The user ID
+/// * `job_index` - The index of the job
+/// * `job` - The job containing all metadata
+/// * `front_key` - The storage key of the front video
+/// * `side_key` - The storage key of the side video
+///
+/// # Fails
+/// * If the job fails to be pushed to the Stage 1 queue (the job is marked as failed)
+/// * If the welcome email fails to send
+pub async fn submit_to_pipeline(
+    app: Arc<AppState>,
+    uid: &str,
+    job_index: usize,
+    job: &Job,
+    front_key: String,
+    side_key: String,
+) -> Result<(), AppError> {
+    let job_id = format!("{}_{}", uid, job_index);
+
+    if let Err(err) = dispatch_to_stage_1(&job_id, uid, front_key, side_key, job).await {
+        return Err(fail_submission(&app, uid, job_index, err).await);
+    }
 
     // Send the welcome email
-    send_welcome_email(app.clone(), &job, &uid, job_index)
+    send_welcome_email(app.clone(), job, uid, job_index)
         .await
         .context("Failed to send welcome email!")?;
 
-    // Update the status of the job
+    // Update status - job has been submitted and is ready for Stage 1
     app.db
         .lock()
         .await
-        .update_status(&uid, job_index, status)
+        .update_status(uid, job_index, JobStatus::submitted(job.locale))
         .await
         .context("Failed to update the status of the job!")?;
 
@@ -308,58 +569,95 @@ pub async fn upload_entrypoint(
     Ok(())
 }
 
-/// Uploads files to AWS S3 and pushes the job to the Stage 1 queue.
+/// Uploads both videos to AWS S3.
 ///
 /// # Arguments
 /// * `app` - The application state
 /// * `job_id` - The full job ID (format: "{user_id}_{job_index}")
-/// * `user_id` - The user ID
 /// * `front_file` - The front video file
 /// * `side_file` - The side video file
-/// * `job` - The job containing all metadata
-async fn upload_and_dispatch(
+///
+/// # Returns
+/// * The storage keys of the front and side videos
+pub async fn upload_files(
     app: Arc<AppState>,
     job_id: &str,
-    user_id: &str,
     front_file: UploadRequestFile,
     side_file: UploadRequestFile,
-    job: &Job,
-) -> Result<()> {
+) -> Result<(String, String)> {
     // Extract file extensions
-    let front_extension = front_file
-        .name
-        .rsplit('.')
-        .next()
-        .context("Front file must have an extension!")?;
-    let side_extension = side_file
-        .name
-        .rsplit('.')
-        .next()
-        .context("Side file must have an extension!")?;
+    let front_extension = front_file.extension().map_err(|e| e.0)?;
+    let side_extension = side_file.extension().map_err(|e| e.0)?;
 
     // Build storage paths
-    let front_key = StoragePaths::upload_front_video(job_id, front_extension);
-    let side_key = StoragePaths::upload_side_video(job_id, side_extension);
+    let front_key = StoragePaths::upload_front_video(job_id, &front_extension);
+    let side_key = StoragePaths::upload_side_video(job_id, &side_extension);
 
     println!("Uploading front video to: {}", front_key);
     let _: () = app.storage
-        .upload(&front_key, front_file.bytes.to_vec(), Some("video/mp4"))
+        .upload(&front_key, front_file.bytes.to_vec(), Some(media::content_type(&front_extension)))
         .await
         .context("Failed to upload front video to AWS S3!")?;
 
     println!("Uploading side video to: {}", side_key);
     let _: () = app.storage
-        .upload(&side_key, side_file.bytes.to_vec(), Some("video/mp4"))
+        .upload(&side_key, side_file.bytes.to_vec(), Some(media::content_type(&side_extension)))
         .await
         .context("Failed to upload side video to AWS S3!")?;
 
-    println!("Files uploaded successfully, pushing to Stage 1 queue...");
+    println!("Files uploaded successfully");
+    Ok((front_key, side_key))
+}
+
+/// Probes both videos and checks them against the configured limits.
+///
+/// # Arguments
+/// * `front_file` - The front video file
+/// * `side_file` - The side video file
+///
+/// # Fails
+/// * With a `4xx` if either video is missing an extension, unreadable, or unsuitable
+/// * If `ffprobe` couldn't be run
+pub async fn probe_files(
+    front_file: &UploadRequestFile,
+    side_file: &UploadRequestFile,
+) -> Result<JobMedia, AppError> {
+    let mut probes = Vec::with_capacity(2);
+    for (name, file) in [("front", front_file), ("side", side_file)] {
+        let extension = file.extension()?;
+
+        let probe = media::probe_bytes(&file.bytes, &extension)
+            .await
+            .context(format!("Failed to probe the {} video", name))?;
+        probes.push(media::validate(name, probe)?);
+    }
+
+    let side = probes.pop().context("Missing side video probe")?;
+    let front = probes.pop().context("Missing front video probe")?;
+    Ok(JobMedia { front, side })
+}
+
+/// Pushes the job to the Stage 1 queue.
+///
+/// # Arguments
+/// * `job_id` - The full job ID (format: "{user_id}_{job_index}")
+/// * `user_id` - The user ID
+/// * `front_key` - The storage key of the front video
+/// * `side_key` - The storage key of the side video
+/// * `job` - The job containing all metadata
+async fn dispatch_to_stage_1(
+    job_id: &str,
+    user_id: &str,
+    front_key: String,
+    side_key: String,
+    job: &Job,
+) -> Result<()> {
+    println!("Pushing job {} to Stage 1 queue...", job_id);
 
     // Build the queue item for Stage 1
     let mut input_keys = HashMap::new();
     input_keys.insert("front_video".to_string(), front_key);
     input_keys.insert("side_video".to_string(), side_key);
-
     // Include all job metadata so it's available in the finalize stage
     let metadata = JobMetadata {
         email: Some(job.email.clone()),
@@ -368,6 +666,8 @@ async fn upload_and_dispatch(
         ethnicity: Some(job.ethnicity.to_string()),
         height: Some(job.height.clone()),
         weight: Some(job.weight),
+        batch_id: job.batch_id.clone(),
+        locale: Some(job.locale),
         extra: HashMap::new(),
     };
 
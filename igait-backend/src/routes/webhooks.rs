@@ -0,0 +1,259 @@
+//! Webhook registration endpoints.
+//!
+//! Lets users register URLs to be notified when their jobs are completed,
+//! fail, or are approved or rejected, and inspect the delivery log.
+//! Delivery itself is handled by `helper::webhooks`.
+
+use std::collections::HashMap;
+
+use axum::{extract::{Path, State}, http::StatusCode, Json};
+use anyhow::{Context, anyhow};
+use firebase_auth::FirebaseUser;
+use serde::{Deserialize, Serialize};
+
+use igait_lib::microservice::{FirebaseRtdb, WebhookEventKind, now_ms};
+
+use crate::helper::{
+    lib::{AppError, AppStatePtr},
+    webhooks::{
+        WebhookDelivery, WebhookRegistration,
+        check_webhook_url, deliveries_path, generate_id, generate_secret, registration_path, registrations_path,
+    },
+};
+
+/// The most webhooks a single user may register.
+const MAX_WEBHOOKS_PER_USER: usize = 10;
+
+/// Request body for registering a webhook.
+#[derive(Debug, Deserialize)]
+pub struct CreateWebhookRequest {
+    /// Where payloads should be POSTed. Must be an `https://` URL whose
+    /// host resolves only to public addresses.
+    pub url: String,
+    /// The event kinds to subscribe to. Defaults to every kind.
+    #[serde(default)]
+    pub events: Option<Vec<WebhookEventKind>>,
+    /// An optional label for the webhook
+    #[serde(default)]
+    pub description: Option<String>,
+}
+
+/// A registered webhook, without its secret.
+#[derive(Debug, Serialize)]
+pub struct WebhookSummary {
+    /// The webhook's ID
+    pub id: String,
+    /// Where payloads are POSTed
+    pub url: String,
+    /// The event kinds this webhook is subscribed to
+    pub events: Vec<WebhookEventKind>,
+    /// The webhook's label
+    pub description: Option<String>,
+    /// When the webhook was registered (Unix timestamp ms)
+    pub created_at: u64,
+}
+
+impl From<WebhookRegistration> for WebhookSummary {
+    fn from(registration: WebhookRegistration) -> Self {
+        Self {
+            id: registration.id,
+            url: registration.url,
+            events: registration.events,
+            description: registration.description,
+            created_at: registration.created_at,
+        }
+    }
+}
+
+/// Response body for registering a webhook.
+#[derive(Debug, Serialize)]
+pub struct CreateWebhookResponse {
+    /// The new webhook
+    pub webhook: WebhookSummary,
+    /// The secret payloads are signed with. This is only ever returned
+    /// here, so it must be stored by the caller.
+    pub secret: String,
+}
+
+/// Response body for listing webhooks.
+#[derive(Debug, Serialize)]
+pub struct WebhooksResponse {
+    /// Every webhook the caller has registered, oldest first
+    pub webhooks: Vec<WebhookSummary>,
+}
+
+/// Response body for deleting a webhook.
+#[derive(Debug, Serialize)]
+pub struct DeleteWebhookResponse {
+    /// Whether the webhook was deleted
+    pub success: bool,
+}
+
+/// Response body for a webhook's delivery log.
+#[derive(Debug, Serialize)]
+pub struct DeliveriesResponse {
+    /// Every delivery made to the webhook, newest first
+    pub deliveries: Vec<WebhookDelivery>,
+}
+
+/// `GET /api/v1/webhooks`
+///
+/// Lists the caller's webhooks.
+pub async fn list_webhooks_entrypoint(
+    current_user: FirebaseUser,
+    State(_app): State<AppStatePtr>,
+) -> Result<Json<WebhooksResponse>, AppError> {
+    let rtdb = FirebaseRtdb::from_env()
+        .context("Failed to initialise Firebase RTDB client")?;
+
+    let mut webhooks: Vec<WebhookSummary> = read_registrations(&rtdb, &current_user.user_id)
+        .await?
+        .into_values()
+        .map(WebhookSummary::from)
+        .collect();
+    webhooks.sort_by(|a, b| a.created_at.cmp(&b.created_at).then_with(|| a.id.cmp(&b.id)));
+
+    Ok(Json(WebhooksResponse { webhooks }))
+}
+
+/// `POST /api/v1/webhooks`
+///
+/// Registers a webhook for the caller's jobs, and returns its signing secret.
+///
+/// # Payloads
+/// Each delivery is a JSON `POST` with the event's `id`, `event` name,
+/// `occurred_at`, `job_id` and event-specific `data`, and these headers:
+/// * `X-iGait-Event` - The event name (e.g. "job.completed")
+/// * `X-iGait-Delivery` - The delivery ID, the same across retries
+/// * `X-iGait-Timestamp` - When the payload was signed (Unix timestamp in seconds)
+/// * `X-iGait-Signature` - `sha256=` followed by the hex HMAC-SHA256 of
+///   `"{timestamp}.{body}"`, keyed with the secret
+///
+/// Any non-2xx response is retried with exponential backoff. Redirects
+/// aren't followed.
+///
+/// # Fails
+/// * With a `400` if the URL doesn't use https, or its host resolves to a
+///   loopback, private, link-local or otherwise non-public address
+pub async fn create_webhook_entrypoint(
+    current_user: FirebaseUser,
+    State(_app): State<AppStatePtr>,
+    Json(request): Json<CreateWebhookRequest>,
+) -> Result<Json<CreateWebhookResponse>, AppError> {
+    let caller_uid = &current_user.user_id;
+
+    // ── 1. Validate the request ─────────────────────────────────────
+    let url = check_webhook_url(&request.url)
+        .await
+        .map_err(|e| AppError::client(StatusCode::BAD_REQUEST, format!("{:#}", e)))?;
+
+    let mut events = request.events.unwrap_or_else(|| WebhookEventKind::ALL.to_vec());
+    events.sort_by_key(|kind| kind.as_str());
+    events.dedup();
+    if events.is_empty() {
+        return Err(AppError(anyhow!("A webhook must subscribe to at least one event.")));
+    }
+
+    let rtdb = FirebaseRtdb::from_env()
+        .context("Failed to initialise Firebase RTDB client")?;
+
+    if read_registrations(&rtdb, caller_uid).await?.len() >= MAX_WEBHOOKS_PER_USER {
+        return Err(AppError(anyhow!(
+            "You can register at most {} webhooks. Delete one before adding another.",
+            MAX_WEBHOOKS_PER_USER
+        )));
+    }
+
+    // ── 2. Store the registration ───────────────────────────────────
+    let registration = WebhookRegistration {
+        id: generate_id(),
+        url: url.to_string(),
+        secret: generate_secret(),
+        events,
+        description: request.description,
+        created_at: now_ms(),
+    };
+
+    rtdb.set(&registration_path(caller_uid, &registration.id), &registration)
+        .await
+        .context("Failed to store the webhook")?;
+    println!("User {} registered webhook {} -> {}", caller_uid, registration.id, registration.url);
+
+    let secret = registration.secret.clone();
+    Ok(Json(CreateWebhookResponse {
+        webhook: registration.into(),
+        secret,
+    }))
+}
+
+/// `DELETE /api/v1/webhooks/:webhook_id`
+///
+/// Deletes one of the caller's webhooks. Pending deliveries to it are
+/// marked as failed on their next attempt.
+pub async fn delete_webhook_entrypoint(
+    current_user: FirebaseUser,
+    State(_app): State<AppStatePtr>,
+    Path(webhook_id): Path<String>,
+) -> Result<Json<DeleteWebhookResponse>, AppError> {
+    let caller_uid = &current_user.user_id;
+
+    let rtdb = FirebaseRtdb::from_env()
+        .context("Failed to initialise Firebase RTDB client")?;
+
+    let path = registration_path(caller_uid, &webhook_id);
+    let existing: Option<WebhookRegistration> = rtdb
+        .get(&path)
+        .await
+        .context("Failed to read the webhook")?;
+    if existing.is_none() {
+        return Err(AppError(anyhow!("Webhook {} does not exist.", webhook_id)));
+    }
+
+    rtdb.delete(&path)
+        .await
+        .context("Failed to delete the webhook")?;
+    println!("User {} deleted webhook {}", caller_uid, webhook_id);
+
+    Ok(Json(DeleteWebhookResponse { success: true }))
+}
+
+/// `GET /api/v1/webhooks/:webhook_id/deliveries`
+///
+/// Returns the delivery log of one of the caller's webhooks, including
+/// every attempt and its outcome.
+pub async fn list_deliveries_entrypoint(
+    current_user: FirebaseUser,
+    State(_app): State<AppStatePtr>,
+    Path(webhook_id): Path<String>,
+) -> Result<Json<DeliveriesResponse>, AppError> {
+    let caller_uid = &current_user.user_id;
+
+    let rtdb = FirebaseRtdb::from_env()
+        .context("Failed to initialise Firebase RTDB client")?;
+
+    let deliveries: HashMap<String, WebhookDelivery> = rtdb
+        .get(&deliveries_path(caller_uid))
+        .await
+        .context("Failed to read the delivery log")?
+        .unwrap_or_default();
+
+    let mut deliveries: Vec<WebhookDelivery> = deliveries
+        .into_values()
+        .filter(|delivery| delivery.webhook_id == webhook_id)
+        .collect();
+    deliveries.sort_by(|a, b| b.created_at.cmp(&a.created_at).then_with(|| b.id.cmp(&a.id)));
+
+    Ok(Json(DeliveriesResponse { deliveries }))
+}
+
+/// Reads every webhook a user has registered, keyed by ID.
+async fn read_registrations(
+    rtdb: &FirebaseRtdb,
+    uid: &str,
+) -> anyhow::Result<HashMap<String, WebhookRegistration>> {
+    Ok(rtdb
+        .get(&registrations_path(uid))
+        .await
+        .context("Failed to read webhook registrations")?
+        .unwrap_or_default())
+}
@@ -10,6 +10,14 @@ import type { RerunResponse, JobFilesResponse } from './types';
 import type { ContributionRequest, ProgressCallback, ResearchContributionRequest } from './types';
 import { validateVideoFile, validateRequired, validateEmail } from './validation';
 
+/**
+ * The locale for a submission's status updates and emails, from the
+ * browser's language (Spanish or English)
+ */
+function submissionLocale(): 'en' | 'es' {
+	return navigator.language?.toLowerCase().startsWith('es') ? 'es' : 'en';
+}
+
 /**
  * Validate all submission fields
  */
@@ -90,6 +98,7 @@ export async function submitContribution(
 	if (request.requiresApproval) {
 		formData.append('requires_approval', 'true');
 	}
+	formData.append('locale', submissionLocale());
 	formData.append('fileuploadfront', request.frontVideo);
 	formData.append('fileuploadside', request.sideVideo);
 
@@ -160,6 +169,9 @@ export async function submitContribution(
 
 		xhr.open('POST', API_ENDPOINTS.upload);
 		xhr.setRequestHeader('Authorization', `Bearer ${tokenResult.value}`);
+		if (request.idempotencyKey) {
+			xhr.setRequestHeader('Idempotency-Key', request.idempotencyKey);
+		}
 		xhr.send(formData);
 	});
 
@@ -218,6 +230,8 @@ export async function submitResearchContribution(
 	const formData = new FormData();
 	formData.append('name', request.name);
 	formData.append('email', request.email);
+	formData.append('consent_version', request.consentVersion);
+	formData.append('locale', submissionLocale());
 	formData.append('fileuploadfront', request.frontVideo);
 	formData.append('fileuploadside', request.sideVideo);
 
@@ -232,7 +246,8 @@ export async function submitResearchContribution(
 			body: formData,
 			signal: controller.signal,
 			headers: {
-				Authorization: `Bearer ${tokenResult.value}`
+				Authorization: `Bearer ${tokenResult.value}`,
+				...(request.idempotencyKey ? { 'Idempotency-Key': request.idempotencyKey } : {})
 			}
 		});
 
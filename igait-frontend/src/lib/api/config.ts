@@ -12,6 +12,12 @@ export const API_ENDPOINTS = {
 	files: (jobId: string) => `${API_BASE_URL}/files/${jobId}`
 } as const;
 
+/**
+ * The version of the research consent form shown on the contribute page.
+ * Must match the backend's `CONSENT_FORM_VERSION`, when that is set.
+ */
+export const CONSENT_FORM_VERSION = '1.0';
+
 /**
  * Default timeout for API requests (60 seconds for large video files)
  */
@@ -6,6 +6,7 @@
 export {
 	API_BASE_URL,
 	API_ENDPOINTS,
+	CONSENT_FORM_VERSION,
 	DEFAULT_TIMEOUT_MS,
 	MAX_VIDEO_SIZE_BYTES,
 	MAX_VIDEO_SIZE_MB,
@@ -32,6 +32,11 @@ export interface ContributionRequest {
 	readonly frontVideo: File;
 	readonly sideVideo: File;
 	readonly requiresApproval?: boolean;
+	/**
+	 * Sent as the `Idempotency-Key` header, so a retried submission
+	 * returns the original result instead of creating another job
+	 */
+	readonly idempotencyKey?: string;
 }
 
 /**
@@ -58,8 +63,17 @@ export interface AssistantMessage {
 export interface ResearchContributionRequest {
 	readonly name: string;
 	readonly email: string;
+	/**
+	 * The version of the consent form the contributor agreed to
+	 */
+	readonly consentVersion: string;
 	readonly frontVideo: File;
 	readonly sideVideo: File;
+	/**
+	 * Sent as the `Idempotency-Key` header, so a retried contribution
+	 * isn't saved twice
+	 */
+	readonly idempotencyKey?: string;
 }
 
 /**
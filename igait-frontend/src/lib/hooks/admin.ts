@@ -188,6 +188,9 @@ export function isQueuesLoaded(
  */
 export interface QueueConfigItem {
 	requires_approval: boolean;
+	paused?: boolean;
+	max_concurrency?: number;
+	parameters?: Record<string, unknown>;
 }
 
 /**
@@ -277,7 +280,12 @@ export function queueItemToJob(item: QueueItem | FinalizeQueueItem): Job & { id:
 			: { code: 'Submitted' as const, value: 'Waiting in queue' },
 		requires_approval: item.requires_approval ?? false,
 		approved: item.approved ?? false,
-		stage_logs: {}
+		stage_logs: {},
+		stages: {},
+		review: null,
+		media: null,
+		batch_id: null,
+		archive_key: null
 	};
 }
 
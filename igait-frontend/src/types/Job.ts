@@ -1,7 +1,10 @@
 // This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
 import type { Ethnicity } from './Ethnicity';
+import type { JobMedia } from './JobMedia';
+import type { JobReview } from './JobReview';
 import type { JobStatus } from './JobStatus';
 import type { Sex } from './Sex';
+import type { StageResult } from './StageResult';
 
 /**
  * The job struct, which contains the job
@@ -17,6 +20,13 @@ import type { Sex } from './Sex';
  * * `email` - The email of the person who submitted the job
  * * `requires_approval` - Whether the user requested manual approval for this job
  * * `approved` - Whether this job has been approved for processing
+ * * `stage_logs` - Per-stage logs collected during processing
+ * * `stages` - Per-stage timing results recorded by the workers
+ * * `review` - The administrator's approval decision, if one was made
+ * * `media` - The properties of the uploaded videos
+ * * `batch_id` - The batch the job was submitted in, if any
+ * * `archive_key` - The storage key of the results archive, once finalized
+ * * `locale` - The language the submitter's status updates and emails are in
  */
 export type Job = {
 	age: number;
@@ -40,4 +50,29 @@ export type Job = {
 	 * Keys are "stage_1" through "stage_7", values are the log text.
 	 */
 	stage_logs: Record<string, string>;
+	/**
+	 * Per-stage timing results recorded by the workers.
+	 * Keys are "stage_1" through "stage_7".
+	 */
+	stages: Record<string, StageResult>;
+	/**
+	 * The administrator's approval decision, if one was made
+	 */
+	review: JobReview | null;
+	/**
+	 * The properties of the uploaded videos, recorded when they were probed
+	 */
+	media: JobMedia | null;
+	/**
+	 * The batch the job was submitted in, if it was part of a cohort
+	 */
+	batch_id: string | null;
+	/**
+	 * The storage key of the results archive, set when the job is finalized
+	 */
+	archive_key: string | null;
+	/**
+	 * The language the submitter's status updates and emails are in
+	 */
+	locale: "en" | "es";
 };
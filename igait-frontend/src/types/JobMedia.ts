@@ -0,0 +1,11 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { VideoProbe } from './VideoProbe';
+
+/**
+ * The probed properties of both of a job's videos.
+ *
+ * # Fields
+ * * `front` - The front video
+ * * `side` - The side video
+ */
+export type JobMedia = { front: VideoProbe; side: VideoProbe };
@@ -0,0 +1,12 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+/**
+ * An administrator's decision on a job that was awaiting approval.
+ *
+ * # Fields
+ * * `approved` - Whether the job was approved (`false` means rejected)
+ * * `reviewer` - The UID of the administrator who made the decision
+ * * `note` - An optional note from the reviewer, shared with the submitter on rejection
+ * * `timestamp` - When the decision was made (Unix timestamp in seconds)
+ */
+export type JobReview = { approved: boolean; reviewer: string; note: string | null; timestamp: number };
@@ -0,0 +1,27 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { StageStatus } from './StageStatus';
+
+/**
+ * The timing result of a single pipeline stage for a job.
+ *
+ * Mirrors `FirestoreStageResult` from `igait-lib`, which the workers write
+ * to `users/{uid}/jobs/{index}/stages/stage_{n}`.
+ *
+ * # Fields
+ * * `status` - The status of the stage
+ * * `started_at` - When a worker started the stage (RFC 3339)
+ * * `completed_at` - When the stage finished (RFC 3339)
+ * * `duration_ms` - How long the stage took to process
+ * * `output_keys` - The outputs the stage produced
+ * * `error` - The error message, if the stage failed
+ * * `worker_id` - The ID of the worker that ran the stage
+ */
+export type StageResult = {
+	status: StageStatus;
+	started_at: string | null;
+	completed_at: string | null;
+	duration_ms: number | null;
+	output_keys: Array<string> | null;
+	error: string | null;
+	worker_id: string | null;
+};
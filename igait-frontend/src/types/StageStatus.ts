@@ -0,0 +1,6 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+/**
+ * The status of a single pipeline stage for a job.
+ */
+export type StageStatus = 'pending' | 'processing' | 'success' | 'failed' | 'skipped';
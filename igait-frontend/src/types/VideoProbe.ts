@@ -0,0 +1,23 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+/**
+ * The properties of a submitted video, as reported by `ffprobe`.
+ *
+ * # Fields
+ * * `container` - The container format (e.g. "mov,mp4,m4a,3gp,3g2,mj2")
+ * * `codec` - The codec of the video stream (e.g. "h264")
+ * * `duration_secs` - The length of the video in seconds
+ * * `width` - The width of the video in pixels
+ * * `height` - The height of the video in pixels
+ * * `frame_rate` - The average frame rate in frames per second
+ * * `size_bytes` - The size of the file in bytes
+ */
+export type VideoProbe = {
+	container: string;
+	codec: string;
+	duration_secs: number;
+	width: number;
+	height: number;
+	frame_rate: number;
+	size_bytes: number;
+};
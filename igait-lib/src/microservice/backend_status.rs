@@ -5,6 +5,8 @@
 
 use serde::{Deserialize, Serialize};
 
+use crate::microservice::Locale;
+
 /// The total number of processing stages in the pipeline
 pub const NUM_STAGES: u8 = 7;
 
@@ -47,51 +49,34 @@ fn default_submitted_value() -> String {
 
 impl JobStatus {
     /// Create a new Submitted status
-    pub fn submitted() -> Self {
+    pub fn submitted(locale: Locale) -> Self {
         Self::Submitted {
-            value: "Job submitted successfully".to_string(),
+            value: locale.message("status.submitted", &[]),
         }
     }
 
     /// Create a new Processing status for a given stage
-    pub fn processing(stage: u8) -> Self {
-        let stage_name = match stage {
-            1 => "Converting video format",
-            2 => "Checking video validity",
-            3 => "Reframing video",
-            4 => "Estimating pose landmarks",
-            5 => "Detecting gait cycles",
-            6 => "Running ML prediction",
-            7 => "Finalizing results",
-            _ => "Processing",
-        };
-        
+    pub fn processing(stage: u8, locale: Locale) -> Self {
         Self::Processing {
             stage,
             num_stages: NUM_STAGES,
-            value: format!("Stage {}/{}: {}...", stage, NUM_STAGES, stage_name),
+            value: processing_message(stage, locale),
         }
     }
 
     /// Create a new Complete status with prediction results
-    pub fn complete(prediction: f32, asd: bool) -> Self {
-        let value = if asd {
-            format!("Analysis complete - ASD indicators detected ({:.1}% confidence)", prediction * 100.0)
-        } else {
-            format!("Analysis complete - No ASD indicators ({:.1}% confidence)", (1.0 - prediction) * 100.0)
-        };
-        
+    pub fn complete(prediction: f32, asd: bool, locale: Locale) -> Self {
         Self::Complete {
             prediction,
             asd,
-            value,
+            value: complete_message(prediction, asd, locale),
         }
     }
 
     /// Create a new Error status with logs
-    pub fn error(logs: String) -> Self {
+    pub fn error(logs: String, locale: Locale) -> Self {
         Self::Error {
-            value: "Analysis failed - see logs for details".to_string(),
+            value: locale.message("status.error", &[]),
             logs,
         }
     }
@@ -131,3 +116,28 @@ impl JobStatus {
         }
     }
 }
+
+/// Describes a job being processed by a stage (e.g. "Stage 4/7: Estimating
+/// pose landmarks...").
+pub fn processing_message(stage: u8, locale: Locale) -> String {
+    let stage_name = match stage {
+        1..=7 => locale.message(&format!("status.stage_{}", stage), &[]),
+        _ => locale.message("status.stage_unknown", &[]),
+    };
+
+    locale.message("status.processing", &[
+        ("stage", &stage.to_string()),
+        ("num_stages", &NUM_STAGES.to_string()),
+        ("stage_name", &stage_name),
+    ])
+}
+
+/// Describes a job's prediction, with the confidence in its outcome.
+pub fn complete_message(prediction: f32, asd: bool, locale: Locale) -> String {
+    let (id, confidence) = match asd {
+        true => ("status.complete_asd", prediction),
+        false => ("status.complete_no_asd", 1.0 - prediction),
+    };
+
+    locale.message(id, &[("confidence", &format!("{:.1}", confidence * 100.0))])
+}
@@ -1,74 +1,125 @@
-//! Email utilities for sending notifications via AWS SES.
+//! Email utilities for sending notifications.
 //!
 //! This module provides standalone email functionality that can be used
 //! by both the backend and stage workers.
+//!
+//! Emails are delivered by an `EmailTransport`, chosen by `EMAIL_TRANSPORT`:
+//! * `ses` (default) - AWS SES, sending as the `SES_FROM_IDENTITY_ARN`
+//!   identity if one is set
+//! * `smtp` - any SMTP server, configured by `SMTP_HOST`, `SMTP_PORT`,
+//!   `SMTP_USERNAME`, `SMTP_PASSWORD` and `SMTP_TLS` (`starttls`, `tls` or `none`)
+//! * `file` - writes each email as an `.eml` file to `EMAIL_OUTPUT_DIR`,
+//!   so local runs and tests work without AWS and can check what was sent
+//!
+//! Emails are sent from `EMAIL_FROM_ADDRESS` (or `SES_FROM_ADDRESS`), with
+//! an HTML body and a plain-text alternative rendered from the templates in
+//! `email_templates`.
 
-use anyhow::{Context, Result};
+use anyhow::{bail, Context, Result};
+use async_trait::async_trait;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
-use tokio::sync::Mutex;
+
+use super::email_templates::{Email, EmailTemplates, RenderedEmail};
+#[cfg(feature = "microservice")]
+use super::StorageClient;
 
 #[cfg(feature = "email")]
 use aws_sdk_sesv2::{
     types::{Body, Content, Destination, EmailContent, Message},
     Client as SesClient,
 };
+#[cfg(feature = "email")]
+use lettre::{
+    message::MultiPart,
+    transport::smtp::authentication::Credentials,
+    AsyncSmtpTransport, AsyncTransport, Tokio1Executor,
+};
+
+/// The sender used when neither `EMAIL_FROM_ADDRESS` nor `SES_FROM_ADDRESS` is set.
+const DEFAULT_FROM_ADDRESS: &str = "noreply@igaitapp.com";
+
+/// An email ready to be handed to a transport.
+#[derive(Debug, Clone)]
+pub struct EmailMessage {
+    pub from: String,
+    pub to: String,
+    pub subject: String,
+    pub body_html: String,
+    pub body_text: String,
+}
 
-/// Email client for sending messages via AWS SES.
 #[cfg(feature = "email")]
-#[derive(Clone)]
-pub struct EmailClient {
-    ses_client: Arc<Mutex<SesClient>>,
-    from_address: String,
-    from_identity_arn: String,
+impl EmailMessage {
+    /// Builds the email as a MIME message, with the plain-text body as an
+    /// alternative to the HTML one.
+    fn to_mime(&self) -> Result<lettre::Message> {
+        lettre::Message::builder()
+            .from(self.from.parse().context("Invalid sender address")?)
+            .to(self.to.parse().context("Invalid recipient address")?)
+            .subject(&self.subject)
+            .multipart(MultiPart::alternative_plain_html(self.body_text.clone(), self.body_html.clone()))
+            .context("Failed to build the email")
+    }
+
+    /// Formats the email as an RFC 5322 message, i.e. the contents of an `.eml` file.
+    pub fn to_rfc5322(&self) -> Result<Vec<u8>> {
+        Ok(self.to_mime()?.formatted())
+    }
+}
+
+/// A way of delivering emails.
+#[async_trait]
+pub trait EmailTransport: Send + Sync {
+    /// A short name for the transport, used in logs (e.g. "smtp").
+    fn name(&self) -> &'static str;
+
+    /// Delivers an email.
+    async fn send(&self, message: &EmailMessage) -> Result<()>;
 }
 
+/// Delivers emails with AWS SES.
 #[cfg(feature = "email")]
-impl EmailClient {
-    /// Creates a new EmailClient from AWS environment configuration.
+pub struct SesTransport {
+    client: SesClient,
+    from_identity_arn: Option<String>,
+}
+
+#[cfg(feature = "email")]
+impl SesTransport {
+    /// Creates an SES transport from AWS environment configuration.
     ///
-    /// Uses AWS credentials from environment variables or IAM roles.
-    pub async fn from_env() -> Result<Self> {
+    /// Uses AWS credentials from environment variables or IAM roles, and
+    /// sends as the `SES_FROM_IDENTITY_ARN` identity if it's set.
+    pub async fn from_env() -> Self {
         let config = aws_config::load_from_env().await;
-        let ses_client = SesClient::new(&config);
-
-        let from_address = std::env::var("SES_FROM_ADDRESS")
-            .unwrap_or_else(|_| "noreply@igaitapp.com".to_string());
         let from_identity_arn = std::env::var("SES_FROM_IDENTITY_ARN")
-            .unwrap_or_else(|_| {
-                "arn:aws:ses:us-east-2:851725269484:identity/noreply@igaitapp.com".to_string()
-            });
+            .ok()
+            .filter(|arn| !arn.is_empty());
 
-        Ok(Self {
-            ses_client: Arc::new(Mutex::new(ses_client)),
-            from_address,
-            from_identity_arn,
-        })
+        Self::with_client(SesClient::new(&config), from_identity_arn)
     }
 
-    /// Creates an EmailClient with a pre-existing SES client.
-    pub fn with_client(
-        ses_client: Arc<Mutex<SesClient>>,
-        from_address: String,
-        from_identity_arn: String,
-    ) -> Self {
+    /// Creates an SES transport with a pre-existing SES client.
+    pub fn with_client(client: SesClient, from_identity_arn: Option<String>) -> Self {
         Self {
-            ses_client,
-            from_address,
+            client,
             from_identity_arn,
         }
     }
+}
 
-    /// Sends an email to the specified address.
-    ///
-    /// # Arguments
-    /// * `to` - The recipient email address
-    /// * `subject` - The email subject
-    /// * `body_html` - The HTML body of the email
-    pub async fn send(&self, to: &str, subject: &str, body_html: &str) -> Result<()> {
-        println!("Sending email to '{to}'...");
+#[cfg(feature = "email")]
+#[async_trait]
+impl EmailTransport for SesTransport {
+    fn name(&self) -> &'static str {
+        "ses"
+    }
 
+    async fn send(&self, message: &EmailMessage) -> Result<()> {
         let destination = Destination::builder()
-            .set_to_addresses(Some(vec![to.into()]))
+            .set_to_addresses(Some(vec![message.to.clone()]))
             .build();
 
         let content = EmailContent::builder()
@@ -76,7 +127,7 @@ impl EmailClient {
                 Message::builder()
                     .set_subject(Some(
                         Content::builder()
-                            .set_data(Some(subject.to_string()))
+                            .set_data(Some(message.subject.clone()))
                             .build()
                             .context("Failed to build email subject")?,
                     ))
@@ -84,170 +135,247 @@ impl EmailClient {
                         Body::builder()
                             .set_html(Some(
                                 Content::builder()
-                                    .set_data(Some(body_html.to_string()))
+                                    .set_data(Some(message.body_html.clone()))
                                     .build()
                                     .context("Failed to build email body")?,
                             ))
+                            .set_text(Some(
+                                Content::builder()
+                                    .set_data(Some(message.body_text.clone()))
+                                    .build()
+                                    .context("Failed to build email text body")?,
+                            ))
                             .build(),
                     ))
                     .build(),
             ))
             .build();
 
-        self.ses_client
-            .lock()
-            .await
+        self.client
             .send_email()
-            .from_email_address(&self.from_address)
-            .from_email_address_identity_arn(&self.from_identity_arn)
+            .from_email_address(&message.from)
+            .set_from_email_address_identity_arn(self.from_identity_arn.clone())
             .destination(destination)
             .content(content)
             .send()
             .await
             .context("Failed to send email via SES")?;
 
-        println!("Successfully sent email to '{to}'!");
         Ok(())
     }
 }
 
-// ============================================================================
-// EMAIL TEMPLATES
-// ============================================================================
-
-/// Pre-built email templates for common notifications.
-pub struct EmailTemplates;
+/// Delivers emails through an SMTP server.
+#[cfg(feature = "email")]
+pub struct SmtpTransport {
+    transport: AsyncSmtpTransport<Tokio1Executor>,
+}
 
-impl EmailTemplates {
-    /// Builds a "submission received" welcome email.
+#[cfg(feature = "email")]
+impl SmtpTransport {
+    /// Creates an SMTP transport from environment configuration.
     ///
-    /// Sent when a user uploads a new job for processing.
-    pub fn submission_received(
-        datetime: &str,
-        age: i16,
-        ethnicity: &str,
-        sex: char,
-        height: &str,
-        weight: i16,
-        uid: &str,
-        job_id: &str,
-    ) -> (String, String) {
-        let subject = "Welcome to iGait!".to_string();
-        let body = format!(
-            "Dear iGAIT user,<br><br>\
-             Your submission on {} has been successfully received! \
-             Please understand that the iGAIT website is still under development. \
-             At this point, the research team will review the screening result of your submission. \
-             We are working on adding the functionality to automatically email you the result. \
-             We hope that will be available soon.<br><br>\
-             In the meanwhile, if you have any questions regarding your submission or user experience, \
-             or any suggestion to help us improve the website, please don't hesitate to contact us at \
-             GaitStudy@niu.edu. Please include the information below about your submission.<br><br>\
-             Submission information:<br>\
-             Age: {}<br>\
-             Ethnicity: {}<br>\
-             Sex: {}<br>\
-             Height: {}<br>\
-             Weight: {}<br><br>\
-             User ID: {}<br>\
-             Job ID: {}",
-            datetime, age, ethnicity, sex, height, weight, uid, job_id
-        );
-        (subject, body)
+    /// # Fails
+    /// * If `SMTP_HOST` isn't set
+    /// * If `SMTP_PORT` isn't a port number
+    /// * If `SMTP_TLS` isn't `starttls` (the default), `tls` or `none`
+    pub fn from_env() -> Result<Self> {
+        let host = std::env::var("SMTP_HOST")
+            .context("SMTP_HOST must be set to send email over SMTP")?;
+        let tls = std::env::var("SMTP_TLS").unwrap_or_else(|_| "starttls".to_string());
+
+        let mut builder = match tls.to_lowercase().as_str() {
+            "starttls" => AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(&host)
+                .context("Failed to configure the SMTP relay")?,
+            "tls" => AsyncSmtpTransport::<Tokio1Executor>::relay(&host)
+                .context("Failed to configure the SMTP relay")?,
+            "none" => AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(&host),
+            other => bail!("Unknown SMTP_TLS '{}' (expected starttls, tls or none)", other),
+        };
+
+        if let Ok(port) = std::env::var("SMTP_PORT") {
+            builder = builder.port(port.parse().context("SMTP_PORT must be a port number")?);
+        }
+        if let (Ok(username), Ok(password)) = (std::env::var("SMTP_USERNAME"), std::env::var("SMTP_PASSWORD")) {
+            builder = builder.credentials(Credentials::new(username, password));
+        }
+
+        Ok(Self {
+            transport: builder.build(),
+        })
     }
+}
 
-    /// Builds a success email with the prediction score.
-    ///
-    /// Sent when the pipeline completes successfully with a prediction.
-    pub fn prediction_success(
-        datetime: &str,
-        score: f64,
-        is_asd: bool,
-        age: Option<i16>,
-        ethnicity: Option<&str>,
-        sex: Option<char>,
-        height: Option<&str>,
-        weight: Option<i16>,
-        uid: &str,
-        job_id: &str,
-    ) -> (String, String) {
-        let subject = "Your recent submission to iGait App has completed!".to_string();
-        
-        let result_text = if is_asd {
-            "Our analysis indicates markers consistent with ASD gait patterns."
-        } else {
-            "Our analysis indicates typical gait patterns."
+#[cfg(feature = "email")]
+#[async_trait]
+impl EmailTransport for SmtpTransport {
+    fn name(&self) -> &'static str {
+        "smtp"
+    }
+
+    async fn send(&self, message: &EmailMessage) -> Result<()> {
+        self.transport
+            .send(message.to_mime()?)
+            .await
+            .context("Failed to send email via SMTP")?;
+
+        Ok(())
+    }
+}
+
+/// Writes emails to a directory as `.eml` files instead of delivering them.
+///
+/// Files are named `{unix ms}-{sequence}.eml`, so they sort in the order
+/// they were sent.
+#[cfg(feature = "email")]
+pub struct FileTransport {
+    dir: PathBuf,
+    sequence: AtomicU64,
+}
+
+#[cfg(feature = "email")]
+impl FileTransport {
+    /// Creates a transport writing to a directory, which is created on first use.
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self {
+            dir: dir.into(),
+            sequence: AtomicU64::new(0),
+        }
+    }
+
+    /// Reads back every email written to the directory, oldest first.
+    pub async fn sent(&self) -> Result<Vec<String>> {
+        let mut paths = Vec::new();
+        let mut entries = match tokio::fs::read_dir(&self.dir).await {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e).context("Failed to read the email directory"),
         };
-        
-        let body = format!(
-            "We determined a likelihood score of {:.2} for your submission on {}!<br><br>\
-             {}<br><br>\
-             Submission information:<br>\
-             Age: {}<br>\
-             Ethnicity: {}<br>\
-             Sex: {}<br>\
-             Height: {}<br>\
-             Weight: {}<br><br>\
-             User ID: {}<br>\
-             Job ID: {}<br><br>\
-             If you have questions about your results, please contact GaitStudy@niu.edu.",
-            score,
-            datetime,
-            result_text,
-            age.map(|a| a.to_string()).unwrap_or_else(|| "N/A".to_string()),
-            ethnicity.unwrap_or("N/A"),
-            sex.map(|s| s.to_string()).unwrap_or_else(|| "N/A".to_string()),
-            height.unwrap_or("N/A"),
-            weight.map(|w| w.to_string()).unwrap_or_else(|| "N/A".to_string()),
-            uid,
-            job_id
-        );
-        (subject, body)
+        while let Some(entry) = entries.next_entry().await? {
+            let path = entry.path();
+            if path.extension().is_some_and(|ext| ext == "eml") {
+                paths.push(path);
+            }
+        }
+        paths.sort();
+
+        let mut emails = Vec::with_capacity(paths.len());
+        for path in paths {
+            emails.push(tokio::fs::read_to_string(&path)
+                .await
+                .context(format!("Failed to read {}", path.display()))?);
+        }
+        Ok(emails)
+    }
+}
+
+#[cfg(feature = "email")]
+#[async_trait]
+impl EmailTransport for FileTransport {
+    fn name(&self) -> &'static str {
+        "file"
+    }
+
+    async fn send(&self, message: &EmailMessage) -> Result<()> {
+        tokio::fs::create_dir_all(&self.dir)
+            .await
+            .context("Failed to create the email directory")?;
+
+        let sequence = self.sequence.fetch_add(1, Ordering::Relaxed);
+        let path = self.dir.join(format!("{}-{:06}.eml", crate::microservice::now_ms(), sequence));
+        tokio::fs::write(&path, message.to_rfc5322()?)
+            .await
+            .context(format!("Failed to write {}", path.display()))?;
+
+        println!("Wrote email to {}", path.display());
+        Ok(())
     }
+}
+
+/// Email client for sending messages through the configured transport.
+#[cfg(feature = "email")]
+#[derive(Clone)]
+pub struct EmailClient {
+    transport: Arc<dyn EmailTransport>,
+    from_address: String,
+    templates: EmailTemplates,
+}
 
-    /// Builds a failure email when processing fails.
+#[cfg(feature = "email")]
+impl EmailClient {
+    /// Creates a new EmailClient with the transport chosen by `EMAIL_TRANSPORT`.
     ///
-    /// Sent when the pipeline encounters an error at any stage.
-    pub fn processing_failure(
-        datetime: &str,
-        failed_stage: Option<u8>,
-        error: &str,
-        uid: &str,
-        job_id: &str,
-    ) -> (String, String) {
-        let subject = "Your recent submission to iGait App failed!".to_string();
-        
-        let stage_info = failed_stage
-            .map(|s| format!("Stage {}", s))
-            .unwrap_or_else(|| "Unknown stage".to_string());
-        
-        let body = format!(
-            "Something went wrong with your submission on {}!<br><br>\
-             Failed at: {}<br>\
-             Error: {}<br><br>\
-             User ID: {}<br>\
-             Job ID: {}<br><br>\
-             Please contact support: GaitStudy@niu.edu",
-            datetime, stage_info, error, uid, job_id
-        );
-        (subject, body)
+    /// # Fails
+    /// * If `EMAIL_TRANSPORT` isn't `ses`, `smtp` or `file`
+    /// * If the chosen transport is misconfigured
+    pub async fn from_env() -> Result<Self> {
+        let from_address = std::env::var("EMAIL_FROM_ADDRESS")
+            .or_else(|_| std::env::var("SES_FROM_ADDRESS"))
+            .unwrap_or_else(|_| DEFAULT_FROM_ADDRESS.to_string());
+
+        let transport: Arc<dyn EmailTransport> = match std::env::var("EMAIL_TRANSPORT")
+            .unwrap_or_else(|_| "ses".to_string())
+            .to_lowercase()
+            .as_str()
+        {
+            "ses" => Arc::new(SesTransport::from_env().await),
+            "smtp" => Arc::new(SmtpTransport::from_env()?),
+            "file" => Arc::new(FileTransport::new(
+                std::env::var("EMAIL_OUTPUT_DIR").unwrap_or_else(|_| "emails".to_string()),
+            )),
+            other => bail!("Unknown EMAIL_TRANSPORT '{}' (expected ses, smtp or file)", other),
+        };
+        println!("Sending email with the {} transport", transport.name());
+
+        Ok(Self::new(transport, from_address))
     }
 
-    /// Builds a contribution thank-you email.
+    /// Creates an EmailClient with a pre-existing transport, rendering
+    /// with the built-in templates.
+    pub fn new(transport: Arc<dyn EmailTransport>, from_address: impl Into<String>) -> Self {
+        Self {
+            transport,
+            from_address: from_address.into(),
+            templates: EmailTemplates::builtin(),
+        }
+    }
+
+    /// Renders emails with any template overrides uploaded to storage.
+    #[cfg(feature = "microservice")]
+    pub fn with_template_overrides(mut self, storage: StorageClient) -> Self {
+        self.templates = EmailTemplates::with_overrides(storage);
+        self
+    }
+
+    /// Renders an email and sends it to the specified address.
     ///
-    /// Sent when a user contributes data to the research study.
-    pub fn contribution_received(name: &str) -> (String, String) {
-        let subject = "Thank you for your contribution to iGait!".to_string();
-        let body = format!(
-            "Dear {}!<br><br>\
-             Your submission has been successfully received. \
-             Thank you for participating in this research study. \
-             If you have any questions or would like to follow up, \
-             please contact GaitStudy@niu.edu.<br><br>\
-             Thank you for your support!",
-            name
-        );
-        (subject, body)
+    /// # Arguments
+    /// * `to` - The recipient email address
+    /// * `email` - The email to render (see `EmailTemplates`)
+    pub async fn send(&self, to: &str, email: &Email) -> Result<()> {
+        let rendered = self.templates
+            .render(email)
+            .await
+            .context(format!("Failed to render the {} email", email.template.name()))?;
+
+        self.send_rendered(to, &rendered).await
+    }
+
+    /// Sends an already rendered email to the specified address.
+    pub async fn send_rendered(&self, to: &str, email: &RenderedEmail) -> Result<()> {
+        println!("Sending email to '{to}'...");
+
+        let message = EmailMessage {
+            from: self.from_address.clone(),
+            to: to.to_string(),
+            subject: email.subject.clone(),
+            body_html: email.html.clone(),
+            body_text: email.text.clone(),
+        };
+        self.transport.send(&message).await?;
+
+        println!("Successfully sent email to '{to}'!");
+        Ok(())
     }
 }
 
@@ -255,7 +383,58 @@ impl EmailTemplates {
 impl std::fmt::Debug for EmailClient {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.debug_struct("EmailClient")
+            .field("transport", &self.transport.name())
             .field("from_address", &self.from_address)
+            .field("templates", &self.templates)
             .finish()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A simple email for the transport tests.
+    fn rendered(subject: &str, html: &str, text: &str) -> RenderedEmail {
+        RenderedEmail {
+            subject: subject.to_string(),
+            html: html.to_string(),
+            text: text.to_string(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_file_transport_writes_eml() {
+        let dir = std::env::temp_dir().join(format!("igait-email-test-{}", crate::microservice::now_ms()));
+        let transport = Arc::new(FileTransport::new(&dir));
+        let client = EmailClient::new(transport.clone(), "noreply@igaitapp.com");
+
+        client.send_rendered("first@example.com", &rendered("First", "<b>Hello</b>", "Hello")).await.unwrap();
+        client.send_rendered("second@example.com", &rendered("Second", "Bye", "Bye")).await.unwrap();
+
+        let sent = transport.sent().await.unwrap();
+        assert_eq!(sent.len(), 2);
+        assert!(sent[0].contains("To: first@example.com"));
+        assert!(sent[0].contains("From: noreply@igaitapp.com"));
+        assert!(sent[0].contains("Subject: First"));
+        assert!(sent[0].contains("Content-Type: multipart/alternative"));
+        assert!(sent[0].contains("Content-Type: text/plain"));
+        assert!(sent[0].contains("Content-Type: text/html"));
+        assert!(sent[0].contains("<b>Hello</b>"));
+        assert!(sent[1].contains("To: second@example.com"));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_invalid_recipient_is_rejected() {
+        let dir = std::env::temp_dir().join(format!("igait-email-test-invalid-{}", crate::microservice::now_ms()));
+        let transport = Arc::new(FileTransport::new(&dir));
+        let client = EmailClient::new(transport.clone(), "noreply@igaitapp.com");
+
+        assert!(client.send_rendered("not an address", &rendered("Subject", "Body", "Body")).await.is_err());
+        assert!(transport.sent().await.unwrap().is_empty());
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
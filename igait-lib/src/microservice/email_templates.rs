@@ -0,0 +1,543 @@
+//! Email templates.
+//!
+//! Every email is rendered from three [minijinja] templates: a subject, an
+//! HTML body and a plain-text body, sent together as a multipart message.
+//! HTML bodies are auto-escaped, so user-supplied values (names, heights,
+//! reviewer notes, ...) can't inject markup.
+//!
+//! Emails are sent in the locale of the job they're about. The built-in
+//! templates for each locale live in `email_templates/{locale}/` and are
+//! compiled in. Any of them can be overridden without a rebuild by uploading
+//! a file with the same name (e.g. `es/prediction_success.html`) under
+//! `email_templates/` in storage. Overrides are read each time an email is
+//! rendered, and if one fails to render, the built-in template is used
+//! instead.
+
+use anyhow::{Context, Result};
+use minijinja::{Environment, UndefinedBehavior};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+
+use crate::microservice::Locale;
+#[cfg(feature = "microservice")]
+use crate::microservice::{StorageClient, StoragePaths};
+
+/// The emails the pipeline sends.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EmailTemplate {
+    SubmissionReceived,
+    PredictionSuccess,
+    ProcessingFailure,
+    SubmissionRejected,
+    BatchCompleted,
+    ContributionReceived,
+}
+
+/// One of the templates an email is rendered from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TemplatePart {
+    /// The subject line (plain text)
+    Subject,
+    /// The HTML body (auto-escaped)
+    Html,
+    /// The plain-text body
+    Text,
+}
+
+impl TemplatePart {
+    /// Every part, in the order they're rendered.
+    pub const ALL: [TemplatePart; 3] = [TemplatePart::Subject, TemplatePart::Html, TemplatePart::Text];
+
+    /// Parses a part from its name (e.g. "html").
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|part| part.name() == name)
+    }
+
+    /// The part's name (e.g. "html").
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Subject => "subject",
+            Self::Html => "html",
+            Self::Text => "text",
+        }
+    }
+
+    /// The file extension of the part's template. Only `.html` templates are auto-escaped.
+    fn extension(&self) -> &'static str {
+        match self {
+            Self::Subject => "subject.txt",
+            Self::Html => "html",
+            Self::Text => "txt",
+        }
+    }
+}
+
+impl EmailTemplate {
+    /// Every template.
+    pub const ALL: [EmailTemplate; 6] = [
+        EmailTemplate::SubmissionReceived,
+        EmailTemplate::PredictionSuccess,
+        EmailTemplate::ProcessingFailure,
+        EmailTemplate::SubmissionRejected,
+        EmailTemplate::BatchCompleted,
+        EmailTemplate::ContributionReceived,
+    ];
+
+    /// Parses a template from its name (e.g. "prediction_success").
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|template| template.name() == name)
+    }
+
+    /// The template's name (e.g. "prediction_success").
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::SubmissionReceived => "submission_received",
+            Self::PredictionSuccess => "prediction_success",
+            Self::ProcessingFailure => "processing_failure",
+            Self::SubmissionRejected => "submission_rejected",
+            Self::BatchCompleted => "batch_completed",
+            Self::ContributionReceived => "contribution_received",
+        }
+    }
+
+    /// The file name of one of the template's parts (e.g. "prediction_success.html").
+    pub fn file_name(&self, part: TemplatePart) -> String {
+        format!("{}.{}", self.name(), part.extension())
+    }
+
+    /// The built-in source of one of the template's parts in a locale.
+    pub fn builtin(&self, locale: Locale, part: TemplatePart) -> &'static str {
+        macro_rules! parts {
+            ($locale:literal, $name:literal) => {
+                match part {
+                    TemplatePart::Subject => include_str!(concat!("email_templates/", $locale, "/", $name, ".subject.txt")),
+                    TemplatePart::Html => include_str!(concat!("email_templates/", $locale, "/", $name, ".html")),
+                    TemplatePart::Text => include_str!(concat!("email_templates/", $locale, "/", $name, ".txt")),
+                }
+            };
+        }
+        macro_rules! locales {
+            ($name:literal) => {
+                match locale {
+                    Locale::En => parts!("en", $name),
+                    Locale::Es => parts!("es", $name),
+                }
+            };
+        }
+
+        match self {
+            Self::SubmissionReceived => locales!("submission_received"),
+            Self::PredictionSuccess => locales!("prediction_success"),
+            Self::ProcessingFailure => locales!("processing_failure"),
+            Self::SubmissionRejected => locales!("submission_rejected"),
+            Self::BatchCompleted => locales!("batch_completed"),
+            Self::ContributionReceived => locales!("contribution_received"),
+        }
+    }
+
+    /// An email with placeholder values, used to check that an override
+    /// only uses variables the template is given.
+    pub fn sample(&self, locale: Locale) -> Email {
+        let datetime = "2024-01-01 12:00:00 CST";
+        match self {
+            Self::SubmissionReceived => EmailTemplates::submission_received(
+                locale, datetime, 8, "Hispanic or Latino", 'F', "4'2\"", 60, "uid", "0",
+            ),
+            Self::PredictionSuccess => EmailTemplates::prediction_success(
+                locale, datetime, 0.42, false, Some(8), Some("Hispanic or Latino"), Some('F'),
+                Some("4'2\""), Some(60), "uid", "uid_0", Some("https://example.com/results.zip"),
+            ),
+            Self::ProcessingFailure => EmailTemplates::processing_failure(
+                locale, datetime, Some(4), "Pose estimation failed", "uid", "uid_0",
+            ),
+            Self::SubmissionRejected => EmailTemplates::submission_rejected(
+                locale, datetime, Some("The side video is too dark."), "uid", "uid_0",
+            ),
+            Self::BatchCompleted => EmailTemplates::batch_completed(
+                locale, datetime, Some("Spring cohort"), "batch", 10, 3, 1,
+            ),
+            Self::ContributionReceived => EmailTemplates::contribution_received(locale, "Alex"),
+        }
+    }
+}
+
+/// An email to render: which template, in which locale, and the values
+/// it's rendered with.
+#[derive(Debug, Clone)]
+pub struct Email {
+    pub template: EmailTemplate,
+    pub locale: Locale,
+    pub context: Value,
+}
+
+/// A rendered email, ready to send.
+#[derive(Debug, Clone)]
+pub struct RenderedEmail {
+    pub subject: String,
+    pub html: String,
+    pub text: String,
+}
+
+/// Renders emails from the built-in templates and any overrides in storage.
+#[derive(Clone, Default)]
+pub struct EmailTemplates {
+    #[cfg(feature = "microservice")]
+    storage: Option<StorageClient>,
+}
+
+impl EmailTemplates {
+    /// Renders with the built-in templates only.
+    pub fn builtin() -> Self {
+        Self::default()
+    }
+
+    /// Renders with the built-in templates, overridden by any in storage.
+    #[cfg(feature = "microservice")]
+    pub fn with_overrides(storage: StorageClient) -> Self {
+        Self { storage: Some(storage) }
+    }
+
+    /// Renders an email.
+    ///
+    /// # Fails
+    /// * If the built-in templates fail to render (overrides that fail
+    ///   are logged and skipped)
+    pub async fn render(&self, email: &Email) -> Result<RenderedEmail> {
+        let overrides = self.overrides(email.template, email.locale).await;
+        if !overrides.is_empty() {
+            match render_parts(email, &overrides) {
+                Ok(rendered) => return Ok(rendered),
+                Err(e) => eprintln!(
+                    "Overridden {} {} email failed to render, using the built-in template: {e:#}",
+                    email.locale, email.template.name()
+                ),
+            }
+        }
+
+        render_parts(email, &HashMap::new())
+    }
+
+    /// Checks that a template override compiles and renders with the
+    /// template's variables.
+    ///
+    /// # Fails
+    /// * With the template error, which includes the line it's on
+    pub fn validate(template: EmailTemplate, locale: Locale, part: TemplatePart, source: &str) -> Result<()> {
+        render_parts(&template.sample(locale), &HashMap::from([(part, source.to_string())])).map(|_| ())
+    }
+
+    /// Reads the overrides of a template's parts from storage.
+    ///
+    /// Storage errors are logged rather than returned, so a storage outage
+    /// falls back to the built-in templates instead of blocking emails.
+    #[cfg(feature = "microservice")]
+    async fn overrides(&self, template: EmailTemplate, locale: Locale) -> HashMap<TemplatePart, String> {
+        let Some(storage) = &self.storage else {
+            return HashMap::new();
+        };
+
+        let prefix = StoragePaths::email_template(locale.code(), &format!("{}.", template.name()));
+        let keys = match storage.list_by_prefix(&prefix).await {
+            Ok(keys) => keys,
+            Err(e) => {
+                eprintln!("Failed to list {} {} email template overrides: {e:#}", locale, template.name());
+                return HashMap::new();
+            }
+        };
+
+        let mut overrides = HashMap::new();
+        for part in TemplatePart::ALL {
+            let key = StoragePaths::email_template(locale.code(), &template.file_name(part));
+            if !keys.contains(&key) {
+                continue;
+            }
+
+            match storage.download(&key).await.and_then(|data| String::from_utf8(data).context("Not UTF-8")) {
+                Ok(source) => {
+                    overrides.insert(part, source);
+                }
+                Err(e) => eprintln!("Failed to read email template override {}: {e:#}", key),
+            }
+        }
+        overrides
+    }
+
+    #[cfg(not(feature = "microservice"))]
+    async fn overrides(&self, _template: EmailTemplate, _locale: Locale) -> HashMap<TemplatePart, String> {
+        HashMap::new()
+    }
+
+    // ========================================================================
+    // EMAILS
+    // ========================================================================
+
+    /// Builds a "submission received" welcome email.
+    ///
+    /// Sent when a user uploads a new job for processing.
+    pub fn submission_received(
+        locale: Locale,
+        datetime: &str,
+        age: i16,
+        ethnicity: &str,
+        sex: char,
+        height: &str,
+        weight: i16,
+        uid: &str,
+        job_id: &str,
+    ) -> Email {
+        Email {
+            template: EmailTemplate::SubmissionReceived,
+            locale,
+            context: json!({
+                "datetime": datetime,
+                "age": age,
+                "ethnicity": ethnicity,
+                "sex": sex,
+                "height": height,
+                "weight": weight,
+                "uid": uid,
+                "job_id": job_id,
+            }),
+        }
+    }
+
+    /// Builds a success email with the prediction score.
+    ///
+    /// Sent when the pipeline completes successfully with a prediction.
+    /// When `archive_url` is given, the email links to the results archive.
+    pub fn prediction_success(
+        locale: Locale,
+        datetime: &str,
+        score: f64,
+        is_asd: bool,
+        age: Option<i16>,
+        ethnicity: Option<&str>,
+        sex: Option<char>,
+        height: Option<&str>,
+        weight: Option<i16>,
+        uid: &str,
+        job_id: &str,
+        archive_url: Option<&str>,
+    ) -> Email {
+        Email {
+            template: EmailTemplate::PredictionSuccess,
+            locale,
+            context: json!({
+                "datetime": datetime,
+                "score": format!("{:.2}", score),
+                "is_asd": is_asd,
+                "age": or_na(age, locale),
+                "ethnicity": or_na(ethnicity, locale),
+                "sex": or_na(sex, locale),
+                "height": or_na(height, locale),
+                "weight": or_na(weight, locale),
+                "uid": uid,
+                "job_id": job_id,
+                "archive_url": archive_url,
+            }),
+        }
+    }
+
+    /// Builds a failure email when processing fails.
+    ///
+    /// Sent when the pipeline encounters an error at any stage.
+    pub fn processing_failure(
+        locale: Locale,
+        datetime: &str,
+        failed_stage: Option<u8>,
+        error: &str,
+        uid: &str,
+        job_id: &str,
+    ) -> Email {
+        Email {
+            template: EmailTemplate::ProcessingFailure,
+            locale,
+            context: json!({
+                "datetime": datetime,
+                "failed_stage": failed_stage,
+                "error": error,
+                "uid": uid,
+                "job_id": job_id,
+            }),
+        }
+    }
+
+    /// Builds a rejection email when a reviewer declines a submission.
+    ///
+    /// Sent when an administrator rejects a job that was awaiting approval.
+    pub fn submission_rejected(
+        locale: Locale,
+        datetime: &str,
+        note: Option<&str>,
+        uid: &str,
+        job_id: &str,
+    ) -> Email {
+        Email {
+            template: EmailTemplate::SubmissionRejected,
+            locale,
+            context: json!({
+                "datetime": datetime,
+                "note": note,
+                "uid": uid,
+                "job_id": job_id,
+            }),
+        }
+    }
+
+    /// Builds a summary email for a batch whose jobs have all finished.
+    ///
+    /// Sent once per batch, instead of an email for each of its jobs.
+    pub fn batch_completed(
+        locale: Locale,
+        datetime: &str,
+        batch_name: Option<&str>,
+        batch_id: &str,
+        completed: usize,
+        asd: usize,
+        failed: usize,
+    ) -> Email {
+        Email {
+            template: EmailTemplate::BatchCompleted,
+            locale,
+            context: json!({
+                "datetime": datetime,
+                "batch_name": batch_name,
+                "batch_id": batch_id,
+                "completed": completed,
+                "asd": asd,
+                "failed": failed,
+            }),
+        }
+    }
+
+    /// Builds a contribution thank-you email.
+    ///
+    /// Sent when a user contributes data to the research study.
+    pub fn contribution_received(locale: Locale, name: &str) -> Email {
+        Email {
+            template: EmailTemplate::ContributionReceived,
+            locale,
+            context: json!({ "name": name }),
+        }
+    }
+}
+
+impl std::fmt::Debug for EmailTemplates {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut debug = f.debug_struct("EmailTemplates");
+        #[cfg(feature = "microservice")]
+        debug.field("overrides", &self.storage.is_some());
+        debug.finish()
+    }
+}
+
+/// Formats an optional value, or "N/A" (in the locale) if it's missing.
+fn or_na<T: ToString>(value: Option<T>, locale: Locale) -> String {
+    value.map(|v| v.to_string()).unwrap_or_else(|| locale.message("value.not_available", &[]))
+}
+
+/// Renders every part of an email, using the given sources in place of
+/// the built-in ones.
+fn render_parts(email: &Email, sources: &HashMap<TemplatePart, String>) -> Result<RenderedEmail> {
+    let mut env = Environment::new();
+    // Fail on misspelt variables rather than silently leaving them blank
+    env.set_undefined_behavior(UndefinedBehavior::Strict);
+
+    let mut rendered = HashMap::new();
+    for part in TemplatePart::ALL {
+        let name = email.template.file_name(part);
+        let source = sources
+            .get(&part)
+            .cloned()
+            .unwrap_or_else(|| email.template.builtin(email.locale, part).to_string());
+        env.add_template_owned(name.clone(), source)
+            .context(format!("Failed to compile {}", name))?;
+
+        let output = env
+            .get_template(&name)?
+            .render(&email.context)
+            .context(format!("Failed to render {}", name))?;
+        rendered.insert(part, output);
+    }
+
+    let mut take = |part| rendered.remove(&part).unwrap_or_default();
+    Ok(RenderedEmail {
+        // Subjects are one line, whatever the template's whitespace
+        subject: take(TemplatePart::Subject).split_whitespace().collect::<Vec<_>>().join(" "),
+        html: take(TemplatePart::Html),
+        text: take(TemplatePart::Text),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_builtin_templates_render() {
+        for locale in Locale::ALL {
+            for template in EmailTemplate::ALL {
+                let name = format!("{}/{}", locale, template.name());
+                let rendered = EmailTemplates::builtin().render(&template.sample(locale)).await.unwrap();
+                assert!(!rendered.subject.is_empty(), "{} has no subject", name);
+                assert!(!rendered.html.is_empty(), "{} has no HTML body", name);
+                assert!(!rendered.text.is_empty(), "{} has no text body", name);
+                assert!(!rendered.text.contains("<br>"), "{} text body has markup", name);
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_html_is_escaped() {
+        let email = EmailTemplates::prediction_success(
+            Locale::En, "now", 0.75, true, Some(8), Some("<script>alert(1)</script>"), Some('M'),
+            Some("5'10\""), Some(70), "uid", "uid_0", None,
+        );
+        let rendered = EmailTemplates::builtin().render(&email).await.unwrap();
+
+        assert!(!rendered.html.contains("<script>"));
+        assert!(rendered.html.contains("&lt;script&gt;"));
+        assert!(rendered.html.contains("5&#x27;10&quot;"));
+        assert!(rendered.html.contains("0.75"));
+        assert!(rendered.html.contains("markers consistent with ASD"));
+        assert!(!rendered.html.contains("Download your results"));
+
+        // Plain text isn't HTML, so it's left as typed
+        assert!(rendered.text.contains("<script>alert(1)</script>"));
+        assert!(rendered.text.contains("5'10\""));
+    }
+
+    #[tokio::test]
+    async fn test_emails_are_localized() {
+        let email = EmailTemplates::prediction_success(
+            Locale::Es, "now", 0.25, false, None, None, None, None, None, "uid", "uid_0", None,
+        );
+        let rendered = EmailTemplates::builtin().render(&email).await.unwrap();
+
+        assert!(rendered.subject.contains("completado"));
+        assert!(rendered.text.contains("patrones de marcha típicos"));
+        assert!(rendered.text.contains("Edad: N/D"));
+    }
+
+    #[test]
+    fn test_validate_rejects_unknown_variables() {
+        let template = EmailTemplate::ContributionReceived;
+        EmailTemplates::validate(template, Locale::Es, TemplatePart::Html, "Hola {{ name }}").unwrap();
+        assert!(EmailTemplates::validate(template, Locale::En, TemplatePart::Html, "Hi {{ nmae }}").is_err());
+        assert!(EmailTemplates::validate(template, Locale::En, TemplatePart::Subject, "{% if %}").is_err());
+    }
+
+    #[test]
+    fn test_names_round_trip() {
+        for template in EmailTemplate::ALL {
+            assert_eq!(EmailTemplate::from_name(template.name()), Some(template));
+        }
+        for part in TemplatePart::ALL {
+            assert_eq!(TemplatePart::from_name(part.name()), Some(part));
+        }
+        assert_eq!(EmailTemplate::PredictionSuccess.file_name(TemplatePart::Subject), "prediction_success.subject.txt");
+    }
+}
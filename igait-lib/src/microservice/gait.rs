@@ -0,0 +1,102 @@
+//! The stage 5 gait analysis.
+//!
+//! Stage 5 runs the gait cycle detection script on each video's landmarks
+//! and uploads its output as `jobs/{job_id}/stage_5/{front|side}_gait_analysis.json`.
+//! `GaitAnalysis` is the part of that output the rest of the pipeline reads;
+//! anything else in it is passed through untouched. Stage 5 checks every
+//! output against it before uploading, so a change in the script's output
+//! shows up as a warning in the stage logs instead of an empty report.
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// The gait analysis of one video.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct GaitAnalysis {
+    /// The detected gait cycles, or `None` if the output has none
+    #[serde(default)]
+    pub gait_cycles: Option<Vec<GaitCycle>>,
+
+    /// The video's frame rate, if the script recorded it
+    #[serde(default)]
+    pub fps: Option<f64>,
+}
+
+/// One detected gait cycle, as frame indices.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum GaitCycle {
+    /// A `[start, end]` pair
+    Bounds([f64; 2]),
+    /// An object with `start` and `end` fields
+    Span { start: f64, end: f64 },
+    /// A cycle in any other shape, which is counted but has no length
+    Other(Value),
+}
+
+impl GaitCycle {
+    /// The cycle's length in frames, if its shape is known.
+    pub fn frames(&self) -> Option<f64> {
+        match self {
+            Self::Bounds([start, end]) | Self::Span { start, end } => Some(end - start),
+            Self::Other(_) => None,
+        }
+    }
+}
+
+impl GaitAnalysis {
+    /// Parses a gait analysis from the JSON stage 5 uploads.
+    pub fn from_slice(data: &[u8]) -> Result<Self> {
+        serde_json::from_slice(data).context("Failed to parse the gait analysis")
+    }
+
+    /// Describes anything in the analysis that would leave its gait
+    /// metrics out of the report, for the stage logs.
+    pub fn warnings(&self) -> Vec<String> {
+        let Some(cycles) = &self.gait_cycles else {
+            return vec!["The gait analysis has no `gait_cycles`".to_string()];
+        };
+
+        let unknown = cycles.iter().filter(|cycle| cycle.frames().is_none()).count();
+        match unknown {
+            0 => Vec::new(),
+            _ => vec![format!(
+                "{} of {} gait cycles aren't [start, end] pairs or {{start, end}} objects",
+                unknown,
+                cycles.len()
+            )],
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_gait_analysis_fixture() {
+        // Follows the contract above, with the landmarks stage 5 passes through
+        let analysis = GaitAnalysis::from_slice(include_bytes!("fixtures/front_gait_analysis.json")).unwrap();
+
+        let cycles = analysis.gait_cycles.as_deref().unwrap();
+        assert_eq!(cycles.len(), 4);
+        assert_eq!(cycles[0], GaitCycle::Bounds([12.0, 47.0]));
+        assert_eq!(cycles.iter().map(|cycle| cycle.frames().unwrap()).sum::<f64>(), 143.0);
+        assert_eq!(analysis.fps, Some(30.0));
+        assert!(analysis.warnings().is_empty());
+    }
+
+    #[test]
+    fn test_gait_analysis_warnings() {
+        let missing = GaitAnalysis::from_slice(br#"{ "fps": 30 }"#).unwrap();
+        assert!(missing.gait_cycles.is_none());
+        assert_eq!(missing.warnings().len(), 1);
+
+        let mixed = GaitAnalysis::from_slice(br#"{ "gait_cycles": [[0, 30], { "start": 30, "end": 62 }, [62]] }"#).unwrap();
+        let cycles = mixed.gait_cycles.as_deref().unwrap();
+        assert_eq!(cycles[1].frames(), Some(32.0));
+        assert_eq!(cycles[2].frames(), None);
+        assert_eq!(mixed.warnings(), ["1 of 3 gait cycles aren't [start, end] pairs or {start, end} objects"]);
+    }
+}
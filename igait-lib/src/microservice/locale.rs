@@ -0,0 +1,150 @@
+//! Locales and message catalogs.
+//!
+//! A job's locale is captured when it's submitted and travels with it in
+//! `JobMetadata`, so every stage can describe it and email its submitter in
+//! their own language.
+//!
+//! The messages for each locale live in a JSON catalog under `locales/`
+//! (e.g. `locales/es.json`), keyed by message ID, with `{name}`
+//! placeholders for the values filled in. Messages missing from a catalog
+//! fall back to English.
+
+use serde::{Deserialize, Deserializer, Serialize};
+use std::{collections::HashMap, str::FromStr, sync::OnceLock};
+
+/// The languages jobs can be described and emailed in.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Locale {
+    #[default]
+    En,
+    Es,
+}
+
+impl Locale {
+    /// Every locale.
+    pub const ALL: [Locale; 2] = [Locale::En, Locale::Es];
+
+    /// The locale's language code (e.g. "es").
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::En => "en",
+            Self::Es => "es",
+        }
+    }
+
+    /// Parses a locale from a language tag, ignoring its region
+    /// (e.g. "es", "es-MX" and "es_US" are all Spanish).
+    pub fn from_tag(tag: &str) -> Option<Self> {
+        let language = tag
+            .trim()
+            .split(['-', '_'])
+            .next()
+            .unwrap_or_default()
+            .to_ascii_lowercase();
+
+        Self::ALL.into_iter().find(|locale| locale.code() == language)
+    }
+
+    /// Looks up a message, filling in its `{name}` placeholders.
+    ///
+    /// Falls back to the English message, then to the ID itself, so a
+    /// missing translation never stops a status or email going out.
+    pub fn message(&self, id: &str, args: &[(&str, &str)]) -> String {
+        let message = catalog(*self)
+            .get(id)
+            .or_else(|| catalog(Locale::En).get(id))
+            .map(String::as_str)
+            .unwrap_or(id);
+
+        args.iter().fold(message.to_string(), |message, (name, value)| {
+            message.replace(&format!("{{{}}}", name), value)
+        })
+    }
+}
+
+impl FromStr for Locale {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::from_tag(s).ok_or_else(|| anyhow::anyhow!("Unsupported locale: {}", s))
+    }
+}
+
+impl std::fmt::Display for Locale {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.code())
+    }
+}
+
+/// Unsupported locales are read as English rather than failing, so a job
+/// submitted with one is still processed.
+impl<'de> Deserialize<'de> for Locale {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let tag = String::deserialize(deserializer)?;
+        Ok(Self::from_tag(&tag).unwrap_or_default())
+    }
+}
+
+/// Returns a locale's message catalog, parsing it on first use.
+fn catalog(locale: Locale) -> &'static HashMap<String, String> {
+    static CATALOGS: OnceLock<HashMap<Locale, HashMap<String, String>>> = OnceLock::new();
+
+    let catalogs = CATALOGS.get_or_init(|| {
+        Locale::ALL
+            .into_iter()
+            .map(|locale| {
+                let source = match locale {
+                    Locale::En => include_str!("locales/en.json"),
+                    Locale::Es => include_str!("locales/es.json"),
+                };
+                let messages = serde_json::from_str(source)
+                    .unwrap_or_else(|e| panic!("The {} message catalog is invalid: {}", locale, e));
+                (locale, messages)
+            })
+            .collect()
+    });
+
+    &catalogs[&locale]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_catalogs_have_the_same_messages() {
+        let english = catalog(Locale::En);
+        for locale in Locale::ALL {
+            let messages = catalog(locale);
+            for id in english.keys() {
+                assert!(messages.contains_key(id), "{} is missing '{}'", locale, id);
+            }
+            for id in messages.keys() {
+                assert!(english.contains_key(id), "{} has unknown message '{}'", locale, id);
+            }
+        }
+    }
+
+    #[test]
+    fn test_message_placeholders() {
+        let message = Locale::Es.message("status.processing", &[
+            ("stage", "4"),
+            ("num_stages", "7"),
+            ("stage_name", "Estimando"),
+        ]);
+        assert_eq!(message, "Etapa 4/7: Estimando...");
+        assert_eq!(Locale::En.message("no.such.message", &[]), "no.such.message");
+    }
+
+    #[test]
+    fn test_locale_parsing() {
+        assert_eq!(Locale::from_tag("es-MX"), Some(Locale::Es));
+        assert_eq!(Locale::from_tag("EN_us"), Some(Locale::En));
+        assert_eq!(Locale::from_tag("fr"), None);
+
+        let locale: Locale = serde_json::from_str("\"fr\"").unwrap();
+        assert_eq!(locale, Locale::En);
+        assert_eq!(serde_json::to_string(&Locale::Es).unwrap(), "\"es\"");
+    }
+}
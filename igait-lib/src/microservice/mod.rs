@@ -7,6 +7,10 @@ mod types;
 mod storage;
 mod queue;
 mod backend_status;
+mod webhook;
+mod precheck;
+mod locale;
+mod gait;
 
 #[cfg(feature = "microservice")]
 mod worker;
@@ -14,13 +18,23 @@ mod worker;
 #[cfg(feature = "email")]
 mod email;
 
+#[cfg(feature = "email")]
+mod email_templates;
+
 pub use types::*;
 pub use storage::*;
 pub use queue::*;
 pub use backend_status::*;
+pub use webhook::*;
+pub use precheck::*;
+pub use locale::*;
+pub use gait::*;
 
 #[cfg(feature = "microservice")]
 pub use worker::*;
 
 #[cfg(feature = "email")]
 pub use email::*;
+
+#[cfg(feature = "email")]
+pub use email_templates::*;
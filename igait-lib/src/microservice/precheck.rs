@@ -0,0 +1,227 @@
+//! Pre-submission checks of a single short clip.
+//!
+//! The backend writes a `PrecheckRequest` to `queues/precheck` in Firebase
+//! RTDB and waits for it. Stage 2 workers pick requests up ahead of their
+//! regular queue, run a shortened version of the validity check, and write
+//! the `PrecheckDetection` back onto the request - so a user can find out
+//! their video won't pass long before a real job would reach Stage 2.
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+
+use crate::microservice::{CLAIM_TIMEOUT_MS, now_ms};
+
+/// How much of the clip the shortened validity check looks at.
+pub const PRECHECK_MAX_SECONDS: u32 = 3;
+
+/// A clip waiting to be checked.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PrecheckRequest {
+    /// Unique request ID, also used as the queue key
+    pub id: String,
+
+    /// User ID who submitted the clip
+    pub user_id: String,
+
+    /// Storage key of the clip
+    pub video_key: String,
+
+    /// When the request was made (Unix timestamp ms)
+    pub requested_at: u64,
+
+    /// Worker ID that claimed this request (None if unclaimed)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub claimed_by: Option<String>,
+
+    /// When the request was claimed (Unix timestamp ms, for timeout detection)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub claimed_at: Option<u64>,
+
+    /// The outcome, once a worker has checked the clip
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub result: Option<PrecheckDetection>,
+}
+
+impl PrecheckRequest {
+    /// Creates a new unclaimed request.
+    pub fn new(id: String, user_id: String, video_key: String) -> Self {
+        Self {
+            id,
+            user_id,
+            video_key,
+            requested_at: now_ms(),
+            claimed_by: None,
+            claimed_at: None,
+            result: None,
+        }
+    }
+
+    /// Checks if this request is waiting for a worker.
+    pub fn is_available(&self) -> bool {
+        if self.result.is_some() {
+            return false;
+        }
+
+        self.claimed_at
+            .is_none_or(|claimed_time| now_ms().saturating_sub(claimed_time) > CLAIM_TIMEOUT_MS)
+    }
+
+    /// Claims this request for a worker.
+    pub fn claim(&self, worker_id: &str) -> Self {
+        Self {
+            claimed_by: Some(worker_id.to_string()),
+            claimed_at: Some(now_ms()),
+            ..self.clone()
+        }
+    }
+}
+
+/// What the shortened validity check found in a clip.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct PrecheckDetection {
+    /// Whether a person was seen at all
+    pub human_detected: bool,
+
+    /// Whether the person was walking
+    pub walking_detected: bool,
+
+    /// How many clips of the video were examined
+    pub clips_processed: u32,
+
+    /// How many of those clips contained a person
+    pub clips_with_person: u32,
+
+    /// How many of those clips contained a person walking
+    pub clips_with_walking: u32,
+
+    /// Why the check couldn't be run, if it couldn't
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl PrecheckDetection {
+    /// The fraction of examined clips that contained a person.
+    pub fn person_coverage(&self) -> f64 {
+        if self.clips_processed == 0 {
+            return 0.0;
+        }
+
+        self.clips_with_person as f64 / self.clips_processed as f64
+    }
+}
+
+/// Splits the entries of the precheck queue into requests, and the keys
+/// of entries that aren't requests (e.g. a result written after its
+/// request was removed).
+pub fn parse_precheck_queue(items: HashMap<String, Value>) -> (HashMap<String, PrecheckRequest>, Vec<String>) {
+    let mut requests = HashMap::new();
+    let mut malformed = Vec::new();
+    for (key, value) in items {
+        match serde_json::from_value::<PrecheckRequest>(value) {
+            Ok(request) => {
+                requests.insert(key, request);
+            }
+            Err(_) => malformed.push(key),
+        }
+    }
+
+    (requests, malformed)
+}
+
+/// Attaches a result to a request as currently stored in the queue.
+///
+/// # Returns
+/// * The answered request, or `None` if the request is gone (or isn't a
+///   request any more), in which case the result should be dropped
+pub fn answer_precheck(current: Option<Value>, result: &PrecheckDetection) -> Option<PrecheckRequest> {
+    let request: PrecheckRequest = serde_json::from_value(current?).ok()?;
+
+    Some(PrecheckRequest { result: Some(result.clone()), ..request })
+}
+
+/// Returns the Firebase RTDB path of the precheck queue.
+pub fn precheck_queue_path() -> &'static str {
+    "queues/precheck"
+}
+
+/// Returns the Firebase RTDB path of a precheck request.
+pub fn precheck_item_path(id: &str) -> String {
+    format!("{}/{}", precheck_queue_path(), id)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_precheck_request_availability() {
+        let request = PrecheckRequest::new(
+            "req_1".to_string(),
+            "user_1".to_string(),
+            "prechecks/user_1/req_1.mp4".to_string(),
+        );
+        assert!(request.is_available());
+
+        let claimed = request.claim("worker-1");
+        assert!(!claimed.is_available());
+
+        let stale = PrecheckRequest {
+            claimed_at: Some(now_ms() - CLAIM_TIMEOUT_MS - 1),
+            ..claimed.clone()
+        };
+        assert!(stale.is_available());
+
+        let answered = PrecheckRequest {
+            result: Some(PrecheckDetection::default()),
+            ..stale
+        };
+        assert!(!answered.is_available());
+    }
+
+    #[test]
+    fn test_late_completion_is_dropped() {
+        let request = PrecheckRequest::new(
+            "req_1".to_string(),
+            "user_1".to_string(),
+            "prechecks/user_1/req_1.mp4".to_string(),
+        ).claim("worker-1");
+        let detection = PrecheckDetection { human_detected: true, ..Default::default() };
+
+        // Answered while the backend is still waiting
+        let current = serde_json::to_value(&request).unwrap();
+        let answered = answer_precheck(Some(current), &detection).unwrap();
+        assert_eq!(answered.result, Some(detection.clone()));
+        assert_eq!(answered.claimed_by.as_deref(), Some("worker-1"));
+
+        // Answered after the backend gave up and removed the request
+        assert!(answer_precheck(None, &detection).is_none());
+
+        // An orphaned result left by an older worker isn't a request to answer
+        let orphan = serde_json::json!({ "result": detection });
+        assert!(answer_precheck(Some(orphan.clone()), &detection).is_none());
+
+        // ...and doesn't stop the rest of the queue being read
+        let queue = HashMap::from([
+            ("req_1".to_string(), serde_json::to_value(&request).unwrap()),
+            ("req_2".to_string(), orphan),
+        ]);
+        let (requests, malformed) = parse_precheck_queue(queue);
+        assert_eq!(requests.keys().collect::<Vec<_>>(), ["req_1"]);
+        assert_eq!(malformed, ["req_2"]);
+    }
+
+    #[test]
+    fn test_person_coverage() {
+        assert_eq!(PrecheckDetection::default().person_coverage(), 0.0);
+
+        let detection = PrecheckDetection {
+            human_detected: true,
+            clips_processed: 4,
+            clips_with_person: 3,
+            ..Default::default()
+        };
+        assert_eq!(detection.person_coverage(), 0.75);
+        assert_eq!(precheck_item_path("req_1"), "queues/precheck/req_1");
+    }
+}
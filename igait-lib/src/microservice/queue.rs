@@ -3,7 +3,8 @@
 //! This module defines the data structures used for Firebase Realtime Database
 //! queue-based job processing with claim-based distributed locking.
 
-use serde::{Deserialize, Serialize};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::Value;
 use std::collections::HashMap;
 
 use crate::microservice::{JobMetadata, StageNumber};
@@ -31,12 +32,40 @@ pub const HEARTBEAT_INTERVAL_SECS: u64 = 30;
 /// Stored at `queue_config/stage_{n}` in Firebase RTDB.
 /// If `requires_approval` is true, all jobs in this queue
 /// must be explicitly approved before workers can claim them.
-#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+/// If `paused` is true, workers stop claiming new jobs, which lets
+/// operations drain a stage before deploying a new version of it.
+#[derive(Debug, Clone, Serialize, Deserialize, Default, PartialEq)]
 pub struct QueueConfig {
     /// Whether this queue globally requires manual approval
     /// before workers can pick up jobs.
     #[serde(default)]
     pub requires_approval: bool,
+
+    /// Whether workers should stop claiming jobs from this queue.
+    /// Jobs already claimed are allowed to finish.
+    #[serde(default)]
+    pub paused: bool,
+
+    /// The maximum number of jobs that may be claimed from this queue
+    /// at once, across all workers. `None` means unlimited.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_concurrency: Option<u32>,
+
+    /// Stage-specific parameter overrides, handed to the worker
+    /// with each job it claims (see `QueueItem::parameter`).
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub parameters: HashMap<String, Value>,
+}
+
+impl QueueConfig {
+    /// Checks whether another job may be claimed, given how many
+    /// jobs in the queue are currently claimed by live workers.
+    pub fn has_capacity(&self, in_flight: usize) -> bool {
+        match self.max_concurrency {
+            Some(max) => in_flight < max as usize,
+            None => true,
+        }
+    }
 }
 
 /// An item in a stage processing queue.
@@ -79,6 +108,11 @@ pub struct QueueItem {
     /// this field is ignored and the job can be picked up freely.
     #[serde(default)]
     pub approved: bool,
+
+    /// Parameter overrides from the queue config, attached when the job
+    /// is claimed. Never persisted to the queue itself.
+    #[serde(skip)]
+    pub parameters: HashMap<String, Value>,
 }
 
 impl QueueItem {
@@ -102,6 +136,7 @@ impl QueueItem {
             // Start unapproved — the worker's `is_approved_for_processing`
             // method will allow pick-up if no approval is required.
             approved: false,
+            parameters: HashMap::new(),
         }
     }
 
@@ -116,13 +151,17 @@ impl QueueItem {
     pub fn is_available(&self) -> bool {
         match self.claimed_at {
             None => true, // Never claimed
-            Some(claimed_time) => {
-                // Check if claim has timed out
-                now_ms().saturating_sub(claimed_time) > CLAIM_TIMEOUT_MS
-            }
+            Some(_) => self.is_claim_stale(),
         }
     }
 
+    /// Checks if this item was claimed but the claiming worker stopped
+    /// sending heartbeats for longer than `CLAIM_TIMEOUT_MS`.
+    pub fn is_claim_stale(&self) -> bool {
+        self.claimed_at
+            .is_some_and(|claimed_time| now_ms().saturating_sub(claimed_time) > CLAIM_TIMEOUT_MS)
+    }
+
     /// Checks whether this item is approved for processing.
     ///
     /// A job is approved if:
@@ -156,6 +195,14 @@ impl QueueItem {
         }
     }
 
+    /// Gets a parameter override from the queue config, if one is set
+    /// and can be deserialized as `T`.
+    pub fn parameter<T: DeserializeOwned>(&self, key: &str) -> Option<T> {
+        self.parameters
+            .get(key)
+            .and_then(|value| serde_json::from_value(value.clone()).ok())
+    }
+
     /// Gets the input storage key for the front video from the input_keys.
     /// Falls back to constructing from job_id if not present.
     pub fn input_front_video(&self, stage: StageNumber) -> String {
@@ -291,12 +338,16 @@ impl FinalizeQueueItem {
     pub fn is_available(&self) -> bool {
         match self.claimed_at {
             None => true,
-            Some(claimed_time) => {
-                now_ms().saturating_sub(claimed_time) > CLAIM_TIMEOUT_MS
-            }
+            Some(_) => self.is_claim_stale(),
         }
     }
 
+    /// Checks if this item was claimed but the claim has timed out.
+    pub fn is_claim_stale(&self) -> bool {
+        self.claimed_at
+            .is_some_and(|claimed_time| now_ms().saturating_sub(claimed_time) > CLAIM_TIMEOUT_MS)
+    }
+
     /// Claims this item for a worker.
     pub fn claim(&self, worker_id: &str) -> Self {
         Self {
@@ -396,8 +447,11 @@ pub enum ClaimResult<T> {
     Claimed(T),
     /// No jobs available in the queue
     QueueEmpty,
-    /// Jobs exist but all are claimed by other workers
+    /// Jobs exist but all are claimed by other workers,
+    /// or the queue is at its concurrency limit
     AllClaimed,
+    /// The queue is paused and no jobs may be claimed
+    Paused,
     /// Error occurred during claim operation
     Error(String),
 }
@@ -453,6 +507,28 @@ mod tests {
         assert!(!claimed.is_available()); // Just claimed, not timed out yet
     }
 
+    #[test]
+    fn test_queue_item_stale_claim() {
+        let item = QueueItem::new(
+            "test_job".to_string(),
+            "test_user".to_string(),
+            HashMap::new(),
+            JobMetadata::default(),
+            false,
+        );
+        assert!(!item.is_claim_stale()); // Never claimed
+
+        let claimed = item.claim("worker_1");
+        assert!(!claimed.is_claim_stale());
+
+        let abandoned = QueueItem {
+            claimed_at: Some(now_ms() - CLAIM_TIMEOUT_MS - 1),
+            ..claimed
+        };
+        assert!(abandoned.is_claim_stale());
+        assert!(abandoned.is_available());
+    }
+
     #[test]
     fn test_approval_logic() {
         // Job that doesn't require approval
@@ -486,4 +562,48 @@ mod tests {
         assert!(item3.is_approved_for_processing(false));
         assert!(item3.is_approved_for_processing(true));
     }
+
+    #[test]
+    fn test_queue_config_capacity() {
+        let unlimited = QueueConfig::default();
+        assert!(unlimited.has_capacity(0));
+        assert!(unlimited.has_capacity(1000));
+
+        let limited = QueueConfig {
+            max_concurrency: Some(2),
+            ..QueueConfig::default()
+        };
+        assert!(limited.has_capacity(1));
+        assert!(!limited.has_capacity(2));
+    }
+
+    #[test]
+    fn test_queue_config_defaults() {
+        // Older configs only stored `requires_approval`
+        let config: QueueConfig = serde_json::from_str(r#"{"requires_approval": true}"#).unwrap();
+        assert!(config.requires_approval);
+        assert!(!config.paused);
+        assert_eq!(config.max_concurrency, None);
+        assert!(config.parameters.is_empty());
+    }
+
+    #[test]
+    fn test_queue_item_parameters() {
+        let mut item = QueueItem::new(
+            "job_1".to_string(),
+            "user_1".to_string(),
+            HashMap::new(),
+            JobMetadata::default(),
+            false,
+        );
+        item.parameters.insert("preset".to_string(), Value::from("medium"));
+
+        assert_eq!(item.parameter::<String>("preset"), Some("medium".to_string()));
+        assert_eq!(item.parameter::<u32>("preset"), None);
+        assert_eq!(item.parameter::<String>("missing"), None);
+
+        // Parameters are never written back to the queue
+        let serialized = serde_json::to_value(&item).unwrap();
+        assert!(serialized.get("parameters").is_none());
+    }
 }
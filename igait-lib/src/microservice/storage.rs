@@ -9,8 +9,15 @@ use aws_sdk_s3::{
     Client,
     presigning::PresigningConfig,
     primitives::ByteStream,
-    types::{Delete, ObjectIdentifier},
+    types::{CompletedMultipartUpload, CompletedPart, Delete, ObjectIdentifier},
 };
+#[cfg(feature = "microservice")]
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+/// The size of each part `StorageClient::upload_file` uploads (8 MiB).
+/// Every part but the last must be at least 5 MiB.
+#[cfg(feature = "microservice")]
+const UPLOAD_PART_SIZE: u64 = 8 * 1024 * 1024;
 
 /// Configuration for storage access.
 #[derive(Debug, Clone)]
@@ -96,6 +103,146 @@ impl StorageClient {
         Ok(())
     }
 
+    /// Starts a multipart upload to a storage key.
+    ///
+    /// Returns the upload ID, which every part and the final
+    /// `complete_multipart_upload` call must reference.
+    pub async fn create_multipart_upload(&self, key: &str, content_type: Option<&str>) -> Result<String> {
+        let mut request = self.client
+            .create_multipart_upload()
+            .bucket(&self.bucket)
+            .key(key);
+
+        if let Some(ct) = content_type {
+            request = request.content_type(ct);
+        }
+
+        let response = request
+            .send()
+            .await
+            .context(format!("Failed to start multipart upload: {}", key))?;
+
+        response.upload_id()
+            .map(String::from)
+            .context(format!("No upload ID returned for multipart upload: {}", key))
+    }
+
+    /// Uploads one part of a multipart upload.
+    ///
+    /// Part numbers start at 1. Every part except the last must be at least
+    /// 5 MiB. Re-uploading a part number replaces the earlier upload.
+    /// Returns the part's ETag, which is needed to complete the upload.
+    pub async fn upload_part(&self, key: &str, upload_id: &str, part_number: i32, data: Vec<u8>) -> Result<String> {
+        let response = self.client
+            .upload_part()
+            .bucket(&self.bucket)
+            .key(key)
+            .upload_id(upload_id)
+            .part_number(part_number)
+            .body(ByteStream::from(data))
+            .send()
+            .await
+            .context(format!("Failed to upload part {} of: {}", part_number, key))?;
+
+        response.e_tag()
+            .map(String::from)
+            .context(format!("No ETag returned for part {} of: {}", part_number, key))
+    }
+
+    /// Completes a multipart upload, assembling the parts into one object.
+    ///
+    /// `parts` are `(part_number, etag)` pairs, and must be in ascending
+    /// order of part number.
+    pub async fn complete_multipart_upload(&self, key: &str, upload_id: &str, parts: &[(i32, String)]) -> Result<()> {
+        let parts = parts
+            .iter()
+            .map(|(part_number, etag)| CompletedPart::builder()
+                .part_number(*part_number)
+                .e_tag(etag)
+                .build())
+            .collect();
+
+        self.client
+            .complete_multipart_upload()
+            .bucket(&self.bucket)
+            .key(key)
+            .upload_id(upload_id)
+            .multipart_upload(CompletedMultipartUpload::builder()
+                .set_parts(Some(parts))
+                .build())
+            .send()
+            .await
+            .context(format!("Failed to complete multipart upload: {}", key))?;
+
+        Ok(())
+    }
+
+    /// Aborts a multipart upload, discarding any parts uploaded so far.
+    pub async fn abort_multipart_upload(&self, key: &str, upload_id: &str) -> Result<()> {
+        self.client
+            .abort_multipart_upload()
+            .bucket(&self.bucket)
+            .key(key)
+            .upload_id(upload_id)
+            .send()
+            .await
+            .context(format!("Failed to abort multipart upload: {}", key))?;
+
+        Ok(())
+    }
+
+    /// Uploads a file in parts, so it's never held in memory whole.
+    ///
+    /// The multipart upload is aborted if any part fails.
+    pub async fn upload_file(&self, key: &str, path: &Path, content_type: Option<&str>) -> Result<()> {
+        let mut file = tokio::fs::File::open(path)
+            .await
+            .context(format!("Failed to open {}", path.display()))?;
+        let upload_id = self.create_multipart_upload(key, content_type).await?;
+
+        let uploaded = async {
+            let mut parts = Vec::new();
+            loop {
+                let mut part = Vec::new();
+                (&mut file).take(UPLOAD_PART_SIZE).read_to_end(&mut part).await?;
+                if part.is_empty() && !parts.is_empty() {
+                    break;
+                }
+
+                let last = (part.len() as u64) < UPLOAD_PART_SIZE;
+                let part_number = parts.len() as i32 + 1;
+                let etag = self.upload_part(key, &upload_id, part_number, part).await?;
+                parts.push((part_number, etag));
+                if last {
+                    break;
+                }
+            }
+
+            self.complete_multipart_upload(key, &upload_id, &parts).await
+        }.await;
+
+        if uploaded.is_err() {
+            if let Err(e) = self.abort_multipart_upload(key, &upload_id).await {
+                eprintln!("Failed to abort the upload of {}: {e:?}", key);
+            }
+        }
+        uploaded
+    }
+
+    /// Copies an object to another key in the same bucket.
+    pub async fn copy(&self, source_key: &str, destination_key: &str) -> Result<()> {
+        self.client
+            .copy_object()
+            .bucket(&self.bucket)
+            .copy_source(format!("{}/{}", self.bucket, source_key))
+            .key(destination_key)
+            .send()
+            .await
+            .context(format!("Failed to copy object {} to {}", source_key, destination_key))?;
+
+        Ok(())
+    }
+
     /// Downloads bytes from a storage key.
     pub async fn download(&self, key: &str) -> Result<Vec<u8>> {
         let response = self.client
@@ -113,6 +260,31 @@ impl StorageClient {
         Ok(data.into_bytes().to_vec())
     }
 
+    /// Downloads an object to a file, so it's never held in memory whole.
+    ///
+    /// Returns the number of bytes written.
+    pub async fn download_to_file(&self, key: &str, path: &Path) -> Result<u64> {
+        let response = self.client
+            .get_object()
+            .bucket(&self.bucket)
+            .key(key)
+            .send()
+            .await
+            .context(format!("Failed to download object: {}", key))?;
+
+        let mut file = tokio::fs::File::create(path)
+            .await
+            .context(format!("Failed to create {}", path.display()))?;
+        let written = tokio::io::copy(&mut response.body.into_async_read(), &mut file)
+            .await
+            .context("Failed to read object body")?;
+        file.flush()
+            .await
+            .context(format!("Failed to write {}", path.display()))?;
+
+        Ok(written)
+    }
+
     /// Deletes an object from storage.
     pub async fn delete(&self, key: &str) -> Result<()> {
         self.client
@@ -253,6 +425,51 @@ impl StorageClient {
         Ok(presigned.uri().to_string())
     }
 
+    /// Generates a presigned PUT URL for a storage key.
+    ///
+    /// The returned URL allows an unauthenticated upload of a single
+    /// object for the specified duration. The `Content-Type` and
+    /// `Content-Length` are part of the signature, so the caller must
+    /// send exactly those headers.
+    pub async fn presign_upload(
+        &self,
+        key: &str,
+        content_type: &str,
+        content_length: u64,
+        expires_in: std::time::Duration,
+    ) -> Result<String> {
+        let presigning_config = PresigningConfig::expires_in(expires_in)
+            .context("Invalid presigning duration")?;
+
+        let presigned = self.client
+            .put_object()
+            .bucket(&self.bucket)
+            .key(key)
+            .content_type(content_type)
+            .content_length(content_length as i64)
+            .presigned(presigning_config)
+            .await
+            .context(format!("Failed to presign upload for key: {}", key))?;
+
+        Ok(presigned.uri().to_string())
+    }
+
+    /// Returns the size of an object in bytes, or `None` if it doesn't exist.
+    pub async fn object_size(&self, key: &str) -> Result<Option<u64>> {
+        let response = self.client
+            .head_object()
+            .bucket(&self.bucket)
+            .key(key)
+            .send()
+            .await;
+
+        match response {
+            Ok(output) => Ok(Some(output.content_length().unwrap_or(0) as u64)),
+            Err(err) if err.as_service_error().is_some_and(|e| e.is_not_found()) => Ok(None),
+            Err(err) => Err(err).context(format!("Failed to read object metadata: {}", key)),
+        }
+    }
+
     /// Lists all objects under `prefix` and returns a presigned URL for each.
     ///
     /// Returns a vec of `(key, presigned_url)` pairs.
@@ -314,6 +531,19 @@ impl StoragePaths {
         format!("jobs/{}/stage_0/side.{}", job_id, extension)
     }
 
+    /// Returns the base path for a resumable upload session's files.
+    /// Format: `uploads/{user_id}/{session_id}/`
+    pub fn upload_session_dir(user_id: &str, session_id: &str) -> String {
+        format!("uploads/{}/{}/", user_id, session_id)
+    }
+
+    /// Returns the path a resumable upload session stages a video at
+    /// until the job is created.
+    /// Format: `uploads/{user_id}/{session_id}/{file}.{extension}`
+    pub fn upload_session_file(user_id: &str, session_id: &str, file: &str, extension: &str) -> String {
+        format!("uploads/{}/{}/{}.{}", user_id, session_id, file, extension)
+    }
+
     /// Returns the full path for a stage output front video.
     pub fn stage_front_video(job_id: &str, stage: u8, extension: &str) -> String {
         format!("jobs/{}/stage_{}/front.{}", job_id, stage, extension)
@@ -329,6 +559,36 @@ impl StoragePaths {
         format!("jobs/{}/stage_7/results.zip", job_id)
     }
 
+    /// Returns the path for the clinical PDF report.
+    /// Format: `jobs/{job_id}/stage_7/report.pdf`
+    pub fn clinical_report(job_id: &str) -> String {
+        format!("jobs/{}/stage_7/report.pdf", job_id)
+    }
+
+    /// Returns the base path for a research export's files.
+    /// Format: `exports/{export_id}/`
+    pub fn export_dir(export_id: &str) -> String {
+        format!("exports/{}/", export_id)
+    }
+
+    /// Returns the path for a research export's archive.
+    /// Format: `exports/{export_id}/igait-research-export-v{version}.zip`
+    pub fn export_archive(export_id: &str, version: u32) -> String {
+        format!("exports/{}/igait-research-export-v{}.zip", export_id, version)
+    }
+
+    /// Returns the path for a research export's data dictionary.
+    /// Format: `exports/{export_id}/data_dictionary.json`
+    pub fn export_dictionary(export_id: &str) -> String {
+        format!("exports/{}/data_dictionary.json", export_id)
+    }
+
+    /// Returns the path of an email template override in a locale.
+    /// Format: `email_templates/{locale}/{file_name}`
+    pub fn email_template(locale: &str, file_name: &str) -> String {
+        format!("email_templates/{}/{}", locale, file_name)
+    }
+
     /// Extracts the job_id from a storage path.
     /// Assumes format: `jobs/{job_id}/...`
     pub fn extract_job_id(path: &str) -> Option<&str> {
@@ -377,6 +637,27 @@ mod tests {
             "jobs/user123_5/stage_0/front.mp4"
         );
         
+        assert_eq!(
+            StoragePaths::upload_session_file("user123", "abc", "side", "mov"),
+            "uploads/user123/abc/side.mov"
+        );
+        assert!(StoragePaths::upload_session_file("user123", "abc", "side", "mov")
+            .starts_with(&StoragePaths::upload_session_dir("user123", "abc")));
+
+        assert_eq!(
+            StoragePaths::clinical_report("user123_5"),
+            "jobs/user123_5/stage_7/report.pdf"
+        );
+        assert!(StoragePaths::clinical_report("user123_5")
+            .starts_with(&StoragePaths::stage_dir("user123_5", 7)));
+
+        assert_eq!(
+            StoragePaths::export_archive("abc", 1),
+            "exports/abc/igait-research-export-v1.zip"
+        );
+        assert!(StoragePaths::export_archive("abc", 1).starts_with(&StoragePaths::export_dir("abc")));
+        assert!(StoragePaths::export_dictionary("abc").starts_with(&StoragePaths::export_dir("abc")));
+        
         assert_eq!(
             StoragePaths::extract_job_id("jobs/user123_5/stage_1/front.mp4"),
             Some("user123_5")
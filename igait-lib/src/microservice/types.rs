@@ -4,6 +4,8 @@ use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use chrono::{DateTime, Utc};
 
+use crate::microservice::Locale;
+
 /// Identifies which stage a microservice handles.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
 #[serde(rename_all = "snake_case")]
@@ -106,6 +108,16 @@ pub struct JobMetadata {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub weight: Option<i16>,
     
+    /// The batch the job was submitted in, if any. Jobs in a batch are
+    /// reported together in one summary email rather than individually.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub batch_id: Option<String>,
+    
+    /// The language the submitter's status updates and emails are in.
+    /// Missing for jobs submitted before locales were recorded (English).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub locale: Option<Locale>,
+    
     /// Any additional key-value pairs
     #[serde(flatten)]
     pub extra: HashMap<String, serde_json::Value>,
@@ -174,6 +186,9 @@ pub enum FirestoreJobStatus {
 }
 
 /// Per-stage result stored in Firestore.
+///
+/// Also written by the worker runner to `users/{uid}/jobs/{idx}/stages/stage_{n}`
+/// in RTDB, giving each job a timeline of where processing time went.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct FirestoreStageResult {
     pub status: FirestoreStageStatus,
@@ -182,6 +197,49 @@ pub struct FirestoreStageResult {
     pub duration_ms: Option<u64>,
     pub output_keys: Option<Vec<String>>,
     pub error: Option<String>,
+    /// ID of the worker that processed (or is processing) this stage
+    #[serde(default)]
+    pub worker_id: Option<String>,
+}
+
+impl FirestoreStageResult {
+    /// Creates a result for a stage a worker has just started processing.
+    pub fn started(worker_id: &str) -> Self {
+        Self {
+            status: FirestoreStageStatus::Processing,
+            started_at: Some(Utc::now()),
+            completed_at: None,
+            duration_ms: None,
+            output_keys: None,
+            error: None,
+            worker_id: Some(worker_id.to_string()),
+        }
+    }
+
+    /// Marks the stage as finished with the given status.
+    ///
+    /// Output keys are sorted so the stored record is stable between runs.
+    pub fn finish(
+        self,
+        status: FirestoreStageStatus,
+        duration_ms: u64,
+        output_keys: Option<Vec<String>>,
+        error: Option<String>,
+    ) -> Self {
+        let output_keys = output_keys.map(|mut keys| {
+            keys.sort();
+            keys
+        });
+
+        Self {
+            status,
+            completed_at: Some(Utc::now()),
+            duration_ms: Some(duration_ms),
+            output_keys,
+            error,
+            ..self
+        }
+    }
 }
 
 /// Stage status in Firestore.
@@ -0,0 +1,125 @@
+//! Webhook event types shared by the backend and the stage workers.
+//!
+//! Anything that wants to notify a user's webhooks writes a `WebhookEvent`
+//! to the outbox at `webhooks/outbox` in Firebase RTDB. The backend picks
+//! events up from there, signs them, and delivers them to every matching
+//! registration - so workers never need to know where webhooks point.
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::microservice::now_ms;
+
+/// The kinds of job lifecycle events a webhook can subscribe to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum WebhookEventKind {
+    /// The pipeline finished and the job has a prediction
+    #[serde(rename = "job.completed")]
+    JobCompleted,
+    /// The pipeline failed and the job is marked as an error
+    #[serde(rename = "job.failed")]
+    JobFailed,
+    /// An administrator approved the job for processing
+    #[serde(rename = "job.approved")]
+    JobApproved,
+    /// An administrator rejected the job
+    #[serde(rename = "job.rejected")]
+    JobRejected,
+}
+
+impl WebhookEventKind {
+    /// Every event kind, for registrations that subscribe to all of them.
+    pub const ALL: [WebhookEventKind; 4] = [
+        Self::JobCompleted,
+        Self::JobFailed,
+        Self::JobApproved,
+        Self::JobRejected,
+    ];
+
+    /// The event name sent to receivers (e.g. "job.completed").
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::JobCompleted => "job.completed",
+            Self::JobFailed => "job.failed",
+            Self::JobApproved => "job.approved",
+            Self::JobRejected => "job.rejected",
+        }
+    }
+}
+
+/// A job lifecycle event waiting in the outbox to be delivered.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WebhookEvent {
+    /// Unique event ID, also used as the outbox key
+    pub id: String,
+
+    /// What happened
+    pub kind: WebhookEventKind,
+
+    /// The job ID (format: "{user_id}_{job_index}")
+    pub job_id: String,
+
+    /// User ID who owns the job, whose webhooks are notified
+    pub user_id: String,
+
+    /// When the event happened (Unix timestamp ms)
+    pub occurred_at: u64,
+
+    /// Event-specific details (e.g. the prediction, or the error)
+    #[serde(default)]
+    pub data: Value,
+}
+
+impl WebhookEvent {
+    /// Creates a new event that happened just now.
+    pub fn new(kind: WebhookEventKind, job_id: String, user_id: String, data: Value) -> Self {
+        let occurred_at = now_ms();
+
+        Self {
+            // RTDB keys can't contain '.', so use '_' in the ID
+            id: format!("{}_{}_{}", occurred_at, job_id, kind.as_str().replace('.', "_")),
+            kind,
+            job_id,
+            user_id,
+            occurred_at,
+            data,
+        }
+    }
+}
+
+/// Returns the Firebase RTDB path of the webhook outbox.
+pub fn webhook_outbox_path() -> &'static str {
+    "webhooks/outbox"
+}
+
+/// Returns the Firebase RTDB path of a specific event in the webhook outbox.
+pub fn webhook_outbox_item_path(event_id: &str) -> String {
+    format!("{}/{}", webhook_outbox_path(), event_id)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_webhook_event_kind_names() {
+        for kind in WebhookEventKind::ALL {
+            let json = serde_json::to_value(kind).unwrap();
+            assert_eq!(json, Value::String(kind.as_str().to_string()));
+        }
+    }
+
+    #[test]
+    fn test_webhook_event_id_is_a_valid_key() {
+        let event = WebhookEvent::new(
+            WebhookEventKind::JobCompleted,
+            "user_1_0".to_string(),
+            "user_1".to_string(),
+            Value::Null,
+        );
+
+        assert!(event.id.contains("user_1_0"));
+        assert!(!event.id.contains('.'));
+        assert_eq!(webhook_outbox_item_path(&event.id), format!("webhooks/outbox/{}", event.id));
+    }
+}
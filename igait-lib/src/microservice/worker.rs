@@ -10,7 +10,12 @@ use crate::microservice::{
         generate_worker_id, next_stage, now_ms, queue_config_path, queue_item_path, queue_path,
     },
     backend_status::JobStatus,
-    StageNumber,
+    webhook::{WebhookEvent, webhook_outbox_item_path},
+    precheck::{
+        PrecheckDetection, PrecheckRequest, answer_precheck, parse_precheck_queue,
+        precheck_item_path, precheck_queue_path,
+    },
+    FirestoreStageResult, FirestoreStageStatus, StageNumber,
 };
 use anyhow::{Context, Result};
 use async_trait::async_trait;
@@ -135,6 +140,78 @@ impl FirebaseRtdb {
         Ok(())
     }
 
+    /// Gets data at a path along with its ETag, for a conditional write
+    /// with `set_if_match` or `delete_if_match`.
+    ///
+    /// Missing data has an ETag too, so a conditional write can also
+    /// create data only if nobody else has in the meantime.
+    pub async fn get_with_etag<T: for<'de> Deserialize<'de>>(&self, path: &str) -> Result<(Option<T>, String)> {
+        let url = self.url(path);
+        let response = self.client.get(&url).header("X-Firebase-ETag", "true").send().await?;
+        
+        if !response.status().is_success() {
+            let status = response.status();
+            let body = response.text().await.unwrap_or_default();
+            anyhow::bail!("Firebase GET failed ({}): {}", status, body);
+        }
+
+        let etag = response.headers()
+            .get("ETag")
+            .and_then(|etag| etag.to_str().ok())
+            .context("Firebase GET didn't return an ETag")?
+            .to_string();
+        
+        let value: Value = response.json().await?;
+        if value.is_null() {
+            return Ok((None, etag));
+        }
+        
+        let data: T = serde_json::from_value(value)?;
+        Ok((Some(data), etag))
+    }
+
+    /// Sets data at a path, but only if it hasn't changed since it was
+    /// read with `get_with_etag`.
+    ///
+    /// # Returns
+    /// * Whether the data was written (`false` if it had changed)
+    pub async fn set_if_match<T: Serialize>(&self, path: &str, data: &T, etag: &str) -> Result<bool> {
+        let url = self.url(path);
+        let response = self.client.put(&url).header("if-match", etag).json(data).send().await?;
+        
+        if response.status() == reqwest::StatusCode::PRECONDITION_FAILED {
+            return Ok(false);
+        }
+        if !response.status().is_success() {
+            let status = response.status();
+            let body = response.text().await.unwrap_or_default();
+            anyhow::bail!("Firebase conditional SET failed ({}): {}", status, body);
+        }
+        
+        Ok(true)
+    }
+
+    /// Deletes data at a path, but only if it hasn't changed since it was
+    /// read with `get_with_etag`.
+    ///
+    /// # Returns
+    /// * Whether the data was deleted (`false` if it had changed)
+    pub async fn delete_if_match(&self, path: &str, etag: &str) -> Result<bool> {
+        let url = self.url(path);
+        let response = self.client.delete(&url).header("if-match", etag).send().await?;
+        
+        if response.status() == reqwest::StatusCode::PRECONDITION_FAILED {
+            return Ok(false);
+        }
+        if !response.status().is_success() {
+            let status = response.status();
+            let body = response.text().await.unwrap_or_default();
+            anyhow::bail!("Firebase conditional DELETE failed ({}): {}", status, body);
+        }
+        
+        Ok(true)
+    }
+
     /// Performs a multi-path update (atomic update to multiple paths).
     /// 
     /// The updates map should have paths as keys (without leading slash)
@@ -177,21 +254,18 @@ impl QueueOps {
     /// other worker fails).
     ///
     /// Jobs that require approval (either via the job flag or the queue config)
-    /// but have not yet been approved will be skipped.
+    /// but have not yet been approved will be skipped. Nothing is claimed while
+    /// the queue is paused or at its concurrency limit.
+    ///
+    /// The claimed item carries the queue's parameter overrides.
     pub async fn claim_job(&self, stage: StageNumber) -> ClaimResult<QueueItem> {
         let path = queue_path(stage);
         
         // Read the queue-level config to check if this queue requires approval
-        let config_path = queue_config_path(stage);
-        let queue_config: QueueConfig = match self.db.get(&config_path).await {
-            Ok(Some(cfg)) => cfg,
-            Ok(None) => QueueConfig::default(),
-            Err(e) => {
-                // Non-fatal: default to not requiring approval
-                eprintln!("Warning: failed to read queue config at {}: {}", config_path, e);
-                QueueConfig::default()
-            }
-        };
+        let queue_config = self.read_queue_config(stage).await;
+        if queue_config.paused {
+            return ClaimResult::Paused;
+        }
 
         // Read all items in the queue
         let items: Option<HashMap<String, QueueItem>> = match self.db.get(&path).await {
@@ -207,6 +281,12 @@ impl QueueOps {
             return ClaimResult::QueueEmpty;
         }
 
+        // Respect the queue's concurrency limit
+        let in_flight = items.values().filter(|item| !item.is_available()).count();
+        if !queue_config.has_capacity(in_flight) {
+            return ClaimResult::AllClaimed;
+        }
+
         // Find an available item (unclaimed or stale) that is approved for processing
         let now = now_ms();
         let mut available_item: Option<(String, QueueItem)> = None;
@@ -228,16 +308,34 @@ impl QueueOps {
         };
 
         // Claim the item
-        let claimed_item = item.claim(&self.worker_id);
+        let mut claimed_item = item.claim(&self.worker_id);
         let item_path = format!("{}/{}", path, key);
 
         if let Err(e) = self.db.set(&item_path, &claimed_item).await {
             return ClaimResult::Error(format!("Failed to claim job: {}", e));
         }
 
+        // Hand the stage's parameter overrides to the worker
+        claimed_item.parameters = queue_config.parameters;
+
         ClaimResult::Claimed(claimed_item)
     }
 
+    /// Reads a queue's configuration, falling back to the defaults
+    /// if it isn't set or can't be read.
+    pub async fn read_queue_config(&self, stage: StageNumber) -> QueueConfig {
+        let config_path = queue_config_path(stage);
+        match self.db.get(&config_path).await {
+            Ok(Some(cfg)) => cfg,
+            Ok(None) => QueueConfig::default(),
+            Err(e) => {
+                // Non-fatal: default to an unpaused queue without approval
+                eprintln!("Warning: failed to read queue config at {}: {}", config_path, e);
+                QueueConfig::default()
+            }
+        }
+    }
+
     /// Updates the heartbeat for a claimed job to prevent timeout.
     pub async fn heartbeat(&self, stage: StageNumber, job_id: &str) -> Result<()> {
         let path = queue_item_path(stage, job_id);
@@ -356,8 +454,16 @@ impl QueueOps {
     }
 
     /// Claims a job from the finalize queue.
+    ///
+    /// Like `claim_job`, nothing is claimed while the queue is paused
+    /// or at its concurrency limit. Approval never applies to finalize.
     pub async fn claim_finalize_job(&self) -> ClaimResult<FinalizeQueueItem> {
         let path = queue_path(StageNumber::Stage7Finalize);
+
+        let queue_config = self.read_queue_config(StageNumber::Stage7Finalize).await;
+        if queue_config.paused {
+            return ClaimResult::Paused;
+        }
         
         let items: Option<HashMap<String, FinalizeQueueItem>> = match self.db.get(&path).await {
             Ok(items) => items,
@@ -372,6 +478,11 @@ impl QueueOps {
             return ClaimResult::QueueEmpty;
         }
 
+        let in_flight = items.values().filter(|item| !item.is_available()).count();
+        if !queue_config.has_capacity(in_flight) {
+            return ClaimResult::AllClaimed;
+        }
+
         let now = now_ms();
         let mut available_item: Option<(String, FinalizeQueueItem)> = None;
 
@@ -412,6 +523,82 @@ impl QueueOps {
         self.db.delete(&path).await
     }
 
+    /// Claims a request from the precheck queue.
+    ///
+    /// Prechecks are small and someone is waiting on each one, so they
+    /// aren't subject to the queue config - they're never paused, held
+    /// for approval or limited in concurrency.
+    pub async fn claim_precheck(&self) -> ClaimResult<PrecheckRequest> {
+        let items: Option<HashMap<String, Value>> = match self.db.get(precheck_queue_path()).await {
+            Ok(items) => items,
+            Err(e) => return ClaimResult::Error(format!("Failed to read precheck queue: {}", e)),
+        };
+
+        let Some(items) = items.filter(|items| !items.is_empty()) else {
+            return ClaimResult::QueueEmpty;
+        };
+
+        // One malformed entry mustn't stop every other request being claimed
+        let (requests, malformed) = parse_precheck_queue(items);
+        for key in malformed {
+            eprintln!("Removing malformed precheck queue entry {}", key);
+            if let Err(e) = self.db.delete(&precheck_item_path(&key)).await {
+                eprintln!("Failed to remove malformed precheck queue entry {}: {e:?}", key);
+            }
+        }
+
+        // Oldest first, so nobody waits longer than they have to
+        let Some(key) = requests
+            .into_iter()
+            .filter(|(_, item)| item.is_available())
+            .min_by_key(|(_, item)| item.requested_at)
+            .map(|(key, _)| key)
+        else {
+            return ClaimResult::AllClaimed;
+        };
+
+        // Claim it only if it's unchanged, so a request the backend has
+        // given up on isn't recreated
+        let path = precheck_item_path(&key);
+        let (item, etag) = match self.db.get_with_etag::<PrecheckRequest>(&path).await {
+            Ok((Some(item), etag)) if item.is_available() => (item, etag),
+            Ok(_) => return ClaimResult::AllClaimed,
+            Err(e) => return ClaimResult::Error(format!("Failed to read precheck: {}", e)),
+        };
+
+        let claimed_item = item.claim(&self.worker_id);
+        match self.db.set_if_match(&path, &claimed_item, &etag).await {
+            Ok(true) => ClaimResult::Claimed(claimed_item),
+            Ok(false) => ClaimResult::AllClaimed,
+            Err(e) => ClaimResult::Error(format!("Failed to claim precheck: {}", e)),
+        }
+    }
+
+    /// Writes the outcome of a precheck back onto its request.
+    ///
+    /// The backend removes the request once it has read the result, or
+    /// once it has given up waiting. A result that arrives after that is
+    /// dropped, rather than recreating the request with only a result.
+    pub async fn complete_precheck(&self, id: &str, result: &PrecheckDetection) -> Result<()> {
+        let path = precheck_item_path(id);
+
+        // Retry if the request changes between reading and writing it
+        // (e.g. it's claimed again after our claim went stale)
+        for _ in 0..3 {
+            let (current, etag) = self.db.get_with_etag::<Value>(&path).await?;
+            let Some(answered) = answer_precheck(current, result) else {
+                println!("Precheck {} was removed before it was answered, dropping its result", id);
+                return Ok(());
+            };
+
+            if self.db.set_if_match(&path, &answered, &etag).await? {
+                return Ok(());
+            }
+        }
+
+        anyhow::bail!("Precheck {} kept changing while its result was being written", id)
+    }
+
     /// Updates the job status directly in Firebase RTDB.
     /// 
     /// This writes to `users/{user_id}/jobs/{job_index}/status`
@@ -428,6 +615,44 @@ impl QueueOps {
         self.db.set(&path, &logs).await
     }
 
+    /// Reads when a job was submitted (Unix timestamp in seconds).
+    ///
+    /// This reads `users/{user_id}/jobs/{job_index}/timestamp`
+    pub async fn job_submitted_at(&self, user_id: &str, job_index: usize) -> Result<Option<i64>> {
+        let path = format!("users/{}/jobs/{}/timestamp", user_id, job_index);
+        self.db.get(&path).await
+    }
+
+    /// Records where a job's results archive was stored.
+    ///
+    /// This writes to `users/{user_id}/jobs/{job_index}/archive_key`
+    pub async fn update_archive_key(&self, user_id: &str, job_index: usize, key: &str) -> Result<()> {
+        let path = format!("users/{}/jobs/{}/archive_key", user_id, job_index);
+        self.db.set(&path, &key).await
+    }
+
+    /// Records a stage's timing result in Firebase RTDB.
+    ///
+    /// This writes to `users/{user_id}/jobs/{job_index}/stages/stage_{n}`
+    pub async fn update_stage_result(
+        &self,
+        user_id: &str,
+        job_index: usize,
+        stage: u8,
+        result: &FirestoreStageResult,
+    ) -> Result<()> {
+        let path = format!("users/{}/jobs/{}/stages/stage_{}", user_id, job_index, stage);
+        self.db.set(&path, result).await
+    }
+
+    /// Adds a job lifecycle event to the webhook outbox, for the backend
+    /// to deliver to the user's registered webhooks.
+    ///
+    /// This writes to `webhooks/outbox/{event_id}`
+    pub async fn enqueue_webhook_event(&self, event: &WebhookEvent) -> Result<()> {
+        self.db.set(&webhook_outbox_item_path(&event.id), event).await
+    }
+
     /// Parses a job_id string into (user_id, job_index).
     /// 
     /// Job IDs are formatted as "{user_id}_{job_index}"
@@ -606,7 +831,7 @@ impl<W: StageWorker> WorkerRunner<W> {
         // Try to claim a job
         let job = match self.queue_ops.claim_job(stage).await {
             ClaimResult::Claimed(job) => job,
-            ClaimResult::QueueEmpty | ClaimResult::AllClaimed => {
+            ClaimResult::QueueEmpty | ClaimResult::AllClaimed | ClaimResult::Paused => {
                 return Ok(false);
             }
             ClaimResult::Error(e) => {
@@ -621,7 +846,11 @@ impl<W: StageWorker> WorkerRunner<W> {
         
         // Update job status to "Processing" in RTDB
         let stage_num = stage.as_u8();
-        self.update_job_status(&job.job_id, JobStatus::processing(stage_num)).await;
+        self.update_job_status(&job.job_id, JobStatus::processing(stage_num, job.metadata.locale.unwrap_or_default())).await;
+
+        // Record when this stage started and which worker picked it up
+        let stage_result = FirestoreStageResult::started(&self.worker_id);
+        self.record_stage_result(&job.job_id, stage_num, &stage_result).await;
 
         // Spawn heartbeat task for long-running jobs
         let heartbeat_db = self.queue_ops.db.clone();
@@ -660,6 +889,13 @@ impl<W: StageWorker> WorkerRunner<W> {
                 
                 // Release the job back to the queue by removing claim
                 let _ = self.queue_ops.release_job(stage, &job.job_id).await;
+
+                // The stage will be re-run by another worker, so it's pending again
+                let pending = FirestoreStageResult {
+                    status: FirestoreStageStatus::Pending,
+                    ..FirestoreStageResult::started(&self.worker_id)
+                };
+                self.record_stage_result(&job.job_id, stage_num, &pending).await;
                 
                 return Ok(false);
             }
@@ -678,6 +914,15 @@ impl<W: StageWorker> WorkerRunner<W> {
 
                 // Upload stage logs to Firebase RTDB
                 self.upload_stage_logs(&job.job_id, stage_num, &logs).await;
+
+                // Record the stage timing
+                let stage_result = stage_result.finish(
+                    FirestoreStageStatus::Success,
+                    duration_ms,
+                    Some(output_keys.values().cloned().collect()),
+                    None,
+                );
+                self.record_stage_result(&job.job_id, stage_num, &stage_result).await;
                 
                 // Note: We don't update status here for intermediate stages.
                 // The next stage will update to its "Processing" status.
@@ -705,9 +950,18 @@ impl<W: StageWorker> WorkerRunner<W> {
 
                 // Upload stage logs to Firebase RTDB
                 self.upload_stage_logs(&job.job_id, stage_num, &logs).await;
+
+                // Record the stage timing
+                let stage_result = stage_result.finish(
+                    FirestoreStageStatus::Failed,
+                    duration_ms,
+                    None,
+                    Some(error.clone()),
+                );
+                self.record_stage_result(&job.job_id, stage_num, &stage_result).await;
                 
                 // Update job status to "Error" in RTDB
-                self.update_job_status(&job.job_id, JobStatus::error(logs.clone())).await;
+                self.update_job_status(&job.job_id, JobStatus::error(logs.clone(), job.metadata.locale.unwrap_or_default())).await;
 
                 self.queue_ops
                     .move_to_finalize_failure(stage, &job, error, Some(logs))
@@ -733,6 +987,20 @@ impl<W: StageWorker> WorkerRunner<W> {
         }
     }
 
+    /// Record a stage timing result in RTDB
+    async fn record_stage_result(&self, job_id: &str, stage: u8, result: &FirestoreStageResult) {
+        match QueueOps::parse_job_id(job_id) {
+            Ok((user_id, job_index)) => {
+                if let Err(e) = self.queue_ops.update_stage_result(&user_id, job_index, stage, result).await {
+                    eprintln!("Failed to record stage {} result in RTDB: {:?}", stage, e);
+                }
+            }
+            Err(e) => {
+                eprintln!("Failed to parse job_id for stage result: {:?}", e);
+            }
+        }
+    }
+
     /// Update job status directly in RTDB
     async fn update_job_status(&self, job_id: &str, status: JobStatus) {
         match QueueOps::parse_job_id(job_id) {
@@ -17,6 +17,14 @@ use std::time::Instant;
 use tokio::fs;
 use tokio::process::Command;
 
+/// Default x264 encoder preset.
+/// Override per-queue with the `preset` parameter in `queue_config/stage_1`.
+const DEFAULT_PRESET: &str = "fast";
+
+/// Default video bitrate in kbps.
+/// Override per-queue with the `video_bitrate_kbps` parameter in `queue_config/stage_1`.
+const DEFAULT_VIDEO_BITRATE_KBPS: u32 = 5000;
+
 /// The media conversion worker.
 pub struct MediaConversionWorker;
 
@@ -110,13 +118,20 @@ impl MediaConversionWorker {
         let front_output_path = temp_dir.join("front.mp4");
         let side_output_path = temp_dir.join("side.mp4");
 
+        // Apply any encoder overrides from the queue config
+        let preset: String = job.parameter("preset")
+            .unwrap_or_else(|| DEFAULT_PRESET.to_string());
+        let bitrate_kbps: u32 = job.parameter("video_bitrate_kbps")
+            .unwrap_or(DEFAULT_VIDEO_BITRATE_KBPS);
+        logs.push_str(&format!("Encoder settings: preset={}, bitrate={}k\n", preset, bitrate_kbps));
+
         logs.push_str("Converting front video...\n");
-        standardize_video(&front_input_path, &front_output_path, logs).await
+        standardize_video(&front_input_path, &front_output_path, &preset, bitrate_kbps, logs).await
             .context("Failed to convert front video")?;
         logs.push_str("Front video conversion done.\n");
 
         logs.push_str("Converting side video...\n");
-        standardize_video(&side_input_path, &side_output_path, logs).await
+        standardize_video(&side_input_path, &side_output_path, &preset, bitrate_kbps, logs).await
             .context("Failed to convert side video")?;
         logs.push_str("Side video conversion done.\n");
 
@@ -158,18 +173,23 @@ impl MediaConversionWorker {
 async fn standardize_video(
     input_file_path: &PathBuf,
     output_file_path: &PathBuf,
+    preset: &str,
+    bitrate_kbps: u32,
     logs: &mut String,
 ) -> Result<()> {
+    let bitrate = format!("{}k", bitrate_kbps);
+    let bufsize = format!("{}k", bitrate_kbps.saturating_mul(2));
+
     let output = Command::new("ffmpeg")
         .args([
             "-y", // Overwrite without asking
             "-i", input_file_path.to_str().context("Invalid input path")?,
             "-vf", "scale=1920:1080:force_original_aspect_ratio=decrease,pad=1920:1080:(ow-iw)/2:(oh-ih)/2",
             "-r", "60",
-            "-b:v", "5000k",
-            "-maxrate", "5000k",
-            "-bufsize", "10000k",
-            "-preset", "fast", // x264 presets: ultrafast, superfast, veryfast, faster, fast, medium, slow, slower, veryslow
+            "-b:v", &bitrate,
+            "-maxrate", &bitrate,
+            "-bufsize", &bufsize,
+            "-preset", preset, // x264 presets: ultrafast, superfast, veryfast, faster, fast, medium, slow, slower, veryslow
             "-c:v", "libx264", // Use software x264 encoder
             "-pix_fmt", "yuv420p",
             "-c:a", "aac",
@@ -8,16 +8,22 @@
 //! - Annotated videos with bounding box overlays (for debugging)
 //!
 //! If either video fails the check, the job errors out of the pipeline.
+//!
+//! Alongside the regular queue, the worker serves prechecks: single clips
+//! sent to `POST /api/v1/precheck` before a job is submitted, checked with
+//! a shortened run of the same detection pipeline.
 
 use anyhow::{Context, Result};
 use async_trait::async_trait;
 use igait_lib::microservice::{
-    run_stage_worker, ProcessingResult, QueueItem, StageNumber, StageWorker, StorageClient,
+    generate_worker_id, run_stage_worker, ClaimResult, FirebaseRtdb, PrecheckDetection,
+    PrecheckRequest, ProcessingResult, QueueItem, QueueOps, StageNumber, StageWorker,
+    StorageClient, PRECHECK_MAX_SECONDS,
 };
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::path::{Path, PathBuf};
-use std::time::Instant;
+use std::time::{Duration, Instant};
 use tokio::fs;
 use tokio::process::Command;
 
@@ -27,6 +33,12 @@ const DETECTION_DIR: &str = "/app/igait-human-gait-detection";
 /// Path to the detection script (in the Docker container).
 const DETECTION_SCRIPT: &str = "yolo_slowfast.py";
 
+/// How much of each video the full validity check looks at.
+const DETECTION_MAX_SECONDS: u32 = 10;
+
+/// How long to wait before looking for prechecks again when there are none.
+const PRECHECK_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
 /// Per-video validity result from the Python detection script.
 #[derive(Debug, Clone, Deserialize, Serialize)]
 struct VideoValidity {
@@ -171,6 +183,7 @@ impl ValidityCheckWorker {
             &front_input_path,
             &front_output_path,
             &front_json_path,
+            DETECTION_MAX_SECONDS,
             logs,
         )
         .await
@@ -190,6 +203,7 @@ impl ValidityCheckWorker {
             &side_input_path,
             &side_output_path,
             &side_json_path,
+            DETECTION_MAX_SECONDS,
             logs,
         )
         .await
@@ -310,12 +324,16 @@ impl ValidityCheckWorker {
 }
 
 /// Runs the Python YOLO+SlowFast detection script on a single video.
+///
+/// Only the first `max_seconds` of the video are examined.
 async fn run_detection(
     input_path: &Path,
     output_path: &Path,
     json_path: &Path,
+    max_seconds: u32,
     logs: &mut String,
 ) -> Result<()> {
+    let max_seconds = max_seconds.to_string();
     let output = Command::new("python3")
         .args([
             DETECTION_SCRIPT,
@@ -328,7 +346,7 @@ async fn run_detection(
             "--device",
             "cpu",
             "--max-seconds",
-            "10",
+            &max_seconds,
         ])
         .current_dir(DETECTION_DIR)
         .output()
@@ -368,8 +386,103 @@ async fn parse_validity_json(path: &Path) -> Result<VideoValidity> {
     Ok(result)
 }
 
+/// Serves the precheck queue until the process exits.
+///
+/// Runs next to the regular worker loop, so prechecks don't wait behind
+/// the job a worker is already processing.
+async fn run_precheck_loop() {
+    let db = match FirebaseRtdb::from_env() {
+        Ok(db) => db,
+        Err(e) => {
+            eprintln!("Prechecks disabled - failed to initialise Firebase RTDB client: {:?}", e);
+            return;
+        }
+    };
+    let storage = match StorageClient::new().await {
+        Ok(storage) => storage,
+        Err(e) => {
+            eprintln!("Prechecks disabled - failed to initialise storage client: {:?}", e);
+            return;
+        }
+    };
+    let queue_ops = QueueOps::new(db, generate_worker_id("igait-stage2-precheck"));
+
+    loop {
+        match queue_ops.claim_precheck().await {
+            ClaimResult::Claimed(request) => {
+                println!("Running precheck {}", request.id);
+                let result = run_precheck(&storage, &request)
+                    .await
+                    .unwrap_or_else(|e| {
+                        eprintln!("Precheck {} failed: {:?}", request.id, e);
+                        PrecheckDetection {
+                            error: Some(e.to_string()),
+                            ..Default::default()
+                        }
+                    });
+
+                if let Err(e) = queue_ops.complete_precheck(&request.id, &result).await {
+                    eprintln!("Failed to record precheck {} result: {:?}", request.id, e);
+                }
+            }
+            ClaimResult::Error(e) => {
+                eprintln!("Failed to claim precheck: {}", e);
+                tokio::time::sleep(PRECHECK_POLL_INTERVAL).await;
+            }
+            _ => tokio::time::sleep(PRECHECK_POLL_INTERVAL).await,
+        }
+    }
+}
+
+/// Runs the shortened validity check on a precheck clip.
+async fn run_precheck(storage: &StorageClient, request: &PrecheckRequest) -> Result<PrecheckDetection> {
+    let temp_dir = PathBuf::from("/tmp").join(format!("precheck_{}", request.id));
+    fs::create_dir_all(&temp_dir)
+        .await
+        .context("Failed to create temp directory")?;
+
+    let video_data = storage
+        .download(&request.video_key)
+        .await
+        .context("Failed to download precheck clip")?;
+    let input_path = temp_dir.join("clip.mp4");
+    fs::write(&input_path, &video_data)
+        .await
+        .context("Failed to write precheck clip")?;
+
+    let json_path = temp_dir.join("validity.json");
+    let mut logs = String::new();
+    let detection = run_detection(
+        &input_path,
+        &temp_dir.join("annotated.mp4"),
+        &json_path,
+        PRECHECK_MAX_SECONDS,
+        &mut logs,
+    )
+    .await;
+    let validity = match detection {
+        Ok(()) => parse_validity_json(&json_path).await,
+        Err(e) => Err(e.context(logs)),
+    };
+
+    if let Err(e) = fs::remove_dir_all(&temp_dir).await {
+        eprintln!("Failed to clean up {:?}: {:?}", temp_dir, e);
+    }
+
+    let validity = validity?;
+    Ok(PrecheckDetection {
+        human_detected: validity.human_detected,
+        walking_detected: validity.walking_detected,
+        clips_processed: validity.clips_processed,
+        clips_with_person: validity.clips_with_person,
+        clips_with_walking: validity.clips_with_walking,
+        error: None,
+    })
+}
+
 #[tokio::main]
 async fn main() -> Result<()> {
     println!("Starting Stage 2 Validity Check worker...");
+    tokio::spawn(run_precheck_loop());
     run_stage_worker(ValidityCheckWorker).await
 }
@@ -3,12 +3,14 @@
 //! Analyzes pose landmark data from stage 4 to identify individual gait cycles
 //! using rhythmic template matching. Runs the `gait_analysis_mediapipe.py` Python
 //! script on each side's landmarks JSON, producing gait cycle indices and
-//! passing through the landmark data.
+//! passing through the landmark data. Each output is checked against
+//! `GaitAnalysis`, the part of it later stages read, before it's uploaded.
 
 use anyhow::{Context, Result};
 use async_trait::async_trait;
 use igait_lib::microservice::{
-    run_stage_worker, ProcessingResult, QueueItem, StageNumber, StageWorker, StorageClient,
+    run_stage_worker, GaitAnalysis, ProcessingResult, QueueItem, StageNumber, StageWorker,
+    StorageClient,
 };
 use std::collections::HashMap;
 use std::path::PathBuf;
@@ -201,6 +203,7 @@ impl CycleDetectionWorker {
         let front_gait_data = fs::read(&front_gait_path)
             .await
             .context("Failed to read front gait analysis JSON")?;
+        check_gait_analysis(&front_gait_data, "front", logs);
         storage
             .upload(&front_gait_key, front_gait_data, Some("application/json"))
             .await
@@ -222,6 +225,7 @@ impl CycleDetectionWorker {
         let side_gait_data = fs::read(&side_gait_path)
             .await
             .context("Failed to read side gait analysis JSON")?;
+        check_gait_analysis(&side_gait_data, "side", logs);
         storage
             .upload(&side_gait_key, side_gait_data, Some("application/json"))
             .await
@@ -294,6 +298,23 @@ async fn run_gait_cycle_detection(
     Ok(())
 }
 
+/// Checks a gait analysis has what later stages read from it, warning in
+/// the logs if not. It's uploaded either way, since the prediction reads
+/// the rest of it.
+fn check_gait_analysis(data: &[u8], view: &str, logs: &mut String) {
+    match GaitAnalysis::from_slice(data) {
+        Ok(analysis) => {
+            for warning in analysis.warnings() {
+                logs.push_str(&format!("WARNING: {} for the {} video\n", warning, view));
+            }
+        }
+        Err(e) => logs.push_str(&format!(
+            "WARNING: The {} gait analysis isn't in the expected format: {:#}\n",
+            view, e
+        )),
+    }
+}
+
 #[tokio::main]
 async fn main() -> Result<()> {
     println!("Starting Stage 5 Cycle Detection worker...");
@@ -2,8 +2,14 @@
 //!
 //! Handles post-processing completion tasks:
 //! - Checks for prediction.json in S3 to determine success/failure
-//! - Sends success/failure emails to users
-//! - Archives processing results
+//! - Sends success/failure emails to users (jobs in a batch are left to
+//!   the backend's batch summary)
+//! - Renders a clinical PDF report to `report.pdf` (see `report`); the
+//!   prediction model's version shown in its footer is read from
+//!   `MODEL_VERSION`
+//! - Archives the key artifacts (videos, pose overlays, landmarks, gait
+//!   analysis, prediction, the report and a summary) to `results.zip`,
+//!   which the success email links to
 //!
 //! This is the terminal stage that receives jobs from the finalize queue.
 
@@ -11,12 +17,21 @@ use anyhow::{Context, Result};
 use async_trait::async_trait;
 use igait_lib::microservice::{
     EmailClient, EmailTemplates, FinalizeQueueItem, ProcessingResult, StorageClient,
-    JobStatus, QueueOps, FirebaseRtdb,
+    JobStatus, QueueOps, FirebaseRtdb, FirestoreStageResult, FirestoreStageStatus,
+    StorageKeyExt, StoragePaths, WebhookEvent, WebhookEventKind, GaitAnalysis,
 };
 use serde::Deserialize;
 use std::collections::HashMap;
-use std::time::{Instant, SystemTime};
+use std::fs::File;
+use std::io::Write;
+use std::path::Path;
+use std::time::{Duration, Instant, SystemTime};
 use chrono::{DateTime, Utc};
+use zip::{CompressionMethod, ZipWriter, write::SimpleFileOptions};
+
+mod report;
+
+use report::{GaitSummary, ReportData, Validity};
 
 /// The expected format of prediction.json from Stage 6.
 ///
@@ -36,6 +51,21 @@ struct PredictionResult {
 /// ASD threshold - scores >= this value indicate ASD markers.
 const ASD_THRESHOLD: f64 = 0.5;
 
+/// How long the results archive link in the success email stays valid.
+/// This is the longest S3 allows for a presigned URL.
+const ARCHIVE_LINK_EXPIRY: Duration = Duration::from_secs(7 * 24 * 60 * 60); // 7 days
+
+/// The artifacts put in the results archive, as
+/// (stage, file name suffix, folder in the archive).
+const ARCHIVE_ARTIFACTS: [(u8, &str, &str); 6] = [
+    (1, "", "videos"),
+    (4, "_pose.mp4", "pose_overlays"),
+    (4, "_landmarks.json", "landmarks"),
+    (5, "_gait_analysis.json", "gait_analysis"),
+    (6, "prediction.json", ""),
+    (7, "report.pdf", ""),
+];
+
 /// The finalize worker handles the final stage of the pipeline.
 pub struct FinalizeStageWorker {
     email_client: EmailClient,
@@ -46,12 +76,13 @@ pub struct FinalizeStageWorker {
 impl FinalizeStageWorker {
     /// Creates a new finalize worker with required clients.
     pub async fn new() -> Result<Self> {
-        let email_client = EmailClient::from_env()
-            .await
-            .context("Failed to create email client")?;
         let storage = StorageClient::new()
             .await
             .context("Failed to create storage client")?;
+        let email_client = EmailClient::from_env()
+            .await
+            .context("Failed to create email client")?
+            .with_template_overrides(storage.clone());
         let db = FirebaseRtdb::from_env()
             .context("Failed to create Firebase RTDB client")?;
         let queue_ops = QueueOps::new(db, "stage7-finalize".to_string());
@@ -125,11 +156,201 @@ impl FinalizeStageWorker {
         }
     }
 
-    /// Sends a success email with the prediction results.
+    /// Renders the clinical PDF report and uploads it.
+    ///
+    /// Returns the report's key.
+    async fn build_report(
+        &self,
+        job: &FinalizeQueueItem,
+        score: f64,
+        logs: &mut String,
+    ) -> Result<String> {
+        let validity = match self.storage.download(&format!("jobs/{}/stage_2/validity.json", job.job_id)).await {
+            Ok(data) => serde_json::from_slice::<Validity>(&data)
+                .inspect_err(|e| logs.push_str(&format!("WARNING: Failed to parse validity.json: {}\n", e)))
+                .ok(),
+            Err(e) => {
+                logs.push_str(&format!("WARNING: No validity.json for the report: {}\n", e));
+                None
+            }
+        };
+
+        let mut gait = Vec::new();
+        for (view, side) in [("Front", "front"), ("Side", "side")] {
+            let key = format!("jobs/{}/stage_5/{}_gait_analysis.json", job.job_id, side);
+            let summary = match self.storage.download(&key).await {
+                Ok(data) => GaitAnalysis::from_slice(&data)
+                    .inspect_err(|e| logs.push_str(&format!("WARNING: Failed to parse {}: {:#}\n", key, e)))
+                    .ok()
+                    .map(|analysis| {
+                        for warning in analysis.warnings() {
+                            logs.push_str(&format!("WARNING: {} in {}, so it's left out of the report\n", warning, key));
+                        }
+                        GaitSummary::from_analysis(&analysis)
+                    }),
+                Err(e) => {
+                    logs.push_str(&format!("WARNING: No {} for the report: {}\n", key, e));
+                    None
+                }
+            };
+            gait.push((view, summary));
+        }
+
+        let (user_id, job_index) = QueueOps::parse_job_id(&job.job_id)?;
+        let submitted_at = self.queue_ops
+            .job_submitted_at(&user_id, job_index)
+            .await
+            .context("Failed to read when the job was submitted")?
+            .and_then(|secs| DateTime::<Utc>::from_timestamp(secs, 0));
+        let model_version = std::env::var("MODEL_VERSION").unwrap_or_else(|_| "unspecified".to_string());
+
+        let pdf = report::render(&ReportData {
+            job_id: &job.job_id,
+            submitted_at,
+            age: job.metadata.age,
+            sex: job.metadata.sex,
+            ethnicity: job.metadata.ethnicity.as_deref(),
+            height: job.metadata.height.as_deref(),
+            weight: job.metadata.weight,
+            score,
+            is_asd: score >= ASD_THRESHOLD,
+            threshold: ASD_THRESHOLD,
+            validity,
+            gait,
+            model_version: &model_version,
+        })?;
+
+        let report_key = StoragePaths::clinical_report(&job.job_id);
+        self.storage
+            .upload(&report_key, pdf, Some("application/pdf"))
+            .await
+            .context("Failed to upload the clinical report")?;
+        logs.push_str(&format!("Uploaded clinical report to: {}\n", report_key));
+
+        Ok(report_key)
+    }
+
+    /// Builds the results archive, uploads it and records its key on the job.
+    ///
+    /// The archive holds the job's videos, so it's built in a temporary
+    /// directory and uploaded from there rather than held in memory.
+    ///
+    /// Returns the archive's key and a presigned URL for it.
+    async fn build_results_archive(
+        &self,
+        job: &FinalizeQueueItem,
+        score: f64,
+        logs: &mut String,
+    ) -> Result<(String, String)> {
+        let temp_dir = std::env::temp_dir().join(format!("igait-results-{}", job.job_id));
+        tokio::fs::create_dir_all(&temp_dir)
+            .await
+            .context(format!("Failed to create {}", temp_dir.display()))?;
+
+        let archive_path = temp_dir.join("results.zip");
+        let archive_key = StoragePaths::results_archive(&job.job_id);
+        let uploaded = async {
+            self.write_results_archive(job, score, &temp_dir, &archive_path, logs).await?;
+            self.storage
+                .upload_file(&archive_key, &archive_path, Some("application/zip"))
+                .await
+                .context("Failed to upload the results archive")
+        }.await;
+
+        if let Err(e) = tokio::fs::remove_dir_all(&temp_dir).await {
+            eprintln!("Failed to remove {}: {e:?}", temp_dir.display());
+        }
+        uploaded?;
+        logs.push_str(&format!("Uploaded results archive to: {}\n", archive_key));
+
+        let (user_id, job_index) = QueueOps::parse_job_id(&job.job_id)?;
+        self.queue_ops
+            .update_archive_key(&user_id, job_index, &archive_key)
+            .await
+            .context("Failed to record the results archive on the job")?;
+
+        let archive_url = self.storage
+            .presign_download(&archive_key, ARCHIVE_LINK_EXPIRY)
+            .await
+            .context("Failed to presign the results archive")?;
+
+        Ok((archive_key, archive_url))
+    }
+
+    /// Writes the results archive to `archive_path`, downloading each
+    /// artifact into `temp_dir` on the way.
+    async fn write_results_archive(
+        &self,
+        job: &FinalizeQueueItem,
+        score: f64,
+        temp_dir: &Path,
+        archive_path: &Path,
+        logs: &mut String,
+    ) -> Result<()> {
+        let file = File::create(archive_path)
+            .context(format!("Failed to create the archive at {}", archive_path.display()))?;
+        let mut archive = ZipWriter::new(file);
+        let deflated = SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+        // Videos are already compressed, so they're stored as-is
+        let stored = SimpleFileOptions::default().compression_method(CompressionMethod::Stored);
+        let artifact_path = temp_dir.join("artifact");
+
+        for (stage, suffix, folder) in ARCHIVE_ARTIFACTS {
+            let keys = self.storage
+                .list_by_prefix(&StoragePaths::stage_dir(&job.job_id, stage))
+                .await
+                .context(format!("Failed to list the stage {} outputs", stage))?;
+
+            for key in keys {
+                let Some(file_name) = key.filename().filter(|name| name.ends_with(suffix)) else {
+                    continue;
+                };
+                self.storage
+                    .download_to_file(&key, &artifact_path)
+                    .await
+                    .context(format!("Failed to download {}", key))?;
+
+                let path = match folder {
+                    "" => file_name.to_string(),
+                    folder => format!("{}/{}", folder, file_name),
+                };
+                let options = if file_name.ends_with(".mp4") { stored } else { deflated };
+                archive.start_file(path.as_str(), options)?;
+                std::io::copy(&mut File::open(&artifact_path)?, &mut archive)
+                    .context(format!("Failed to archive {}", key))?;
+                logs.push_str(&format!("Archived {} as {}\n", key, path));
+            }
+        }
+
+        let summary = serde_json::json!({
+            "job_id": job.job_id,
+            "user_id": job.user_id,
+            "generated_at": Utc::now(),
+            "score": score,
+            "asd": score >= ASD_THRESHOLD,
+            "asd_threshold": ASD_THRESHOLD,
+            "demographics": {
+                "age": job.metadata.age,
+                "sex": job.metadata.sex,
+                "ethnicity": job.metadata.ethnicity,
+                "height": job.metadata.height,
+                "weight": job.metadata.weight,
+            },
+        });
+        archive.start_file("summary.json", deflated)?;
+        archive.write_all(&serde_json::to_vec_pretty(&summary)?)?;
+        archive.finish()?.sync_all()?;
+
+        Ok(())
+    }
+
+    /// Sends a success email with the prediction results, linking to the
+    /// results archive if there is one.
     async fn send_success_email(
         &self,
         job: &FinalizeQueueItem,
         score: f64,
+        archive_url: Option<&str>,
         logs: &mut String,
     ) -> Result<()> {
         let email = job.metadata.email.as_deref()
@@ -140,7 +361,8 @@ impl FinalizeStageWorker {
         
         let is_asd = score >= ASD_THRESHOLD;
         
-        let (subject, body) = EmailTemplates::prediction_success(
+        let message = EmailTemplates::prediction_success(
+            job.metadata.locale.unwrap_or_default(),
             &dt_now_cst.to_string(),
             score,
             is_asd,
@@ -151,12 +373,13 @@ impl FinalizeStageWorker {
             job.metadata.weight,
             &job.user_id,
             &job.job_id,
+            archive_url,
         );
 
         logs.push_str(&format!("Sending success email to {}\n", email));
         logs.push_str(&format!("Score: {:.2}, ASD indicator: {}\n", score, is_asd));
         
-        self.email_client.send(email, &subject, &body).await?;
+        self.email_client.send(email, &message).await?;
         logs.push_str("Success email sent\n");
         
         Ok(())
@@ -175,7 +398,8 @@ impl FinalizeStageWorker {
         let dt_now_utc: DateTime<Utc> = SystemTime::now().into();
         let dt_now_cst = dt_now_utc.with_timezone(&chrono_tz::US::Central);
         
-        let (subject, body) = EmailTemplates::processing_failure(
+        let message = EmailTemplates::processing_failure(
+            job.metadata.locale.unwrap_or_default(),
             &dt_now_cst.to_string(),
             job.failed_at_stage,
             error,
@@ -186,7 +410,7 @@ impl FinalizeStageWorker {
         logs.push_str(&format!("Sending failure email to {}\n", email));
         logs.push_str(&format!("Failed at stage: {:?}, Error: {}\n", job.failed_at_stage, error));
         
-        self.email_client.send(email, &subject, &body).await?;
+        self.email_client.send(email, &message).await?;
         logs.push_str("Failure email sent\n");
         
         Ok(())
@@ -219,6 +443,28 @@ impl FinalizeStageWorker {
             }
         }
     }
+
+    /// Queue a webhook event so the backend notifies the user's webhooks
+    async fn notify_webhooks(&self, kind: WebhookEventKind, job: &FinalizeQueueItem, data: serde_json::Value) {
+        let event = WebhookEvent::new(kind, job.job_id.clone(), job.user_id.clone(), data);
+        if let Err(e) = self.queue_ops.enqueue_webhook_event(&event).await {
+            eprintln!("Failed to queue {} webhook event for {}: {:?}", kind.as_str(), job.job_id, e);
+        }
+    }
+
+    /// Record the stage 7 timing result in Firebase RTDB
+    async fn record_stage_result(&self, job_id: &str, result: &FirestoreStageResult) {
+        match QueueOps::parse_job_id(job_id) {
+            Ok((user_id, job_index)) => {
+                if let Err(e) = self.queue_ops.update_stage_result(&user_id, job_index, 7, result).await {
+                    eprintln!("Failed to record stage 7 result in RTDB: {:?}", e);
+                }
+            }
+            Err(e) => {
+                eprintln!("Failed to parse job_id for stage result: {:?}", e);
+            }
+        }
+    }
 }
 
 /// Trait for finalize workers (separate from regular StageWorker).
@@ -244,9 +490,10 @@ impl FinalizeWorker for FinalizeStageWorker {
         println!("Processing finalize job {}", job.job_id);
         logs.push_str(&format!("Starting finalization for job {}\n", job.job_id));
         logs.push_str(&format!("Queue item success flag: {}\n", job.success));
+        let locale = job.metadata.locale.unwrap_or_default();
 
         // Update status to stage 7 on entry
-        self.update_job_status(&job.job_id, JobStatus::processing(7)).await;
+        self.update_job_status(&job.job_id, JobStatus::processing(7, locale)).await;
 
         // Check for prediction.json in S3 - this is the source of truth
         let prediction_score = self.get_prediction_score(&job.job_id).await;
@@ -254,30 +501,71 @@ impl FinalizeWorker for FinalizeStageWorker {
         let result = if let Some(score) = prediction_score {
             // Prediction file exists - this was a successful pipeline run
             logs.push_str(&format!("Prediction found: score = {:.4}\n", score));
-            
-            match self.send_success_email(job, score, &mut logs).await {
-                Ok(_) => {
-                    logs.push_str("Job completed successfully\n");
+
+            // Render the report first, so it's included in the archive
+            let report_key = match self.build_report(job, score, &mut logs).await {
+                Ok(key) => Some(key),
+                Err(e) => {
+                    // Log report failure but don't fail the job
+                    eprintln!("Failed to build clinical report for {}: {:?}", job.job_id, e);
+                    logs.push_str(&format!("WARNING: Failed to build clinical report: {:#}\n", e));
+                    None
                 }
+            };
+
+            let archive = match self.build_results_archive(job, score, &mut logs).await {
+                Ok(archive) => Some(archive),
                 Err(e) => {
-                    // Log email failure but don't fail the job
-                    eprintln!("Failed to send success email for {}: {}", job.job_id, e);
-                    logs.push_str(&format!("WARNING: Failed to send email: {}\n", e));
+                    // Log archive failure but don't fail the job - the email goes out without the link
+                    eprintln!("Failed to build results archive for {}: {:?}", job.job_id, e);
+                    logs.push_str(&format!("WARNING: Failed to build results archive: {:#}\n", e));
+                    None
+                }
+            };
+            
+            if let Some(batch_id) = &job.metadata.batch_id {
+                // The backend sends one summary once the whole batch has finished
+                logs.push_str(&format!("Part of batch {}, leaving the email to the batch summary\n", batch_id));
+            } else {
+                let archive_url = archive.as_ref().map(|(_, url)| url.as_str());
+                match self.send_success_email(job, score, archive_url, &mut logs).await {
+                    Ok(_) => {
+                        logs.push_str("Job completed successfully\n");
+                    }
+                    Err(e) => {
+                        // Log email failure but don't fail the job
+                        eprintln!("Failed to send success email for {}: {}", job.job_id, e);
+                        logs.push_str(&format!("WARNING: Failed to send email: {}\n", e));
+                    }
                 }
             }
             
             // Update job status to Complete
             let is_asd = score >= ASD_THRESHOLD;
-            self.update_job_status(&job.job_id, JobStatus::complete(score as f32, is_asd)).await;
+            let status = JobStatus::complete(score as f32, is_asd, locale);
+            self.update_job_status(&job.job_id, status.clone()).await;
+            self.notify_webhooks(WebhookEventKind::JobCompleted, job, serde_json::json!({
+                "score": score,
+                "asd": is_asd,
+                "status": status,
+            })).await;
 
             // Upload stage 7 logs to Firebase RTDB
             self.upload_stage_logs(&job.job_id, &logs).await;
             
+            let mut output_keys = HashMap::from([
+                ("score".to_string(), score.to_string()),
+                ("is_asd".to_string(), (score >= ASD_THRESHOLD).to_string()),
+            ]);
+            if let Some(report_key) = report_key {
+                output_keys.insert("report_key".to_string(), report_key);
+            }
+            if let Some((archive_key, _)) = archive {
+                output_keys.insert("archive_key".to_string(), archive_key);
+            }
+            
             ProcessingResult::Success {
-                output_keys: HashMap::from([
-                    ("score".to_string(), score.to_string()),
-                    ("is_asd".to_string(), (score >= ASD_THRESHOLD).to_string()),
-                ]),
+                output_keys,
                 logs,
                 duration_ms: start_time.elapsed().as_millis() as u64,
             }
@@ -294,18 +582,29 @@ impl FinalizeWorker for FinalizeStageWorker {
                 logs.push_str(&format!("Failed at stage: {}\n", stage));
             }
             
-            match self.send_failure_email(job, &error_msg, &mut logs).await {
-                Ok(_) => {
-                    logs.push_str("Failure notification sent\n");
-                }
-                Err(e) => {
-                    eprintln!("Failed to send failure email for {}: {}", job.job_id, e);
-                    logs.push_str(&format!("WARNING: Failed to send email: {}\n", e));
+            if let Some(batch_id) = &job.metadata.batch_id {
+                // The backend sends one summary once the whole batch has finished
+                logs.push_str(&format!("Part of batch {}, leaving the email to the batch summary\n", batch_id));
+            } else {
+                match self.send_failure_email(job, &error_msg, &mut logs).await {
+                    Ok(_) => {
+                        logs.push_str("Failure notification sent\n");
+                    }
+                    Err(e) => {
+                        eprintln!("Failed to send failure email for {}: {}", job.job_id, e);
+                        logs.push_str(&format!("WARNING: Failed to send email: {}\n", e));
+                    }
                 }
             }
             
             // Update job status to Error
-            self.update_job_status(&job.job_id, JobStatus::error(error_msg.clone())).await;
+            let status = JobStatus::error(error_msg.clone(), locale);
+            self.update_job_status(&job.job_id, status.clone()).await;
+            self.notify_webhooks(WebhookEventKind::JobFailed, job, serde_json::json!({
+                "error": error_msg,
+                "failed_at_stage": job.failed_at_stage,
+                "status": status,
+            })).await;
 
             // Upload stage 7 logs to Firebase RTDB
             self.upload_stage_logs(&job.job_id, &logs).await;
@@ -384,6 +683,10 @@ pub async fn run_finalize_worker(worker: FinalizeStageWorker) -> Result<()> {
         match queue_ops.claim_finalize_job().await {
             ClaimResult::Claimed(job) => {
                 println!("[{}] Claimed finalize job {}", worker_id, job.job_id);
+
+                // Record when finalization started
+                let stage_result = FirestoreStageResult::started(&worker_id);
+                worker.record_stage_result(&job.job_id, &stage_result).await;
                 
                 // Process the job with cancellation support
                 let process_result = tokio::select! {
@@ -400,11 +703,20 @@ pub async fn run_finalize_worker(worker: FinalizeStageWorker) -> Result<()> {
                 };
                 
                 match process_result {
-                    ProcessingResult::Success { duration_ms, .. } => {
+                    ProcessingResult::Success { output_keys, duration_ms, .. } => {
                         println!(
                             "[{}] Finalize job {} completed in {}ms",
                             worker_id, job.job_id, duration_ms
                         );
+
+                        // Stage 7 output keys are result values (score, etc.), not storage keys
+                        let stage_result = stage_result.finish(
+                            FirestoreStageStatus::Success,
+                            duration_ms,
+                            Some(output_keys.into_keys().collect()),
+                            None,
+                        );
+                        worker.record_stage_result(&job.job_id, &stage_result).await;
                         
                         // Remove from finalize queue (job is done)
                         if let Err(e) = queue_ops.complete_finalize(&job.job_id).await {
@@ -417,10 +729,18 @@ pub async fn run_finalize_worker(worker: FinalizeStageWorker) -> Result<()> {
                             "[{}] Finalize job {} failed after {}ms: {}",
                             worker_id, job.job_id, duration_ms, error
                         );
+
+                        let stage_result = stage_result.finish(
+                            FirestoreStageStatus::Failed,
+                            duration_ms,
+                            None,
+                            Some(error),
+                        );
+                        worker.record_stage_result(&job.job_id, &stage_result).await;
                     }
                 }
             }
-            ClaimResult::QueueEmpty | ClaimResult::AllClaimed => {
+            ClaimResult::QueueEmpty | ClaimResult::AllClaimed | ClaimResult::Paused => {
                 // No jobs available, wait before polling again (or until shutdown)
                 tokio::select! {
                     _ = tokio::time::sleep(Duration::from_secs(5)) => {},
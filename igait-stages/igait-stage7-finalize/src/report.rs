@@ -0,0 +1,335 @@
+//! Clinical PDF report.
+//!
+//! Renders a one-page printable summary of a completed job for clinicians:
+//! patient demographics, the prediction, the stage 2 validity check and
+//! the stage 5 gait cycles, with a model/version footer and a disclaimer.
+//!
+//! The PDF only uses the built-in Helvetica fonts, so text is limited to
+//! Windows-1252 characters.
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use printpdf::{
+    BuiltinFont, IndirectFontRef, Line, Mm, PdfDocument, PdfLayerReference, Point,
+};
+use igait_lib::microservice::GaitAnalysis;
+use serde::Deserialize;
+
+/// A4 page size.
+const PAGE_WIDTH: f32 = 210.0;
+const PAGE_HEIGHT: f32 = 297.0;
+
+/// The space left around the page's content.
+const MARGIN: f32 = 20.0;
+
+/// The column values are written at, after their labels.
+const VALUE_COLUMN: f32 = 75.0;
+
+/// Millimetres per point.
+const PT_TO_MM: f32 = 0.3528;
+
+/// Shown at the bottom of every report.
+const DISCLAIMER: &str = "This report is produced by an automated screening tool for research \
+    and clinical decision support. It is not a diagnosis. The score reflects how closely the \
+    submitted walking videos match gait patterns seen in children with autism spectrum disorder, \
+    and can be affected by video quality. Results should be interpreted by a qualified clinician \
+    together with a full developmental assessment.";
+
+/// Per-video result of the stage 2 validity check.
+#[derive(Debug, Clone, Deserialize)]
+pub struct VideoValidity {
+    pub valid: bool,
+    pub human_detected: bool,
+    pub walking_detected: bool,
+    pub total_frames: u32,
+    pub clips_processed: u32,
+    pub clips_with_person: u32,
+    pub clips_with_walking: u32,
+}
+
+/// The stage 2 validity check, as written to `stage_2/validity.json`.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Validity {
+    pub overall_valid: bool,
+    pub front: VideoValidity,
+    pub side: VideoValidity,
+}
+
+/// Summary metrics of the gait cycles detected in one video.
+///
+/// # Fields
+/// * `cycles` - How many gait cycles were detected
+/// * `mean_frames` - The mean cycle length in frames
+/// * `min_frames` - The shortest cycle in frames
+/// * `max_frames` - The longest cycle in frames
+/// * `fps` - The video's frame rate, if the analysis recorded it
+#[derive(Debug, Clone, Default)]
+pub struct GaitSummary {
+    pub cycles: usize,
+    pub mean_frames: Option<f64>,
+    pub min_frames: Option<f64>,
+    pub max_frames: Option<f64>,
+    pub fps: Option<f64>,
+}
+
+impl GaitSummary {
+    /// Summarises a stage 5 gait analysis.
+    ///
+    /// Cycles whose shape isn't known are counted but left out of the
+    /// length metrics (see `GaitAnalysis::warnings`).
+    pub fn from_analysis(analysis: &GaitAnalysis) -> Self {
+        let Some(cycles) = &analysis.gait_cycles else {
+            return Self::default();
+        };
+
+        let lengths: Vec<f64> = cycles.iter().filter_map(|cycle| cycle.frames()).collect();
+
+        let (mean_frames, min_frames, max_frames) = match lengths.is_empty() {
+            true => (None, None, None),
+            false => (
+                Some(lengths.iter().sum::<f64>() / lengths.len() as f64),
+                lengths.iter().copied().reduce(f64::min),
+                lengths.iter().copied().reduce(f64::max),
+            ),
+        };
+
+        Self {
+            cycles: cycles.len(),
+            mean_frames,
+            min_frames,
+            max_frames,
+            fps: analysis.fps.filter(|fps| *fps > 0.0),
+        }
+    }
+
+    /// Describes a cycle length, in seconds too when the frame rate is known.
+    fn describe_length(&self, frames: Option<f64>) -> String {
+        match (frames, self.fps) {
+            (Some(frames), Some(fps)) => format!("{:.1} frames ({:.2} s)", frames, frames / fps),
+            (Some(frames), None) => format!("{:.1} frames", frames),
+            (None, _) => "N/A".to_string(),
+        }
+    }
+}
+
+/// Everything shown on a report.
+pub struct ReportData<'a> {
+    pub job_id: &'a str,
+    pub submitted_at: Option<DateTime<Utc>>,
+    pub age: Option<i16>,
+    pub sex: Option<char>,
+    pub ethnicity: Option<&'a str>,
+    pub height: Option<&'a str>,
+    pub weight: Option<i16>,
+    pub score: f64,
+    pub is_asd: bool,
+    pub threshold: f64,
+    pub validity: Option<Validity>,
+    /// The gait summary of each view (e.g. "Front"), if its analysis was found
+    pub gait: Vec<(&'a str, Option<GaitSummary>)>,
+    pub model_version: &'a str,
+}
+
+/// Writes lines down a page, starting a new page when one fills up.
+struct PageWriter<'a> {
+    doc: &'a printpdf::PdfDocumentReference,
+    layer: PdfLayerReference,
+    regular: IndirectFontRef,
+    bold: IndirectFontRef,
+    /// The baseline of the next line, from the bottom of the page
+    y: f32,
+}
+
+impl PageWriter<'_> {
+    /// Starts a new page if there's less than `height` left on this one.
+    fn reserve(&mut self, height: f32) {
+        if self.y - height < MARGIN {
+            let (page, layer) = self.doc.add_page(Mm(PAGE_WIDTH), Mm(PAGE_HEIGHT), "Layer 1");
+            self.layer = self.doc.get_page(page).get_layer(layer);
+            self.y = PAGE_HEIGHT - MARGIN;
+        }
+    }
+
+    /// Writes a section heading with a rule under it.
+    fn heading(&mut self, text: &str) {
+        self.reserve(14.0);
+        self.y -= 6.0;
+        self.layer.use_text(text, 13.0, Mm(MARGIN), Mm(self.y), &self.bold);
+        self.y -= 2.0;
+        self.rule(0.4);
+        self.y -= 5.0;
+    }
+
+    /// Writes a label and its value on one line.
+    fn row(&mut self, label: &str, value: &str) {
+        self.reserve(6.0);
+        self.layer.use_text(label, 10.0, Mm(MARGIN), Mm(self.y), &self.bold);
+        self.layer.use_text(value, 10.0, Mm(VALUE_COLUMN), Mm(self.y), &self.regular);
+        self.y -= 5.5;
+    }
+
+    /// Writes text wrapped to the width of the page.
+    fn paragraph(&mut self, text: &str, size: f32) {
+        let line_height = size * PT_TO_MM * 1.4;
+        for line in wrap(text, size, PAGE_WIDTH - 2.0 * MARGIN) {
+            self.reserve(line_height);
+            self.layer.use_text(line, size, Mm(MARGIN), Mm(self.y), &self.regular);
+            self.y -= line_height;
+        }
+    }
+
+    /// Draws a horizontal line across the page at the current position.
+    fn rule(&mut self, thickness: f32) {
+        self.layer.set_outline_thickness(thickness);
+        self.layer.add_line(Line {
+            points: vec![
+                (Point::new(Mm(MARGIN), Mm(self.y)), false),
+                (Point::new(Mm(PAGE_WIDTH - MARGIN), Mm(self.y)), false),
+            ],
+            is_closed: false,
+        });
+    }
+}
+
+/// Splits text into lines that fit a width, using Helvetica's average
+/// character width (about half the font size).
+fn wrap(text: &str, size: f32, width: f32) -> Vec<String> {
+    let max_chars = (width / (size * 0.5 * PT_TO_MM)) as usize;
+
+    let mut lines = Vec::new();
+    let mut line = String::new();
+    for word in text.split_whitespace() {
+        if !line.is_empty() && line.len() + 1 + word.len() > max_chars {
+            lines.push(std::mem::take(&mut line));
+        }
+        if !line.is_empty() {
+            line.push(' ');
+        }
+        line.push_str(word);
+    }
+    if !line.is_empty() {
+        lines.push(line);
+    }
+    lines
+}
+
+/// Formats an optional value, or "N/A" if it's missing.
+fn or_na<T: ToString>(value: Option<T>) -> String {
+    value.map(|v| v.to_string()).unwrap_or_else(|| "N/A".to_string())
+}
+
+/// Renders a report to PDF bytes.
+pub fn render(report: &ReportData) -> Result<Vec<u8>> {
+    let (doc, page, layer) = PdfDocument::new(
+        "iGait Gait Screening Report",
+        Mm(PAGE_WIDTH),
+        Mm(PAGE_HEIGHT),
+        "Layer 1",
+    );
+    let regular = doc.add_builtin_font(BuiltinFont::Helvetica)
+        .context("Failed to add the regular font")?;
+    let bold = doc.add_builtin_font(BuiltinFont::HelveticaBold)
+        .context("Failed to add the bold font")?;
+
+    let mut page = PageWriter {
+        layer: doc.get_page(page).get_layer(layer),
+        doc: &doc,
+        regular,
+        bold,
+        y: PAGE_HEIGHT - MARGIN,
+    };
+
+    // ── Title ───────────────────────────────────────────────────────
+    page.layer.use_text("iGait Gait Screening Report", 20.0, Mm(MARGIN), Mm(page.y), &page.bold);
+    page.y -= 8.0;
+    page.layer.use_text(format!("Job {}", report.job_id), 9.0, Mm(MARGIN), Mm(page.y), &page.regular);
+    page.y -= 4.0;
+
+    // ── Patient ─────────────────────────────────────────────────────
+    page.heading("Patient");
+    page.row("Age", &or_na(report.age));
+    page.row("Sex", &or_na(report.sex));
+    page.row("Ethnicity", &or_na(report.ethnicity));
+    page.row("Height", &or_na(report.height));
+    page.row("Weight", &report.weight.map(|w| format!("{} lbs", w)).unwrap_or_else(|| "N/A".to_string()));
+    page.row("Submitted", &or_na(report.submitted_at.map(|at| at.format("%B %-d, %Y %H:%M UTC"))));
+
+    // ── Result ──────────────────────────────────────────────────────
+    page.heading("Result");
+    page.row("Likelihood score", &format!("{:.2} (threshold {:.2})", report.score, report.threshold));
+    page.row("Classification", match report.is_asd {
+        true => "Markers consistent with ASD gait patterns",
+        false => "Typical gait patterns",
+    });
+
+    // ── Video validity ──────────────────────────────────────────────
+    page.heading("Video validity (stage 2)");
+    match &report.validity {
+        Some(validity) => {
+            page.row("Overall", if validity.overall_valid { "Valid" } else { "Not valid" });
+            for (view, video) in [("Front", &validity.front), ("Side", &validity.side)] {
+                page.row(
+                    &format!("{} video", view),
+                    &format!(
+                        "{} - {} frames; person in {}/{} clips, walking in {}/{} clips",
+                        match (video.valid, video.human_detected, video.walking_detected) {
+                            (true, ..) => "valid",
+                            (false, false, _) => "no person detected",
+                            (false, true, false) => "no walking detected",
+                            (false, true, true) => "not valid",
+                        },
+                        video.total_frames,
+                        video.clips_with_person,
+                        video.clips_processed,
+                        video.clips_with_walking,
+                        video.clips_processed,
+                    ),
+                );
+            }
+        }
+        None => page.row("Overall", "N/A"),
+    }
+
+    // ── Gait cycles ─────────────────────────────────────────────────
+    page.heading("Gait cycles (stage 5)");
+    for (view, summary) in &report.gait {
+        match summary {
+            Some(summary) => {
+                page.row(&format!("{} cycles detected", view), &summary.cycles.to_string());
+                page.row(&format!("{} mean cycle length", view), &summary.describe_length(summary.mean_frames));
+                page.row(
+                    &format!("{} cycle length range", view),
+                    &match (summary.min_frames, summary.max_frames) {
+                        (Some(_), Some(_)) => format!(
+                            "{} to {}",
+                            summary.describe_length(summary.min_frames),
+                            summary.describe_length(summary.max_frames),
+                        ),
+                        _ => "N/A".to_string(),
+                    },
+                );
+            }
+            None => page.row(&format!("{} cycles detected", view), "N/A"),
+        }
+    }
+
+    // ── Disclaimer and footer ───────────────────────────────────────
+    page.heading("Disclaimer");
+    page.paragraph(DISCLAIMER, 9.0);
+    page.y -= 4.0;
+    page.reserve(12.0);
+    page.rule(0.2);
+    page.y -= 4.0;
+    page.paragraph(
+        &format!(
+            "Generated {} by the iGait pipeline (finalize stage v{}), prediction model {}. \
+             Questions: GaitStudy@niu.edu",
+            Utc::now().format("%Y-%m-%d %H:%M UTC"),
+            env!("CARGO_PKG_VERSION"),
+            report.model_version,
+        ),
+        8.0,
+    );
+
+    doc.save_to_bytes().context("Failed to write the PDF")
+}